@@ -314,7 +314,7 @@ mod tests {
     fn test_decoder_incremental() {
         let input = b"5\r\nHello\r\n0\r\n\r\n";
         let mut decoder = ChunkedDecoder::new();
-        let mut output = vec![0u8; 100];
+        let mut output = [0u8; 100];
         let mut total_decoded = 0;
         let mut total_consumed = 0;
 
@@ -2,9 +2,10 @@
 //!
 //! This module provides HTTP client functionality for testing.
 
+use super::h2::{self, H2Client, H2ClientBuilder};
 use super::{
-    chunked, Error, Headers, HttpRequest, HttpResponse, HttpSession, Method, ResponseParser,
-    Result, SessionOps,
+    chunked, Error, Headers, HttpRequest, HttpResponse, HttpSession, Method, PrefixedSession,
+    ResponseParser, Result, SessionOps,
 };
 
 /// HTTP client
@@ -206,6 +207,52 @@ impl<S: SessionOps> HttpClient<S> {
     pub fn session_mut(&mut self) -> &mut HttpSession<S> {
         &mut self.session
     }
+
+    /// Unwrap the session, with any bytes received past the last response
+    pub fn into_parts(mut self) -> (S, Vec<u8>) {
+        let remaining = self.parser.take_remaining();
+        (self.session.into_inner(), remaining)
+    }
+
+    /// Ask a proxy for a tunnel to `authority` with CONNECT
+    ///
+    /// On a 2xx response the connection becomes a raw byte stream, see
+    /// `into_tunnel`. Other responses fail with `Error::Protocol`.
+    pub fn connect_tunnel(mut self, authority: &str) -> Result<PrefixedSession<S>> {
+        let request = HttpRequest::builder()
+            .method(Method::Connect)
+            .uri(authority)
+            .header("Host", authority)
+            .build();
+
+        self.send_request(&request)?;
+        let response = self.receive_response()?;
+        if !response.status().is_success() {
+            return Err(Error::Protocol(format!(
+                "CONNECT {} refused with status {}",
+                authority,
+                response.status().code()
+            )));
+        }
+
+        Ok(self.into_tunnel())
+    }
+
+    /// Hand over the connection as a raw byte stream
+    ///
+    /// Meant for after a 2xx response to CONNECT (RFC 9110 Section 9.3.6).
+    /// Bytes received past the response are replayed first.
+    pub fn into_tunnel(self) -> PrefixedSession<S> {
+        let (session, remaining) = self.into_parts();
+        PrefixedSession::new(session, remaining)
+    }
+
+    /// Upgrade the connection to HTTP/2 with `Upgrade: h2c`
+    ///
+    /// See `H2ClientBuilder::upgrade`; this uses the default client settings.
+    pub fn upgrade_h2c(self, request: HttpRequest) -> h2::Result<H2Client<PrefixedSession<S>>> {
+        H2ClientBuilder::new().upgrade(self, request)
+    }
 }
 
 #[cfg(test)]
@@ -260,7 +307,7 @@ mod tests {
             let (mut stream, _) = listener.accept().unwrap();
             // Read request (but ignore it)
             let mut buf = vec![0u8; 1024];
-            stream.read(&mut buf).unwrap();
+            let _ = stream.read(&mut buf).unwrap();
 
             // Send response
             stream
@@ -295,7 +342,7 @@ mod tests {
         let handle = thread::spawn(move || {
             let (mut stream, _) = listener.accept().unwrap();
             let mut buf = vec![0u8; 1024];
-            stream.read(&mut buf).unwrap();
+            let _ = stream.read(&mut buf).unwrap();
 
             stream
                 .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK")
@@ -5,15 +5,31 @@
 
 use super::codec::FrameCodec;
 use super::error::{Error, ErrorCode, Result};
-use super::flow_control::ConnectionFlowControl;
+use super::flow_control::{ConnectionFlowControl, FlowControlPolicy};
 use super::frames::*;
-use super::settings::{Settings, SettingsBuilder};
-use super::stream::{StreamId, StreamManager};
+use super::ping::{KeepAlive, KeepAliveAction, PingTracker};
+use super::hpack::{HeaderField, HpackDecoder};
+use super::priority::{DataScheduler, ExtensiblePriority, PRIORITY_HEADER};
+use super::script::ScriptConnection;
+use super::settings::{Settings, SettingsBuilder, SettingsTracker};
+use super::stream::{H2Stream, StreamId, StreamManager, StreamState};
+use super::validate::{FrameValidator, Violation, ViolationScope};
+use super::h2c;
+use super::tunnel::{H2Tunnel, TunnelEndpoint};
+use super::websocket::{WebSocket, WEBSOCKET_PROTOCOL, WEBSOCKET_VERSION};
 use super::{CONNECTION_PREFACE, CONNECTION_STREAM_ID};
-use crate::http::{SessionOps, HttpSession};
+use crate::http::session::{PollEvents, PrefixedSession};
+use crate::http::{HttpClient, HttpRequest, HttpSession, SessionOps};
 use bytes::Bytes;
 use hpack::Encoder as HpackEncoder;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Number of reset streams whose frames in flight are discarded
+///
+/// Frames arriving on older reset streams are handled as on any closed
+/// stream.
+const MAX_RESET_STREAMS: usize = 128;
 
 /// HTTP/2 client
 ///
@@ -29,11 +45,38 @@ pub struct H2Client<S: SessionOps> {
     /// HPACK encoder
     hpack_encoder: HpackEncoder<'static>,
     /// HPACK decoder
-    hpack_decoder: hpack::Decoder<'static>,
-    /// Client settings
+    hpack_decoder: HpackDecoder,
+    /// Receive window replenishment policy
+    flow_control_policy: FlowControlPolicy,
+    /// Client settings in effect, as acknowledged by the server
     local_settings: Settings,
+    /// Settings sent with the connection preface
+    initial_settings: Settings,
     /// Remote (server) settings
     remote_settings: Settings,
+    /// Sent SETTINGS frames awaiting the server's ACK
+    settings_tracker: SettingsTracker,
+    /// Sent PING frames awaiting their ACK, and keepalive state
+    ping_tracker: PingTracker,
+    /// Validator for received frames
+    validator: FrameValidator,
+    /// Finished streams whose response has not been collected
+    completed: VecDeque<StreamId>,
+    /// PUSH_PROMISE awaiting CONTINUATION frames (promised stream, header block)
+    pending_push: Option<(StreamId, Vec<u8>)>,
+    /// Received push promises not yet collected
+    pushes: VecDeque<H2Push>,
+    /// Streams we recently reset with RST_STREAM (refused pushes, stream
+    /// errors), oldest first
+    reset_streams: VecDeque<StreamId>,
+    /// Header block of a reset stream, decoded and discarded
+    discarded_block: Vec<u8>,
+    /// Last GOAWAY received
+    goaway: Option<GoawayFrame>,
+    /// `:scheme` of the requests
+    scheme: String,
+    /// `:authority` of the requests
+    authority: String,
     /// Connection established
     connected: bool,
 }
@@ -54,7 +97,7 @@ impl<S: SessionOps> H2Client<S> {
         self.session.write(CONNECTION_PREFACE)?;
 
         // Send initial SETTINGS frame
-        let settings_frame = SettingsFrame::new(self.local_settings.clone());
+        let settings_frame = SettingsFrame::new(self.initial_settings.clone());
         self.send_settings(&settings_frame)?;
 
         // Wait for server SETTINGS
@@ -65,9 +108,14 @@ impl<S: SessionOps> H2Client<S> {
     }
 
     /// Send a SETTINGS frame
+    ///
+    /// The values take effect locally once the server acknowledges them.
     pub fn send_settings(&mut self, frame: &SettingsFrame) -> Result<()> {
         let encoded = FrameCodec::encode_settings_frame(frame);
         self.session.write(&encoded)?;
+        if !frame.ack {
+            self.settings_tracker.sent(frame.settings.clone());
+        }
         Ok(())
     }
 
@@ -88,14 +136,22 @@ impl<S: SessionOps> H2Client<S> {
             )));
         }
 
+        self.handle_settings(flags, stream_id, &payload)
+    }
+
+    /// Apply a received SETTINGS frame and acknowledge it
+    fn handle_settings(&mut self, flags: FrameFlags, stream_id: StreamId, payload: &[u8]) -> Result<()> {
         if stream_id != CONNECTION_STREAM_ID {
             return Err(Error::Protocol(
                 "SETTINGS frame must have stream ID 0".to_string(),
             ));
         }
 
-        // If it's an ACK, we're done
+        // An ACK applies our oldest outstanding SETTINGS
         if flags.is_ack() {
+            if let Some(settings) = self.settings_tracker.acknowledge() {
+                self.apply_local_settings(&settings)?;
+            }
             return Ok(());
         }
 
@@ -134,19 +190,11 @@ impl<S: SessionOps> H2Client<S> {
 
         // Update stream manager max concurrent streams
         self.stream_manager
-            .set_max_concurrent_streams(settings.max_concurrent_streams);
+            .set_max_concurrent_streams(self.remote_settings.max_concurrent_streams);
 
         // Update flow control if initial window size changed
         if let Some(new_size) = settings.initial_window_size {
-            // Update all existing streams
-            for stream_id in self.stream_manager.stream_ids() {
-                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
-                    stream
-                        .flow_control_mut()
-                        .send_window_mut()
-                        .update_initial_size(new_size)?;
-                }
-            }
+            self.stream_manager.update_initial_send_window_size(new_size)?;
         }
 
         // Send SETTINGS ACK
@@ -155,6 +203,63 @@ impl<S: SessionOps> H2Client<S> {
         Ok(())
     }
 
+    /// Apply our own settings once the server acknowledged them
+    fn apply_local_settings(&mut self, settings: &Settings) -> Result<()> {
+        self.local_settings.merge(settings);
+
+        if let Some(size) = settings.header_table_size {
+            self.hpack_decoder.set_max_table_size(size as usize);
+        }
+        if let Some(size) = settings.max_header_list_size {
+            self.hpack_decoder.set_max_header_list_size(Some(size as usize));
+        }
+        if let Some(size) = settings.initial_window_size {
+            self.stream_manager.update_initial_recv_window_size(size)?;
+        }
+        if let Some(size) = settings.max_frame_size {
+            self.validator.set_max_frame_size(size);
+        }
+        if let Some(enable) = settings.enable_push {
+            self.validator.set_enable_push(enable);
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the next frame while running the SETTINGS and keepalive timers
+    ///
+    /// Fails with SETTINGS_TIMEOUT if a SETTINGS ACK is overdue, and with
+    /// `Error::Timeout` if a keepalive PING goes unacknowledged. Sends a
+    /// keepalive PING whenever the connection has been idle long enough.
+    fn wait_for_frame(&mut self) -> Result<()> {
+        loop {
+            let wait = [self.settings_tracker.remaining(), self.ping_tracker.keepalive_remaining()]
+                .into_iter()
+                .flatten()
+                .min();
+            let Some(wait) = wait else {
+                return Ok(());
+            };
+            if !wait.is_zero() && self.session.get_ref().poll(PollEvents::Read, Some(wait))? {
+                return Ok(());
+            }
+
+            if self.settings_tracker.is_expired() {
+                let last_stream_id = self.stream_manager.last_remote_stream_id();
+                self.send_goaway(last_stream_id, ErrorCode::SettingsTimeout, "SETTINGS not acknowledged")?;
+                return Err(Error::SettingsTimeout);
+            }
+            match self.ping_tracker.keepalive_action() {
+                KeepAliveAction::Ping => {
+                    let data = self.ping_tracker.next_payload();
+                    self.send_ping(data)?;
+                }
+                KeepAliveAction::Expired => return Err(Error::Timeout),
+                KeepAliveAction::Wait => {}
+            }
+        }
+    }
+
     /// Send a simple GET request
     pub fn get(&mut self, path: &str) -> Result<H2Response> {
         self.request("GET", path, &[], Bytes::new())
@@ -178,19 +283,229 @@ impl<S: SessionOps> H2Client<S> {
             self.connect()?;
         }
 
+        let has_body = !body.is_empty();
+        let stream_id = self.open_stream(method, path, headers, !has_body)?;
+
+        // Send DATA frame if there's a body
+        if has_body {
+            self.send_body(stream_id, body, true)?;
+        }
+
+        // Receive response
+        self.recv_response(stream_id)
+    }
+
+    /// Open a new request stream by sending its HEADERS frame
+    ///
+    /// Fails with `TooManyStreams` if the server's SETTINGS_MAX_CONCURRENT_STREAMS
+    /// would be exceeded, and with `ConnectionClosed` once the server sent
+    /// GOAWAY. Responses are collected with `recv_response` or
+    /// `recv_any_response`, in any order.
+    pub fn open_stream(
+        &mut self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        end_stream: bool,
+    ) -> Result<StreamId> {
+        let scheme = self.scheme.clone();
+        let authority = self.authority.clone();
+        let pseudo_headers = [
+            (":method", method),
+            (":path", path),
+            (":scheme", scheme.as_str()),
+            (":authority", authority.as_str()),
+        ];
+        self.send_request_headers(&pseudo_headers, headers, end_stream)
+    }
+
+    /// Open a CONNECT stream to `authority` (RFC 9113 Section 8.5)
+    ///
+    /// Only `:method` and `:authority` are sent. The response is collected
+    /// with `recv_connect_response`.
+    pub fn open_connect(&mut self, authority: &str, headers: &[(&str, &str)]) -> Result<StreamId> {
+        let pseudo_headers = [(":method", "CONNECT"), (":authority", authority)];
+        self.send_request_headers(&pseudo_headers, headers, false)
+    }
+
+    /// Open a CONNECT tunnel to `authority` and wait for it to be established
+    ///
+    /// Fails with `Error::Connect` if the proxy answers with a non-2xx
+    /// status.
+    pub fn connect_tunnel(&mut self, authority: &str, headers: &[(&str, &str)]) -> Result<H2Tunnel<'_, Self>> {
+        let stream_id = self.open_connect(authority, headers)?;
+        self.establish_tunnel(stream_id)
+    }
+
+    /// Open an extended CONNECT stream (RFC 8441)
+    ///
+    /// The server must have advertised SETTINGS_ENABLE_CONNECT_PROTOCOL.
+    /// The response is collected with `recv_connect_response`.
+    pub fn open_extended_connect(
+        &mut self,
+        protocol: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<StreamId> {
+        if !self.remote_settings.get_enable_connect_protocol() {
+            return Err(Error::Protocol(
+                "Server did not enable extended CONNECT".to_string(),
+            ));
+        }
+
+        let scheme = self.scheme.clone();
+        let authority = self.authority.clone();
+        let pseudo_headers = [
+            (":method", "CONNECT"),
+            (":protocol", protocol),
+            (":scheme", scheme.as_str()),
+            (":path", path),
+            (":authority", authority.as_str()),
+        ];
+        self.send_request_headers(&pseudo_headers, headers, false)
+    }
+
+    /// Open an extended CONNECT tunnel and wait for it to be established
+    ///
+    /// Fails with `Error::Connect` if the server answers with a non-2xx
+    /// status.
+    pub fn extended_connect(
+        &mut self,
+        protocol: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<H2Tunnel<'_, Self>> {
+        let stream_id = self.open_extended_connect(protocol, path, headers)?;
+        self.establish_tunnel(stream_id)
+    }
+
+    /// Open a WebSocket over HTTP/2 (RFC 8441 Section 5)
+    pub fn connect_websocket(
+        &mut self,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<WebSocket<H2Tunnel<'_, Self>>> {
+        let mut ws_headers = vec![("sec-websocket-version", WEBSOCKET_VERSION)];
+        ws_headers.extend_from_slice(headers);
+
+        let tunnel = self.extended_connect(WEBSOCKET_PROTOCOL, path, &ws_headers)?;
+        Ok(WebSocket::client(tunnel))
+    }
+
+    /// Wait for the response to a CONNECT request and wrap the tunnel
+    fn establish_tunnel(&mut self, stream_id: StreamId) -> Result<H2Tunnel<'_, Self>> {
+        let response = self.recv_connect_response(stream_id)?;
+        if !(200..300).contains(&response.status) {
+            return Err(Error::Connect(format!(
+                "CONNECT on stream {} refused with status {}",
+                stream_id, response.status
+            )));
+        }
+
+        Ok(H2Tunnel::new(self, stream_id))
+    }
+
+    /// Receive the response headers of a CONNECT request
+    ///
+    /// Returns as soon as the final response headers arrive, without a
+    /// body: on success, the stream carries the tunnel.
+    pub fn recv_connect_response(&mut self, stream_id: StreamId) -> Result<H2Response> {
+        loop {
+            let stream = self
+                .stream_manager
+                .get_stream(stream_id)
+                .ok_or(Error::InvalidStreamId(stream_id))?;
+
+            // Refused with END_STREAM, or reset
+            if stream.is_finished() {
+                self.completed.retain(|&id| id != stream_id);
+                return self.take_response(stream_id);
+            }
+
+            let status = stream
+                .headers()
+                .iter()
+                .find(|(name, _)| name == ":status")
+                .and_then(|(_, value)| value.parse::<u16>().ok());
+
+            if let Some(status) = status.filter(|status| *status >= 200) {
+                return Ok(H2Response {
+                    stream_id,
+                    status,
+                    headers: stream
+                        .headers()
+                        .iter()
+                        .filter(|(name, _)| name != ":status")
+                        .cloned()
+                        .collect(),
+                    trailers: HashMap::new(),
+                    body: Bytes::new(),
+                });
+            }
+
+            self.process_frame()?;
+        }
+    }
+
+    /// Receive the data of a stream as it arrives
+    ///
+    /// Returns the DATA received since the last call, waiting for some if
+    /// needed, or None once the server ended the stream. Meant for tunnels,
+    /// whose body only ends when either side closes.
+    pub fn recv_stream_data(&mut self, stream_id: StreamId) -> Result<Option<Bytes>> {
+        loop {
+            let stream = self
+                .stream_manager
+                .get_stream_mut(stream_id)
+                .ok_or(Error::InvalidStreamId(stream_id))?;
+
+            if !stream.body().is_empty() {
+                return Ok(Some(Bytes::from(stream.take_body())));
+            }
+            if stream.reset_code().is_some() {
+                return Err(Error::Cancel(stream_id));
+            }
+            if stream.stream_complete() {
+                self.completed.retain(|&id| id != stream_id);
+                return Ok(None);
+            }
+
+            self.process_frame()?;
+        }
+    }
+
+    /// Send the HEADERS frame opening a new request stream
+    fn send_request_headers(
+        &mut self,
+        pseudo_headers: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        end_stream: bool,
+    ) -> Result<StreamId> {
+        // No new streams once the server is going away
+        if self.goaway.is_some() {
+            return Err(Error::ConnectionClosed);
+        }
+
         // Create new stream
         let stream_id = self.stream_manager.create_stream()?;
 
-        // Build headers
-        let mut hpack_headers = Vec::new();
-        hpack_headers.push((":method", method));
-        hpack_headers.push((":path", path));
-        hpack_headers.push((":scheme", "https"));
-        hpack_headers.push((":authority", "localhost"));
+        // Build headers, pseudo-headers given explicitly replacing ours
+        let mut hpack_headers: Vec<(&str, &str)> = pseudo_headers
+            .iter()
+            .copied()
+            .filter(|(name, _)| !headers.iter().any(|(n, _)| n == name))
+            .collect();
 
         // Add custom headers
-        for (name, value) in headers {
+        for &(name, value) in headers {
             hpack_headers.push((name, value));
+
+            // Track the priority we signal (RFC 9218 Section 5)
+            if name.eq_ignore_ascii_case(PRIORITY_HEADER) {
+                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                    stream.set_extensible_priority(ExtensiblePriority::parse(value));
+                }
+            }
         }
 
         // Encode headers with HPACK
@@ -204,23 +519,94 @@ impl<S: SessionOps> H2Client<S> {
             .map_err(|e| Error::Internal(format!("HPACK encode error: {}", e)))?;
 
         // Send HEADERS frame
-        let has_body = !body.is_empty();
         let headers_frame = HeadersFrame::new(
             stream_id,
             Bytes::from(header_block_vec),
-            !has_body, // END_STREAM if no body
-            true,      // END_HEADERS (no continuation for now)
+            end_stream,
+            true, // END_HEADERS (no continuation for now)
         );
         self.send_headers(&headers_frame)?;
 
-        // Send DATA frame if there's a body
-        if has_body {
-            let data_frame = DataFrame::new(stream_id, body, true);
-            self.send_data(&data_frame)?;
+        Ok(stream_id)
+    }
+
+    /// Send request body data on an open stream
+    ///
+    /// The data is split into DATA frames that fit the peer's max frame size
+    /// and the available flow-control windows. When the windows are exhausted,
+    /// incoming frames are processed until WINDOW_UPDATE opens them again.
+    /// Nothing is sent for an empty body that does not end the stream.
+    pub fn send_body(&mut self, stream_id: StreamId, data: Bytes, end_stream: bool) -> Result<()> {
+        if data.is_empty() && !end_stream {
+            return Ok(());
         }
 
-        // Receive response
-        self.recv_response(stream_id)
+        let max_frame_size = self.remote_settings.get_max_frame_size() as usize;
+        let mut remaining = data;
+
+        loop {
+            let stream = self
+                .stream_manager
+                .get_stream(stream_id)
+                .ok_or(Error::StreamClosed(stream_id))?;
+            if stream.reset_code().is_some() {
+                return Err(Error::Cancel(stream_id));
+            }
+
+            let window = std::cmp::min(
+                self.flow_control.send_window().size(),
+                stream.flow_control().send_window().size(),
+            );
+
+            // Wait for WINDOW_UPDATE
+            if window <= 0 && !remaining.is_empty() {
+                self.process_frame()?;
+                continue;
+            }
+
+            let len = std::cmp::min(remaining.len(), std::cmp::min(window.max(0) as usize, max_frame_size));
+            let chunk = remaining.split_to(len);
+            let last = remaining.is_empty();
+
+            self.send_data(&DataFrame::new(stream_id, chunk, end_stream && last))?;
+
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Send bodies on several streams, ordered by the dependency tree
+    ///
+    /// DATA frames are interleaved so that streams get bandwidth in
+    /// proportion to their weight, and dependents wait for their parent
+    /// (RFC 7540 Section 5.3). Every body ends its stream. Blocks on flow
+    /// control like `send_body`.
+    pub fn send_prioritized(&mut self, bodies: Vec<(StreamId, Bytes)>) -> Result<()> {
+        let max_frame_size = self.remote_settings.get_max_frame_size() as usize;
+
+        let mut scheduler = DataScheduler::new();
+        for (stream_id, data) in bodies {
+            scheduler.push(stream_id, data, true);
+        }
+
+        while !scheduler.is_empty() {
+            let connection_window = self.flow_control.send_window().size().max(0) as usize;
+            let streams = &self.stream_manager;
+            let window = |stream_id| {
+                streams
+                    .get_stream(stream_id)
+                    .map_or(0, |stream| stream.flow_control().send_window().size().max(0) as usize)
+                    .min(connection_window)
+            };
+
+            match scheduler.next(streams.priority_tree(), window, max_frame_size) {
+                Some(frame) => self.send_data(&frame)?,
+                None => self.process_frame().map(|_| ())?,
+            }
+        }
+
+        Ok(())
     }
 
     /// Send a HEADERS frame
@@ -229,6 +615,9 @@ impl<S: SessionOps> H2Client<S> {
         if let Some(stream) = self.stream_manager.get_stream_mut(frame.stream_id) {
             stream.send_headers(frame.end_stream)?;
         }
+        if let Some(priority) = &frame.priority {
+            self.stream_manager.reprioritize(frame.stream_id, priority)?;
+        }
 
         let encoded = FrameCodec::encode_headers_frame(frame);
         self.session.write(&encoded)?;
@@ -236,45 +625,111 @@ impl<S: SessionOps> H2Client<S> {
     }
 
     /// Send a DATA frame
+    ///
+    /// The whole frame (including padding) must fit in both the connection
+    /// and stream send windows; use `send_body` to wait for WINDOW_UPDATE.
     pub fn send_data(&mut self, frame: &DataFrame) -> Result<()> {
+        let len = frame.frame_size();
+
         // Check connection-level flow control
-        let sendable_conn = self.flow_control.consume_send_window(frame.data.len())?;
-        if sendable_conn == 0 {
+        if !self.flow_control.can_send(len) {
             return Err(Error::FlowControl("Connection window exhausted".to_string()));
         }
 
         // Check stream-level flow control
         if let Some(stream) = self.stream_manager.get_stream_mut(frame.stream_id) {
-            let sendable_stream = stream.send_data(frame.data.len(), frame.end_stream)?;
-            if sendable_stream == 0 {
+            if !stream.flow_control().can_send(len) {
                 return Err(Error::FlowControl("Stream window exhausted".to_string()));
             }
+            stream.send_data(len, frame.end_stream)?;
         }
+        self.flow_control.consume_send_window(len)?;
 
         let encoded = FrameCodec::encode_data_frame(frame);
         self.session.write(&encoded)?;
         Ok(())
     }
 
+    /// Send a frame of any type, with its flags and payload as-is
+    ///
+    /// Useful for extension and unknown frame types, which the receiver
+    /// must ignore (RFC 9113 Section 5.5).
+    pub fn send_frame(&mut self, frame: &Frame) -> Result<()> {
+        let encoded = FrameCodec::encode_frame(frame);
+        self.session.write(&encoded)?;
+        Ok(())
+    }
+
+    /// Send a PRIORITY_UPDATE frame (RFC 9218 Section 7.1)
+    ///
+    /// The stream may not be open yet: a server applies a priority received
+    /// before the request once the stream opens.
+    pub fn send_priority_update(&mut self, stream_id: StreamId, priority: ExtensiblePriority) -> Result<()> {
+        let frame = PriorityUpdateFrame::new(stream_id, priority.to_string());
+        let encoded = FrameCodec::encode_priority_update_frame(&frame);
+        self.session.write(&encoded)?;
+
+        if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+            stream.set_extensible_priority(priority);
+        }
+
+        Ok(())
+    }
+
+    /// Send a PRIORITY frame
+    ///
+    /// The local dependency tree is updated too, so that
+    /// `send_prioritized` follows the priorities we signal.
+    pub fn send_priority(&mut self, stream_id: StreamId, priority: PrioritySpec) -> Result<()> {
+        let frame = PriorityFrame::new(stream_id, priority);
+        let encoded = FrameCodec::encode_priority_frame(&frame);
+        self.session.write(&encoded)?;
+
+        self.stream_manager.reprioritize(stream_id, &priority)
+    }
+
     /// Send a PING frame
+    ///
+    /// The ping is tracked until the matching ACK arrives.
     pub fn send_ping(&mut self, data: [u8; 8]) -> Result<()> {
         let frame = PingFrame::new(data);
         let encoded = FrameCodec::encode_ping_frame(&frame);
         self.session.write(&encoded)?;
+        self.ping_tracker.sent(data);
         Ok(())
     }
 
+    /// Send a PING and wait for its ACK, returning the round-trip time
+    ///
+    /// Frames received meanwhile are processed as usual. Fails with
+    /// `Error::Timeout` if no ACK arrives in time.
+    pub fn ping_and_wait(&mut self, timeout: Duration) -> Result<Duration> {
+        let data = self.ping_tracker.next_payload();
+        self.send_ping(data)?;
+
+        let deadline = Instant::now() + timeout;
+        while self.ping_tracker.is_outstanding(data) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !self.session.get_ref().poll(PollEvents::Read, Some(remaining))? {
+                return Err(Error::Timeout);
+            }
+            self.process_frame()?;
+        }
+
+        Ok(self.ping_tracker.last_rtt().unwrap_or_default())
+    }
+
     /// Send a WINDOW_UPDATE frame
     pub fn send_window_update(&mut self, stream_id: StreamId, increment: u32) -> Result<()> {
         let frame = WindowUpdateFrame::new(stream_id, increment);
         let encoded = FrameCodec::encode_window_update_frame(&frame);
         self.session.write(&encoded)?;
 
-        // Update flow control
+        // Update flow control (our receive window grows)
         if stream_id == CONNECTION_STREAM_ID {
-            self.flow_control.increase_send_window(increment)?;
+            self.flow_control.send_window_update(increment)?;
         } else if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
-            stream.flow_control_mut().increase_send_window(increment)?;
+            stream.flow_control_mut().send_window_update(increment)?;
         }
 
         Ok(())
@@ -286,10 +741,16 @@ impl<S: SessionOps> H2Client<S> {
         let encoded = FrameCodec::encode_rst_stream_frame(&frame);
         self.session.write(&encoded)?;
 
-        // Close the stream
+        // Close the stream, frames still in flight on it are discarded
         if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
             stream.close();
         }
+        if !self.reset_streams.contains(&stream_id) {
+            if self.reset_streams.len() == MAX_RESET_STREAMS {
+                self.reset_streams.pop_front();
+            }
+            self.reset_streams.push_back(stream_id);
+        }
 
         Ok(())
     }
@@ -303,123 +764,515 @@ impl<S: SessionOps> H2Client<S> {
     }
 
     /// Receive a frame
+    ///
+    /// Fails with `Error::SettingsTimeout`, after sending GOAWAY, if a
+    /// SETTINGS ACK is still missing when the timeout expires, and with
+    /// `Error::Timeout` if a keepalive PING is never acknowledged.
     pub fn recv_frame(&mut self) -> Result<(FrameType, FrameFlags, StreamId, Bytes)> {
-        FrameCodec::read_frame_from_session(&mut self.session)
-            .map_err(|e| Error::Io(e))
-    }
-
-    /// Receive a response for a stream
-    pub fn recv_response(&mut self, stream_id: StreamId) -> Result<H2Response> {
-        let mut response = H2Response {
-            stream_id,
-            status: 0,
-            headers: HashMap::new(),
-            body: Bytes::new(),
-        };
-
-        let mut headers_received = false;
-        let mut stream_ended = false;
-
-        while !stream_ended {
-            let (frame_type, flags, recv_stream_id, payload) = self.recv_frame()?;
-
-            // Skip frames for other streams (interleaved)
-            if recv_stream_id != stream_id && recv_stream_id != CONNECTION_STREAM_ID {
-                continue;
+        self.wait_for_frame()?;
+        let (frame_type, flags, stream_id, payload) =
+            FrameCodec::read_frame_from_session(&mut self.session).map_err(Error::Io)?;
+
+        self.ping_tracker.record_activity();
+        if frame_type == FrameType::Ping && flags.is_ack() {
+            if let Ok(data) = <[u8; 8]>::try_from(&payload[..]) {
+                self.ping_tracker.acknowledge(data);
             }
+        }
 
-            match frame_type {
-                FrameType::Headers => {
-                    if headers_received {
-                        // Trailers - ignore for now
-                        continue;
-                    }
-
-                    // Decode headers with HPACK
-                    let decoded = self.hpack_decoder
-                        .decode(&payload)
-                        .map_err(|e| Error::Compression(format!("HPACK decode error: {:?}", e)))?;
-
-                    for (name, value) in decoded {
-                        let name_str = String::from_utf8_lossy(&name).to_string();
-                        let value_str = String::from_utf8_lossy(&value).to_string();
+        Ok((frame_type, flags, stream_id, payload))
+    }
 
-                        if name_str == ":status" {
-                            response.status = value_str.parse().unwrap_or(0);
-                        } else {
-                            response.headers.insert(name_str, value_str);
-                        }
-                    }
+    /// Receive and route a single frame
+    ///
+    /// Every incoming frame is dispatched to its stream in the stream
+    /// manager, so frames for other streams are never lost. Streams that
+    /// finish (END_STREAM or RST_STREAM) are queued for `recv_any_response`.
+    pub fn process_frame(&mut self) -> Result<(FrameType, StreamId)> {
+        let (frame_type, flags, stream_id, payload) = self.recv_frame()?;
 
-                    headers_received = true;
+        // Streams we reset may still have frames in flight; these are
+        // checked as if the stream were open, then discarded
+        let mut discard = self.reset_streams.contains(&stream_id);
+        let state = if discard {
+            StreamState::Open
+        } else {
+            self.stream_manager.stream_state(stream_id)
+        };
 
-                    if flags.is_end_stream() {
-                        stream_ended = true;
-                    }
+        let mut result = self.validator.validate(frame_type, flags, stream_id, &payload, state);
+        if result.is_ok() && frame_type == FrameType::Data {
+            // Frames discarded on reset streams only count on the connection
+            let stream_window = self
+                .stream_manager
+                .get_stream(stream_id)
+                .filter(|_| !discard)
+                .map(|stream| stream.flow_control().recv_window().size());
+            let connection_window = self.flow_control.recv_window().size();
+            result = self
+                .validator
+                .validate_data_window(stream_id, payload.len(), connection_window, stream_window);
+        }
+        if let Err(violation) = result {
+            match violation.scope {
+                ViolationScope::Connection => return Err(self.connection_error(violation)),
+                ViolationScope::Stream(id) => {
+                    self.reset_stream(id, violation.error_code)?;
+                    discard = true;
                 }
-                FrameType::Data => {
-                    // Update flow control
-                    self.flow_control.consume_recv_window(payload.len());
-                    if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
-                        stream.flow_control_mut().consume_recv_window(payload.len());
-                    }
+            }
+        }
 
-                    response.body = payload;
+        if discard {
+            self.discard_frame(frame_type, flags, stream_id, payload)?;
+            return Ok((frame_type, stream_id));
+        }
 
-                    if flags.is_end_stream() {
-                        stream_ended = true;
-                    }
+        match frame_type {
+            FrameType::Headers => {
+                let frame = FrameCodec::decode_headers_frame(stream_id, flags, payload)?;
+                self.stream_manager
+                    .get_or_create_stream(stream_id)?
+                    .receive_headers(&frame)?;
+                if let Some(priority) = &frame.priority {
+                    self.stream_manager.reprioritize(stream_id, priority)?;
                 }
-                FrameType::Settings => {
-                    // Handle SETTINGS during response
-                    self.recv_settings()?;
+
+                if frame.end_headers {
+                    self.decode_header_block(stream_id)?;
                 }
-                FrameType::WindowUpdate => {
-                    // Handle WINDOW_UPDATE
-                    if payload.len() != 4 {
-                        return Err(Error::FrameSize("WINDOW_UPDATE must be 4 bytes".to_string()));
+            }
+            FrameType::Continuation => {
+                let frame = FrameCodec::decode_continuation_frame(stream_id, flags, payload)?;
+                if let Some((_, block)) = self.pending_push.as_mut() {
+                    block.extend_from_slice(&frame.header_block);
+                    if frame.end_headers {
+                        self.finish_push_promise(stream_id)?;
+                    }
+                } else {
+                    if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                        stream.receive_continuation(&frame)?;
                     }
-                    let increment = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
 
-                    if recv_stream_id == CONNECTION_STREAM_ID {
-                        self.flow_control.increase_send_window(increment)?;
-                    } else if let Some(stream) = self.stream_manager.get_stream_mut(recv_stream_id) {
-                        stream.flow_control_mut().increase_send_window(increment)?;
+                    if frame.end_headers {
+                        self.decode_header_block(stream_id)?;
                     }
                 }
-                FrameType::Ping => {
-                    // Respond to PING
-                    if flags.is_ack() {
-                        // PING ACK - ignore
-                    } else {
-                        // Send PING ACK
-                        let mut data = [0u8; 8];
-                        data.copy_from_slice(&payload[..8]);
-                        let pong = PingFrame::ack(data);
-                        let encoded = FrameCodec::encode_ping_frame(&pong);
-                        self.session.write(&encoded)?;
+            }
+            FrameType::PushPromise => {
+                self.receive_push_promise(flags, stream_id, payload)?;
+            }
+            FrameType::Priority => {
+                let frame = FrameCodec::decode_priority_frame(stream_id, payload)?;
+                self.stream_manager.reprioritize(stream_id, &frame.priority)?;
+            }
+            FrameType::Data => {
+                // Flow control covers the whole payload, including padding
+                self.flow_control.consume_recv_window(payload.len());
+
+                let frame = FrameCodec::decode_data_frame(stream_id, flags, payload)?;
+                let stream = self
+                    .stream_manager
+                    .get_stream_mut(stream_id)
+                    .ok_or(Error::StreamClosed(stream_id))?;
+                stream.receive_data(&frame)?;
+
+                self.replenish_windows(stream_id)?;
+            }
+            FrameType::Settings => {
+                self.handle_settings(flags, stream_id, &payload)?;
+            }
+            FrameType::WindowUpdate => {
+                let increment = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7FFFFFFF;
+
+                if stream_id == CONNECTION_STREAM_ID {
+                    self.flow_control.increase_send_window(increment)?;
+                } else if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                    // A stream window overflow is a stream error (RFC 9113 Section 6.9.1)
+                    if stream.flow_control_mut().increase_send_window(increment).is_err() {
+                        self.reset_stream(stream_id, ErrorCode::FlowControlError)?;
                     }
                 }
-                FrameType::Goaway => {
-                    return Err(Error::ConnectionClosed);
+            }
+            FrameType::Ping if !flags.is_ack() => {
+                // Respond to PING
+                let mut data = [0u8; 8];
+                data.copy_from_slice(&payload);
+                let pong = PingFrame::ack(data);
+                let encoded = FrameCodec::encode_ping_frame(&pong);
+                self.session.write(&encoded)?;
+            }
+            FrameType::Goaway => {
+                let frame = FrameCodec::decode_goaway_frame(payload)?;
+                self.receive_goaway(frame);
+            }
+            FrameType::RstStream => {
+                let code = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                let error_code = ErrorCode::from_u32(code).unwrap_or(ErrorCode::InternalError);
+
+                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                    stream.receive_rst_stream(error_code);
                 }
-                FrameType::RstStream => {
-                    // Stream reset
-                    if let Some(stream) = self.stream_manager.get_stream_mut(recv_stream_id) {
-                        stream.close();
-                    }
-                    return Err(Error::Cancel(recv_stream_id));
+            }
+            _ => {
+                // Ignore other frame types
+            }
+        }
+
+        // Queue streams that just finished
+        if stream_id != CONNECTION_STREAM_ID && !self.completed.contains(&stream_id) {
+            if let Some(stream) = self.stream_manager.get_stream(stream_id) {
+                if stream.is_finished() {
+                    self.completed.push_back(stream_id);
                 }
-                _ => {
-                    // Ignore other frame types
+            }
+        }
+
+        Ok((frame_type, stream_id))
+    }
+
+    /// Process a GOAWAY frame (RFC 9113 Section 6.8)
+    ///
+    /// Streams we opened above the last stream ID were not processed: they
+    /// are refused, so their requests can be retried on a new connection.
+    /// Streams up to it run to completion. A later GOAWAY may lower the
+    /// last stream ID further.
+    fn receive_goaway(&mut self, frame: GoawayFrame) {
+        let mut refused: Vec<StreamId> = self
+            .stream_manager
+            .stream_ids()
+            .into_iter()
+            .filter(|&id| !id.is_multiple_of(2) && id > frame.last_stream_id)
+            .collect();
+        refused.sort_unstable();
+
+        for stream_id in refused {
+            if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                if !stream.is_finished() {
+                    stream.receive_rst_stream(ErrorCode::RefusedStream);
+                    self.completed.push_back(stream_id);
                 }
             }
         }
 
-        Ok(response)
+        // The server sends nothing more on the streams it did not process
+        self.reset_streams.retain(|&id| id.is_multiple_of(2) || id <= frame.last_stream_id);
+
+        self.goaway = Some(frame);
+    }
+
+    /// Get the last GOAWAY received, if any
+    pub fn goaway(&self) -> Option<&GoawayFrame> {
+        self.goaway.as_ref()
+    }
+
+    /// Process a PUSH_PROMISE frame (RFC 7540 Section 6.6)
+    ///
+    /// The validator already checked that push is enabled, the associated
+    /// stream is open and the promised stream ID is valid.
+    fn receive_push_promise(&mut self, flags: FrameFlags, stream_id: StreamId, payload: Bytes) -> Result<()> {
+        let frame = FrameCodec::decode_push_promise_frame(stream_id, flags, payload)?;
+        let promised = frame.promised_stream_id;
+
+        self.stream_manager
+            .get_or_create_stream(promised)?
+            .set_state(StreamState::ReservedRemote);
+
+        self.pending_push = Some((promised, frame.header_block.to_vec()));
+        if frame.end_headers {
+            self.finish_push_promise(stream_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decode the promised request headers of a complete PUSH_PROMISE
+    fn finish_push_promise(&mut self, stream_id: StreamId) -> Result<()> {
+        let Some((promised_stream_id, block)) = self.pending_push.take() else {
+            return Ok(());
+        };
+
+        let headers = self
+            .hpack_decoder
+            .decode(&block)?
+            .into_iter()
+            .map(|field| {
+                (
+                    String::from_utf8_lossy(&field.name).to_string(),
+                    String::from_utf8_lossy(&field.value).to_string(),
+                )
+            })
+            .collect();
+
+        self.pushes.push_back(H2Push {
+            stream_id,
+            promised_stream_id,
+            headers,
+        });
+
+        Ok(())
+    }
+
+    /// Absorb a frame received on a stream we reset
+    ///
+    /// DATA still counts against the connection window, and header blocks
+    /// are decoded to keep the HPACK dynamic table in sync.
+    fn discard_frame(
+        &mut self,
+        frame_type: FrameType,
+        flags: FrameFlags,
+        stream_id: StreamId,
+        payload: Bytes,
+    ) -> Result<()> {
+        let (fragment, end_headers) = match frame_type {
+            FrameType::Data => {
+                self.flow_control.consume_recv_window(payload.len());
+                return self.replenish_windows(stream_id);
+            }
+            FrameType::Headers => {
+                let frame = FrameCodec::decode_headers_frame(stream_id, flags, payload)?;
+                (frame.header_block, frame.end_headers)
+            }
+            FrameType::Continuation => {
+                let frame = FrameCodec::decode_continuation_frame(stream_id, flags, payload)?;
+                (frame.header_block, frame.end_headers)
+            }
+            _ => return Ok(()),
+        };
+
+        self.discarded_block.extend_from_slice(&fragment);
+        if end_headers {
+            let block = std::mem::take(&mut self.discarded_block);
+            self.decode_hpack(&block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send GOAWAY for a connection error and return the matching error
+    fn connection_error(&mut self, violation: Violation) -> Error {
+        let last_stream_id = self.stream_manager.last_remote_stream_id();
+        if let Err(e) = self.send_goaway(last_stream_id, violation.error_code, &violation.reason) {
+            return e;
+        }
+        violation.into_error()
+    }
+
+    /// Reset a stream for a stream error
+    ///
+    /// A pending response on the stream completes with an error, and
+    /// frames still in flight on it are discarded.
+    fn reset_stream(&mut self, stream_id: StreamId, error_code: ErrorCode) -> Result<()> {
+        self.send_rst_stream(stream_id, error_code)?;
+
+        if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+            stream.receive_rst_stream(error_code);
+            if !self.completed.contains(&stream_id) {
+                self.completed.push_back(stream_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// HPACK-decode a complete header block
+    ///
+    /// A decoding failure is a connection error (RFC 9113 Section 4.3):
+    /// GOAWAY with COMPRESSION_ERROR is sent before failing.
+    fn decode_hpack(&mut self, block: &[u8]) -> Result<Vec<HeaderField>> {
+        match self.hpack_decoder.decode(block) {
+            Ok(fields) => Ok(fields),
+            Err(e) => {
+                let last_stream_id = self.stream_manager.last_remote_stream_id();
+                self.send_goaway(last_stream_id, ErrorCode::CompressionError, &e.to_string())?;
+                Err(Error::Hpack(e))
+            }
+        }
+    }
+
+    /// Send WINDOW_UPDATE for receive windows below the policy threshold
+    fn replenish_windows(&mut self, stream_id: StreamId) -> Result<()> {
+        let policy = self.flow_control_policy;
+
+        if let Some(increment) = policy.replenish_increment(self.flow_control.recv_window()) {
+            self.send_window_update(CONNECTION_STREAM_ID, increment)?;
+        }
+
+        // No more DATA can arrive once the peer ended the stream
+        let increment = self
+            .stream_manager
+            .get_stream(stream_id)
+            .filter(|stream| stream.state().can_receive())
+            .and_then(|stream| policy.replenish_increment(stream.flow_control().recv_window()));
+        if let Some(increment) = increment {
+            self.send_window_update(stream_id, increment)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decode a complete header block accumulated on a stream
+    fn decode_header_block(&mut self, stream_id: StreamId) -> Result<()> {
+        let Some(stream) = self.stream_manager.get_stream_mut(stream_id) else {
+            return Ok(());
+        };
+
+        let block = stream.take_header_block();
+        let decoded = self.decode_hpack(&block)?;
+        let Some(stream) = self.stream_manager.get_stream_mut(stream_id) else {
+            return Ok(());
+        };
+
+        let fields = decoded
+            .into_iter()
+            .map(|field| {
+                (
+                    String::from_utf8_lossy(&field.name).to_string(),
+                    String::from_utf8_lossy(&field.value).to_string(),
+                )
+            })
+            .collect();
+        stream.set_decoded_headers(fields);
+
+        Ok(())
+    }
+
+    /// Receive a response for a stream
+    ///
+    /// Frames for other streams received meanwhile are routed to their
+    /// streams and can be collected later.
+    pub fn recv_response(&mut self, stream_id: StreamId) -> Result<H2Response> {
+        loop {
+            if let Some(pos) = self.completed.iter().position(|&id| id == stream_id) {
+                self.completed.remove(pos);
+                return self.take_response(stream_id);
+            }
+
+            if self.stream_manager.get_stream(stream_id).is_none() {
+                return Err(Error::InvalidStreamId(stream_id));
+            }
+
+            self.process_frame()?;
+        }
+    }
+
+    /// Receive the next response to complete, on any stream we opened
+    ///
+    /// Pushed responses are collected with `recv_response` on the
+    /// promised stream ID.
+    pub fn recv_any_response(&mut self) -> Result<H2Response> {
+        loop {
+            if let Some(pos) = self.completed.iter().position(|id| !id.is_multiple_of(2)) {
+                let stream_id = self.completed.remove(pos).unwrap();
+                return self.take_response(stream_id);
+            }
+
+            self.process_frame()?;
+        }
+    }
+
+    /// Receive the next push promise
+    ///
+    /// Promises received while waiting for responses are queued and
+    /// returned first. Requires push to be enabled with
+    /// `H2ClientBuilder::enable_push`.
+    pub fn recv_push_promise(&mut self) -> Result<H2Push> {
+        loop {
+            if let Some(push) = self.pushes.pop_front() {
+                return Ok(push);
+            }
+
+            self.process_frame()?;
+        }
+    }
+
+    /// Refuse a pushed stream with RST_STREAM (REFUSED_STREAM)
+    ///
+    /// Frames the server already sent on the stream are discarded.
+    pub fn refuse_push(&mut self, promised_stream_id: StreamId) -> Result<()> {
+        let pushed = promised_stream_id.is_multiple_of(2) && self.stream_manager.get_stream(promised_stream_id).is_some();
+        if !pushed {
+            return Err(Error::InvalidStreamId(promised_stream_id));
+        }
+
+        self.send_rst_stream(promised_stream_id, ErrorCode::RefusedStream)?;
+
+        self.stream_manager.remove_stream(promised_stream_id);
+        self.completed.retain(|&id| id != promised_stream_id);
+        self.pushes.retain(|push| push.promised_stream_id != promised_stream_id);
+
+        Ok(())
+    }
+
+    /// Build the response of a finished stream
+    fn take_response(&mut self, stream_id: StreamId) -> Result<H2Response> {
+        let stream = self
+            .stream_manager
+            .get_stream_mut(stream_id)
+            .ok_or(Error::InvalidStreamId(stream_id))?;
+
+        let mut response = H2Response {
+            stream_id,
+            status: 0,
+            headers: HashMap::new(),
+            trailers: HashMap::new(),
+            body: Bytes::from(stream.take_body()),
+        };
+
+        let reset_code = stream.reset_code();
+
+        for (name, value) in stream.headers() {
+            if name == ":status" {
+                response.status = value.parse().unwrap_or(0);
+            } else {
+                response.headers.insert(name.clone(), value.clone());
+            }
+        }
+
+        for (name, value) in stream.trailers() {
+            response.trailers.insert(name.clone(), value.clone());
+        }
+
+        // Forget fully closed streams
+        if stream.state().is_closed() {
+            self.stream_manager.remove_stream(stream_id);
+        }
+
+        match reset_code {
+            Some(ErrorCode::RefusedStream) => Err(Error::RefusedStream(stream_id)),
+            Some(_) => Err(Error::Cancel(stream_id)),
+            None => Ok(response),
+        }
+    }
+
+    /// Get connection-level flow control
+    pub fn flow_control(&self) -> &ConnectionFlowControl {
+        &self.flow_control
+    }
+
+    /// Get the receive window replenishment policy
+    pub fn flow_control_policy(&self) -> FlowControlPolicy {
+        self.flow_control_policy
+    }
+
+    /// Set the receive window replenishment policy
+    pub fn set_flow_control_policy(&mut self, policy: FlowControlPolicy) {
+        self.flow_control_policy = policy;
+    }
+
+    /// Get the stream manager
+    pub fn stream_manager(&self) -> &StreamManager {
+        &self.stream_manager
+    }
+
+    /// Get a stream by ID
+    pub fn stream(&self, stream_id: StreamId) -> Option<&H2Stream> {
+        self.stream_manager.get_stream(stream_id)
     }
 
     /// Get local settings
+    ///
+    /// Settings sent to the server only show up here once acknowledged.
     pub fn local_settings(&self) -> &Settings {
         &self.local_settings
     }
@@ -428,6 +1281,81 @@ impl<S: SessionOps> H2Client<S> {
     pub fn remote_settings(&self) -> &Settings {
         &self.remote_settings
     }
+
+    /// Get the SETTINGS frames awaiting the server's ACK
+    pub fn settings_tracker(&self) -> &SettingsTracker {
+        &self.settings_tracker
+    }
+
+    /// Get the outstanding pings and measured round-trip times
+    pub fn ping_tracker(&self) -> &PingTracker {
+        &self.ping_tracker
+    }
+
+    /// Get the HPACK decoder (for dynamic table inspection)
+    pub fn hpack_decoder(&self) -> &HpackDecoder {
+        &self.hpack_decoder
+    }
+}
+
+impl<S: SessionOps> ScriptConnection for H2Client<S> {
+    fn is_client(&self) -> bool {
+        true
+    }
+
+    fn poll_readable(&self, timeout: Duration) -> Result<bool> {
+        Ok(self.session.get_ref().poll(PollEvents::Read, Some(timeout))?)
+    }
+
+    fn read_frame(&mut self) -> Result<(FrameType, FrameFlags, StreamId, Bytes)> {
+        FrameCodec::read_frame_from_session(&mut self.session).map_err(Error::Io)
+    }
+
+    fn decode_header_block(&mut self, block: &[u8]) -> Result<Vec<HeaderField>> {
+        self.decode_hpack(block)
+    }
+
+    fn write_headers(&mut self, stream_id: StreamId, headers: &[(String, String)], end_stream: bool) -> Result<()> {
+        let mut header_block_vec = Vec::new();
+        let header_tuples: Vec<(&[u8], &[u8])> = headers
+            .iter()
+            .map(|(name, value)| (name.as_bytes(), value.as_bytes()))
+            .collect();
+        self.hpack_encoder
+            .encode_into(header_tuples, &mut header_block_vec)
+            .map_err(|e| Error::Internal(format!("HPACK encode error: {}", e)))?;
+
+        let frame = HeadersFrame::new(stream_id, Bytes::from(header_block_vec), end_stream, true);
+        self.session.write(&FrameCodec::encode_headers_frame(&frame))?;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.session.write(frame)?;
+        Ok(())
+    }
+}
+
+impl<S: SessionOps> TunnelEndpoint for H2Client<S> {
+    fn send_stream_data(&mut self, stream_id: StreamId, data: Bytes, end_stream: bool) -> Result<()> {
+        self.send_body(stream_id, data, end_stream)
+    }
+
+    fn recv_stream_data(&mut self, stream_id: StreamId) -> Result<Option<Bytes>> {
+        H2Client::recv_stream_data(self, stream_id)
+    }
+
+    fn poll_stream(&self, stream_id: StreamId, timeout: Option<Duration>) -> Result<bool> {
+        let ready = self
+            .stream_manager
+            .get_stream(stream_id)
+            .is_none_or(|stream| !stream.body().is_empty() || stream.is_finished());
+        if ready {
+            return Ok(true);
+        }
+
+        Ok(self.session.get_ref().poll(PollEvents::Read, timeout)?)
+    }
 }
 
 /// HTTP/2 response
@@ -439,6 +1367,8 @@ pub struct H2Response {
     pub status: u16,
     /// Headers
     pub headers: HashMap<String, String>,
+    /// Trailers
+    pub trailers: HashMap<String, String>,
     /// Body
     pub body: Bytes,
 }
@@ -454,6 +1384,11 @@ impl H2Response {
         self.headers.get(name).map(|s| s.as_str())
     }
 
+    /// Get trailer value
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers.get(name).map(|s| s.as_str())
+    }
+
     /// Get body as bytes
     pub fn body(&self) -> &[u8] {
         &self.body
@@ -466,9 +1401,45 @@ impl H2Response {
     }
 }
 
+/// Server push announced with PUSH_PROMISE
+#[derive(Debug, Clone)]
+pub struct H2Push {
+    /// Stream the promise was received on
+    pub stream_id: StreamId,
+    /// Reserved stream carrying the pushed response
+    pub promised_stream_id: StreamId,
+    /// Promised request headers, including pseudo-headers
+    pub headers: Vec<(String, String)>,
+}
+
+impl H2Push {
+    /// Get a promised request header value
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Get the promised request method
+    pub fn method(&self) -> Option<&str> {
+        self.header(":method")
+    }
+
+    /// Get the promised request path
+    pub fn path(&self) -> Option<&str> {
+        self.header(":path")
+    }
+}
+
 /// HTTP/2 client builder
 pub struct H2ClientBuilder {
     settings: SettingsBuilder,
+    flow_control_policy: FlowControlPolicy,
+    settings_timeout: Option<Duration>,
+    keepalive: Option<KeepAlive>,
+    scheme: String,
+    authority: String,
 }
 
 impl H2ClientBuilder {
@@ -480,6 +1451,11 @@ impl H2ClientBuilder {
                 .enable_push(false)
                 .initial_window_size(65535)
                 .max_frame_size(16384),
+            flow_control_policy: FlowControlPolicy::default(),
+            settings_timeout: None,
+            keepalive: None,
+            scheme: "https".to_string(),
+            authority: "localhost".to_string(),
         }
     }
 
@@ -513,23 +1489,158 @@ impl H2ClientBuilder {
         self
     }
 
+    /// Set max header list size
+    pub fn max_header_list_size(mut self, size: u32) -> Self {
+        self.settings = self.settings.max_header_list_size(size);
+        self
+    }
+
+    /// Set the receive window replenishment policy
+    pub fn flow_control_policy(mut self, policy: FlowControlPolicy) -> Self {
+        self.flow_control_policy = policy;
+        self
+    }
+
+    /// Fail with SETTINGS_TIMEOUT if a SETTINGS ACK takes longer than this
+    ///
+    /// By default the client waits for acknowledgements indefinitely.
+    pub fn settings_timeout(mut self, timeout: Duration) -> Self {
+        self.settings_timeout = Some(timeout);
+        self
+    }
+
+    /// Send a PING after `interval` without receiving any frame
+    ///
+    /// Reading fails with `Error::Timeout` if the server does not
+    /// acknowledge a ping within `timeout`.
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive = Some(KeepAlive { interval, timeout });
+        self
+    }
+
+    /// Set the `:scheme` of the requests, `https` by default
+    ///
+    /// Use `http` for cleartext HTTP/2 with prior knowledge;
+    /// `upgrade` takes care of it for h2c upgrades.
+    pub fn scheme(mut self, scheme: &str) -> Self {
+        self.scheme = scheme.to_string();
+        self
+    }
+
+    /// Set the `:authority` of the requests, `localhost` by default
+    pub fn authority(mut self, authority: &str) -> Self {
+        self.authority = authority.to_string();
+        self
+    }
+
     /// Build the client
     pub fn build<S: SessionOps>(self, session: S) -> Result<H2Client<S>> {
-        let local_settings = self.settings.build()?;
+        // Our settings take effect once acknowledged, until then the
+        // defaults apply (RFC 9113 Section 6.5.3)
+        let initial_settings = self.settings.build()?;
+        let local_settings = Settings::default_settings();
+
+        let mut hpack_decoder = HpackDecoder::new(local_settings.get_header_table_size() as usize);
+        hpack_decoder.set_max_header_list_size(
+            local_settings.get_max_header_list_size().map(|size| size as usize),
+        );
+
+        let mut stream_manager = StreamManager::new(true); // Client uses odd stream IDs
+        stream_manager.set_initial_recv_window_size(local_settings.get_initial_window_size());
+
+        let mut validator = FrameValidator::new(true);
+        validator.set_max_frame_size(local_settings.get_max_frame_size());
+        validator.set_enable_push(local_settings.get_enable_push());
 
         Ok(H2Client {
             session: HttpSession::new(session),
-            stream_manager: StreamManager::new(true), // Client uses odd stream IDs
+            stream_manager,
             flow_control: ConnectionFlowControl::new(),
             hpack_encoder: HpackEncoder::new(),
-            hpack_decoder: hpack::Decoder::new(),
+            hpack_decoder,
+            flow_control_policy: self.flow_control_policy,
             local_settings,
+            initial_settings,
             remote_settings: Settings::default_settings(),
+            settings_tracker: SettingsTracker::new(self.settings_timeout),
+            ping_tracker: PingTracker::new(self.keepalive),
+            validator,
+            completed: VecDeque::new(),
+            pending_push: None,
+            pushes: VecDeque::new(),
+            reset_streams: VecDeque::new(),
+            discarded_block: Vec::new(),
+            goaway: None,
+            scheme: self.scheme,
+            authority: self.authority,
             connected: false,
         })
     }
 }
 
+impl H2ClientBuilder {
+    /// Switch an HTTP/1.1 connection to HTTP/2 with `Upgrade: h2c`
+    ///
+    /// `request` is sent over HTTP/1.1 with the upgrade headers and becomes
+    /// stream 1 of the HTTP/2 connection (RFC 7540 Section 3.2); collect its
+    /// response with `recv_response(1)`. Fails unless the server answers
+    /// `101 Switching Protocols`.
+    pub fn upgrade<S: SessionOps>(
+        self,
+        mut http: HttpClient<S>,
+        mut request: HttpRequest,
+    ) -> Result<H2Client<PrefixedSession<S>>> {
+        // Requests go on in cleartext, to the host of the upgrade request
+        let mut builder = self.scheme("http");
+        if let Some(host) = request.headers().get("Host") {
+            builder = builder.authority(host);
+        }
+
+        let settings = builder.settings.clone().build()?;
+        let settings_value = h2c::encode_settings_header(&settings);
+
+        let headers = request.headers_mut();
+        headers.remove("Connection");
+        headers.remove("Upgrade");
+        headers.remove(h2c::HTTP2_SETTINGS_HEADER);
+        headers.insert("Connection", "Upgrade, HTTP2-Settings");
+        headers.insert("Upgrade", h2c::H2C_TOKEN);
+        headers.insert(h2c::HTTP2_SETTINGS_HEADER, settings_value);
+
+        http.send_request(&request)?;
+        let response = http.receive_response()?;
+
+        if response.status().code() != 101 {
+            return Err(Error::Protocol(format!(
+                "h2c upgrade refused with status {}",
+                response.status()
+            )));
+        }
+        let upgraded = response
+            .headers()
+            .get("Upgrade")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case(h2c::H2C_TOKEN));
+        if !upgraded {
+            return Err(Error::Protocol("101 response without Upgrade: h2c".to_string()));
+        }
+
+        let (session, remaining) = http.into_parts();
+        let mut client = builder.build(PrefixedSession::new(session, remaining))?;
+
+        // The 101 response acknowledges HTTP2-Settings (RFC 7540 Section 3.2.1)
+        client.apply_local_settings(&settings)?;
+
+        // The upgrade request was sent in full: stream 1 is half-closed (local)
+        let stream_id = client.stream_manager.create_stream()?;
+        if let Some(stream) = client.stream_manager.get_stream_mut(stream_id) {
+            stream.set_state(StreamState::HalfClosedLocal);
+        }
+
+        client.connect()?;
+        Ok(client)
+    }
+}
+
 impl Default for H2ClientBuilder {
     fn default() -> Self {
         Self::new()
@@ -539,12 +1650,10 @@ impl Default for H2ClientBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::http::session::FdSessionOps;
-    use std::net::TcpStream;
 
     #[test]
     fn test_client_builder() {
-        let builder = H2ClientBuilder::new()
+        let _builder = H2ClientBuilder::new()
             .header_table_size(8192)
             .enable_push(false)
             .initial_window_size(65535);
@@ -561,6 +1670,7 @@ mod tests {
             stream_id: 1,
             status: 200,
             headers,
+            trailers: HashMap::new(),
             body: Bytes::from("Hello"),
         };
 
@@ -569,4 +1679,28 @@ mod tests {
         assert_eq!(response.body(), b"Hello");
         assert_eq!(response.body_string().unwrap(), "Hello");
     }
+
+    #[test]
+    fn test_reset_streams_pruned() {
+        use crate::http::session::FdSessionOps;
+        use std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _peer = listener.accept().unwrap();
+        let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+
+        // Only the most recent resets are remembered
+        for stream_id in (1..).step_by(2).take(MAX_RESET_STREAMS + 10) {
+            client.send_rst_stream(stream_id, ErrorCode::Cancel).unwrap();
+        }
+        assert_eq!(client.reset_streams.len(), MAX_RESET_STREAMS);
+        assert_eq!(client.reset_streams.front(), Some(&21));
+
+        // Streams the server never processed are forgotten on GOAWAY
+        client.send_rst_stream(2, ErrorCode::RefusedStream).unwrap();
+        client.receive_goaway(GoawayFrame::new(99, ErrorCode::NoError, Bytes::new()));
+        assert!(client.reset_streams.iter().all(|&id| id <= 99));
+        assert!(client.reset_streams.contains(&2));
+    }
 }
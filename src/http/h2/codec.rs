@@ -5,6 +5,7 @@
 //!
 //! This is a direct port of the C implementation's frame handling.
 
+use super::error::{Error, ErrorCode, Result};
 use super::frames::*;
 use super::settings::SettingsParameter;
 use bytes::{BufMut, Bytes, BytesMut};
@@ -66,8 +67,8 @@ impl FrameCodec {
             | ((bytes[1] as usize) << 8)
             | (bytes[2] as usize);
 
-        // Type (8 bits)
-        let frame_type = FrameType::from_u8(bytes[3]).unwrap_or(FrameType::Data);
+        // Type (8 bits), unknown types are kept as-is
+        let frame_type = FrameType::from(bytes[3]);
 
         // Flags (8 bits)
         let flags = FrameFlags::from_u8(bytes[4]);
@@ -371,6 +372,266 @@ impl FrameCodec {
         buf.freeze()
     }
 
+    /// Encode a generic frame, of any type, with its flags and payload as-is
+    pub fn encode_frame(frame: &Frame) -> Bytes {
+        let mut buf = BytesMut::with_capacity(FRAME_HEADER_SIZE + frame.payload.len());
+
+        let header = Self::encode_header(frame.frame_type, frame.flags, frame.stream_id, frame.payload.len());
+        buf.put_slice(&header);
+        buf.put_slice(&frame.payload);
+
+        buf.freeze()
+    }
+
+    /// Encode an ALTSVC frame (RFC 7838 Section 4)
+    pub fn encode_altsvc_frame(frame: &AltSvcFrame) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        let payload_len = 2 + frame.origin.len() + frame.field_value.len();
+        let header = Self::encode_header(FrameType::AltSvc, FrameFlags::empty(), frame.stream_id, payload_len);
+        buf.put_slice(&header);
+
+        // Origin-Len, Origin, Alt-Svc-Field-Value
+        buf.put_u16(frame.origin.len() as u16);
+        buf.put_slice(&frame.origin);
+        buf.put_slice(&frame.field_value);
+
+        buf.freeze()
+    }
+
+    /// Encode an ORIGIN frame (RFC 8336 Section 2)
+    pub fn encode_origin_frame(frame: &OriginFrame) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        let payload_len: usize = frame.origins.iter().map(|origin| 2 + origin.len()).sum();
+        let header = Self::encode_header(FrameType::Origin, FrameFlags::empty(), 0, payload_len);
+        buf.put_slice(&header);
+
+        // Origin-Entry: Origin-Len, ASCII-Origin
+        for origin in &frame.origins {
+            buf.put_u16(origin.len() as u16);
+            buf.put_slice(origin.as_bytes());
+        }
+
+        buf.freeze()
+    }
+
+    /// Encode a PRIORITY_UPDATE frame (RFC 9218 Section 7.1)
+    pub fn encode_priority_update_frame(frame: &PriorityUpdateFrame) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        let payload_len = 4 + frame.priority_field_value.len();
+        let header = Self::encode_header(FrameType::PriorityUpdate, FrameFlags::empty(), 0, payload_len);
+        buf.put_slice(&header);
+
+        // Prioritized stream ID (reserved bit must be 0), priority field value
+        buf.put_u32(frame.prioritized_stream_id & 0x7FFFFFFF);
+        buf.put_slice(&frame.priority_field_value);
+
+        buf.freeze()
+    }
+
+    /// Strip padding from a frame payload (RFC 7540 Section 6.1)
+    ///
+    /// Returns the payload without the pad length field and trailing padding,
+    /// along with the pad length if the PADDED flag was set.
+    fn strip_padding(flags: FrameFlags, payload: Bytes) -> Result<(Bytes, Option<u8>)> {
+        if !flags.is_padded() {
+            return Ok((payload, None));
+        }
+
+        if payload.is_empty() {
+            return Err(Error::FrameSize("Padded frame without pad length".to_string()));
+        }
+
+        let pad_len = payload[0];
+        if pad_len as usize >= payload.len() {
+            return Err(Error::Protocol(format!(
+                "Padding length {} exceeds payload length {}",
+                pad_len,
+                payload.len() - 1
+            )));
+        }
+
+        let end = payload.len() - pad_len as usize;
+        Ok((payload.slice(1..end), Some(pad_len)))
+    }
+
+    /// Decode a DATA frame payload
+    pub fn decode_data_frame(stream_id: u32, flags: FrameFlags, payload: Bytes) -> Result<DataFrame> {
+        if stream_id == 0 {
+            return Err(Error::Protocol("DATA frame on stream 0".to_string()));
+        }
+
+        let (data, padding) = Self::strip_padding(flags, payload)?;
+
+        Ok(DataFrame {
+            stream_id,
+            data,
+            end_stream: flags.is_end_stream(),
+            padding,
+        })
+    }
+
+    /// Decode a HEADERS frame payload
+    pub fn decode_headers_frame(stream_id: u32, flags: FrameFlags, payload: Bytes) -> Result<HeadersFrame> {
+        if stream_id == 0 {
+            return Err(Error::Protocol("HEADERS frame on stream 0".to_string()));
+        }
+
+        let (mut block, padding) = Self::strip_padding(flags, payload)?;
+
+        let priority = if flags.is_priority() {
+            if block.len() < 5 {
+                return Err(Error::FrameSize("HEADERS priority fields truncated".to_string()));
+            }
+            let dep = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+            let priority = PrioritySpec::new(dep & 0x7FFFFFFF, dep & 0x80000000 != 0, block[4]);
+            block = block.slice(5..);
+            Some(priority)
+        } else {
+            None
+        };
+
+        Ok(HeadersFrame {
+            stream_id,
+            header_block: block,
+            end_stream: flags.is_end_stream(),
+            end_headers: flags.is_end_headers(),
+            priority,
+            padding,
+        })
+    }
+
+    /// Decode a PRIORITY frame payload
+    pub fn decode_priority_frame(stream_id: u32, payload: Bytes) -> Result<PriorityFrame> {
+        if stream_id == 0 {
+            return Err(Error::Protocol("PRIORITY frame on stream 0".to_string()));
+        }
+        if payload.len() != 5 {
+            return Err(Error::FrameSize(format!("PRIORITY payload of {} bytes", payload.len())));
+        }
+
+        let dependency = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+
+        Ok(PriorityFrame {
+            stream_id,
+            priority: PrioritySpec {
+                stream_dependency: dependency & 0x7FFFFFFF,
+                exclusive: dependency & 0x80000000 != 0,
+                weight: payload[4],
+            },
+        })
+    }
+
+    /// Decode a GOAWAY frame payload
+    ///
+    /// Unknown error codes are reported as INTERNAL_ERROR.
+    pub fn decode_goaway_frame(payload: Bytes) -> Result<GoawayFrame> {
+        if payload.len() < 8 {
+            return Err(Error::FrameSize(format!("GOAWAY payload of {} bytes", payload.len())));
+        }
+
+        let last_stream_id = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7FFFFFFF;
+        let code = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+
+        Ok(GoawayFrame {
+            last_stream_id,
+            error_code: ErrorCode::from_u32(code).unwrap_or(ErrorCode::InternalError),
+            debug_data: payload.slice(8..),
+        })
+    }
+
+    /// Decode a PUSH_PROMISE frame payload
+    pub fn decode_push_promise_frame(stream_id: u32, flags: FrameFlags, payload: Bytes) -> Result<PushPromiseFrame> {
+        if stream_id == 0 {
+            return Err(Error::Protocol("PUSH_PROMISE frame on stream 0".to_string()));
+        }
+
+        let (block, padding) = Self::strip_padding(flags, payload)?;
+        if block.len() < 4 {
+            return Err(Error::FrameSize("PUSH_PROMISE promised stream ID truncated".to_string()));
+        }
+
+        let promised_stream_id = u32::from_be_bytes([block[0], block[1], block[2], block[3]]) & 0x7FFFFFFF;
+
+        Ok(PushPromiseFrame {
+            stream_id,
+            promised_stream_id,
+            header_block: block.slice(4..),
+            end_headers: flags.is_end_headers(),
+            padding,
+        })
+    }
+
+    /// Decode a CONTINUATION frame payload
+    pub fn decode_continuation_frame(stream_id: u32, flags: FrameFlags, payload: Bytes) -> Result<ContinuationFrame> {
+        if stream_id == 0 {
+            return Err(Error::Protocol("CONTINUATION frame on stream 0".to_string()));
+        }
+
+        Ok(ContinuationFrame {
+            stream_id,
+            header_block: payload,
+            end_headers: flags.is_end_headers(),
+        })
+    }
+
+    /// Decode an ALTSVC frame payload
+    pub fn decode_altsvc_frame(stream_id: u32, payload: Bytes) -> Result<AltSvcFrame> {
+        if payload.len() < 2 {
+            return Err(Error::FrameSize("ALTSVC origin length truncated".to_string()));
+        }
+
+        let origin_len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+        if payload.len() < 2 + origin_len {
+            return Err(Error::FrameSize(format!(
+                "ALTSVC origin length {} exceeds payload length {}",
+                origin_len,
+                payload.len() - 2
+            )));
+        }
+
+        Ok(AltSvcFrame {
+            stream_id,
+            origin: payload.slice(2..2 + origin_len),
+            field_value: payload.slice(2 + origin_len..),
+        })
+    }
+
+    /// Decode a PRIORITY_UPDATE frame payload
+    pub fn decode_priority_update_frame(payload: Bytes) -> Result<PriorityUpdateFrame> {
+        if payload.len() < 4 {
+            return Err(Error::FrameSize("PRIORITY_UPDATE prioritized stream ID truncated".to_string()));
+        }
+
+        Ok(PriorityUpdateFrame {
+            prioritized_stream_id: u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7FFFFFFF,
+            priority_field_value: payload.slice(4..),
+        })
+    }
+
+    /// Decode an ORIGIN frame payload
+    pub fn decode_origin_frame(payload: Bytes) -> Result<OriginFrame> {
+        let mut origins = Vec::new();
+        let mut rest = &payload[..];
+
+        while !rest.is_empty() {
+            if rest.len() < 2 {
+                return Err(Error::FrameSize("ORIGIN entry length truncated".to_string()));
+            }
+
+            let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let origin = rest
+                .get(2..2 + len)
+                .ok_or_else(|| Error::FrameSize(format!("ORIGIN entry length {} exceeds payload", len)))?;
+            origins.push(String::from_utf8_lossy(origin).to_string());
+            rest = &rest[2 + len..];
+        }
+
+        Ok(OriginFrame { origins })
+    }
+
     /// Write a frame to a writer (generic over any Write)
     pub fn write_frame<W: Write>(writer: &mut W, frame_data: &[u8]) -> io::Result<()> {
         writer.write_all(frame_data)?;
@@ -413,7 +674,7 @@ impl FrameCodec {
         while read < FRAME_HEADER_SIZE {
             let n = session.read(&mut header[read..]).map_err(|e| match e {
                 crate::http::Error::Io(io_err) => io_err,
-                other => io::Error::new(io::ErrorKind::Other, other.to_string()),
+                other => io::Error::other(other.to_string()),
             })?;
             if n == 0 {
                 return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"));
@@ -438,7 +699,7 @@ impl FrameCodec {
             while read < payload_len {
                 let n = session.read(&mut payload[read..]).map_err(|e| match e {
                     crate::http::Error::Io(io_err) => io_err,
-                    other => io::Error::new(io::ErrorKind::Other, other.to_string()),
+                    other => io::Error::other(other.to_string()),
                 })?;
                 if n == 0 {
                     return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"));
@@ -571,4 +832,139 @@ mod tests {
         let increment = u32::from_be_bytes([encoded[9], encoded[10], encoded[11], encoded[12]]);
         assert_eq!(increment, 1000);
     }
+
+    #[test]
+    fn test_decode_data_frame_strips_padding() {
+        let frame = DataFrame::new(3, Bytes::from("Hi"), true).with_padding(4);
+        let encoded = FrameCodec::encode_data_frame(&frame);
+        let flags = FrameFlags::from_u8(encoded[4]);
+
+        let decoded = FrameCodec::decode_data_frame(3, flags, encoded.slice(9..)).unwrap();
+        assert_eq!(&decoded.data[..], b"Hi");
+        assert_eq!(decoded.padding, Some(4));
+        assert!(decoded.end_stream);
+
+        // Pad length covering the whole payload is a protocol error
+        let bad = Bytes::from_static(&[5, 0, 0]);
+        let padded = FrameFlags::from_u8(FrameFlags::PADDED);
+        assert!(FrameCodec::decode_data_frame(3, padded, bad).is_err());
+    }
+
+    #[test]
+    fn test_decode_headers_frame_with_priority() {
+        let frame = HeadersFrame::new(5, Bytes::from("block"), false, true)
+            .with_priority(PrioritySpec::new(3, true, 15))
+            .with_padding(2);
+        let encoded = FrameCodec::encode_headers_frame(&frame);
+        let flags = FrameFlags::from_u8(encoded[4]);
+
+        let decoded = FrameCodec::decode_headers_frame(5, flags, encoded.slice(9..)).unwrap();
+        assert_eq!(&decoded.header_block[..], b"block");
+        assert!(decoded.end_headers);
+        assert!(!decoded.end_stream);
+
+        let priority = decoded.priority.unwrap();
+        assert_eq!(priority.stream_dependency, 3);
+        assert!(priority.exclusive);
+        assert_eq!(priority.weight, 15);
+    }
+
+    #[test]
+    fn test_decode_priority_frame() {
+        let frame = PriorityFrame::new(7, PrioritySpec::new(3, true, 255));
+        let encoded = FrameCodec::encode_priority_frame(&frame);
+
+        let decoded = FrameCodec::decode_priority_frame(7, encoded.slice(9..)).unwrap();
+        assert_eq!(decoded.priority.stream_dependency, 3);
+        assert!(decoded.priority.exclusive);
+        assert_eq!(decoded.priority.weight, 255);
+
+        assert!(FrameCodec::decode_priority_frame(7, Bytes::from_static(&[0, 0, 0, 3])).is_err());
+    }
+
+    #[test]
+    fn test_decode_goaway_frame() {
+        let frame = GoawayFrame::new(5, ErrorCode::EnhanceYourCalm, Bytes::from("calm down"));
+        let encoded = FrameCodec::encode_goaway_frame(&frame);
+
+        let decoded = FrameCodec::decode_goaway_frame(encoded.slice(9..)).unwrap();
+        assert_eq!(decoded.last_stream_id, 5);
+        assert_eq!(decoded.error_code, ErrorCode::EnhanceYourCalm);
+        assert_eq!(&decoded.debug_data[..], b"calm down");
+
+        assert!(FrameCodec::decode_goaway_frame(Bytes::from_static(&[0, 0, 0, 1])).is_err());
+    }
+
+    #[test]
+    fn test_decode_push_promise_frame() {
+        let frame = PushPromiseFrame::new(1, 2, Bytes::from("block"), true).with_padding(3);
+        let encoded = FrameCodec::encode_push_promise_frame(&frame);
+        let flags = FrameFlags::from_u8(encoded[4]);
+
+        let decoded = FrameCodec::decode_push_promise_frame(1, flags, encoded.slice(9..)).unwrap();
+        assert_eq!(decoded.promised_stream_id, 2);
+        assert_eq!(&decoded.header_block[..], b"block");
+        assert_eq!(decoded.padding, Some(3));
+        assert!(decoded.end_headers);
+    }
+
+    #[test]
+    fn test_unknown_frame_roundtrip() {
+        let frame = Frame::new(FrameType::Unknown(0xbb), FrameFlags::from_u8(0xa5), 7, Bytes::from("opaque"));
+        let encoded = FrameCodec::encode_frame(&frame);
+
+        let (frame_type, flags, stream_id, payload) = FrameCodec::read_frame(&mut &encoded[..]).unwrap();
+        assert_eq!(frame_type, FrameType::Unknown(0xbb));
+        assert_eq!(flags.as_u8(), 0xa5);
+        assert_eq!(stream_id, 7);
+        assert_eq!(&payload[..], b"opaque");
+    }
+
+    #[test]
+    fn test_altsvc_frame_roundtrip() {
+        let frame = AltSvcFrame::new("https://example.com", "h2=\":8443\"; ma=60");
+        let encoded = FrameCodec::encode_altsvc_frame(&frame);
+        assert_eq!(encoded[3], 0xa);
+        assert_eq!(&encoded[5..9], &[0, 0, 0, 0]);
+
+        let decoded = FrameCodec::decode_altsvc_frame(0, encoded.slice(9..)).unwrap();
+        assert_eq!(&decoded.origin[..], b"https://example.com");
+        assert_eq!(&decoded.field_value[..], b"h2=\":8443\"; ma=60");
+
+        let encoded = FrameCodec::encode_altsvc_frame(&AltSvcFrame::for_stream(3, "clear"));
+        let decoded = FrameCodec::decode_altsvc_frame(3, encoded.slice(9..)).unwrap();
+        assert!(decoded.origin.is_empty());
+        assert_eq!(&decoded.field_value[..], b"clear");
+
+        assert!(FrameCodec::decode_altsvc_frame(0, Bytes::from_static(&[0, 5, b'a'])).is_err());
+    }
+
+    #[test]
+    fn test_priority_update_frame_roundtrip() {
+        let frame = PriorityUpdateFrame::new(5, "u=1, i");
+        let encoded = FrameCodec::encode_priority_update_frame(&frame);
+        assert_eq!(encoded[3], 0x10);
+        assert_eq!(&encoded[5..9], &[0, 0, 0, 0]);
+
+        let decoded = FrameCodec::decode_priority_update_frame(encoded.slice(9..)).unwrap();
+        assert_eq!(decoded.prioritized_stream_id, 5);
+        assert_eq!(&decoded.priority_field_value[..], b"u=1, i");
+
+        assert!(FrameCodec::decode_priority_update_frame(Bytes::from_static(&[0, 0, 1])).is_err());
+    }
+
+    #[test]
+    fn test_origin_frame_roundtrip() {
+        let frame = OriginFrame::default()
+            .with_origin("https://example.com")
+            .with_origin("https://www.example.com");
+        let encoded = FrameCodec::encode_origin_frame(&frame);
+        assert_eq!(encoded[3], 0xc);
+
+        let decoded = FrameCodec::decode_origin_frame(encoded.slice(9..)).unwrap();
+        assert_eq!(decoded.origins, vec!["https://example.com", "https://www.example.com"]);
+
+        assert!(FrameCodec::decode_origin_frame(Bytes::from_static(&[0, 9, b'x'])).is_err());
+        assert!(FrameCodec::decode_origin_frame(Bytes::from_static(&[0])).is_err());
+    }
 }
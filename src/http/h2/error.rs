@@ -52,6 +52,10 @@ pub enum Error {
     #[error("Compression error: {0}")]
     Compression(String),
 
+    /// HPACK decoding error, a COMPRESSION_ERROR with its precise cause
+    #[error("Compression error: {0}")]
+    Hpack(#[from] super::hpack::HpackError),
+
     /// Connect error (RFC 7540 Section 7 - Error code 0xa)
     #[error("Connect error: {0}")]
     Connect(String),
@@ -111,6 +115,20 @@ pub enum Error {
     /// Invalid header
     #[error("Invalid header: {0}")]
     InvalidHeader(String),
+
+    /// Stream script expectation failed
+    #[error("Expectation failed: {0}")]
+    ExpectFailed(String),
+}
+
+impl Error {
+    /// Check whether the request can be retried on a new connection
+    ///
+    /// True for streams refused with REFUSED_STREAM, including streams
+    /// above the last stream ID of a GOAWAY (RFC 9113 Section 8.7).
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::RefusedStream(_))
+    }
 }
 
 /// HTTP/2 error codes as defined in RFC 7540 Section 7
@@ -8,6 +8,47 @@
 use super::error::{Error, Result};
 use super::DEFAULT_INITIAL_WINDOW_SIZE;
 
+/// Policy for replenishing receive windows
+///
+/// Controls whether WINDOW_UPDATE frames are sent automatically as data is
+/// received, or left entirely to the test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControlPolicy {
+    /// Send WINDOW_UPDATE once a receive window drops below half of its
+    /// initial size, restoring it to its initial size
+    Auto,
+    /// Send WINDOW_UPDATE once a receive window drops below `threshold`
+    /// bytes, restoring it to its initial size
+    AutoBelow {
+        /// Window size below which the window is replenished
+        threshold: u32,
+    },
+    /// Never send WINDOW_UPDATE automatically; the test drives every update
+    Manual,
+}
+
+impl FlowControlPolicy {
+    /// Automatic replenishment when half of a window is consumed
+    pub fn auto() -> Self {
+        FlowControlPolicy::Auto
+    }
+
+    /// Get the WINDOW_UPDATE increment due for a receive window, if any
+    pub fn replenish_increment(&self, window: &FlowControlWindow) -> Option<u32> {
+        match *self {
+            FlowControlPolicy::Auto => window.replenish_increment(window.initial_size() as i64 / 2),
+            FlowControlPolicy::AutoBelow { threshold } => window.replenish_increment(threshold as i64),
+            FlowControlPolicy::Manual => None,
+        }
+    }
+}
+
+impl Default for FlowControlPolicy {
+    fn default() -> Self {
+        Self::auto()
+    }
+}
+
 /// Flow control window
 ///
 /// Tracks the available window size for sending data.
@@ -127,6 +168,16 @@ impl FlowControlWindow {
         Ok(())
     }
 
+    /// Get the increment that restores the window to its initial size,
+    /// if the window has dropped below `threshold`
+    pub fn replenish_increment(&self, threshold: i64) -> Option<u32> {
+        if self.current_size < threshold && self.current_size < self.initial_size as i64 {
+            Some((self.initial_size as i64 - self.current_size) as u32)
+        } else {
+            None
+        }
+    }
+
     /// Reset window to initial size
     pub fn reset(&mut self) {
         self.current_size = self.initial_size as i64;
@@ -211,16 +262,9 @@ impl ConnectionFlowControl {
     ///
     /// Returns the suggested increment if an update is needed
     pub fn should_send_window_update(&self) -> Option<u32> {
-        let recv_size = self.recv_window.size();
-        let initial_size = self.recv_window.initial_size() as i64;
-
         // Send update if window is less than half of initial size
-        if recv_size < initial_size / 2 {
-            let increment = (initial_size - recv_size) as u32;
-            Some(increment)
-        } else {
-            None
-        }
+        let initial_size = self.recv_window.initial_size() as i64;
+        self.recv_window.replenish_increment(initial_size / 2)
     }
 
     /// Send WINDOW_UPDATE (increases receive window)
@@ -314,16 +358,9 @@ impl StreamFlowControl {
 
     /// Check if we need to send WINDOW_UPDATE
     pub fn should_send_window_update(&self) -> Option<u32> {
-        let recv_size = self.recv_window.size();
-        let initial_size = self.recv_window.initial_size() as i64;
-
         // Send update if window is less than half of initial size
-        if recv_size < initial_size / 2 {
-            let increment = (initial_size - recv_size) as u32;
-            Some(increment)
-        } else {
-            None
-        }
+        let initial_size = self.recv_window.initial_size() as i64;
+        self.recv_window.replenish_increment(initial_size / 2)
     }
 
     /// Send WINDOW_UPDATE (increases receive window)
@@ -338,7 +375,7 @@ mod tests {
 
     #[test]
     fn test_flow_control_window_basic() {
-        let mut window = FlowControlWindow::new();
+        let window = FlowControlWindow::new();
         assert_eq!(window.size(), DEFAULT_INITIAL_WINDOW_SIZE as i64);
         assert!(window.has_capacity());
     }
@@ -433,6 +470,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_policy_replenish_increment() {
+        let mut window = FlowControlWindow::with_initial_size(1 << 20);
+        window.decrease(400_000);
+        assert_eq!(FlowControlPolicy::auto().replenish_increment(&window), None);
+        assert_eq!(
+            FlowControlPolicy::AutoBelow { threshold: 700_000 }.replenish_increment(&window),
+            Some(400_000)
+        );
+
+        // Half of the window's own initial size, not of the default
+        window.decrease(200_000);
+        assert_eq!(FlowControlPolicy::auto().replenish_increment(&window), Some(600_000));
+        assert_eq!(FlowControlPolicy::Manual.replenish_increment(&window), None);
+    }
+
     #[test]
     fn test_should_send_window_update() {
         let mut flow_control = ConnectionFlowControl::with_initial_sizes(100, 100);
@@ -446,4 +499,18 @@ mod tests {
         assert!(update.is_some());
         assert_eq!(update.unwrap(), 60);
     }
+
+    #[test]
+    fn test_replenish_increment() {
+        let mut window = FlowControlWindow::with_initial_size(100);
+        assert_eq!(window.replenish_increment(50), None);
+
+        window.decrease(60);
+        assert_eq!(window.replenish_increment(50), Some(60));
+        assert_eq!(window.replenish_increment(30), None);
+
+        // Over-committed windows are restored in full
+        window.decrease(60);
+        assert_eq!(window.replenish_increment(50), Some(120));
+    }
 }
@@ -1,6 +1,10 @@
 //! HTTP/2 frame types and utilities
 //!
-//! This module defines the frame types specified in RFC 7540 Section 6.
+//! This module defines the frame types specified in RFC 7540 Section 6,
+//! along with the ALTSVC (RFC 7838), ORIGIN (RFC 8336) and PRIORITY_UPDATE
+//! (RFC 9218) extension frames.
+//! Frames of any other type are kept as `FrameType::Unknown` so they can be
+//! ignored (RFC 9113 Section 4.1) or inspected as-is.
 
 use super::error::ErrorCode;
 use super::settings::Settings;
@@ -9,37 +13,62 @@ use std::fmt;
 
 /// HTTP/2 frame types (RFC 7540 Section 6)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
 pub enum FrameType {
     /// DATA frame (0x0) - Conveys arbitrary, variable-length sequences of octets
-    Data = 0x0,
+    Data,
     /// HEADERS frame (0x1) - Opens a stream and carries header block fragment
-    Headers = 0x1,
+    Headers,
     /// PRIORITY frame (0x2) - Specifies sender-advised priority of a stream
-    Priority = 0x2,
+    Priority,
     /// RST_STREAM frame (0x3) - Allows immediate termination of a stream
-    RstStream = 0x3,
+    RstStream,
     /// SETTINGS frame (0x4) - Conveys configuration parameters
-    Settings = 0x4,
+    Settings,
     /// PUSH_PROMISE frame (0x5) - Used to notify peer of intent to initiate stream
-    PushPromise = 0x5,
+    PushPromise,
     /// PING frame (0x6) - Mechanism for measuring round-trip time
-    Ping = 0x6,
+    Ping,
     /// GOAWAY frame (0x7) - Initiates shutdown of connection
-    Goaway = 0x7,
+    Goaway,
     /// WINDOW_UPDATE frame (0x8) - Implements flow control
-    WindowUpdate = 0x8,
+    WindowUpdate,
     /// CONTINUATION frame (0x9) - Continues sequence of header block fragments
-    Continuation = 0x9,
+    Continuation,
+    /// ALTSVC frame (0xa) - Advertises alternative services (RFC 7838)
+    AltSvc,
+    /// ORIGIN frame (0xc) - Lists origins the server is authoritative for (RFC 8336)
+    Origin,
+    /// PRIORITY_UPDATE frame (0x10) - Reprioritizes a request (RFC 9218)
+    PriorityUpdate,
+    /// Frame type without built-in support, kept as its type byte
+    Unknown(u8),
 }
 
 impl FrameType {
     /// Convert frame type to u8
     pub fn as_u8(self) -> u8 {
-        self as u8
+        match self {
+            FrameType::Data => 0x0,
+            FrameType::Headers => 0x1,
+            FrameType::Priority => 0x2,
+            FrameType::RstStream => 0x3,
+            FrameType::Settings => 0x4,
+            FrameType::PushPromise => 0x5,
+            FrameType::Ping => 0x6,
+            FrameType::Goaway => 0x7,
+            FrameType::WindowUpdate => 0x8,
+            FrameType::Continuation => 0x9,
+            FrameType::AltSvc => 0xa,
+            FrameType::Origin => 0xc,
+            FrameType::PriorityUpdate => 0x10,
+            FrameType::Unknown(byte) => byte,
+        }
     }
 
     /// Create frame type from u8
+    ///
+    /// Returns `None` for types without built-in support; use `From<u8>`
+    /// to keep them as `FrameType::Unknown`.
     pub fn from_u8(byte: u8) -> Option<Self> {
         match byte {
             0x0 => Some(FrameType::Data),
@@ -52,6 +81,9 @@ impl FrameType {
             0x7 => Some(FrameType::Goaway),
             0x8 => Some(FrameType::WindowUpdate),
             0x9 => Some(FrameType::Continuation),
+            0xa => Some(FrameType::AltSvc),
+            0xc => Some(FrameType::Origin),
+            0x10 => Some(FrameType::PriorityUpdate),
             _ => None,
         }
     }
@@ -69,8 +101,23 @@ impl FrameType {
             FrameType::Goaway => "GOAWAY",
             FrameType::WindowUpdate => "WINDOW_UPDATE",
             FrameType::Continuation => "CONTINUATION",
+            FrameType::AltSvc => "ALTSVC",
+            FrameType::Origin => "ORIGIN",
+            FrameType::PriorityUpdate => "PRIORITY_UPDATE",
+            FrameType::Unknown(_) => "UNKNOWN",
         }
     }
+
+    /// Check if the type has no built-in support
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, FrameType::Unknown(_))
+    }
+}
+
+impl From<u8> for FrameType {
+    fn from(byte: u8) -> Self {
+        FrameType::from_u8(byte).unwrap_or(FrameType::Unknown(byte))
+    }
 }
 
 impl fmt::Display for FrameType {
@@ -272,7 +319,7 @@ pub struct PrioritySpec {
     pub stream_dependency: u32,
     /// Exclusive flag
     pub exclusive: bool,
-    /// Weight (1-256)
+    /// Weight as sent on the wire (0-255, for an effective weight of 1-256)
     pub weight: u8,
 }
 
@@ -296,6 +343,13 @@ pub struct PriorityFrame {
     pub priority: PrioritySpec,
 }
 
+impl PriorityFrame {
+    /// Create a new PRIORITY frame
+    pub fn new(stream_id: u32, priority: PrioritySpec) -> Self {
+        PriorityFrame { stream_id, priority }
+    }
+}
+
 /// RST_STREAM frame (RFC 7540 Section 6.4)
 #[derive(Debug, Clone, Copy)]
 pub struct RstStreamFrame {
@@ -407,6 +461,16 @@ impl GoawayFrame {
             debug_data,
         }
     }
+
+    /// Get the debug data as text
+    pub fn debug_string(&self) -> String {
+        String::from_utf8_lossy(&self.debug_data).to_string()
+    }
+
+    /// Check whether this is a graceful shutdown (NO_ERROR)
+    pub fn is_graceful(&self) -> bool {
+        self.error_code == ErrorCode::NoError
+    }
 }
 
 /// WINDOW_UPDATE frame (RFC 7540 Section 6.9)
@@ -439,6 +503,79 @@ pub struct ContinuationFrame {
     pub end_headers: bool,
 }
 
+/// ALTSVC frame (RFC 7838 Section 4)
+///
+/// On stream 0 the origin names the service being advertised; on another
+/// stream the origin is empty and the stream's origin applies.
+#[derive(Debug, Clone)]
+pub struct AltSvcFrame {
+    /// Stream ID
+    pub stream_id: u32,
+    /// Origin (ASCII serialization, RFC 6454)
+    pub origin: Bytes,
+    /// Alt-Svc field value (e.g. `h2=":443"; ma=3600`)
+    pub field_value: Bytes,
+}
+
+impl AltSvcFrame {
+    /// Create an ALTSVC frame for an origin, sent on stream 0
+    pub fn new(origin: impl Into<Bytes>, field_value: impl Into<Bytes>) -> Self {
+        AltSvcFrame {
+            stream_id: 0,
+            origin: origin.into(),
+            field_value: field_value.into(),
+        }
+    }
+
+    /// Create an ALTSVC frame for the origin of a stream
+    pub fn for_stream(stream_id: u32, field_value: impl Into<Bytes>) -> Self {
+        AltSvcFrame {
+            stream_id,
+            origin: Bytes::new(),
+            field_value: field_value.into(),
+        }
+    }
+}
+
+/// PRIORITY_UPDATE frame (RFC 9218 Section 7.1), always sent on stream 0
+#[derive(Debug, Clone)]
+pub struct PriorityUpdateFrame {
+    /// Stream whose priority is updated
+    pub prioritized_stream_id: u32,
+    /// Priority field value, in the `priority` header syntax
+    pub priority_field_value: Bytes,
+}
+
+impl PriorityUpdateFrame {
+    /// Create a PRIORITY_UPDATE frame
+    pub fn new(prioritized_stream_id: u32, priority_field_value: impl Into<Bytes>) -> Self {
+        PriorityUpdateFrame {
+            prioritized_stream_id,
+            priority_field_value: priority_field_value.into(),
+        }
+    }
+}
+
+/// ORIGIN frame (RFC 8336 Section 2), always sent on stream 0
+#[derive(Debug, Clone, Default)]
+pub struct OriginFrame {
+    /// Origins (ASCII serialization, RFC 6454)
+    pub origins: Vec<String>,
+}
+
+impl OriginFrame {
+    /// Create an ORIGIN frame
+    pub fn new(origins: Vec<String>) -> Self {
+        OriginFrame { origins }
+    }
+
+    /// Add an origin
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins.push(origin.into());
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -454,6 +591,17 @@ mod tests {
         assert_eq!(FrameType::from_u8(0xff), None);
     }
 
+    #[test]
+    fn test_frame_type_extensions() {
+        assert_eq!(FrameType::from(0xa), FrameType::AltSvc);
+        assert_eq!(FrameType::from(0xc), FrameType::Origin);
+        assert_eq!(FrameType::from(0x10), FrameType::PriorityUpdate);
+        assert_eq!(FrameType::from(0xb), FrameType::Unknown(0xb));
+        assert_eq!(FrameType::Unknown(0xb).as_u8(), 0xb);
+        assert!(FrameType::from(0xff).is_unknown());
+        assert_eq!(FrameType::Unknown(0xff).to_string(), "UNKNOWN (0xff)");
+    }
+
     #[test]
     fn test_frame_type_name() {
         assert_eq!(FrameType::Data.name(), "DATA");
@@ -0,0 +1,224 @@
+//! Cleartext HTTP/2 (h2c)
+//!
+//! This module provides the helpers shared by the two ways of starting
+//! HTTP/2 without TLS (RFC 7540 Section 3.2 and 3.4):
+//!
+//! - **Upgrade**: an HTTP/1.1 request carrying `Upgrade: h2c` and
+//!   `HTTP2-Settings`, answered with `101 Switching Protocols`. The request
+//!   becomes stream 1 of the HTTP/2 connection. See
+//!   `HttpClient::upgrade_h2c` and `HttpServer::upgrade_h2c`.
+//! - **Prior knowledge**: the client sends the connection preface right
+//!   away, with `H2ClientBuilder::scheme("http")`. `H2ServerBuilder::detect`
+//!   tells both cases apart so a single listener can serve HTTP/1 and h2c.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vtest2::http::h2::{H2ServerBuilder, ServerConnection, h2c};
+//! use vtest2::http::session::FdSessionOps;
+//! use std::net::TcpListener;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let listener = TcpListener::bind("127.0.0.1:8080")?;
+//! let (stream, _) = listener.accept()?;
+//!
+//! match H2ServerBuilder::new().detect(FdSessionOps::new(stream))? {
+//!     ServerConnection::H2(mut server) => {
+//!         let request = server.recv_request()?;
+//!         server.send_response(request.stream_id, 200, &[], "Hello".into())?;
+//!     }
+//!     ServerConnection::Http1(mut http) => {
+//!         let request = http.receive_request()?;
+//!         if h2c::is_upgrade_request(&request) {
+//!             // The upgrade request is answered on stream 1
+//!             let mut server = http.upgrade_h2c(request)?;
+//!             let request = server.recv_request()?;
+//!             server.send_response(request.stream_id, 200, &[], "Hello".into())?;
+//!         } else {
+//!             http.send_ok(b"Hello")?;
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::codec::{FrameCodec, FRAME_HEADER_SIZE};
+use super::error::{Error, Result};
+use super::frames::SettingsFrame;
+use super::server::H2Server;
+use super::settings::Settings;
+use crate::http::session::PrefixedSession;
+use crate::http::{HttpRequest, HttpServer, SessionOps};
+use openssl::base64;
+
+/// Upgrade token for HTTP/2 over cleartext TCP
+pub const H2C_TOKEN: &str = "h2c";
+
+/// Header carrying the client settings in an upgrade request
+pub const HTTP2_SETTINGS_HEADER: &str = "HTTP2-Settings";
+
+/// Connection accepted by `H2ServerBuilder::detect`
+pub enum ServerConnection<S: SessionOps> {
+    /// HTTP/1 connection (which may still request an h2c upgrade)
+    Http1(HttpServer<PrefixedSession<S>>),
+    /// HTTP/2 with prior knowledge, connection preface already exchanged
+    H2(Box<H2Server<PrefixedSession<S>>>),
+}
+
+/// Check whether a request asks for an h2c upgrade
+///
+/// Requires `Upgrade: h2c`, exactly one `HTTP2-Settings` header, and both
+/// listed as connection options (RFC 7540 Section 3.2).
+pub fn is_upgrade_request(request: &HttpRequest) -> bool {
+    let headers = request.headers();
+
+    let upgrade = headers
+        .get_all("Upgrade")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case(H2C_TOKEN));
+
+    let connection: Vec<String> = headers
+        .get_all("Connection")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|option| option.trim().to_ascii_lowercase())
+        .collect();
+
+    upgrade
+        && headers.count(HTTP2_SETTINGS_HEADER) == 1
+        && connection.iter().any(|option| option == "upgrade")
+        && connection.iter().any(|option| option == "http2-settings")
+}
+
+/// Encode settings as an `HTTP2-Settings` header value
+///
+/// The value is the SETTINGS frame payload in base64url without padding.
+pub fn encode_settings_header(settings: &Settings) -> String {
+    let frame = FrameCodec::encode_settings_frame(&SettingsFrame::new(settings.clone()));
+    base64url_encode(&frame[FRAME_HEADER_SIZE..])
+}
+
+/// Decode an `HTTP2-Settings` header value
+pub fn decode_settings_header(value: &str) -> Result<Settings> {
+    let payload = base64url_decode(value.trim())
+        .ok_or_else(|| Error::InvalidSettings(format!("Invalid {} value: {:?}", HTTP2_SETTINGS_HEADER, value)))?;
+    Settings::from_payload(&payload)
+}
+
+/// Encode bytes as base64url without padding (RFC 4648 Section 5)
+pub fn base64url_encode(data: &[u8]) -> String {
+    base64::encode_block(data)
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Decode base64url, with or without padding
+pub fn base64url_decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    if input.len() % 4 == 1 || input.contains(['+', '/']) {
+        return None;
+    }
+
+    // Back to the standard alphabet, padded, as OpenSSL expects
+    let mut standard: String = input
+        .chars()
+        .map(|c| match c {
+            '-' => '+',
+            '_' => '/',
+            c => c,
+        })
+        .collect();
+    while !standard.len().is_multiple_of(4) {
+        standard.push('=');
+    }
+
+    base64::decode_block(&standard).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Method;
+
+    #[test]
+    fn test_base64url_roundtrip() {
+        assert_eq!(base64url_encode(b""), "");
+        assert_eq!(base64url_encode(b"f"), "Zg");
+        assert_eq!(base64url_encode(b"fo"), "Zm8");
+        assert_eq!(base64url_encode(b"foo"), "Zm9v");
+        assert_eq!(base64url_encode(&[0xfb, 0xff]), "-_8");
+
+        assert_eq!(base64url_decode("Zm9vYg").unwrap(), b"foob");
+        assert_eq!(base64url_decode("Zm9vYg==").unwrap(), b"foob");
+        assert_eq!(base64url_decode("-_8").unwrap(), vec![0xfb, 0xff]);
+        assert!(base64url_decode("Zm9v+").is_none());
+        assert!(base64url_decode("Z").is_none());
+    }
+
+    #[test]
+    fn test_base64url_rfc4648_vectors() {
+        // RFC 4648 Section 10, unpadded
+        let vectors = [
+            ("", ""),
+            ("f", "Zg"),
+            ("fo", "Zm8"),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg"),
+            ("fooba", "Zm9vYmE"),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(base64url_encode(plain.as_bytes()), encoded);
+            assert_eq!(base64url_decode(encoded).unwrap(), plain.as_bytes());
+        }
+
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(base64url_decode(&base64url_encode(&data)).unwrap(), data);
+        assert!(base64url_decode("Zm9v*").is_none());
+    }
+
+    #[test]
+    fn test_settings_header_roundtrip() {
+        let settings = Settings {
+            max_concurrent_streams: Some(100),
+            initial_window_size: Some(1 << 20),
+            ..Settings::new()
+        };
+
+        let value = encode_settings_header(&settings);
+        let decoded = decode_settings_header(&value).unwrap();
+        assert_eq!(decoded.max_concurrent_streams, Some(100));
+        assert_eq!(decoded.initial_window_size, Some(1 << 20));
+        assert_eq!(decoded.header_table_size, None);
+
+        assert!(decode_settings_header("AAMAAABk!").is_err());
+    }
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let request = HttpRequest::builder()
+            .method(Method::Get)
+            .uri("/")
+            .header("Connection", "Upgrade, HTTP2-Settings")
+            .header("Upgrade", "h2c")
+            .header("HTTP2-Settings", "")
+            .build();
+        assert!(is_upgrade_request(&request));
+
+        let request = HttpRequest::builder()
+            .method(Method::Get)
+            .uri("/")
+            .header("Upgrade", "h2c")
+            .header("HTTP2-Settings", "")
+            .build();
+        assert!(!is_upgrade_request(&request));
+    }
+}
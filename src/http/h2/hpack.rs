@@ -0,0 +1,624 @@
+//! HPACK header decoding
+//!
+//! This module implements the HPACK decoder (RFC 7541) used by the HTTP/2
+//! client and server. Unlike the opaque decoder from the `hpack` crate, the
+//! dynamic table is exposed so tests can inspect its entries, size and
+//! evictions after each header block, mirroring the C `tbl.dec.*` variables.
+
+use hpack::huffman::HuffmanDecoder;
+use std::collections::VecDeque;
+
+/// Per-entry overhead counted against the table size (RFC 7541 Section 4.1)
+pub const ENTRY_OVERHEAD: usize = 32;
+
+/// Number of entries in the static table (RFC 7541 Appendix A)
+pub const STATIC_TABLE_LEN: usize = 61;
+
+/// HPACK static table (RFC 7541 Appendix A)
+const STATIC_TABLE: [(&str, &str); STATIC_TABLE_LEN] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// HPACK decoding errors
+///
+/// Each variant describes a precise cause of a COMPRESSION_ERROR, so tests
+/// can assert on why a header block was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HpackError {
+    /// Header block ended in the middle of a representation
+    #[error("header block truncated")]
+    Truncated,
+
+    /// Integer representation does not fit in 32 bits
+    #[error("integer overflow")]
+    IntegerOverflow,
+
+    /// Index 0, or an index beyond the static and dynamic tables
+    #[error("invalid index {0}")]
+    InvalidIndex(usize),
+
+    /// Huffman-encoded string could not be decoded
+    #[error("invalid huffman string: {0}")]
+    InvalidHuffman(String),
+
+    /// Dynamic table size update after the first header field
+    #[error("dynamic table size update not at start of header block")]
+    TableSizeUpdateNotAtStart,
+
+    /// Dynamic table size update above the SETTINGS_HEADER_TABLE_SIZE limit
+    #[error("dynamic table size update to {requested} exceeds limit {limit}")]
+    TableSizeExceedsLimit { requested: usize, limit: usize },
+
+    /// Settings limit was lowered but the block did not start with an update
+    #[error("missing dynamic table size update after limit change")]
+    MissingTableSizeUpdate,
+
+    /// Decoded header list exceeds SETTINGS_MAX_HEADER_LIST_SIZE
+    #[error("header list size {size} exceeds limit {limit}")]
+    HeaderListTooLarge { size: usize, limit: usize },
+}
+
+/// How a header field was represented in the header block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    /// Indexed header field (RFC 7541 Section 6.1)
+    Indexed,
+    /// Literal with incremental indexing (RFC 7541 Section 6.2.1)
+    IncrementalIndexing,
+    /// Literal without indexing (RFC 7541 Section 6.2.2)
+    WithoutIndexing,
+    /// Literal never indexed (RFC 7541 Section 6.2.3)
+    NeverIndexed,
+}
+
+/// A decoded header field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+    pub representation: Representation,
+}
+
+impl HeaderField {
+    /// Size of the field as counted by HPACK and SETTINGS_MAX_HEADER_LIST_SIZE
+    pub fn size(&self) -> usize {
+        self.name.len() + self.value.len() + ENTRY_OVERHEAD
+    }
+}
+
+/// HPACK dynamic table
+///
+/// Entries are stored newest first, so `get(0)` is the most recently
+/// inserted entry (HPACK index 62).
+#[derive(Debug, Clone)]
+pub struct DynamicTable {
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+    size: usize,
+    max_size: usize,
+    evictions: usize,
+}
+
+impl DynamicTable {
+    /// Create a new dynamic table
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            size: 0,
+            max_size,
+            evictions: 0,
+        }
+    }
+
+    /// Number of entries in the table
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the table is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Current table size in bytes (including per-entry overhead)
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Current maximum table size in bytes
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Total number of entries evicted since the table was created
+    pub fn evictions(&self) -> usize {
+        self.evictions
+    }
+
+    /// Get an entry by position (0 is the newest entry)
+    pub fn get(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        self.entries
+            .get(index)
+            .map(|(name, value)| (name.as_slice(), value.as_slice()))
+    }
+
+    /// Iterate over entries, newest first
+    pub fn entries(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_slice(), value.as_slice()))
+    }
+
+    /// Insert an entry, evicting older entries as needed
+    ///
+    /// An entry larger than the maximum size empties the table and is not
+    /// inserted (RFC 7541 Section 4.4).
+    fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) -> usize {
+        let entry_size = name.len() + value.len() + ENTRY_OVERHEAD;
+        let evicted = self.evict_to(self.max_size.saturating_sub(entry_size));
+
+        if entry_size <= self.max_size {
+            self.entries.push_front((name, value));
+            self.size += entry_size;
+        }
+
+        evicted
+    }
+
+    /// Change the maximum size, evicting entries that no longer fit
+    fn set_max_size(&mut self, max_size: usize) -> usize {
+        self.max_size = max_size;
+        self.evict_to(max_size)
+    }
+
+    fn evict_to(&mut self, target: usize) -> usize {
+        let mut evicted = 0;
+        while self.size > target {
+            match self.entries.pop_back() {
+                Some((name, value)) => {
+                    self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+                    evicted += 1;
+                }
+                None => break,
+            }
+        }
+        self.evictions += evicted;
+        evicted
+    }
+}
+
+/// HPACK decoder
+///
+/// Decodes complete header blocks and keeps the dynamic table available for
+/// inspection between blocks.
+#[derive(Debug, Clone)]
+pub struct HpackDecoder {
+    table: DynamicTable,
+    /// Table size limit advertised in SETTINGS_HEADER_TABLE_SIZE
+    max_table_size: usize,
+    /// Limit advertised in SETTINGS_MAX_HEADER_LIST_SIZE
+    max_header_list_size: Option<usize>,
+    /// Set when the limit dropped below the current table size
+    size_update_required: bool,
+    /// Entries evicted while decoding the last header block
+    block_evictions: usize,
+}
+
+impl HpackDecoder {
+    /// Create a decoder with the given SETTINGS_HEADER_TABLE_SIZE
+    pub fn new(max_table_size: usize) -> Self {
+        Self {
+            table: DynamicTable::new(max_table_size),
+            max_table_size,
+            max_header_list_size: None,
+            size_update_required: false,
+            block_evictions: 0,
+        }
+    }
+
+    /// Get the dynamic table
+    pub fn table(&self) -> &DynamicTable {
+        &self.table
+    }
+
+    /// Get the SETTINGS_HEADER_TABLE_SIZE limit
+    pub fn max_table_size(&self) -> usize {
+        self.max_table_size
+    }
+
+    /// Get the SETTINGS_MAX_HEADER_LIST_SIZE limit
+    pub fn max_header_list_size(&self) -> Option<usize> {
+        self.max_header_list_size
+    }
+
+    /// Number of entries evicted while decoding the last header block
+    pub fn last_block_evictions(&self) -> usize {
+        self.block_evictions
+    }
+
+    /// Update the SETTINGS_HEADER_TABLE_SIZE limit
+    ///
+    /// If the limit drops below the current table maximum, the peer must
+    /// signal a dynamic table size update at the start of the next block.
+    pub fn set_max_table_size(&mut self, size: usize) {
+        if size < self.table.max_size() {
+            self.size_update_required = true;
+        }
+        self.max_table_size = size;
+    }
+
+    /// Update the SETTINGS_MAX_HEADER_LIST_SIZE limit
+    pub fn set_max_header_list_size(&mut self, size: Option<usize>) {
+        self.max_header_list_size = size;
+    }
+
+    /// Look up an HPACK index in the static and dynamic tables
+    pub fn get(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        match index {
+            0 => None,
+            1..=STATIC_TABLE_LEN => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Some((name.as_bytes(), value.as_bytes()))
+            }
+            _ => self.table.get(index - STATIC_TABLE_LEN - 1),
+        }
+    }
+
+    /// Decode a complete header block
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<HeaderField>, HpackError> {
+        self.block_evictions = 0;
+
+        let mut fields = Vec::new();
+        let mut list_size = 0;
+        let mut pos = 0;
+        let mut at_start = true;
+
+        while pos < block.len() {
+            let byte = block[pos];
+
+            // Dynamic table size update (RFC 7541 Section 6.3)
+            if byte & 0xe0 == 0x20 {
+                if !at_start {
+                    return Err(HpackError::TableSizeUpdateNotAtStart);
+                }
+                let requested = decode_integer(block, &mut pos, 5)?;
+                if requested > self.max_table_size {
+                    return Err(HpackError::TableSizeExceedsLimit {
+                        requested,
+                        limit: self.max_table_size,
+                    });
+                }
+                self.block_evictions += self.table.set_max_size(requested);
+                self.size_update_required = false;
+                continue;
+            }
+
+            if at_start && self.size_update_required {
+                return Err(HpackError::MissingTableSizeUpdate);
+            }
+            at_start = false;
+
+            let field = if byte & 0x80 != 0 {
+                let index = decode_integer(block, &mut pos, 7)?;
+                let (name, value) = self.get(index).ok_or(HpackError::InvalidIndex(index))?;
+                HeaderField {
+                    name: name.to_vec(),
+                    value: value.to_vec(),
+                    representation: Representation::Indexed,
+                }
+            } else {
+                let (prefix, representation) = if byte & 0xc0 == 0x40 {
+                    (6, Representation::IncrementalIndexing)
+                } else if byte & 0xf0 == 0x10 {
+                    (4, Representation::NeverIndexed)
+                } else {
+                    (4, Representation::WithoutIndexing)
+                };
+
+                let index = decode_integer(block, &mut pos, prefix)?;
+                let name = if index == 0 {
+                    decode_string(block, &mut pos)?
+                } else {
+                    self.get(index)
+                        .ok_or(HpackError::InvalidIndex(index))?
+                        .0
+                        .to_vec()
+                };
+                let value = decode_string(block, &mut pos)?;
+
+                if representation == Representation::IncrementalIndexing {
+                    self.block_evictions += self.table.insert(name.clone(), value.clone());
+                }
+
+                HeaderField {
+                    name,
+                    value,
+                    representation,
+                }
+            };
+
+            list_size += field.size();
+            if let Some(limit) = self.max_header_list_size {
+                if list_size > limit {
+                    return Err(HpackError::HeaderListTooLarge {
+                        size: list_size,
+                        limit,
+                    });
+                }
+            }
+
+            fields.push(field);
+        }
+
+        Ok(fields)
+    }
+}
+
+impl Default for HpackDecoder {
+    fn default() -> Self {
+        Self::new(super::DEFAULT_HEADER_TABLE_SIZE as usize)
+    }
+}
+
+/// Decode an integer with an N-bit prefix (RFC 7541 Section 5.1)
+fn decode_integer(buf: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, HpackError> {
+    let mask = ((1u16 << prefix) - 1) as u8;
+    let first = *buf.get(*pos).ok_or(HpackError::Truncated)? & mask;
+    *pos += 1;
+
+    if first < mask {
+        return Ok(first as usize);
+    }
+
+    let mut value = mask as u64;
+    let mut shift = 0;
+    loop {
+        let byte = *buf.get(*pos).ok_or(HpackError::Truncated)?;
+        *pos += 1;
+
+        value += ((byte & 0x7f) as u64) << shift;
+        if value > u32::MAX as u64 {
+            return Err(HpackError::IntegerOverflow);
+        }
+        if byte & 0x80 == 0 {
+            return Ok(value as usize);
+        }
+
+        shift += 7;
+        if shift > 28 {
+            return Err(HpackError::IntegerOverflow);
+        }
+    }
+}
+
+/// Decode a string literal (RFC 7541 Section 5.2)
+fn decode_string(buf: &[u8], pos: &mut usize) -> Result<Vec<u8>, HpackError> {
+    let huffman = *buf.get(*pos).ok_or(HpackError::Truncated)? & 0x80 != 0;
+    let len = decode_integer(buf, pos, 7)?;
+
+    let end = pos.checked_add(len).ok_or(HpackError::Truncated)?;
+    let raw = buf.get(*pos..end).ok_or(HpackError::Truncated)?;
+    *pos = end;
+
+    if huffman {
+        HuffmanDecoder::new()
+            .decode(raw)
+            .map_err(|e| HpackError::InvalidHuffman(format!("{:?}", e)))
+    } else {
+        Ok(raw.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(fields: &[HeaderField]) -> Vec<(&str, &str)> {
+        fields
+            .iter()
+            .map(|f| {
+                (
+                    std::str::from_utf8(&f.name).unwrap(),
+                    std::str::from_utf8(&f.value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_integer_decoding() {
+        // RFC 7541 C.1.1, C.1.2, C.1.3
+        let mut pos = 0;
+        assert_eq!(decode_integer(&[0x0a], &mut pos, 5).unwrap(), 10);
+
+        let mut pos = 0;
+        assert_eq!(decode_integer(&[0x1f, 0x9a, 0x0a], &mut pos, 5).unwrap(), 1337);
+        assert_eq!(pos, 3);
+
+        let mut pos = 0;
+        assert_eq!(decode_integer(&[0x2a], &mut pos, 8).unwrap(), 42);
+
+        let mut pos = 0;
+        assert_eq!(
+            decode_integer(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0x7f], &mut pos, 5),
+            Err(HpackError::IntegerOverflow)
+        );
+
+        let mut pos = 0;
+        assert_eq!(decode_integer(&[0x1f, 0x9a], &mut pos, 5), Err(HpackError::Truncated));
+    }
+
+    #[test]
+    fn test_requests_without_huffman() {
+        // RFC 7541 C.3
+        let mut decoder = HpackDecoder::new(4096);
+
+        let fields = decoder
+            .decode(b"\x82\x86\x84\x41\x0fwww.example.com")
+            .unwrap();
+        assert_eq!(
+            pairs(&fields),
+            vec![
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]
+        );
+        assert_eq!(decoder.table().len(), 1);
+        assert_eq!(decoder.table().size(), 57);
+
+        let fields = decoder
+            .decode(b"\x82\x86\x84\xbe\x58\x08no-cache")
+            .unwrap();
+        assert_eq!(pairs(&fields)[3], (":authority", "www.example.com"));
+        assert_eq!(pairs(&fields)[4], ("cache-control", "no-cache"));
+        assert_eq!(decoder.table().len(), 2);
+        assert_eq!(decoder.table().size(), 110);
+        assert_eq!(decoder.table().get(0), Some((&b"cache-control"[..], &b"no-cache"[..])));
+    }
+
+    #[test]
+    fn test_responses_with_huffman_and_eviction() {
+        // RFC 7541 C.6
+        let mut decoder = HpackDecoder::new(256);
+
+        let block1: &[u8] = &[
+            0x48, 0x82, 0x64, 0x02, 0x58, 0x85, 0xae, 0xc3, 0x77, 0x1a, 0x4b, 0x61, 0x96, 0xd0,
+            0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95, 0x04, 0x0b, 0x81,
+            0x66, 0xe0, 0x82, 0xa6, 0x2d, 0x1b, 0xff, 0x6e, 0x91, 0x9d, 0x29, 0xad, 0x17, 0x18,
+            0x63, 0xc7, 0x8f, 0x0b, 0x97, 0xc8, 0xe9, 0xae, 0x82, 0xae, 0x43, 0xd3,
+        ];
+        let fields = decoder.decode(block1).unwrap();
+        assert_eq!(pairs(&fields)[0], (":status", "302"));
+        assert_eq!(pairs(&fields)[3], ("location", "https://www.example.com"));
+        assert_eq!(decoder.table().size(), 222);
+        assert_eq!(decoder.last_block_evictions(), 0);
+
+        let block2: &[u8] = &[0x48, 0x83, 0x64, 0x0e, 0xff, 0xc1, 0xc0, 0xbf];
+        let fields = decoder.decode(block2).unwrap();
+        assert_eq!(pairs(&fields)[0], (":status", "307"));
+        assert_eq!(decoder.table().size(), 222);
+        assert_eq!(decoder.last_block_evictions(), 1);
+        assert_eq!(decoder.table().evictions(), 1);
+    }
+
+    #[test]
+    fn test_table_size_update() {
+        let mut decoder = HpackDecoder::new(4096);
+        decoder.decode(b"\x41\x0fwww.example.com").unwrap();
+        assert_eq!(decoder.table().len(), 1);
+
+        // Size update to 0 empties the table
+        decoder.decode(b"\x20\x82").unwrap();
+        assert_eq!(decoder.table().max_size(), 0);
+        assert!(decoder.table().is_empty());
+        assert_eq!(decoder.last_block_evictions(), 1);
+
+        // Above the SETTINGS limit
+        assert_eq!(
+            decoder.decode(b"\x3f\xe2\x1f"),
+            Err(HpackError::TableSizeExceedsLimit { requested: 4097, limit: 4096 })
+        );
+
+        // Not at the start of the block
+        assert_eq!(
+            decoder.decode(b"\x82\x20"),
+            Err(HpackError::TableSizeUpdateNotAtStart)
+        );
+    }
+
+    #[test]
+    fn test_missing_size_update_after_limit_change() {
+        let mut decoder = HpackDecoder::new(4096);
+        decoder.set_max_table_size(100);
+
+        assert_eq!(decoder.decode(b"\x82"), Err(HpackError::MissingTableSizeUpdate));
+        decoder.decode(b"\x3f\x45\x82").unwrap();
+        assert_eq!(decoder.table().max_size(), 100);
+    }
+
+    #[test]
+    fn test_invalid_index_and_list_size() {
+        let mut decoder = HpackDecoder::new(4096);
+        assert_eq!(decoder.decode(b"\x80"), Err(HpackError::InvalidIndex(0)));
+        assert_eq!(decoder.decode(b"\xbe"), Err(HpackError::InvalidIndex(62)));
+
+        decoder.set_max_header_list_size(Some(50));
+        assert_eq!(
+            decoder.decode(b"\x82\x86"),
+            Err(HpackError::HeaderListTooLarge { size: 85, limit: 50 })
+        );
+    }
+
+    #[test]
+    fn test_error_keeps_cause() {
+        fn decode(decoder: &mut HpackDecoder, block: &[u8]) -> crate::http::h2::Result<Vec<HeaderField>> {
+            Ok(decoder.decode(block)?)
+        }
+
+        let mut decoder = HpackDecoder::new(4096);
+        let err = decode(&mut decoder, b"\xbe").unwrap_err();
+        assert!(matches!(err, crate::http::h2::Error::Hpack(HpackError::InvalidIndex(62))), "{:?}", err);
+        assert_eq!(err.to_string(), "Compression error: invalid index 62");
+    }
+}
@@ -13,14 +13,30 @@
 //! ## Features
 //!
 //! - **Frame handling**: All HTTP/2 frame types (DATA, HEADERS, PRIORITY,
-//!   RST_STREAM, SETTINGS, PUSH_PROMISE, PING, GOAWAY, WINDOW_UPDATE, CONTINUATION)
+//!   RST_STREAM, SETTINGS, PUSH_PROMISE, PING, GOAWAY, WINDOW_UPDATE, CONTINUATION),
+//!   the ALTSVC, ORIGIN and PRIORITY_UPDATE extensions, and unknown types kept as-is
 //! - **Stream multiplexing**: Multiple concurrent streams per connection
-//! - **HPACK compression**: Header compression/decompression (via h2 crate)
+//! - **HPACK compression**: Header compression, and decompression with an
+//!   inspectable dynamic table
 //! - **Flow control**: Connection and stream-level window management
 //! - **ALPN integration**: Protocol negotiation via TLS
-//! - **Settings exchange**: Initial connection setup and configuration
-//! - **Priority handling**: Stream priority and dependencies
+//! - **Settings exchange**: Initial connection setup and configuration, with
+//!   values applied on ACK and an optional SETTINGS_TIMEOUT
+//! - **Priority handling**: RFC 7540 dependency tree with weighted DATA
+//!   scheduling, and RFC 9218 extensible priorities (`priority` header,
+//!   PRIORITY_UPDATE)
 //! - **Server push**: PUSH_PROMISE frames
+//! - **Extended CONNECT**: `:protocol` tunnels (RFC 8441) with a byte-stream
+//!   API, and WebSockets over HTTP/2
+//! - **Cleartext HTTP/2**: `Upgrade: h2c` and prior-knowledge preface detection
+//! - **Stream scripts**: Per-stream step lists (`stream N { ... }`) run
+//!   concurrently on a shared connection
+//! - **Frame validation**: RFC 9113 checks on received frames, answered with
+//!   the exact GOAWAY or RST_STREAM error code
+//! - **PING tracking**: Round-trip times, `ping_and_wait`, and an optional
+//!   keepalive that detects peers no longer acknowledging pings
+//! - **Connection shutdown**: GOAWAY refuses streams above the last stream ID,
+//!   and servers can shut down gracefully with the two-GOAWAY sequence
 //!
 //! # Examples
 //!
@@ -31,12 +47,12 @@
 //! use vtest2::http::tls::{TlsConfig, TlsVersion};
 //! use std::net::TcpStream;
 //!
-//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! // Create TLS config with ALPN for HTTP/2
 //! let tls_config = TlsConfig::client()
 //!     .version(TlsVersion::Tls13)
 //!     .servername("example.com")
-//!     .alpn(&["h2"])
+//!     .alpn(&["h2"])?
 //!     .build()?;
 //!
 //! // Connect with TLS
@@ -44,12 +60,11 @@
 //! let tls_session = tls_config.connect(tcp_stream)?;
 //!
 //! // Create HTTP/2 client
-//! let mut client = H2ClientBuilder::new()
-//!     .build(tls_session)
-//!     .await?;
+//! let mut client: H2Client<_> = H2ClientBuilder::new().build(tls_session)?;
+//! client.connect()?;
 //!
 //! // Send request
-//! let response = client.get("/").await?;
+//! let response = client.get("/")?;
 //! println!("Status: {}", response.status());
 //! # Ok(())
 //! # }
@@ -60,14 +75,15 @@
 //! ```no_run
 //! use vtest2::http::h2::{H2Server, H2ServerBuilder};
 //! use vtest2::http::tls::{TlsConfig, TlsVersion};
+//! use bytes::Bytes;
 //! use std::net::TcpListener;
 //!
-//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! // Create TLS config with ALPN for HTTP/2
 //! let tls_config = TlsConfig::server()
 //!     .cert_file("server.pem")?
 //!     .version(TlsVersion::Tls13)
-//!     .alpn(&["h2"])
+//!     .alpn(&["h2"])?
 //!     .build()?;
 //!
 //! // Accept connection
@@ -76,13 +92,12 @@
 //! let tls_session = tls_config.accept(tcp_stream)?;
 //!
 //! // Create HTTP/2 server
-//! let mut server = H2ServerBuilder::new()
-//!     .build(tls_session)
-//!     .await?;
+//! let mut server: H2Server<_> = H2ServerBuilder::new().build(tls_session)?;
+//! server.accept()?;
 //!
 //! // Process request
-//! let request = server.receive_request().await?;
-//! server.send_response(200, &[], b"OK").await?;
+//! let request = server.recv_request()?;
+//! server.send_response(request.stream_id, 200, &[], Bytes::from("OK"))?;
 //! # Ok(())
 //! # }
 //! ```
@@ -95,12 +110,29 @@ pub mod flow_control;
 pub mod settings;
 pub mod error;
 pub mod codec;
+pub mod hpack;
+pub mod h2c;
+pub mod script;
+pub mod validate;
+pub mod priority;
+pub mod ping;
+pub mod tunnel;
+pub mod websocket;
 
-pub use client::{H2Client, H2ClientBuilder, H2Response};
+pub use client::{H2Client, H2ClientBuilder, H2Push, H2Response};
 pub use server::{H2Server, H2ServerBuilder, H2Request};
 pub use stream::{StreamId, StreamState, H2Stream};
-pub use frames::{Frame, FrameType, FrameFlags, DataFrame, HeadersFrame, SettingsFrame, PushPromiseFrame};
-pub use settings::{Settings, SettingsBuilder};
+pub use frames::{Frame, FrameType, FrameFlags, DataFrame, HeadersFrame, SettingsFrame, PushPromiseFrame, PriorityFrame, PrioritySpec, AltSvcFrame, OriginFrame, PriorityUpdateFrame};
+pub use priority::{DataScheduler, ExtensiblePriority, PrioritySignal, PrioritySource, PriorityTree};
+pub use settings::{Settings, SettingsBuilder, SettingsTracker};
+pub use ping::{KeepAlive, PingTracker};
+pub use flow_control::FlowControlPolicy;
+pub use self::hpack::{HpackDecoder, HpackError, DynamicTable, HeaderField};
+pub use h2c::ServerConnection;
+pub use script::{ScriptConnection, ScriptRunner, ScriptHandle, StreamScript, Step};
+pub use validate::{FrameValidator, Violation, ViolationScope};
+pub use tunnel::{H2Tunnel, TunnelEndpoint};
+pub use websocket::{WebSocket, WsFrame};
 pub use error::{Error, Result};
 
 /// HTTP/2 connection preface that must be sent by clients
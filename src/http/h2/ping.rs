@@ -0,0 +1,210 @@
+//! PING round-trip tracking and keepalive
+//!
+//! A PING is answered with a PING ACK carrying the same 8 bytes of opaque
+//! data (RFC 9113 Section 6.7). `PingTracker` matches ACKs to the pings
+//! still outstanding, measures round-trip times, and drives an optional
+//! keepalive: after the connection has been idle for a while a PING is
+//! sent, and a peer that leaves it unacknowledged is considered dead.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Keepalive configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// Idle time after which a PING is sent
+    pub interval: Duration,
+    /// How long to wait for the ACK before giving up on the peer
+    pub timeout: Duration,
+}
+
+/// What the keepalive requires once its timer fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAliveAction {
+    /// Nothing to do yet
+    Wait,
+    /// The connection has been idle for the interval: send a PING
+    Ping,
+    /// A PING went unacknowledged for the timeout
+    Expired,
+}
+
+/// Outstanding PING frames and measured round-trip times
+#[derive(Debug, Clone)]
+pub struct PingTracker {
+    /// Sent pings in order, with the time they were sent
+    outstanding: VecDeque<([u8; 8], Instant)>,
+    /// Counter used to generate ping payloads
+    next_payload: u64,
+    /// Round-trip time of the last acknowledged ping
+    last_rtt: Option<Duration>,
+    /// Smallest round-trip time seen
+    min_rtt: Option<Duration>,
+    /// Number of acknowledged pings
+    acked: usize,
+    /// Keepalive configuration (None = disabled)
+    keepalive: Option<KeepAlive>,
+    /// Time the last frame was received
+    last_activity: Instant,
+}
+
+impl PingTracker {
+    /// Create a tracker, with keepalive if configured
+    pub fn new(keepalive: Option<KeepAlive>) -> Self {
+        PingTracker {
+            outstanding: VecDeque::new(),
+            next_payload: 0,
+            last_rtt: None,
+            min_rtt: None,
+            acked: 0,
+            keepalive,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Get the keepalive configuration
+    pub fn keepalive(&self) -> Option<KeepAlive> {
+        self.keepalive
+    }
+
+    /// Set the keepalive configuration
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepAlive>) {
+        self.keepalive = keepalive;
+        self.last_activity = Instant::now();
+    }
+
+    /// Generate a payload not used by earlier pings
+    pub fn next_payload(&mut self) -> [u8; 8] {
+        self.next_payload += 1;
+        self.next_payload.to_be_bytes()
+    }
+
+    /// Record a PING sent to the peer
+    pub fn sent(&mut self, data: [u8; 8]) {
+        self.outstanding.push_back((data, Instant::now()));
+    }
+
+    /// Record a received PING ACK, returning the round-trip time
+    ///
+    /// Returns None if the payload matches no outstanding ping.
+    pub fn acknowledge(&mut self, data: [u8; 8]) -> Option<Duration> {
+        let pos = self.outstanding.iter().position(|(sent, _)| *sent == data)?;
+        let (_, sent_at) = self.outstanding.remove(pos)?;
+        let rtt = sent_at.elapsed();
+
+        self.last_rtt = Some(rtt);
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        self.acked += 1;
+        Some(rtt)
+    }
+
+    /// Record that a frame was received
+    pub fn record_activity(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// Check whether a ping with this payload awaits its ACK
+    pub fn is_outstanding(&self, data: [u8; 8]) -> bool {
+        self.outstanding.iter().any(|(sent, _)| *sent == data)
+    }
+
+    /// Number of pings awaiting their ACK
+    pub fn outstanding_count(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    /// Number of pings acknowledged by the peer
+    pub fn acked_count(&self) -> usize {
+        self.acked
+    }
+
+    /// Round-trip time of the last acknowledged ping
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.last_rtt
+    }
+
+    /// Smallest round-trip time measured
+    pub fn min_rtt(&self) -> Option<Duration> {
+        self.min_rtt
+    }
+
+    /// Time left before the keepalive needs attention
+    ///
+    /// While a ping is outstanding this is the time left for its ACK,
+    /// otherwise the idle time left before the next ping. Returns None if
+    /// keepalive is disabled.
+    pub fn keepalive_remaining(&self) -> Option<Duration> {
+        let keepalive = self.keepalive?;
+        Some(match self.outstanding.front() {
+            Some((_, sent_at)) => keepalive.timeout.saturating_sub(sent_at.elapsed()),
+            None => keepalive.interval.saturating_sub(self.last_activity.elapsed()),
+        })
+    }
+
+    /// What the keepalive requires now
+    pub fn keepalive_action(&self) -> KeepAliveAction {
+        match self.keepalive_remaining() {
+            Some(Duration::ZERO) if self.outstanding.is_empty() => KeepAliveAction::Ping,
+            Some(Duration::ZERO) => KeepAliveAction::Expired,
+            _ => KeepAliveAction::Wait,
+        }
+    }
+}
+
+impl Default for PingTracker {
+    fn default() -> Self {
+        PingTracker::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_tracker_matches_acks() {
+        let mut tracker = PingTracker::default();
+        let first = tracker.next_payload();
+        let second = tracker.next_payload();
+        assert_ne!(first, second);
+
+        tracker.sent(first);
+        tracker.sent(second);
+        assert_eq!(tracker.outstanding_count(), 2);
+
+        // ACKs may arrive in any order; unknown payloads are ignored
+        assert!(tracker.acknowledge(*b"unknown!").is_none());
+        let rtt = tracker.acknowledge(second).unwrap();
+        assert!(!tracker.is_outstanding(second));
+        assert!(tracker.is_outstanding(first));
+        assert_eq!(tracker.last_rtt(), Some(rtt));
+        assert_eq!(tracker.min_rtt(), Some(rtt));
+
+        tracker.acknowledge(first).unwrap();
+        assert_eq!(tracker.acked_count(), 2);
+        assert!(tracker.acknowledge(first).is_none());
+    }
+
+    #[test]
+    fn test_keepalive_action() {
+        let mut tracker = PingTracker::default();
+        assert_eq!(tracker.keepalive_remaining(), None);
+        assert_eq!(tracker.keepalive_action(), KeepAliveAction::Wait);
+
+        tracker.set_keepalive(Some(KeepAlive {
+            interval: Duration::ZERO,
+            timeout: Duration::from_secs(60),
+        }));
+        assert_eq!(tracker.keepalive_action(), KeepAliveAction::Ping);
+
+        let data = tracker.next_payload();
+        tracker.sent(data);
+        assert_eq!(tracker.keepalive_action(), KeepAliveAction::Wait);
+
+        tracker.set_keepalive(Some(KeepAlive {
+            interval: Duration::ZERO,
+            timeout: Duration::ZERO,
+        }));
+        assert_eq!(tracker.keepalive_action(), KeepAliveAction::Expired);
+    }
+}
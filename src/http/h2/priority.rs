@@ -0,0 +1,629 @@
+//! Stream prioritization
+//!
+//! Two schemes are supported:
+//!
+//! - **RFC 7540 dependency tree**: streams depend on a parent stream and
+//!   share its resources according to their weight. `PriorityTree` keeps
+//!   the tree up to date from HEADERS and PRIORITY frames, and
+//!   `DataScheduler` orders outgoing DATA frames by it.
+//! - **RFC 9218 extensible priorities**: an urgency (0 = highest to
+//!   7 = lowest, default 3) and an incremental flag, signalled end-to-end
+//!   with the `priority` header field and hop-by-hop with PRIORITY_UPDATE
+//!   frames.
+//!
+//! # Example
+//!
+//! ```
+//! use vtest2::http::h2::priority::ExtensiblePriority;
+//!
+//! let priority = ExtensiblePriority::parse("u=1, i");
+//! assert_eq!(priority.urgency, 1);
+//! assert!(priority.incremental);
+//! assert_eq!(priority.to_string(), "u=1, i");
+//!
+//! // Unknown members and out-of-range values are ignored
+//! let priority = ExtensiblePriority::parse("u=9, foo=bar");
+//! assert_eq!(priority, ExtensiblePriority::default());
+//! ```
+
+use super::error::{Error, Result};
+use super::frames::{DataFrame, PrioritySpec};
+use super::stream::StreamId;
+use super::CONNECTION_STREAM_ID;
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Default weight of a stream in the dependency tree (RFC 7540 Section 5.3.5)
+pub const DEFAULT_WEIGHT: u16 = 16;
+
+/// Name of the priority header field
+pub const PRIORITY_HEADER: &str = "priority";
+
+/// Default urgency
+pub const DEFAULT_URGENCY: u8 = 3;
+
+/// Lowest urgency (highest value)
+pub const MAX_URGENCY: u8 = 7;
+
+/// Priority parameters of a request (RFC 9218 Section 4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtensiblePriority {
+    /// Urgency, 0 (highest) to 7 (lowest)
+    pub urgency: u8,
+    /// Whether the response can be processed incrementally
+    pub incremental: bool,
+}
+
+impl Default for ExtensiblePriority {
+    fn default() -> Self {
+        ExtensiblePriority {
+            urgency: DEFAULT_URGENCY,
+            incremental: false,
+        }
+    }
+}
+
+impl ExtensiblePriority {
+    /// Create priority parameters
+    pub fn new(urgency: u8, incremental: bool) -> Self {
+        ExtensiblePriority {
+            urgency: urgency.min(MAX_URGENCY),
+            incremental,
+        }
+    }
+
+    /// Parse a `priority` field value (a Structured Fields dictionary)
+    ///
+    /// Parameters missing or invalid keep their default value, as required
+    /// by RFC 9218 Section 4; unknown members and member parameters are
+    /// ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut priority = ExtensiblePriority::default();
+
+        for member in value.split(',') {
+            // Member parameters (";key=value") carry nothing we use
+            let member = member.split(';').next().unwrap_or("").trim();
+            let (key, value) = match member.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (member, None),
+            };
+
+            match key {
+                "u" => {
+                    if let Some(urgency) = value.and_then(|v| v.parse::<u8>().ok()) {
+                        if urgency <= MAX_URGENCY {
+                            priority.urgency = urgency;
+                        }
+                    }
+                }
+                "i" => match value {
+                    None | Some("?1") => priority.incremental = true,
+                    Some("?0") => priority.incremental = false,
+                    Some(_) => {}
+                },
+                _ => {}
+            }
+        }
+
+        priority
+    }
+}
+
+impl fmt::Display for ExtensiblePriority {
+    /// Serialize as a field value, omitting default parameters
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.urgency != DEFAULT_URGENCY, self.incremental) {
+            (true, true) => write!(f, "u={}, i", self.urgency),
+            (true, false) => write!(f, "u={}", self.urgency),
+            (false, true) => write!(f, "i"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+/// Where a priority signal came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrioritySource {
+    /// `priority` header field of the request
+    Header,
+    /// PRIORITY_UPDATE frame
+    Frame,
+}
+
+/// A priority signal received from the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrioritySignal {
+    /// Prioritized stream
+    pub stream_id: StreamId,
+    /// Signalled parameters
+    pub priority: ExtensiblePriority,
+    /// Header field or frame
+    pub source: PrioritySource,
+}
+
+/// Node of the dependency tree
+#[derive(Debug, Clone)]
+struct PriorityNode {
+    parent: StreamId,
+    weight: u16,
+    children: Vec<StreamId>,
+}
+
+/// Stream dependency tree (RFC 7540 Section 5.3)
+///
+/// The tree is rooted at stream 0. Streams enter the tree with the default
+/// priority (depending on stream 0 with weight 16) and move when a HEADERS
+/// or PRIORITY frame carries a new priority. Weights are effective weights,
+/// 1 to 256 (the wire value plus one).
+#[derive(Debug, Clone)]
+pub struct PriorityTree {
+    nodes: HashMap<StreamId, PriorityNode>,
+}
+
+impl Default for PriorityTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriorityTree {
+    /// Create a tree holding only the root
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            CONNECTION_STREAM_ID,
+            PriorityNode {
+                parent: CONNECTION_STREAM_ID,
+                weight: 0,
+                children: Vec::new(),
+            },
+        );
+        PriorityTree { nodes }
+    }
+
+    /// Check if a stream is in the tree
+    pub fn contains(&self, stream_id: StreamId) -> bool {
+        stream_id != CONNECTION_STREAM_ID && self.nodes.contains_key(&stream_id)
+    }
+
+    /// Get the number of streams in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Check if the tree has no streams
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the parent of a stream
+    pub fn parent(&self, stream_id: StreamId) -> Option<StreamId> {
+        self.node(stream_id).map(|node| node.parent)
+    }
+
+    /// Get the effective weight of a stream (1-256)
+    pub fn weight(&self, stream_id: StreamId) -> Option<u16> {
+        self.node(stream_id).map(|node| node.weight)
+    }
+
+    /// Get the streams depending on a stream (or on stream 0)
+    pub fn children(&self, stream_id: StreamId) -> &[StreamId] {
+        self.nodes
+            .get(&stream_id)
+            .map(|node| node.children.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn node(&self, stream_id: StreamId) -> Option<&PriorityNode> {
+        if stream_id == CONNECTION_STREAM_ID {
+            return None;
+        }
+        self.nodes.get(&stream_id)
+    }
+
+    /// Add a stream with the default priority, unless already present
+    pub fn insert(&mut self, stream_id: StreamId) {
+        if stream_id == CONNECTION_STREAM_ID || self.nodes.contains_key(&stream_id) {
+            return;
+        }
+
+        self.nodes.insert(
+            stream_id,
+            PriorityNode {
+                parent: CONNECTION_STREAM_ID,
+                weight: DEFAULT_WEIGHT,
+                children: Vec::new(),
+            },
+        );
+        self.attach(stream_id, CONNECTION_STREAM_ID);
+    }
+
+    /// Apply a priority specification to a stream (RFC 7540 Section 5.3.3)
+    ///
+    /// The stream is added if needed. A dependency on a stream outside the
+    /// tree gives the default priority. When the new parent depends on the
+    /// stream, the parent first moves to the stream's former parent. An
+    /// exclusive dependency makes the stream the sole child of its parent,
+    /// adopting the parent's former children.
+    ///
+    /// A stream depending on itself is a PROTOCOL_ERROR.
+    pub fn reprioritize(&mut self, stream_id: StreamId, spec: &PrioritySpec) -> Result<()> {
+        if stream_id == CONNECTION_STREAM_ID {
+            return Err(Error::Protocol("Cannot prioritize stream 0".to_string()));
+        }
+        if spec.stream_dependency == stream_id {
+            return Err(Error::Protocol(format!("Stream {} depends on itself", stream_id)));
+        }
+
+        self.insert(stream_id);
+
+        let (mut parent, mut weight, mut exclusive) =
+            (spec.stream_dependency, spec.weight as u16 + 1, spec.exclusive);
+        if !self.nodes.contains_key(&parent) {
+            (parent, weight, exclusive) = (CONNECTION_STREAM_ID, DEFAULT_WEIGHT, false);
+        }
+
+        // Moving below a descendant: the descendant takes our place first
+        if self.is_descendant(parent, stream_id) {
+            let former_parent = self.nodes[&stream_id].parent;
+            self.detach(parent);
+            self.attach(parent, former_parent);
+        }
+
+        self.detach(stream_id);
+
+        if exclusive {
+            let adopted = std::mem::take(&mut self.nodes.get_mut(&parent).unwrap().children);
+            for &child in &adopted {
+                self.nodes.get_mut(&child).unwrap().parent = stream_id;
+            }
+            self.nodes.get_mut(&stream_id).unwrap().children.extend(adopted);
+        }
+
+        self.nodes.get_mut(&stream_id).unwrap().weight = weight;
+        self.attach(stream_id, parent);
+
+        Ok(())
+    }
+
+    /// Remove a stream (RFC 7540 Section 5.3.4)
+    ///
+    /// Its children move to its parent, sharing its weight in proportion
+    /// to their own weights.
+    pub fn remove(&mut self, stream_id: StreamId) {
+        if stream_id == CONNECTION_STREAM_ID {
+            return;
+        }
+        let Some(node) = self.nodes.get(&stream_id).cloned() else {
+            return;
+        };
+
+        self.detach(stream_id);
+        self.nodes.remove(&stream_id);
+
+        let total: u32 = node
+            .children
+            .iter()
+            .map(|child| self.nodes[child].weight as u32)
+            .sum();
+        for child in node.children {
+            let child_node = self.nodes.get_mut(&child).unwrap();
+            let weight = child_node.weight as u32 * node.weight as u32 / total.max(1);
+            child_node.weight = weight.clamp(1, 256) as u16;
+            self.attach(child, node.parent);
+        }
+    }
+
+    /// Compute the share of resources of each ready stream
+    ///
+    /// A stream only gets resources when none of its ancestors is ready
+    /// (RFC 7540 Section 5.3); a share not used by a stream flows to its
+    /// dependents. Shares of all ready streams in the tree sum to 1.
+    pub fn shares(&self, ready: &HashSet<StreamId>) -> HashMap<StreamId, f64> {
+        let mut shares = HashMap::new();
+        self.allocate(CONNECTION_STREAM_ID, 1.0, ready, &mut shares);
+        shares
+    }
+
+    fn allocate(&self, stream_id: StreamId, share: f64, ready: &HashSet<StreamId>, shares: &mut HashMap<StreamId, f64>) {
+        if stream_id != CONNECTION_STREAM_ID && ready.contains(&stream_id) {
+            shares.insert(stream_id, share);
+            return;
+        }
+
+        let active: Vec<StreamId> = self
+            .children(stream_id)
+            .iter()
+            .copied()
+            .filter(|&child| self.has_ready(child, ready))
+            .collect();
+        let total: u32 = active.iter().map(|child| self.nodes[child].weight as u32).sum();
+
+        for child in active {
+            let weight = self.nodes[&child].weight as f64;
+            self.allocate(child, share * weight / total as f64, ready, shares);
+        }
+    }
+
+    fn has_ready(&self, stream_id: StreamId, ready: &HashSet<StreamId>) -> bool {
+        ready.contains(&stream_id)
+            || self
+                .children(stream_id)
+                .iter()
+                .any(|&child| self.has_ready(child, ready))
+    }
+
+    fn is_descendant(&self, stream_id: StreamId, ancestor: StreamId) -> bool {
+        let mut current = stream_id;
+        while current != CONNECTION_STREAM_ID {
+            match self.nodes.get(&current) {
+                Some(node) if node.parent == ancestor => return true,
+                Some(node) => current = node.parent,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    fn detach(&mut self, stream_id: StreamId) {
+        let parent = self.nodes[&stream_id].parent;
+        if let Some(parent) = self.nodes.get_mut(&parent) {
+            parent.children.retain(|&child| child != stream_id);
+        }
+    }
+
+    fn attach(&mut self, stream_id: StreamId, parent: StreamId) {
+        self.nodes.get_mut(&stream_id).unwrap().parent = parent;
+        self.nodes.get_mut(&parent).unwrap().children.push(stream_id);
+    }
+}
+
+/// Body data waiting to be sent on a stream
+#[derive(Debug)]
+struct PendingData {
+    stream_id: StreamId,
+    data: Bytes,
+    end_stream: bool,
+    sent: usize,
+}
+
+/// Scheduler ordering outgoing DATA frames by the dependency tree
+///
+/// Each call to `next` picks the stream furthest behind its share of the
+/// bytes sent so far, so that concurrent bodies interleave in proportion to
+/// their weights and dependents wait for their parents.
+#[derive(Debug, Default)]
+pub struct DataScheduler {
+    pending: Vec<PendingData>,
+}
+
+impl DataScheduler {
+    /// Create an empty scheduler
+    pub fn new() -> Self {
+        DataScheduler { pending: Vec::new() }
+    }
+
+    /// Queue body data for a stream
+    pub fn push(&mut self, stream_id: StreamId, data: Bytes, end_stream: bool) {
+        self.pending.push(PendingData {
+            stream_id,
+            data,
+            end_stream,
+            sent: 0,
+        });
+    }
+
+    /// Check if all data was scheduled
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Get the next DATA frame to send
+    ///
+    /// `window` gives the bytes a stream may send (the minimum of its own
+    /// and the connection window). Frames are at most `max_frame_size`
+    /// bytes. Returns `None` while data remains but every window is closed.
+    pub fn next<F>(&mut self, tree: &PriorityTree, window: F, max_frame_size: usize) -> Option<DataFrame>
+    where
+        F: Fn(StreamId) -> usize,
+    {
+        // A final empty frame needs no window
+        let ready: HashSet<StreamId> = self
+            .pending
+            .iter()
+            .filter(|p| p.data.is_empty() || window(p.stream_id) > 0)
+            .map(|p| p.stream_id)
+            .collect();
+        let shares = tree.shares(&ready);
+
+        let progress = |p: &PendingData| {
+            let share = shares.get(&p.stream_id).copied().unwrap_or(0.0);
+            if share > 0.0 {
+                p.sent as f64 / share
+            } else {
+                f64::INFINITY
+            }
+        };
+
+        let index = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| ready.contains(&p.stream_id))
+            .min_by(|(_, a), (_, b)| {
+                progress(a)
+                    .total_cmp(&progress(b))
+                    .then(a.stream_id.cmp(&b.stream_id))
+            })
+            .map(|(index, _)| index)?;
+
+        let pending = &mut self.pending[index];
+        let len = pending.data.len().min(window(pending.stream_id)).min(max_frame_size);
+        let chunk = pending.data.split_to(len);
+        pending.sent += len;
+
+        let last = pending.data.is_empty();
+        let frame = DataFrame::new(pending.stream_id, chunk, pending.end_stream && last);
+        if last {
+            self.pending.remove(index);
+        }
+
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!(ExtensiblePriority::parse(""), ExtensiblePriority::default());
+        assert_eq!(ExtensiblePriority::parse("u=0"), ExtensiblePriority::new(0, false));
+        assert_eq!(ExtensiblePriority::parse("i"), ExtensiblePriority::new(3, true));
+        assert_eq!(ExtensiblePriority::parse("u=5,i=?1"), ExtensiblePriority::new(5, true));
+        assert_eq!(ExtensiblePriority::parse("i=?0, u=7"), ExtensiblePriority::new(7, false));
+        assert_eq!(ExtensiblePriority::parse("u=2;foo=1, x, i"), ExtensiblePriority::new(2, true));
+
+        // Invalid values keep the defaults
+        assert_eq!(ExtensiblePriority::parse("u=8, i=1"), ExtensiblePriority::default());
+        assert_eq!(ExtensiblePriority::parse("u=high"), ExtensiblePriority::default());
+    }
+
+    fn spec(dependency: StreamId, exclusive: bool, weight: u16) -> PrioritySpec {
+        PrioritySpec::new(dependency, exclusive, (weight - 1) as u8)
+    }
+
+    #[test]
+    fn test_tree_default_priority() {
+        let mut tree = PriorityTree::new();
+        tree.insert(1);
+        assert_eq!(tree.parent(1), Some(0));
+        assert_eq!(tree.weight(1), Some(DEFAULT_WEIGHT));
+
+        // Dependency on a stream outside the tree gives the default priority
+        tree.reprioritize(3, &spec(11, true, 200)).unwrap();
+        assert_eq!(tree.parent(3), Some(0));
+        assert_eq!(tree.weight(3), Some(DEFAULT_WEIGHT));
+        assert_eq!(tree.children(0), &[1, 3]);
+
+        assert!(tree.reprioritize(5, &spec(5, false, 16)).is_err());
+    }
+
+    #[test]
+    fn test_tree_exclusive_dependency() {
+        // RFC 7540 Section 5.3.1, Figure 4
+        let mut tree = PriorityTree::new();
+        tree.insert(1);
+        tree.reprioritize(3, &spec(1, false, 16)).unwrap();
+        tree.reprioritize(5, &spec(1, false, 16)).unwrap();
+        tree.reprioritize(7, &spec(1, true, 32)).unwrap();
+
+        assert_eq!(tree.children(1), &[7]);
+        assert_eq!(tree.children(7), &[3, 5]);
+        assert_eq!(tree.weight(7), Some(32));
+    }
+
+    #[test]
+    fn test_tree_reprioritize_below_descendant() {
+        // RFC 7540 Section 5.3.3, Figure 5: A made dependent on D
+        let (a, b, c, d, e, f) = (1, 3, 5, 7, 9, 11);
+        let mut tree = PriorityTree::new();
+        tree.insert(a);
+        tree.reprioritize(b, &spec(a, false, 16)).unwrap();
+        tree.reprioritize(c, &spec(a, false, 16)).unwrap();
+        tree.reprioritize(d, &spec(c, false, 16)).unwrap();
+        tree.reprioritize(e, &spec(c, false, 16)).unwrap();
+        tree.reprioritize(f, &spec(d, false, 16)).unwrap();
+
+        // Non-exclusive: D moves under A's former parent (the root)
+        let mut moved = tree.clone();
+        moved.reprioritize(a, &spec(d, false, 16)).unwrap();
+        assert_eq!(moved.parent(d), Some(0));
+        assert_eq!(moved.parent(a), Some(d));
+        assert_eq!(moved.children(d), &[f, a]);
+        assert_eq!(moved.children(a), &[b, c]);
+
+        // Exclusive: A also adopts F
+        tree.reprioritize(a, &spec(d, true, 16)).unwrap();
+        assert_eq!(tree.children(d), &[a]);
+        assert_eq!(tree.children(a), &[b, c, f]);
+    }
+
+    #[test]
+    fn test_tree_remove_redistributes_weight() {
+        let mut tree = PriorityTree::new();
+        tree.reprioritize(1, &spec(0, false, 64)).unwrap();
+        tree.reprioritize(3, &spec(1, false, 30)).unwrap();
+        tree.reprioritize(5, &spec(1, false, 10)).unwrap();
+
+        tree.remove(1);
+        assert!(!tree.contains(1));
+        assert_eq!(tree.parent(3), Some(0));
+        assert_eq!(tree.weight(3), Some(48));
+        assert_eq!(tree.weight(5), Some(16));
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_tree_shares() {
+        let mut tree = PriorityTree::new();
+        tree.reprioritize(1, &spec(0, false, 200)).unwrap();
+        tree.reprioritize(3, &spec(0, false, 100)).unwrap();
+        tree.reprioritize(5, &spec(1, false, 16)).unwrap();
+
+        let ready: HashSet<_> = [1, 3, 5].into_iter().collect();
+        let shares = tree.shares(&ready);
+        assert!((shares[&1] - 2.0 / 3.0).abs() < 1e-9);
+        assert!((shares[&3] - 1.0 / 3.0).abs() < 1e-9);
+        assert!(!shares.contains_key(&5));
+
+        // The share of a blocked parent goes to its dependents
+        let ready: HashSet<_> = [3, 5].into_iter().collect();
+        let shares = tree.shares(&ready);
+        assert!((shares[&5] - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_scheduler_orders_by_weight() {
+        let mut tree = PriorityTree::new();
+        tree.reprioritize(1, &spec(0, false, 192)).unwrap();
+        tree.reprioritize(3, &spec(0, false, 64)).unwrap();
+        tree.reprioritize(5, &spec(1, false, 16)).unwrap();
+
+        let mut scheduler = DataScheduler::new();
+        scheduler.push(1, Bytes::from(vec![0; 300]), true);
+        scheduler.push(3, Bytes::from(vec![0; 300]), true);
+        scheduler.push(5, Bytes::from(vec![0; 100]), true);
+
+        let mut order = Vec::new();
+        while let Some(frame) = scheduler.next(&tree, |_| usize::MAX, 100) {
+            order.push((frame.stream_id, frame.end_stream));
+        }
+        assert!(scheduler.is_empty());
+
+        // Stream 1 gets three frames for each of stream 3, and stream 5
+        // only starts once stream 1 is done, inheriting its share
+        let ids: Vec<_> = order.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![1, 3, 1, 1, 5, 3, 3]);
+        assert_eq!(order.iter().filter(|(_, end)| *end).count(), 3);
+
+        // Closed windows hold data back
+        let mut scheduler = DataScheduler::new();
+        scheduler.push(1, Bytes::from_static(b"x"), false);
+        assert!(scheduler.next(&tree, |_| 0, 100).is_none());
+        assert!(!scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_serialize_priority() {
+        assert_eq!(ExtensiblePriority::default().to_string(), "");
+        assert_eq!(ExtensiblePriority::new(0, false).to_string(), "u=0");
+        assert_eq!(ExtensiblePriority::new(3, true).to_string(), "i");
+        assert_eq!(ExtensiblePriority::new(9, true).to_string(), "u=7, i");
+
+        let priority = ExtensiblePriority::new(6, true);
+        assert_eq!(ExtensiblePriority::parse(&priority.to_string()), priority);
+    }
+}
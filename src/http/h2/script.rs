@@ -0,0 +1,1096 @@
+//! HTTP/2 stream scripting
+//!
+//! This module mirrors the C `stream N { ... }` specifications: each stream
+//! runs a list of steps (`txreq`, `rxresp`, `txdata`, `rxwinup`, `txrst`,
+//! `expect`, ...) in its own thread, on a connection shared by all streams.
+//! A dispatcher thread reads frames from the connection, decodes header
+//! blocks in arrival order and routes every frame to the queue of its stream.
+//! Stream 0 acts as the coordinator for connection-level frames.
+//!
+//! Steps send frames as-is, without going through the stream state machine
+//! or flow control of the underlying `H2Client`/`H2Server`, so scripts can
+//! produce any frame sequence. The connection must already be established
+//! (`H2Client::connect` or `H2Server::accept`) before scripts are started.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vtest2::http::h2::{H2Client, ScriptRunner, StreamScript};
+//! use vtest2::http::session::FdSessionOps;
+//! use std::net::TcpStream;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let tcp_stream = TcpStream::connect("127.0.0.1:8080")?;
+//! let mut client = H2Client::new(FdSessionOps::new(tcp_stream))?;
+//! client.connect()?;
+//!
+//! let mut runner = ScriptRunner::new(client);
+//!
+//! // stream 1 { txreq; rxresp; expect resp.status == 200 } -start
+//! let s1 = runner.start(
+//!     StreamScript::new(1)
+//!         .txreq("GET", "/a", &[], "")
+//!         .rxresp()
+//!         .expect("resp.status", "==", "200"),
+//! );
+//!
+//! // stream next { ... } -run
+//! runner.run(StreamScript::next().txreq("GET", "/b", &[], "").rxresp())?;
+//!
+//! s1.wait()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::codec::FrameCodec;
+use super::error::{Error, ErrorCode, Result};
+use super::frames::*;
+use super::hpack::HeaderField;
+use super::settings::Settings;
+use super::stream::StreamId;
+use super::CONNECTION_STREAM_ID;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Default time a step waits for a frame
+pub const DEFAULT_SCRIPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time the dispatcher waits for a frame while holding the connection
+///
+/// Bounds how long a script writing a frame waits for the connection.
+const DISPATCH_POLL_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Connection operations needed to run stream scripts
+///
+/// Implemented by `H2Client` and `H2Server`.
+pub trait ScriptConnection {
+    /// Check if this is the client side of the connection
+    fn is_client(&self) -> bool;
+
+    /// Check whether incoming data is ready to be read
+    fn poll_readable(&self, timeout: Duration) -> Result<bool>;
+
+    /// Read a single frame
+    fn read_frame(&mut self) -> Result<(FrameType, FrameFlags, StreamId, Bytes)>;
+
+    /// Decode a complete header block
+    fn decode_header_block(&mut self, block: &[u8]) -> Result<Vec<HeaderField>>;
+
+    /// HPACK-encode a header list and send it in a HEADERS frame
+    fn write_headers(&mut self, stream_id: StreamId, headers: &[(String, String)], end_stream: bool) -> Result<()>;
+
+    /// Write an encoded frame
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()>;
+}
+
+/// A frame routed to a stream
+#[derive(Debug, Clone)]
+pub struct ScriptFrame {
+    /// Frame type
+    pub frame_type: FrameType,
+    /// Frame flags
+    pub flags: FrameFlags,
+    /// Stream ID
+    pub stream_id: StreamId,
+    /// Raw payload
+    pub payload: Bytes,
+    /// Decoded header fields, set on the frame completing a header block
+    pub headers: Option<Vec<(String, String)>>,
+}
+
+/// Comparison operator for `expect` steps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl ExpectOp {
+    /// Parse an operator (`==`, `!=`, `<`, `<=`, `>`, `>=`)
+    pub fn parse(op: &str) -> Option<Self> {
+        match op {
+            "==" => Some(ExpectOp::Eq),
+            "!=" => Some(ExpectOp::Ne),
+            "<" => Some(ExpectOp::Lt),
+            "<=" => Some(ExpectOp::Le),
+            ">" => Some(ExpectOp::Gt),
+            ">=" => Some(ExpectOp::Ge),
+            _ => None,
+        }
+    }
+
+    /// Get the operator string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpectOp::Eq => "==",
+            ExpectOp::Ne => "!=",
+            ExpectOp::Lt => "<",
+            ExpectOp::Le => "<=",
+            ExpectOp::Gt => ">",
+            ExpectOp::Ge => ">=",
+        }
+    }
+
+    /// Compare two values, numerically if both parse as numbers
+    fn compare(&self, lhs: &str, rhs: &str) -> bool {
+        let ordering = match (lhs.parse::<f64>(), rhs.parse::<f64>()) {
+            (Ok(l), Ok(r)) => l.partial_cmp(&r),
+            _ => Some(lhs.cmp(rhs)),
+        };
+
+        match (self, ordering) {
+            (ExpectOp::Eq, Some(o)) => o.is_eq(),
+            (ExpectOp::Ne, Some(o)) => o.is_ne(),
+            (ExpectOp::Lt, Some(o)) => o.is_lt(),
+            (ExpectOp::Le, Some(o)) => o.is_le(),
+            (ExpectOp::Gt, Some(o)) => o.is_gt(),
+            (ExpectOp::Ge, Some(o)) => o.is_ge(),
+            (ExpectOp::Ne, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A single step of a stream script
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Send a request: HEADERS, then DATA if there is a body (client only)
+    TxReq {
+        method: String,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Bytes,
+    },
+    /// Send a response: HEADERS, then DATA if there is a body (server only)
+    TxResp {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Bytes,
+    },
+    /// Send a DATA frame
+    TxData { data: Bytes, end_stream: bool },
+    /// Send a RST_STREAM frame
+    TxRst(ErrorCode),
+    /// Send a WINDOW_UPDATE frame
+    TxWinup(u32),
+    /// Send a PING frame
+    TxPing { data: [u8; 8], ack: bool },
+    /// Send a SETTINGS frame
+    TxSettings(Settings),
+    /// Send a SETTINGS ACK
+    TxSettingsAck,
+    /// Send a GOAWAY frame
+    TxGoaway {
+        last_stream_id: StreamId,
+        error_code: ErrorCode,
+        debug: Bytes,
+    },
+    /// Receive a request: HEADERS, CONTINUATION and DATA until END_STREAM (server only)
+    RxReq,
+    /// Receive a response: HEADERS, CONTINUATION and DATA until END_STREAM (client only)
+    RxResp,
+    /// Receive a HEADERS frame and its CONTINUATION frames
+    RxHdrs,
+    /// Receive a DATA frame
+    RxData,
+    /// Receive a RST_STREAM frame
+    RxRst,
+    /// Receive a WINDOW_UPDATE frame
+    RxWinup,
+    /// Receive a PING frame
+    RxPing,
+    /// Receive a SETTINGS frame
+    RxSettings,
+    /// Receive a GOAWAY frame
+    RxGoaway,
+    /// Receive a PUSH_PROMISE frame and its CONTINUATION frames
+    RxPush,
+    /// Receive any frame
+    RxFrame,
+    /// Check a variable against a value
+    Expect {
+        var: String,
+        op: String,
+        value: String,
+    },
+    /// Sleep
+    Delay(Duration),
+}
+
+/// Script for a single stream
+///
+/// Built with chained step methods named after the C stream commands.
+#[derive(Debug, Clone)]
+pub struct StreamScript {
+    /// Stream ID, or `None` for the next available stream
+    id: Option<StreamId>,
+    /// Steps to run
+    steps: Vec<Step>,
+}
+
+impl StreamScript {
+    /// Create a script for a stream ID (0 is the connection)
+    pub fn new(id: StreamId) -> Self {
+        StreamScript {
+            id: Some(id),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Create a script for the next available stream (`stream next`)
+    pub fn next() -> Self {
+        StreamScript {
+            id: None,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Get the stream ID, if explicit
+    pub fn id(&self) -> Option<StreamId> {
+        self.id
+    }
+
+    /// Get the steps
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Append a step
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Send a request
+    pub fn txreq(self, method: &str, path: &str, headers: &[(&str, &str)], body: impl Into<Bytes>) -> Self {
+        self.step(Step::TxReq {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: to_owned_headers(headers),
+            body: body.into(),
+        })
+    }
+
+    /// Send a response
+    pub fn txresp(self, status: u16, headers: &[(&str, &str)], body: impl Into<Bytes>) -> Self {
+        self.step(Step::TxResp {
+            status,
+            headers: to_owned_headers(headers),
+            body: body.into(),
+        })
+    }
+
+    /// Send a DATA frame
+    pub fn txdata(self, data: impl Into<Bytes>, end_stream: bool) -> Self {
+        self.step(Step::TxData {
+            data: data.into(),
+            end_stream,
+        })
+    }
+
+    /// Send a RST_STREAM frame
+    pub fn txrst(self, error_code: ErrorCode) -> Self {
+        self.step(Step::TxRst(error_code))
+    }
+
+    /// Send a WINDOW_UPDATE frame
+    pub fn txwinup(self, increment: u32) -> Self {
+        self.step(Step::TxWinup(increment))
+    }
+
+    /// Send a PING frame
+    pub fn txping(self, data: [u8; 8]) -> Self {
+        self.step(Step::TxPing { data, ack: false })
+    }
+
+    /// Send a PING ACK frame
+    pub fn txping_ack(self, data: [u8; 8]) -> Self {
+        self.step(Step::TxPing { data, ack: true })
+    }
+
+    /// Send a SETTINGS frame
+    pub fn txsettings(self, settings: Settings) -> Self {
+        self.step(Step::TxSettings(settings))
+    }
+
+    /// Send a SETTINGS ACK
+    pub fn txsettings_ack(self) -> Self {
+        self.step(Step::TxSettingsAck)
+    }
+
+    /// Send a GOAWAY frame
+    pub fn txgoaway(self, last_stream_id: StreamId, error_code: ErrorCode, debug: &str) -> Self {
+        self.step(Step::TxGoaway {
+            last_stream_id,
+            error_code,
+            debug: Bytes::from(debug.to_string()),
+        })
+    }
+
+    /// Receive a request
+    pub fn rxreq(self) -> Self {
+        self.step(Step::RxReq)
+    }
+
+    /// Receive a response
+    pub fn rxresp(self) -> Self {
+        self.step(Step::RxResp)
+    }
+
+    /// Receive a header block
+    pub fn rxhdrs(self) -> Self {
+        self.step(Step::RxHdrs)
+    }
+
+    /// Receive a DATA frame
+    pub fn rxdata(self) -> Self {
+        self.step(Step::RxData)
+    }
+
+    /// Receive a RST_STREAM frame
+    pub fn rxrst(self) -> Self {
+        self.step(Step::RxRst)
+    }
+
+    /// Receive a WINDOW_UPDATE frame
+    pub fn rxwinup(self) -> Self {
+        self.step(Step::RxWinup)
+    }
+
+    /// Receive a PING frame
+    pub fn rxping(self) -> Self {
+        self.step(Step::RxPing)
+    }
+
+    /// Receive a SETTINGS frame
+    pub fn rxsettings(self) -> Self {
+        self.step(Step::RxSettings)
+    }
+
+    /// Receive a GOAWAY frame
+    pub fn rxgoaway(self) -> Self {
+        self.step(Step::RxGoaway)
+    }
+
+    /// Receive a PUSH_PROMISE frame
+    pub fn rxpush(self) -> Self {
+        self.step(Step::RxPush)
+    }
+
+    /// Receive any frame
+    pub fn rxframe(self) -> Self {
+        self.step(Step::RxFrame)
+    }
+
+    /// Check a variable, e.g. `expect("resp.status", "==", "200")`
+    pub fn expect(self, var: &str, op: &str, value: &str) -> Self {
+        self.step(Step::Expect {
+            var: var.to_string(),
+            op: op.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Sleep for a duration
+    pub fn delay(self, duration: Duration) -> Self {
+        self.step(Step::Delay(duration))
+    }
+}
+
+fn to_owned_headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Frame queues shared between the dispatcher and stream threads
+#[derive(Debug, Default)]
+struct RouterState {
+    /// Received frames per stream
+    queues: HashMap<StreamId, VecDeque<ScriptFrame>>,
+    /// Reason the dispatcher stopped, if it did
+    closed: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Router {
+    state: Mutex<RouterState>,
+    cond: Condvar,
+}
+
+impl Router {
+    fn push(&self, frame: ScriptFrame) {
+        let mut state = self.state.lock().unwrap();
+        state.queues.entry(frame.stream_id).or_default().push_back(frame);
+        self.cond.notify_all();
+    }
+
+    fn close(&self, reason: String) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = Some(reason);
+        self.cond.notify_all();
+    }
+
+    /// Wait for the next frame on a stream
+    fn next_frame(&self, stream_id: StreamId, timeout: Duration) -> Result<ScriptFrame> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(frame) = state.queues.get_mut(&stream_id).and_then(|q| q.pop_front()) {
+                return Ok(frame);
+            }
+
+            if let Some(reason) = &state.closed {
+                return Err(if reason.is_empty() {
+                    Error::ConnectionClosed
+                } else {
+                    Error::Internal(reason.clone())
+                });
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout);
+            }
+
+            state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// Runs stream scripts on a shared connection
+///
+/// Creating the runner starts the dispatcher thread, which routes incoming
+/// frames to the streams until the runner is dropped.
+pub struct ScriptRunner<C: ScriptConnection + Send + 'static> {
+    /// Shared connection
+    conn: Arc<Mutex<C>>,
+    /// Frame router
+    router: Arc<Router>,
+    /// Dispatcher stop flag
+    stop: Arc<AtomicBool>,
+    /// Dispatcher thread
+    dispatcher: Option<JoinHandle<()>>,
+    /// Next stream ID for `stream next`
+    next_stream_id: StreamId,
+    /// Time a step waits for a frame
+    timeout: Duration,
+    /// `:authority` of the requests sent by `txreq`
+    authority: Option<String>,
+    /// Client side of the connection
+    is_client: bool,
+}
+
+impl<C: ScriptConnection + Send + 'static> ScriptRunner<C> {
+    /// Create a runner and start its dispatcher thread
+    pub fn new(conn: C) -> Self {
+        let is_client = conn.is_client();
+        let conn = Arc::new(Mutex::new(conn));
+        let router = Arc::new(Router::default());
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let dispatcher = {
+            let conn = Arc::clone(&conn);
+            let router = Arc::clone(&router);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || dispatch(conn, router, stop))
+        };
+
+        ScriptRunner {
+            conn,
+            router,
+            stop,
+            dispatcher: Some(dispatcher),
+            next_stream_id: if is_client { 1 } else { 2 },
+            timeout: DEFAULT_SCRIPT_TIMEOUT,
+            authority: None,
+            is_client,
+        }
+    }
+
+    /// Set the time a step waits for a frame
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set the `:authority` of the requests sent by `txreq`
+    ///
+    /// Without one, requests carry no `:authority` unless given as a
+    /// header of the step.
+    pub fn set_authority(&mut self, authority: &str) {
+        self.authority = Some(authority.to_string());
+    }
+
+    /// Get the shared connection
+    pub fn connection(&self) -> &Arc<Mutex<C>> {
+        &self.conn
+    }
+
+    /// Start a script in its own thread (`-start`)
+    pub fn start(&mut self, script: StreamScript) -> ScriptHandle {
+        let stream_id = match script.id {
+            Some(id) => id,
+            None => self.next_stream_id,
+        };
+        if stream_id != CONNECTION_STREAM_ID && stream_id >= self.next_stream_id {
+            self.next_stream_id = stream_id + 2;
+        }
+
+        let mut ctx = StreamContext {
+            stream_id,
+            conn: Arc::clone(&self.conn),
+            router: Arc::clone(&self.router),
+            timeout: self.timeout,
+            authority: self.authority.clone(),
+            is_client: self.is_client,
+            vars: StreamVars::default(),
+        };
+
+        let thread = thread::spawn(move || {
+            for (index, step) in script.steps.iter().enumerate() {
+                ctx.execute(step).map_err(|e| {
+                    let context = format!("stream {} step {} ({:?})", stream_id, index, step);
+                    match e {
+                        Error::ExpectFailed(reason) => Error::ExpectFailed(format!("{}: {}", context, reason)),
+                        e => Error::Internal(format!("{}: {}", context, e)),
+                    }
+                })?;
+            }
+            Ok(())
+        });
+
+        ScriptHandle { stream_id, thread }
+    }
+
+    /// Run a script to completion (`-run`)
+    pub fn run(&mut self, script: StreamScript) -> Result<()> {
+        self.start(script).wait()
+    }
+
+    /// Stop the dispatcher and return the connection
+    ///
+    /// Fails if scripts are still holding the connection.
+    pub fn into_inner(mut self) -> Result<C> {
+        self.shutdown();
+        let conn = Arc::clone(&self.conn);
+        drop(self);
+
+        Arc::try_unwrap(conn)
+            .map(|mutex| mutex.into_inner().unwrap())
+            .map_err(|_| Error::Internal("Stream scripts still running".to_string()))
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(dispatcher) = self.dispatcher.take() {
+            let _ = dispatcher.join();
+        }
+    }
+}
+
+impl<C: ScriptConnection + Send + 'static> Drop for ScriptRunner<C> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Handle to a running stream script
+pub struct ScriptHandle {
+    stream_id: StreamId,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl ScriptHandle {
+    /// Get the stream ID the script runs on
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Wait for the script to finish (`-wait`)
+    pub fn wait(self) -> Result<()> {
+        self.thread
+            .join()
+            .map_err(|_| Error::Internal(format!("stream {} panicked", self.stream_id)))?
+    }
+}
+
+/// Dispatcher loop: read frames and route them to their streams
+fn dispatch<C: ScriptConnection>(conn: Arc<Mutex<C>>, router: Arc<Router>, stop: Arc<AtomicBool>) {
+    // Header blocks awaiting CONTINUATION, per stream
+    let mut pending_blocks: HashMap<StreamId, Vec<u8>> = HashMap::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        let result = {
+            let mut conn = conn.lock().unwrap();
+            match conn.poll_readable(DISPATCH_POLL_TIMEOUT) {
+                Ok(true) => conn
+                    .read_frame()
+                    .and_then(|frame| decode_frame(&mut *conn, &mut pending_blocks, frame))
+                    .map(Some),
+                Ok(false) => Ok(None),
+                Err(e) => Err(e),
+            }
+        };
+
+        match result {
+            Ok(Some(frame)) => router.push(frame),
+            // Let the scripts waiting to write take the connection
+            Ok(None) => thread::yield_now(),
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                router.close(String::new());
+                return;
+            }
+            Err(e) => {
+                router.close(e.to_string());
+                return;
+            }
+        }
+    }
+
+    router.close("Script runner stopped".to_string());
+}
+
+/// Decode the header block of a frame, in connection order
+fn decode_frame<C: ScriptConnection>(
+    conn: &mut C,
+    pending_blocks: &mut HashMap<StreamId, Vec<u8>>,
+    (frame_type, flags, stream_id, payload): (FrameType, FrameFlags, StreamId, Bytes),
+) -> Result<ScriptFrame> {
+    let fragment = match frame_type {
+        FrameType::Headers => Some(FrameCodec::decode_headers_frame(stream_id, flags, payload.clone())?.header_block),
+        FrameType::PushPromise => Some(FrameCodec::decode_push_promise_frame(stream_id, flags, payload.clone())?.header_block),
+        FrameType::Continuation => Some(payload.clone()),
+        _ => None,
+    };
+
+    let mut headers = None;
+    if let Some(fragment) = fragment {
+        let block = pending_blocks.entry(stream_id).or_default();
+        block.extend_from_slice(&fragment);
+
+        if flags.is_end_headers() {
+            let block = pending_blocks.remove(&stream_id).unwrap_or_default();
+            let fields = conn.decode_header_block(&block)?;
+            headers = Some(
+                fields
+                    .into_iter()
+                    .map(|field| {
+                        (
+                            String::from_utf8_lossy(&field.name).to_string(),
+                            String::from_utf8_lossy(&field.value).to_string(),
+                        )
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    Ok(ScriptFrame {
+        frame_type,
+        flags,
+        stream_id,
+        payload,
+        headers,
+    })
+}
+
+/// Per-stream variables, resolved by `expect`
+#[derive(Debug, Default)]
+struct StreamVars {
+    /// Last frame received
+    frame: Option<ScriptFrame>,
+    /// Headers of the message sent
+    tx_headers: Vec<(String, String)>,
+    /// Body of the message sent
+    tx_body: Vec<u8>,
+    /// Headers of the message received, the final (non-1xx) block
+    rx_headers: Vec<(String, String)>,
+    /// Trailers of the message received
+    rx_trailers: Vec<(String, String)>,
+    /// Body of the message received
+    rx_body: Vec<u8>,
+}
+
+/// Execution context of a stream script
+struct StreamContext<C: ScriptConnection> {
+    stream_id: StreamId,
+    conn: Arc<Mutex<C>>,
+    router: Arc<Router>,
+    timeout: Duration,
+    authority: Option<String>,
+    is_client: bool,
+    vars: StreamVars,
+}
+
+impl<C: ScriptConnection> StreamContext<C> {
+    fn execute(&mut self, step: &Step) -> Result<()> {
+        match step {
+            Step::TxReq { method, path, headers, body } => {
+                if !self.is_client {
+                    return Err(Error::Protocol("txreq can only be used by a client".to_string()));
+                }
+                // Same defaults as the C txreq: plain http
+                let mut pseudo = vec![
+                    (":method", method.as_str()),
+                    (":path", path.as_str()),
+                    (":scheme", "http"),
+                ];
+                let authority = self.authority.clone();
+                if let Some(authority) = &authority {
+                    pseudo.push((":authority", authority.as_str()));
+                }
+                self.send_message(&pseudo, headers, body.clone())
+            }
+            Step::TxResp { status, headers, body } => {
+                if self.is_client {
+                    return Err(Error::Protocol("txresp can only be used by a server".to_string()));
+                }
+                let status = status.to_string();
+                self.send_message(&[(":status", status.as_str())], headers, body.clone())
+            }
+            Step::TxData { data, end_stream } => {
+                self.vars.tx_body.extend_from_slice(data);
+                let frame = DataFrame::new(self.stream_id, data.clone(), *end_stream);
+                self.write(&FrameCodec::encode_data_frame(&frame))
+            }
+            Step::TxRst(error_code) => {
+                let frame = RstStreamFrame {
+                    stream_id: self.stream_id,
+                    error_code: *error_code,
+                };
+                self.write(&FrameCodec::encode_rst_stream_frame(&frame))
+            }
+            Step::TxWinup(increment) => {
+                let frame = WindowUpdateFrame::new(self.stream_id, *increment);
+                self.write(&FrameCodec::encode_window_update_frame(&frame))
+            }
+            Step::TxPing { data, ack } => {
+                let frame = if *ack { PingFrame::ack(*data) } else { PingFrame::new(*data) };
+                self.write(&FrameCodec::encode_ping_frame(&frame))
+            }
+            Step::TxSettings(settings) => {
+                let frame = SettingsFrame::new(settings.clone());
+                self.write(&FrameCodec::encode_settings_frame(&frame))
+            }
+            Step::TxSettingsAck => self.write(&FrameCodec::encode_settings_frame(&SettingsFrame::ack())),
+            Step::TxGoaway { last_stream_id, error_code, debug } => {
+                let frame = GoawayFrame::new(*last_stream_id, *error_code, debug.clone());
+                self.write(&FrameCodec::encode_goaway_frame(&frame))
+            }
+            Step::RxReq => {
+                if self.is_client {
+                    return Err(Error::Protocol("rxreq can only be used by a server".to_string()));
+                }
+                self.receive_message()
+            }
+            Step::RxResp => {
+                if !self.is_client {
+                    return Err(Error::Protocol("rxresp can only be used by a client".to_string()));
+                }
+                self.receive_message()
+            }
+            Step::RxHdrs => {
+                self.receive(FrameType::Headers)?;
+                self.receive_continuations()
+            }
+            Step::RxPush => {
+                self.receive(FrameType::PushPromise)?;
+                self.receive_continuations()
+            }
+            Step::RxData => self.receive(FrameType::Data).map(|_| ()),
+            Step::RxRst => self.receive(FrameType::RstStream).map(|_| ()),
+            Step::RxWinup => self.receive(FrameType::WindowUpdate).map(|_| ()),
+            Step::RxPing => self.receive(FrameType::Ping).map(|_| ()),
+            Step::RxSettings => self.receive(FrameType::Settings).map(|_| ()),
+            Step::RxGoaway => self.receive(FrameType::Goaway).map(|_| ()),
+            Step::RxFrame => self.next_frame().map(|_| ()),
+            Step::Expect { var, op, value } => self.expect(var, op, value),
+            Step::Delay(duration) => {
+                thread::sleep(*duration);
+                Ok(())
+            }
+        }
+    }
+
+    fn write(&self, frame: &[u8]) -> Result<()> {
+        self.conn.lock().unwrap().write_frame(frame)
+    }
+
+    /// Send HEADERS (and DATA if there is a body)
+    fn send_message(&mut self, pseudo: &[(&str, &str)], headers: &[(String, String)], body: Bytes) -> Result<()> {
+        // Explicit headers override the default pseudo-headers
+        let mut fields: Vec<(String, String)> = pseudo
+            .iter()
+            .filter(|(name, _)| !headers.iter().any(|(n, _)| n == name))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        fields.extend(headers.iter().cloned());
+
+        let has_body = !body.is_empty();
+        {
+            let mut conn = self.conn.lock().unwrap();
+            conn.write_headers(self.stream_id, &fields, !has_body)?;
+            if has_body {
+                let frame = DataFrame::new(self.stream_id, body.clone(), true);
+                conn.write_frame(&FrameCodec::encode_data_frame(&frame))?;
+            }
+        }
+
+        self.vars.tx_headers = fields;
+        self.vars.tx_body = body.to_vec();
+        Ok(())
+    }
+
+    /// Wait for the next frame on this stream and record it
+    fn next_frame(&mut self) -> Result<ScriptFrame> {
+        let frame = self.router.next_frame(self.stream_id, self.timeout)?;
+
+        if let Some(headers) = &frame.headers {
+            if frame.frame_type != FrameType::PushPromise {
+                // Interim responses are replaced, later blocks are trailers
+                let interim = resolve_message(&self.vars.rx_headers, &[], "status")
+                    .is_some_and(|status| status.starts_with('1'));
+                if self.vars.rx_headers.is_empty() || interim {
+                    self.vars.rx_headers = headers.clone();
+                } else {
+                    self.vars.rx_trailers = headers.clone();
+                }
+            }
+        }
+        if frame.frame_type == FrameType::Data {
+            let data = FrameCodec::decode_data_frame(frame.stream_id, frame.flags, frame.payload.clone())?;
+            self.vars.rx_body.extend_from_slice(&data.data);
+        }
+
+        self.vars.frame = Some(frame.clone());
+        Ok(frame)
+    }
+
+    /// Receive a frame of the expected type
+    fn receive(&mut self, expected: FrameType) -> Result<ScriptFrame> {
+        let frame = self.next_frame()?;
+        if frame.frame_type != expected {
+            return Err(Error::Protocol(format!(
+                "Expected {} frame, got {}",
+                expected, frame.frame_type
+            )));
+        }
+        Ok(frame)
+    }
+
+    /// Receive CONTINUATION frames until the header block is complete
+    fn receive_continuations(&mut self) -> Result<()> {
+        let mut complete = self.vars.frame.as_ref().is_some_and(|f| f.flags.is_end_headers());
+        while !complete {
+            complete = self.receive(FrameType::Continuation)?.flags.is_end_headers();
+        }
+        Ok(())
+    }
+
+    /// Receive a complete message (rxreq/rxresp)
+    fn receive_message(&mut self) -> Result<()> {
+        self.vars.rx_headers.clear();
+        self.vars.rx_trailers.clear();
+        self.vars.rx_body.clear();
+
+        // WINDOW_UPDATE frames may precede the message
+        let mut frame = self.next_frame()?;
+        while frame.frame_type == FrameType::WindowUpdate {
+            frame = self.next_frame()?;
+        }
+        if frame.frame_type != FrameType::Headers {
+            return Err(Error::Protocol(format!("Expected HEADERS frame, got {}", frame.frame_type)));
+        }
+
+        let mut end_stream = frame.flags.is_end_stream();
+        self.receive_continuations()?;
+
+        while !end_stream {
+            let frame = self.next_frame()?;
+            match frame.frame_type {
+                FrameType::Data | FrameType::Headers => {}
+                FrameType::Continuation => continue,
+                other => {
+                    return Err(Error::Protocol(format!("Expected DATA frame, got {}", other)));
+                }
+            }
+            end_stream = frame.flags.is_end_stream();
+        }
+
+        Ok(())
+    }
+
+    fn expect(&self, var: &str, op: &str, value: &str) -> Result<()> {
+        let op = ExpectOp::parse(op)
+            .ok_or_else(|| Error::ExpectFailed(format!("Unknown operator {:?}", op)))?;
+        let actual = self.resolve(var).unwrap_or_else(|| "<undef>".to_string());
+
+        if op.compare(&actual, value) {
+            Ok(())
+        } else {
+            Err(Error::ExpectFailed(format!(
+                "{} ({}) {} {}",
+                var,
+                actual,
+                op.as_str(),
+                value
+            )))
+        }
+    }
+
+    /// Resolve a variable, following the names of the C stream variables
+    fn resolve(&self, var: &str) -> Option<String> {
+        // The message received is the request for a server, the response for a client
+        let (rx, tx) = if self.is_client { ("resp", "req") } else { ("req", "resp") };
+
+        if let Some((prefix, rest)) = var.split_once('.') {
+            if prefix == rx {
+                if let Some(name) = rest.strip_prefix("trailer.") {
+                    return resolve_message(&self.vars.rx_trailers, &[], &format!("http.{}", name));
+                }
+                return resolve_message(&self.vars.rx_headers, &self.vars.rx_body, rest);
+            }
+            if prefix == tx {
+                return resolve_message(&self.vars.tx_headers, &self.vars.tx_body, rest);
+            }
+        }
+
+        if var == "stream.id" {
+            return Some(self.stream_id.to_string());
+        }
+
+        resolve_frame(self.vars.frame.as_ref()?, var)
+    }
+}
+
+/// Resolve `req.*`/`resp.*` variables
+fn resolve_message(headers: &[(String, String)], body: &[u8], var: &str) -> Option<String> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+    };
+
+    match var {
+        "status" => header(":status"),
+        "method" => header(":method"),
+        "url" => header(":path"),
+        "scheme" => header(":scheme"),
+        "authority" => header(":authority"),
+        "body" => Some(String::from_utf8_lossy(body).to_string()),
+        "bodylen" => Some(body.len().to_string()),
+        _ => var.strip_prefix("http.").and_then(header),
+    }
+}
+
+/// Resolve variables of the last frame received
+fn resolve_frame(frame: &ScriptFrame, var: &str) -> Option<String> {
+    let payload = &frame.payload;
+    let be32 = |offset: usize| {
+        payload
+            .get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let bool_str = |b: bool| if b { "true" } else { "false" }.to_string();
+
+    match var {
+        "frame.type" => Some(frame.frame_type.as_u8().to_string()),
+        "frame.size" => Some(payload.len().to_string()),
+        "frame.stream" => Some(frame.stream_id.to_string()),
+        "frame.flags" => Some(frame.flags.as_u8().to_string()),
+        "frame.data" => Some(String::from_utf8_lossy(payload).to_string()),
+        "frame.padding" if frame.flags.is_padded() => payload.first().map(|p| p.to_string()),
+        "ping.data" if frame.frame_type == FrameType::Ping => {
+            Some(String::from_utf8_lossy(payload).to_string())
+        }
+        "ping.ack" if frame.frame_type == FrameType::Ping => Some(bool_str(frame.flags.is_ack())),
+        "winup.size" if frame.frame_type == FrameType::WindowUpdate => {
+            be32(0).map(|size| (size & 0x7FFFFFFF).to_string())
+        }
+        "rst.err" if frame.frame_type == FrameType::RstStream => be32(0).map(|err| err.to_string()),
+        "goaway.laststream" if frame.frame_type == FrameType::Goaway => {
+            be32(0).map(|id| (id & 0x7FFFFFFF).to_string())
+        }
+        "goaway.err" if frame.frame_type == FrameType::Goaway => be32(4).map(|err| err.to_string()),
+        "goaway.debug" if frame.frame_type == FrameType::Goaway => {
+            payload.get(8..).map(|d| String::from_utf8_lossy(d).to_string())
+        }
+        "push.id" if frame.frame_type == FrameType::PushPromise => {
+            let offset = if frame.flags.is_padded() { 1 } else { 0 };
+            be32(offset).map(|id| (id & 0x7FFFFFFF).to_string())
+        }
+        "settings.ack" if frame.frame_type == FrameType::Settings => Some(bool_str(frame.flags.is_ack())),
+        _ if frame.frame_type == FrameType::Settings => {
+            let id = match var.strip_prefix("settings.")? {
+                "hdrtbl" => 0x1,
+                "push" => 0x2,
+                "maxstreams" => 0x3,
+                "winsize" => 0x4,
+                "framesize" => 0x5,
+                "hdrsize" => 0x6,
+                _ => return None,
+            };
+            payload
+                .chunks_exact(6)
+                .rfind(|entry| u16::from_be_bytes([entry[0], entry[1]]) == id)
+                .map(|entry| u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]).to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expect_op_compare() {
+        assert!(ExpectOp::Eq.compare("200", "200.0"));
+        assert!(ExpectOp::Lt.compare("9", "10"));
+        assert!(ExpectOp::Ne.compare("abc", "abd"));
+        assert!(ExpectOp::Ge.compare("b", "a"));
+        assert_eq!(ExpectOp::parse("=~"), None);
+    }
+
+    #[test]
+    fn test_script_builder() {
+        let script = StreamScript::new(1)
+            .txreq("GET", "/", &[("x-test", "1")], "")
+            .rxresp()
+            .expect("resp.status", "==", "200");
+
+        assert_eq!(script.id(), Some(1));
+        assert_eq!(script.steps().len(), 3);
+        assert!(matches!(script.steps()[1], Step::RxResp));
+        assert_eq!(StreamScript::next().id(), None);
+    }
+
+    #[test]
+    fn test_resolve_frame_vars() {
+        let frame = ScriptFrame {
+            frame_type: FrameType::RstStream,
+            flags: FrameFlags::empty(),
+            stream_id: 3,
+            payload: Bytes::from_static(&[0, 0, 0, 8]),
+            headers: None,
+        };
+        assert_eq!(resolve_frame(&frame, "rst.err").as_deref(), Some("8"));
+        assert_eq!(resolve_frame(&frame, "frame.stream").as_deref(), Some("3"));
+        assert_eq!(resolve_frame(&frame, "winup.size"), None);
+
+        let headers = vec![(":status".to_string(), "404".to_string())];
+        assert_eq!(resolve_message(&headers, b"nope", "status").as_deref(), Some("404"));
+        assert_eq!(resolve_message(&headers, b"nope", "bodylen").as_deref(), Some("4"));
+    }
+}
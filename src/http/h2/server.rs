@@ -5,15 +5,24 @@
 
 use super::codec::FrameCodec;
 use super::error::{Error, ErrorCode, Result};
-use super::flow_control::ConnectionFlowControl;
+use super::flow_control::{ConnectionFlowControl, FlowControlPolicy};
 use super::frames::*;
-use super::settings::{Settings, SettingsBuilder};
-use super::stream::{StreamId, StreamManager};
-use super::{CONNECTION_PREFACE, CONNECTION_STREAM_ID};
-use crate::http::{SessionOps, HttpSession};
-use bytes::Bytes;
+use super::ping::{KeepAlive, KeepAliveAction, PingTracker};
+use super::hpack::{HeaderField, HpackDecoder};
+use super::script::ScriptConnection;
+use super::settings::{Settings, SettingsBuilder, SettingsTracker};
+use super::priority::{DataScheduler, ExtensiblePriority, PrioritySignal, PrioritySource, PRIORITY_HEADER};
+use super::stream::{StreamId, StreamManager, StreamState};
+use super::validate::{FrameValidator, ViolationScope};
+use super::h2c::{self, ServerConnection};
+use super::tunnel::{H2Tunnel, TunnelEndpoint};
+use super::{CONNECTION_PREFACE, CONNECTION_STREAM_ID, MAX_STREAM_ID};
+use crate::http::session::{PollEvents, PrefixedSession};
+use crate::http::{HttpRequest, HttpResponse, HttpServer, HttpSession, SessionOps, Status};
+use bytes::{Bytes, BytesMut};
 use hpack::Encoder as HpackEncoder;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 
 /// HTTP/2 server
 ///
@@ -29,11 +38,41 @@ pub struct H2Server<S: SessionOps> {
     /// HPACK encoder
     hpack_encoder: HpackEncoder<'static>,
     /// HPACK decoder
-    hpack_decoder: hpack::Decoder<'static>,
-    /// Server settings
+    hpack_decoder: HpackDecoder,
+    /// Receive window replenishment policy
+    flow_control_policy: FlowControlPolicy,
+    /// Server settings in effect, as acknowledged by the client
     local_settings: Settings,
+    /// Settings sent with the connection preface
+    initial_settings: Settings,
     /// Remote (client) settings
     remote_settings: Settings,
+    /// Sent SETTINGS frames awaiting the client's ACK
+    settings_tracker: SettingsTracker,
+    /// Sent PING frames awaiting their ACK, and keepalive state
+    ping_tracker: PingTracker,
+    /// Frames received while blocked on flow control, not yet processed
+    pending_frames: VecDeque<(FrameType, FrameFlags, StreamId, Bytes)>,
+    /// Requests whose body is still being received, by stream
+    partial_requests: HashMap<StreamId, H2Request>,
+    /// HEADERS awaiting CONTINUATION frames (stream, END_STREAM)
+    continued_headers: Option<(StreamId, bool)>,
+    /// Header block of a reset stream, decoded and discarded
+    discarded_block: Vec<u8>,
+    /// Validator for received frames
+    validator: FrameValidator,
+    /// PRIORITY_UPDATE received before its stream opened
+    pending_priorities: HashMap<StreamId, ExtensiblePriority>,
+    /// Priority signals received, not yet collected
+    priority_signals: VecDeque<PrioritySignal>,
+    /// Request received over HTTP/1.1 before an h2c upgrade (stream 1)
+    upgraded_request: Option<H2Request>,
+    /// CONNECT streams, whose DATA is kept for `recv_stream_data`
+    tunnels: HashSet<StreamId>,
+    /// Last GOAWAY received
+    goaway: Option<GoawayFrame>,
+    /// Last stream ID announced by the final GOAWAY of a graceful shutdown
+    goaway_sent: Option<StreamId>,
     /// Connection established
     connected: bool,
 }
@@ -68,7 +107,7 @@ impl<S: SessionOps> H2Server<S> {
         }
 
         // Send initial SETTINGS frame
-        let settings_frame = SettingsFrame::new(self.local_settings.clone());
+        let settings_frame = SettingsFrame::new(self.initial_settings.clone());
         self.send_settings(&settings_frame)?;
 
         // Wait for client SETTINGS
@@ -79,9 +118,14 @@ impl<S: SessionOps> H2Server<S> {
     }
 
     /// Send a SETTINGS frame
+    ///
+    /// The values take effect locally once the client acknowledges them.
     pub fn send_settings(&mut self, frame: &SettingsFrame) -> Result<()> {
         let encoded = FrameCodec::encode_settings_frame(frame);
         self.session.write(&encoded)?;
+        if !frame.ack {
+            self.settings_tracker.sent(frame.settings.clone());
+        }
         Ok(())
     }
 
@@ -102,14 +146,22 @@ impl<S: SessionOps> H2Server<S> {
             )));
         }
 
+        self.handle_settings(flags, stream_id, &payload)
+    }
+
+    /// Apply a received SETTINGS frame and acknowledge it
+    fn handle_settings(&mut self, flags: FrameFlags, stream_id: StreamId, payload: &[u8]) -> Result<()> {
         if stream_id != CONNECTION_STREAM_ID {
             return Err(Error::Protocol(
                 "SETTINGS frame must have stream ID 0".to_string(),
             ));
         }
 
-        // If it's an ACK, we're done
+        // An ACK applies our oldest outstanding SETTINGS
         if flags.is_ack() {
+            if let Some(settings) = self.settings_tracker.acknowledge() {
+                self.apply_local_settings(&settings)?;
+            }
             return Ok(());
         }
 
@@ -142,104 +194,156 @@ impl<S: SessionOps> H2Server<S> {
             pos += 6;
         }
 
-        // Apply settings
+        self.apply_remote_settings(&settings)?;
+
+        // Send SETTINGS ACK
+        self.send_settings_ack()?;
+
+        Ok(())
+    }
+
+    /// Apply our own settings once the client acknowledged them
+    fn apply_local_settings(&mut self, settings: &Settings) -> Result<()> {
+        self.local_settings.merge(settings);
+
+        if let Some(size) = settings.header_table_size {
+            self.hpack_decoder.set_max_table_size(size as usize);
+        }
+        if let Some(size) = settings.max_header_list_size {
+            self.hpack_decoder.set_max_header_list_size(Some(size as usize));
+        }
+        if let Some(size) = settings.initial_window_size {
+            self.stream_manager.update_initial_recv_window_size(size)?;
+        }
+        if let Some(size) = settings.max_frame_size {
+            self.validator.set_max_frame_size(size);
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the next frame while running the SETTINGS and keepalive timers
+    ///
+    /// Fails with SETTINGS_TIMEOUT if a SETTINGS ACK is overdue, and with
+    /// `Error::Timeout` if a keepalive PING goes unacknowledged. Sends a
+    /// keepalive PING whenever the connection has been idle long enough.
+    fn wait_for_frame(&mut self) -> Result<()> {
+        loop {
+            let wait = [self.settings_tracker.remaining(), self.ping_tracker.keepalive_remaining()]
+                .into_iter()
+                .flatten()
+                .min();
+            let Some(wait) = wait else {
+                return Ok(());
+            };
+            if !wait.is_zero() && self.session.get_ref().poll(PollEvents::Read, Some(wait))? {
+                return Ok(());
+            }
+
+            if self.settings_tracker.is_expired() {
+                let last_stream_id = self.stream_manager.last_remote_stream_id();
+                self.send_goaway(last_stream_id, ErrorCode::SettingsTimeout, "SETTINGS not acknowledged")?;
+                return Err(Error::SettingsTimeout);
+            }
+            match self.ping_tracker.keepalive_action() {
+                KeepAliveAction::Ping => {
+                    let data = self.ping_tracker.next_payload();
+                    self.send_ping(data)?;
+                }
+                KeepAliveAction::Expired => return Err(Error::Timeout),
+                KeepAliveAction::Wait => {}
+            }
+        }
+    }
+
+    /// Apply settings received from the client
+    fn apply_remote_settings(&mut self, settings: &Settings) -> Result<()> {
         settings.validate()?;
-        self.remote_settings.merge(&settings);
+        self.remote_settings.merge(settings);
 
         // Update stream manager max concurrent streams
         self.stream_manager
-            .set_max_concurrent_streams(settings.max_concurrent_streams);
+            .set_max_concurrent_streams(self.remote_settings.max_concurrent_streams);
 
         // Update flow control if initial window size changed
         if let Some(new_size) = settings.initial_window_size {
-            // Update all existing streams
-            for stream_id in self.stream_manager.stream_ids() {
-                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
-                    stream
-                        .flow_control_mut()
-                        .send_window_mut()
-                        .update_initial_size(new_size)?;
-                }
-            }
+            self.stream_manager.update_initial_send_window_size(new_size)?;
         }
 
-        // Send SETTINGS ACK
-        self.send_settings_ack()?;
-
         Ok(())
     }
 
     /// Receive a request
+    ///
+    /// Returns the first request to complete: requests arriving
+    /// interleaved are assembled per stream, and the others are returned
+    /// by later calls. A request whose stream is reset is dropped. After an
+    /// h2c upgrade, the first request returned is the upgrade request on
+    /// stream 1.
     pub fn recv_request(&mut self) -> Result<H2Request> {
+        if let Some(request) = self.upgraded_request.take() {
+            return Ok(request);
+        }
+
         // Ensure connection is established
         if !self.connected {
             self.accept()?;
         }
 
-        let mut request = H2Request {
-            stream_id: 0,
-            method: String::new(),
-            path: String::new(),
-            scheme: String::new(),
-            authority: String::new(),
-            headers: HashMap::new(),
-            body: Bytes::new(),
-        };
+        loop {
+            let (frame_type, flags, recv_stream_id, payload) = match self.pending_frames.pop_front() {
+                Some(frame) => frame,
+                None => self.recv_frame()?,
+            };
 
-        let mut headers_received = false;
-        let mut stream_ended = false;
+            if !self.check_frame(frame_type, flags, recv_stream_id, &payload)? {
+                continue;
+            }
 
-        while !stream_ended {
-            let (frame_type, flags, recv_stream_id, payload) = self.recv_frame()?;
+            // Tunnel data is kept on its stream
+            if self.tunnels.contains(&recv_stream_id) && matches!(frame_type, FrameType::Data | FrameType::RstStream) {
+                self.receive_tunnel_frame(frame_type, flags, recv_stream_id, payload)?;
+                continue;
+            }
 
             match frame_type {
                 FrameType::Headers => {
-                    if headers_received {
-                        // Trailers - ignore for now
-                        continue;
+                    let headers_frame = FrameCodec::decode_headers_frame(recv_stream_id, flags, payload)?;
+                    self.stream_manager
+                        .get_or_create_stream(recv_stream_id)?
+                        .receive_headers(&headers_frame)?;
+                    if let Some(priority) = &headers_frame.priority {
+                        self.stream_manager.reprioritize(recv_stream_id, priority)?;
                     }
 
-                    request.stream_id = recv_stream_id;
-
-                    // Create stream if needed
-                    if self.stream_manager.get_stream(recv_stream_id).is_none() {
-                        self.stream_manager.get_or_create_stream(recv_stream_id)?;
+                    // The header block is decoded once complete
+                    if !headers_frame.end_headers {
+                        self.continued_headers = Some((recv_stream_id, headers_frame.end_stream));
+                        continue;
                     }
-
-                    // Update stream state
-                    let headers_frame = HeadersFrame::new(recv_stream_id, payload.clone(), flags.is_end_stream(), true);
-                    if let Some(stream) = self.stream_manager.get_stream_mut(recv_stream_id) {
-                        stream.receive_headers(&headers_frame)?;
+                    if let Some(request) = self.receive_header_block(recv_stream_id, headers_frame.end_stream)? {
+                        return Ok(request);
                     }
+                }
+                FrameType::Continuation => {
+                    let Some((stream_id, end_stream)) = self.continued_headers else {
+                        self.discard_header_fragment(frame_type, flags, recv_stream_id, payload)?;
+                        continue;
+                    };
 
-                    // Decode headers with HPACK
-                    let decoded = self.hpack_decoder
-                        .decode(&payload)
-                        .map_err(|e| Error::Compression(format!("HPACK decode error: {:?}", e)))?;
-
-                    for (name, value) in decoded {
-                        let name_str = String::from_utf8_lossy(&name).to_string();
-                        let value_str = String::from_utf8_lossy(&value).to_string();
-
-                        match name_str.as_str() {
-                            ":method" => request.method = value_str,
-                            ":path" => request.path = value_str,
-                            ":scheme" => request.scheme = value_str,
-                            ":authority" => request.authority = value_str,
-                            _ => {
-                                request.headers.insert(name_str, value_str);
-                            }
-                        }
+                    let frame = FrameCodec::decode_continuation_frame(recv_stream_id, flags, payload)?;
+                    if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                        stream.receive_continuation(&frame)?;
                     }
-
-                    headers_received = true;
-
-                    if flags.is_end_stream() {
-                        stream_ended = true;
+                    if frame.end_headers {
+                        self.continued_headers = None;
+                        if let Some(request) = self.receive_header_block(stream_id, end_stream)? {
+                            return Ok(request);
+                        }
                     }
                 }
                 FrameType::Data => {
-                    if !headers_received {
+                    if !self.partial_requests.contains_key(&recv_stream_id) {
                         return Err(Error::Protocol(
                             "DATA frame before HEADERS".to_string(),
                         ));
@@ -248,74 +352,168 @@ impl<S: SessionOps> H2Server<S> {
                     // Update flow control
                     self.flow_control.consume_recv_window(payload.len());
 
-                    let data_frame = DataFrame::new(recv_stream_id, payload.clone(), flags.is_end_stream());
+                    let data_frame = FrameCodec::decode_data_frame(recv_stream_id, flags, payload)?;
                     if let Some(stream) = self.stream_manager.get_stream_mut(recv_stream_id) {
-                        stream.flow_control_mut().consume_recv_window(payload.len());
                         stream.receive_data(&data_frame)?;
                     }
 
-                    request.body = payload;
+                    if let Some(request) = self.partial_requests.get_mut(&recv_stream_id) {
+                        let mut body = BytesMut::from(&request.body[..]);
+                        body.extend_from_slice(&data_frame.data);
+                        request.body = body.freeze();
+                    }
+
+                    self.replenish_windows(recv_stream_id)?;
+                    self.forget_closed_stream(recv_stream_id);
 
                     if flags.is_end_stream() {
-                        stream_ended = true;
+                        if let Some(request) = self.partial_requests.remove(&recv_stream_id) {
+                            return Ok(request);
+                        }
                     }
                 }
                 FrameType::Settings => {
                     // Handle SETTINGS during request
-                    self.recv_settings()?;
+                    self.handle_settings(flags, recv_stream_id, &payload)?;
                 }
                 FrameType::WindowUpdate => {
-                    // Handle WINDOW_UPDATE
-                    if payload.len() != 4 {
-                        return Err(Error::FrameSize("WINDOW_UPDATE must be 4 bytes".to_string()));
-                    }
-                    let increment = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
-
-                    if recv_stream_id == CONNECTION_STREAM_ID {
-                        self.flow_control.increase_send_window(increment)?;
-                    } else if let Some(stream) = self.stream_manager.get_stream_mut(recv_stream_id) {
-                        stream.flow_control_mut().increase_send_window(increment)?;
-                    }
+                    self.handle_window_update(recv_stream_id, &payload)?;
+                }
+                FrameType::PriorityUpdate => {
+                    self.handle_priority_update(payload)?;
+                }
+                FrameType::Priority => {
+                    let frame = FrameCodec::decode_priority_frame(recv_stream_id, payload)?;
+                    self.stream_manager.reprioritize(recv_stream_id, &frame.priority)?;
                 }
                 FrameType::Ping => {
                     // Respond to PING
                     if flags.is_ack() {
-                        // PING ACK - ignore
+                        // PING ACK - matched by recv_frame
                     } else {
                         // Send PING ACK
                         let mut data = [0u8; 8];
-                        data.copy_from_slice(&payload[..8]);
+                        data.copy_from_slice(&payload);
                         let pong = PingFrame::ack(data);
                         let encoded = FrameCodec::encode_ping_frame(&pong);
                         self.session.write(&encoded)?;
                     }
                 }
                 FrameType::Goaway => {
-                    return Err(Error::ConnectionClosed);
+                    self.receive_goaway(payload)?;
+
+                    // Requests in progress may still complete
+                    if self.partial_requests.is_empty() {
+                        return Err(Error::ConnectionClosed);
+                    }
                 }
                 FrameType::RstStream => {
-                    // Stream reset
+                    // Only the reset stream's request is dropped
                     if let Some(stream) = self.stream_manager.get_stream_mut(recv_stream_id) {
                         stream.close();
                     }
-                    return Err(Error::Cancel(recv_stream_id));
+                    self.partial_requests.remove(&recv_stream_id);
+                    self.forget_closed_stream(recv_stream_id);
                 }
                 _ => {
                     // Ignore other frame types
                 }
             }
+        }
+    }
+
+    /// Process a complete header block received on a stream
+    ///
+    /// Returns the request once it is complete: at the end of its headers
+    /// without a body, or at the end of its trailers.
+    fn receive_header_block(&mut self, stream_id: StreamId, end_stream: bool) -> Result<Option<H2Request>> {
+        let block = self
+            .stream_manager
+            .get_stream_mut(stream_id)
+            .map(|stream| stream.take_header_block())
+            .unwrap_or_default();
+        let decoded = self.decode_hpack(&block)?;
+
+        // Opened after our final GOAWAY: refused, with the header block
+        // decoded to keep HPACK in sync
+        if self.goaway_sent.is_some_and(|last| stream_id > last) {
+            self.send_rst_stream(stream_id, ErrorCode::RefusedStream)?;
+            return Ok(None);
+        }
+
+        if let Some(request) = self.partial_requests.get_mut(&stream_id) {
+            for field in decoded {
+                let name = String::from_utf8_lossy(&field.name).to_string();
+                let value = String::from_utf8_lossy(&field.value).to_string();
+                request.trailers.insert(name, value);
+            }
+            self.forget_closed_stream(stream_id);
+            if end_stream {
+                return Ok(self.partial_requests.remove(&stream_id));
+            }
+            return Ok(None);
+        }
 
-            // Break if we have a complete request
-            if headers_received && stream_ended {
-                break;
+        let mut request = H2Request {
+            stream_id,
+            method: String::new(),
+            path: String::new(),
+            scheme: String::new(),
+            authority: String::new(),
+            headers: HashMap::new(),
+            trailers: HashMap::new(),
+            body: Bytes::new(),
+        };
+
+        for field in decoded {
+            let name_str = String::from_utf8_lossy(&field.name).to_string();
+            let value_str = String::from_utf8_lossy(&field.value).to_string();
+
+            match name_str.as_str() {
+                ":method" => request.method = value_str,
+                ":path" => request.path = value_str,
+                ":scheme" => request.scheme = value_str,
+                ":authority" => request.authority = value_str,
+                _ => {
+                    request.headers.insert(name_str, value_str);
+                }
             }
         }
 
-        if !headers_received {
-            return Err(Error::Protocol("No headers received".to_string()));
+        self.apply_request_priority(stream_id, request.headers.get(PRIORITY_HEADER).map(String::as_str));
+
+        if end_stream {
+            return Ok(Some(request));
+        } else if request.is_connect() {
+            // The request is complete; any DATA belongs to the tunnel
+            self.open_tunnel(&request)?;
+            return Ok(Some(request));
+        }
+        self.partial_requests.insert(stream_id, request);
+        Ok(None)
+    }
+
+    /// Decode the header block fragment of a reset stream, keeping HPACK in sync
+    fn discard_header_fragment(&mut self, frame_type: FrameType, flags: FrameFlags, stream_id: StreamId, payload: Bytes) -> Result<()> {
+        let (fragment, end_headers) = match frame_type {
+            FrameType::Headers => {
+                let frame = FrameCodec::decode_headers_frame(stream_id, flags, payload)?;
+                (frame.header_block, frame.end_headers)
+            }
+            FrameType::Continuation => {
+                let frame = FrameCodec::decode_continuation_frame(stream_id, flags, payload)?;
+                (frame.header_block, frame.end_headers)
+            }
+            _ => return Ok(()),
+        };
+
+        self.discarded_block.extend_from_slice(&fragment);
+        if end_headers {
+            let block = std::mem::take(&mut self.discarded_block);
+            self.decode_hpack(&block)?;
         }
 
-        Ok(request)
+        Ok(())
     }
 
     /// Send a response
@@ -325,6 +523,28 @@ impl<S: SessionOps> H2Server<S> {
         status: u16,
         headers: &[(&str, &str)],
         body: Bytes,
+    ) -> Result<()> {
+        let has_body = !body.is_empty();
+        self.send_response_headers(stream_id, status, headers, !has_body)?;
+
+        // Send DATA frames if there's a body
+        if has_body {
+            self.send_body(stream_id, body, true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the response HEADERS frame alone
+    ///
+    /// With `end_stream` false, the body follows through `send_body` or
+    /// `send_prioritized`.
+    pub fn send_response_headers(
+        &mut self,
+        stream_id: StreamId,
+        status: u16,
+        headers: &[(&str, &str)],
+        end_stream: bool,
     ) -> Result<()> {
         // Build headers
         let mut hpack_headers = Vec::new();
@@ -345,20 +565,569 @@ impl<S: SessionOps> H2Server<S> {
             .encode_into(header_tuples, &mut header_block_vec)
             .map_err(|e| Error::Internal(format!("HPACK encode error: {}", e)))?;
 
-        // Send HEADERS frame
-        let has_body = !body.is_empty();
         let headers_frame = HeadersFrame::new(
             stream_id,
             Bytes::from(header_block_vec),
-            !has_body, // END_STREAM if no body
-            true,      // END_HEADERS (no continuation for now)
+            end_stream,
+            true, // END_HEADERS (no continuation for now)
         );
-        self.send_headers(&headers_frame)?;
+        self.send_headers(&headers_frame)
+    }
 
-        // Send DATA frame if there's a body
-        if has_body {
-            let data_frame = DataFrame::new(stream_id, body, true);
-            self.send_data(&data_frame)?;
+    /// Accept a CONNECT request with a 200 response, establishing the tunnel
+    pub fn accept_connect(&mut self, stream_id: StreamId, headers: &[(&str, &str)]) -> Result<()> {
+        self.send_response_headers(stream_id, 200, headers, false)
+    }
+
+    /// Get the tunnel of an accepted CONNECT request
+    pub fn tunnel(&mut self, stream_id: StreamId) -> H2Tunnel<'_, Self> {
+        H2Tunnel::new(self, stream_id)
+    }
+
+    /// Receive the data of a stream as it arrives
+    ///
+    /// Returns the DATA received since the last call, waiting for some if
+    /// needed, or None once the client ended the stream. Meant for CONNECT
+    /// tunnels; frames for other streams are queued for `recv_request`.
+    pub fn recv_stream_data(&mut self, stream_id: StreamId) -> Result<Option<Bytes>> {
+        loop {
+            let stream = self
+                .stream_manager
+                .get_stream_mut(stream_id)
+                .ok_or(Error::InvalidStreamId(stream_id))?;
+
+            if !stream.body().is_empty() {
+                return Ok(Some(Bytes::from(stream.take_body())));
+            }
+            if stream.reset_code().is_some() {
+                return Err(Error::Cancel(stream_id));
+            }
+            if stream.stream_complete() {
+                return Ok(None);
+            }
+
+            // Frames queued while blocked on flow control come first
+            let queued = self.pending_frames.iter().position(|(frame_type, _, id, _)| {
+                *id == stream_id && matches!(frame_type, FrameType::Data | FrameType::RstStream)
+            });
+            let (frame_type, flags, recv_stream_id, payload) = match queued {
+                Some(pos) => self.pending_frames.remove(pos).unwrap(),
+                None => self.recv_frame()?,
+            };
+
+            let tunnel = self.tunnels.contains(&recv_stream_id)
+                && matches!(frame_type, FrameType::Data | FrameType::RstStream);
+            let control = matches!(
+                frame_type,
+                FrameType::WindowUpdate | FrameType::Settings | FrameType::Ping | FrameType::Goaway
+            );
+            if !tunnel && !control {
+                self.pending_frames.push_back((frame_type, flags, recv_stream_id, payload));
+                continue;
+            }
+
+            if !self.check_frame(frame_type, flags, recv_stream_id, &payload)? {
+                continue;
+            }
+
+            if tunnel {
+                self.receive_tunnel_frame(frame_type, flags, recv_stream_id, payload)?;
+            } else {
+                self.handle_control_frame(frame_type, flags, recv_stream_id, payload)?;
+            }
+        }
+    }
+
+    /// Process a connection control frame received while waiting for another
+    ///
+    /// Handles WINDOW_UPDATE, SETTINGS, PING and GOAWAY; other frames are
+    /// ignored.
+    fn handle_control_frame(&mut self, frame_type: FrameType, flags: FrameFlags, stream_id: StreamId, payload: Bytes) -> Result<()> {
+        match frame_type {
+            FrameType::WindowUpdate => self.handle_window_update(stream_id, &payload),
+            FrameType::Settings => self.handle_settings(flags, stream_id, &payload),
+            FrameType::Ping if !flags.is_ack() => {
+                let mut data = [0u8; 8];
+                data.copy_from_slice(&payload);
+                let pong = PingFrame::ack(data);
+                self.session.write(&FrameCodec::encode_ping_frame(&pong))?;
+                Ok(())
+            }
+            FrameType::Goaway => self.receive_goaway(payload),
+            _ => Ok(()),
+        }
+    }
+
+    /// Process a GOAWAY frame (RFC 9113 Section 6.8)
+    ///
+    /// Pushed streams above the last stream ID are refused; no new push
+    /// can be promised.
+    fn receive_goaway(&mut self, payload: Bytes) -> Result<()> {
+        let frame = FrameCodec::decode_goaway_frame(payload)?;
+
+        for stream_id in self.stream_manager.stream_ids() {
+            if stream_id % 2 == 0 && stream_id > frame.last_stream_id {
+                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                    stream.receive_rst_stream(ErrorCode::RefusedStream);
+                }
+            }
+        }
+
+        self.goaway = Some(frame);
+        Ok(())
+    }
+
+    /// Send a PING and wait for its ACK, returning the round-trip time
+    ///
+    /// Requests and other stream frames received meanwhile are queued for
+    /// `recv_request`. Fails with `Error::Timeout` if no ACK arrives in time.
+    pub fn ping_and_wait(&mut self, timeout: Duration) -> Result<Duration> {
+        let data = self.ping_tracker.next_payload();
+        self.send_ping(data)?;
+        self.wait_for_ping_ack(data, Some(timeout))?;
+        Ok(self.ping_tracker.last_rtt().unwrap_or_default())
+    }
+
+    /// Process connection control frames until a PING is acknowledged
+    ///
+    /// Other frames are queued; they are checked once `recv_request`
+    /// processes them.
+    fn wait_for_ping_ack(&mut self, data: [u8; 8], timeout: Option<Duration>) -> Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        while self.ping_tracker.is_outstanding(data) {
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() || !self.session.get_ref().poll(PollEvents::Read, Some(remaining))? {
+                    return Err(Error::Timeout);
+                }
+            }
+
+            let (frame_type, flags, stream_id, payload) = self.recv_frame()?;
+            let control = matches!(
+                frame_type,
+                FrameType::WindowUpdate | FrameType::Settings | FrameType::Ping | FrameType::Goaway
+            );
+            if !control {
+                self.pending_frames.push_back((frame_type, flags, stream_id, payload));
+                continue;
+            }
+
+            if self.check_frame(frame_type, flags, stream_id, &payload)? {
+                self.handle_control_frame(frame_type, flags, stream_id, payload)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the last GOAWAY received, if any
+    pub fn goaway(&self) -> Option<&GoawayFrame> {
+        self.goaway.as_ref()
+    }
+
+    /// Shut the connection down gracefully (RFC 9113 Section 6.8)
+    ///
+    /// Sends GOAWAY with the largest stream ID, then a PING. Once the PING
+    /// is acknowledged, every stream the client opened before seeing the
+    /// GOAWAY has arrived, and a second GOAWAY announces the actual last
+    /// stream ID, which is returned. Requests received meanwhile are queued
+    /// for `recv_request` and should still be answered; streams opened
+    /// later are refused. Fails with `Error::Timeout` if the PING is not
+    /// acknowledged within `timeout`.
+    pub fn graceful_shutdown(&mut self, debug: &str, timeout: Duration) -> Result<StreamId> {
+        const SHUTDOWN_PING: [u8; 8] = *b"shutdown";
+
+        self.send_goaway(MAX_STREAM_ID, ErrorCode::NoError, debug)?;
+        self.send_ping(SHUTDOWN_PING)?;
+        self.wait_for_ping_ack(SHUTDOWN_PING, Some(timeout))?;
+
+        let queued = self
+            .pending_frames
+            .iter()
+            .filter(|(frame_type, _, id, _)| *frame_type == FrameType::Headers && id % 2 == 1)
+            .map(|(_, _, id, _)| *id);
+        let last_stream_id = queued
+            .chain([self.stream_manager.last_remote_stream_id()])
+            .max()
+            .unwrap_or(CONNECTION_STREAM_ID);
+
+        self.send_goaway(last_stream_id, ErrorCode::NoError, debug)?;
+        self.goaway_sent = Some(last_stream_id);
+
+        Ok(last_stream_id)
+    }
+
+    /// Turn the stream of a CONNECT request into a tunnel
+    ///
+    /// `:protocol` is only allowed once we advertised
+    /// SETTINGS_ENABLE_CONNECT_PROTOCOL (RFC 8441 Section 4); otherwise the
+    /// stream is reset with PROTOCOL_ERROR.
+    fn open_tunnel(&mut self, request: &H2Request) -> Result<()> {
+        if request.protocol().is_some() && !self.local_settings.get_enable_connect_protocol() {
+            self.send_rst_stream(request.stream_id, ErrorCode::ProtocolError)?;
+            return Err(Error::Protocol(format!(
+                "Extended CONNECT on stream {} without SETTINGS_ENABLE_CONNECT_PROTOCOL",
+                request.stream_id
+            )));
+        }
+
+        self.tunnels.insert(request.stream_id);
+        Ok(())
+    }
+
+    /// Process a DATA or RST_STREAM frame received on a tunnel
+    fn receive_tunnel_frame(&mut self, frame_type: FrameType, flags: FrameFlags, stream_id: StreamId, payload: Bytes) -> Result<()> {
+        if frame_type == FrameType::RstStream {
+            let code = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                stream.receive_rst_stream(ErrorCode::from_u32(code).unwrap_or(ErrorCode::InternalError));
+            }
+            return Ok(());
+        }
+
+        // Flow control covers the whole payload, including padding
+        self.flow_control.consume_recv_window(payload.len());
+
+        let frame = FrameCodec::decode_data_frame(stream_id, flags, payload)?;
+        self.stream_manager
+            .get_stream_mut(stream_id)
+            .ok_or(Error::StreamClosed(stream_id))?
+            .receive_data(&frame)?;
+
+        self.replenish_windows(stream_id)
+    }
+
+    /// Send response body data on a stream
+    ///
+    /// The data is split into DATA frames that fit the peer's max frame size
+    /// and the available flow-control windows. When the windows are exhausted,
+    /// this blocks until WINDOW_UPDATE opens them again; other frames received
+    /// meanwhile are queued for `recv_request`.
+    pub fn send_body(&mut self, stream_id: StreamId, data: Bytes, end_stream: bool) -> Result<()> {
+        let max_frame_size = self.remote_settings.get_max_frame_size() as usize;
+        let mut remaining = data;
+
+        loop {
+            let stream = self
+                .stream_manager
+                .get_stream(stream_id)
+                .ok_or(Error::StreamClosed(stream_id))?;
+            if stream.reset_code().is_some() {
+                self.forget_closed_stream(stream_id);
+                return Err(Error::Cancel(stream_id));
+            }
+
+            let stream_window = stream.flow_control().send_window().size();
+            let window = std::cmp::min(self.flow_control.send_window().size(), stream_window);
+
+            // Wait for WINDOW_UPDATE
+            if window <= 0 && !remaining.is_empty() {
+                self.wait_for_window_update()?;
+                continue;
+            }
+
+            let len = std::cmp::min(remaining.len(), std::cmp::min(window.max(0) as usize, max_frame_size));
+            let chunk = remaining.split_to(len);
+            let last = remaining.is_empty();
+
+            self.send_data(&DataFrame::new(stream_id, chunk, end_stream && last))?;
+
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Send bodies on several streams, ordered by the dependency tree
+    ///
+    /// DATA frames are interleaved so that streams get bandwidth in
+    /// proportion to their weight, and dependents wait for their parent
+    /// (RFC 7540 Section 5.3). Every body ends its stream. Blocks on flow
+    /// control like `send_body`.
+    pub fn send_prioritized(&mut self, bodies: Vec<(StreamId, Bytes)>) -> Result<()> {
+        let max_frame_size = self.remote_settings.get_max_frame_size() as usize;
+
+        let stream_ids: Vec<StreamId> = bodies.iter().map(|(stream_id, _)| *stream_id).collect();
+        let mut scheduler = DataScheduler::new();
+        for (stream_id, data) in bodies {
+            scheduler.push(stream_id, data, true);
+        }
+
+        while !scheduler.is_empty() {
+            let reset = stream_ids.iter().find(|stream_id| {
+                self.stream_manager
+                    .get_stream(**stream_id)
+                    .is_some_and(|stream| stream.reset_code().is_some())
+            });
+            if let Some(&stream_id) = reset {
+                self.forget_closed_stream(stream_id);
+                return Err(Error::Cancel(stream_id));
+            }
+
+            let connection_window = self.flow_control.send_window().size().max(0) as usize;
+            let streams = &self.stream_manager;
+            let window = |stream_id| {
+                streams
+                    .get_stream(stream_id)
+                    .map_or(0, |stream| stream.flow_control().send_window().size().max(0) as usize)
+                    .min(connection_window)
+            };
+
+            match scheduler.next(streams.priority_tree(), window, max_frame_size) {
+                Some(frame) => self.send_data(&frame)?,
+                None => self.wait_for_window_update()?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Process frames until a WINDOW_UPDATE or RST_STREAM is received
+    ///
+    /// Connection control frames are handled directly, and resets recorded
+    /// on their stream; anything else is queued for `recv_request`.
+    fn wait_for_window_update(&mut self) -> Result<()> {
+        loop {
+            let (frame_type, flags, stream_id, payload) = self.recv_frame()?;
+
+            // Queued frames are checked once recv_request processes them
+            let queued = !matches!(
+                frame_type,
+                FrameType::WindowUpdate
+                    | FrameType::Settings
+                    | FrameType::RstStream
+                    | FrameType::Ping
+                    | FrameType::Goaway
+            );
+            if queued {
+                self.pending_frames.push_back((frame_type, flags, stream_id, payload));
+                continue;
+            }
+
+            if !self.check_frame(frame_type, flags, stream_id, &payload)? {
+                continue;
+            }
+
+            match frame_type {
+                FrameType::WindowUpdate => {
+                    self.handle_window_update(stream_id, &payload)?;
+                    return Ok(());
+                }
+                FrameType::RstStream => {
+                    // The sender gives up if this is one of its streams
+                    let code = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                    if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                        stream.receive_rst_stream(ErrorCode::from_u32(code).unwrap_or(ErrorCode::InternalError));
+                    }
+                    return Ok(());
+                }
+                _ => self.handle_control_frame(frame_type, flags, stream_id, payload)?,
+            }
+        }
+    }
+
+    /// Check a received frame against the protocol rules
+    ///
+    /// A connection error sends GOAWAY and fails. A stream error resets the
+    /// stream and returns false: the frame must be dropped, its header
+    /// block having been decoded to keep the HPACK dynamic table in sync,
+    /// and DATA charged to the connection window.
+    fn check_frame(&mut self, frame_type: FrameType, flags: FrameFlags, stream_id: StreamId, payload: &Bytes) -> Result<bool> {
+        let state = self.stream_manager.stream_state(stream_id);
+        let mut result = self.validator.validate(frame_type, flags, stream_id, payload, state);
+        if result.is_ok() && frame_type == FrameType::Data {
+            let stream_window = self
+                .stream_manager
+                .get_stream(stream_id)
+                .map(|stream| stream.flow_control().recv_window().size());
+            let connection_window = self.flow_control.recv_window().size();
+            result = self
+                .validator
+                .validate_data_window(stream_id, payload.len(), connection_window, stream_window);
+        }
+        let Err(violation) = result else {
+            return Ok(true);
+        };
+
+        match violation.scope {
+            ViolationScope::Connection => {
+                let last_stream_id = self.stream_manager.last_remote_stream_id();
+                self.send_goaway(last_stream_id, violation.error_code, &violation.reason)?;
+                Err(violation.into_error())
+            }
+            ViolationScope::Stream(id) => {
+                self.send_rst_stream(id, violation.error_code)?;
+
+                // Dropped DATA still counts against the connection window
+                if frame_type == FrameType::Data {
+                    self.flow_control.consume_recv_window(payload.len());
+                    self.replenish_windows(stream_id)?;
+                }
+                self.discard_header_fragment(frame_type, flags, stream_id, payload.clone())?;
+
+                Ok(false)
+            }
+        }
+    }
+
+    /// Apply the priority signals of a request that opened a stream
+    ///
+    /// A PRIORITY_UPDATE received before the request takes precedence over
+    /// its `priority` header field (RFC 9218 Section 7.1).
+    fn apply_request_priority(&mut self, stream_id: StreamId, header: Option<&str>) {
+        let mut priority = None;
+
+        if let Some(value) = header {
+            let signal = ExtensiblePriority::parse(value);
+            self.priority_signals.push_back(PrioritySignal {
+                stream_id,
+                priority: signal,
+                source: PrioritySource::Header,
+            });
+            priority = Some(signal);
+        }
+
+        if let Some(update) = self.pending_priorities.remove(&stream_id) {
+            priority = Some(update);
+        }
+
+        if let (Some(priority), Some(stream)) = (priority, self.stream_manager.get_stream_mut(stream_id)) {
+            stream.set_extensible_priority(priority);
+        }
+    }
+
+    /// Apply a received PRIORITY_UPDATE frame
+    fn handle_priority_update(&mut self, payload: Bytes) -> Result<()> {
+        let frame = FrameCodec::decode_priority_update_frame(payload)?;
+        let stream_id = frame.prioritized_stream_id;
+        let priority = ExtensiblePriority::parse(&String::from_utf8_lossy(&frame.priority_field_value));
+
+        self.priority_signals.push_back(PrioritySignal {
+            stream_id,
+            priority,
+            source: PrioritySource::Frame,
+        });
+
+        match self.stream_manager.stream_state(stream_id) {
+            StreamState::Idle => {
+                self.pending_priorities.insert(stream_id, priority);
+            }
+            StreamState::Closed => {}
+            _ => {
+                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                    stream.set_extensible_priority(priority);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the extensible priority of a stream (RFC 9218)
+    ///
+    /// Streams without a priority signal have the default priority; `None`
+    /// is returned for streams the server does not know about.
+    pub fn stream_priority(&self, stream_id: StreamId) -> Option<ExtensiblePriority> {
+        self.stream_manager
+            .get_stream(stream_id)
+            .map(|stream| stream.extensible_priority())
+            .or_else(|| self.pending_priorities.get(&stream_id).copied())
+    }
+
+    /// Take the priority signals received so far, oldest first
+    ///
+    /// Signals are recorded as `recv_request` processes frames, whether
+    /// they come from a `priority` header field or a PRIORITY_UPDATE frame.
+    pub fn take_priority_signals(&mut self) -> Vec<PrioritySignal> {
+        self.priority_signals.drain(..).collect()
+    }
+
+    /// Receive the next priority signal
+    ///
+    /// Frames other than PRIORITY_UPDATE and SETTINGS received meanwhile
+    /// are queued for `recv_request`.
+    pub fn recv_priority_signal(&mut self) -> Result<PrioritySignal> {
+        loop {
+            if let Some(signal) = self.priority_signals.pop_front() {
+                return Ok(signal);
+            }
+
+            let (frame_type, flags, stream_id, payload) = self.recv_frame()?;
+            match frame_type {
+                FrameType::PriorityUpdate | FrameType::Settings => {
+                    if !self.check_frame(frame_type, flags, stream_id, &payload)? {
+                        continue;
+                    }
+
+                    if frame_type == FrameType::Settings {
+                        self.handle_settings(flags, stream_id, &payload)?;
+                    } else {
+                        self.handle_priority_update(payload)?;
+                    }
+                }
+                FrameType::Goaway => {
+                    self.receive_goaway(payload)?;
+                    return Err(Error::ConnectionClosed);
+                }
+                _ => {
+                    self.pending_frames.push_back((frame_type, flags, stream_id, payload));
+                }
+            }
+        }
+    }
+
+    /// Apply a received WINDOW_UPDATE frame
+    fn handle_window_update(&mut self, stream_id: StreamId, payload: &[u8]) -> Result<()> {
+        let increment = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7FFFFFFF;
+
+        if stream_id == CONNECTION_STREAM_ID {
+            self.flow_control.increase_send_window(increment)?;
+        } else if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+            // A stream window overflow is a stream error (RFC 9113 Section 6.9.1)
+            if stream.flow_control_mut().increase_send_window(increment).is_err() {
+                self.send_rst_stream(stream_id, ErrorCode::FlowControlError)?;
+                // Recorded as a reset, so that a send on the stream gives up
+                if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
+                    stream.receive_rst_stream(ErrorCode::FlowControlError);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// HPACK-decode a complete header block
+    ///
+    /// A decoding failure is a connection error (RFC 9113 Section 4.3):
+    /// GOAWAY with COMPRESSION_ERROR is sent before failing.
+    fn decode_hpack(&mut self, block: &[u8]) -> Result<Vec<HeaderField>> {
+        match self.hpack_decoder.decode(block) {
+            Ok(fields) => Ok(fields),
+            Err(e) => {
+                let last_stream_id = self.stream_manager.last_remote_stream_id();
+                self.send_goaway(last_stream_id, ErrorCode::CompressionError, &e.to_string())?;
+                Err(Error::Hpack(e))
+            }
+        }
+    }
+
+    /// Send WINDOW_UPDATE for receive windows below the policy threshold
+    fn replenish_windows(&mut self, stream_id: StreamId) -> Result<()> {
+        let policy = self.flow_control_policy;
+
+        if let Some(increment) = policy.replenish_increment(self.flow_control.recv_window()) {
+            self.send_window_update(CONNECTION_STREAM_ID, increment)?;
+        }
+
+        // No more DATA can arrive once the peer ended the stream
+        let increment = self
+            .stream_manager
+            .get_stream(stream_id)
+            .filter(|stream| stream.state().can_receive())
+            .and_then(|stream| policy.replenish_increment(stream.flow_control().recv_window()));
+        if let Some(increment) = increment {
+            self.send_window_update(stream_id, increment)?;
         }
 
         Ok(())
@@ -373,35 +1142,95 @@ impl<S: SessionOps> H2Server<S> {
 
         let encoded = FrameCodec::encode_headers_frame(frame);
         self.session.write(&encoded)?;
+        self.forget_closed_stream(frame.stream_id);
         Ok(())
     }
 
     /// Send a DATA frame
+    ///
+    /// The whole frame (including padding) must fit in both the connection
+    /// and stream send windows; use `send_body` to wait for WINDOW_UPDATE.
     pub fn send_data(&mut self, frame: &DataFrame) -> Result<()> {
+        let len = frame.frame_size();
+
         // Check connection-level flow control
-        let sendable_conn = self.flow_control.consume_send_window(frame.data.len())?;
-        if sendable_conn == 0 {
+        if !self.flow_control.can_send(len) {
             return Err(Error::FlowControl("Connection window exhausted".to_string()));
         }
 
         // Check stream-level flow control
         if let Some(stream) = self.stream_manager.get_stream_mut(frame.stream_id) {
-            let sendable_stream = stream.send_data(frame.data.len(), frame.end_stream)?;
-            if sendable_stream == 0 {
+            if !stream.flow_control().can_send(len) {
                 return Err(Error::FlowControl("Stream window exhausted".to_string()));
             }
+            stream.send_data(len, frame.end_stream)?;
         }
+        self.flow_control.consume_send_window(len)?;
 
         let encoded = FrameCodec::encode_data_frame(frame);
         self.session.write(&encoded)?;
+        self.forget_closed_stream(frame.stream_id);
         Ok(())
     }
 
+    /// Forget a stream once fully closed
+    ///
+    /// Its response was sent, or it was reset and the reset reported; the
+    /// stream then only lives on as an implicitly closed ID.
+    fn forget_closed_stream(&mut self, stream_id: StreamId) {
+        let closed = self
+            .stream_manager
+            .get_stream(stream_id)
+            .is_some_and(|stream| stream.state().is_closed());
+        if closed {
+            self.stream_manager.remove_stream(stream_id);
+            self.tunnels.remove(&stream_id);
+        }
+    }
+
+    /// Send a frame of any type, with its flags and payload as-is
+    ///
+    /// Useful for extension and unknown frame types, which the receiver
+    /// must ignore (RFC 9113 Section 5.5).
+    pub fn send_frame(&mut self, frame: &Frame) -> Result<()> {
+        let encoded = FrameCodec::encode_frame(frame);
+        self.session.write(&encoded)?;
+        Ok(())
+    }
+
+    /// Send an ALTSVC frame (RFC 7838)
+    pub fn send_altsvc(&mut self, frame: &AltSvcFrame) -> Result<()> {
+        let encoded = FrameCodec::encode_altsvc_frame(frame);
+        self.session.write(&encoded)?;
+        Ok(())
+    }
+
+    /// Send an ORIGIN frame (RFC 8336)
+    pub fn send_origin(&mut self, frame: &OriginFrame) -> Result<()> {
+        let encoded = FrameCodec::encode_origin_frame(frame);
+        self.session.write(&encoded)?;
+        Ok(())
+    }
+
+    /// Send a PRIORITY frame
+    ///
+    /// The local dependency tree is updated too.
+    pub fn send_priority(&mut self, stream_id: StreamId, priority: PrioritySpec) -> Result<()> {
+        let frame = PriorityFrame::new(stream_id, priority);
+        let encoded = FrameCodec::encode_priority_frame(&frame);
+        self.session.write(&encoded)?;
+
+        self.stream_manager.reprioritize(stream_id, &priority)
+    }
+
     /// Send a PING frame
+    ///
+    /// The ping is tracked until the matching ACK arrives.
     pub fn send_ping(&mut self, data: [u8; 8]) -> Result<()> {
         let frame = PingFrame::new(data);
         let encoded = FrameCodec::encode_ping_frame(&frame);
         self.session.write(&encoded)?;
+        self.ping_tracker.sent(data);
         Ok(())
     }
 
@@ -411,11 +1240,11 @@ impl<S: SessionOps> H2Server<S> {
         let encoded = FrameCodec::encode_window_update_frame(&frame);
         self.session.write(&encoded)?;
 
-        // Update flow control
+        // Update flow control (our receive window grows)
         if stream_id == CONNECTION_STREAM_ID {
-            self.flow_control.increase_send_window(increment)?;
+            self.flow_control.send_window_update(increment)?;
         } else if let Some(stream) = self.stream_manager.get_stream_mut(stream_id) {
-            stream.flow_control_mut().increase_send_window(increment)?;
+            stream.flow_control_mut().send_window_update(increment)?;
         }
 
         Ok(())
@@ -450,6 +1279,11 @@ impl<S: SessionOps> H2Server<S> {
         promised_stream_id: StreamId,
         headers: &[(&str, &str)],
     ) -> Result<()> {
+        // No push once the client is going away
+        if self.goaway.is_some() {
+            return Err(Error::ConnectionClosed);
+        }
+
         // Build headers for the promise
         let mut hpack_headers = Vec::new();
         for (name, value) in headers {
@@ -466,6 +1300,12 @@ impl<S: SessionOps> H2Server<S> {
             .encode_into(header_tuples, &mut header_block_vec)
             .map_err(|e| Error::Internal(format!("HPACK encode error: {}", e)))?;
 
+        // Reserve the promised stream so the pushed response can be sent on it
+        let promised = self.stream_manager.get_or_create_stream(promised_stream_id)?;
+        if promised.state() == StreamState::Idle {
+            promised.set_state(StreamState::ReservedLocal);
+        }
+
         let frame = PushPromiseFrame::new(
             stream_id,
             promised_stream_id,
@@ -480,12 +1320,28 @@ impl<S: SessionOps> H2Server<S> {
     }
 
     /// Receive a frame
+    ///
+    /// Fails with `Error::SettingsTimeout`, after sending GOAWAY, if a
+    /// SETTINGS ACK is still missing when the timeout expires, and with
+    /// `Error::Timeout` if a keepalive PING is never acknowledged.
     pub fn recv_frame(&mut self) -> Result<(FrameType, FrameFlags, StreamId, Bytes)> {
-        FrameCodec::read_frame_from_session(&mut self.session)
-            .map_err(|e| Error::Io(e))
+        self.wait_for_frame()?;
+        let (frame_type, flags, stream_id, payload) =
+            FrameCodec::read_frame_from_session(&mut self.session).map_err(Error::Io)?;
+
+        self.ping_tracker.record_activity();
+        if frame_type == FrameType::Ping && flags.is_ack() {
+            if let Ok(data) = <[u8; 8]>::try_from(&payload[..]) {
+                self.ping_tracker.acknowledge(data);
+            }
+        }
+
+        Ok((frame_type, flags, stream_id, payload))
     }
 
     /// Get local settings
+    ///
+    /// Settings sent to the client only show up here once acknowledged.
     pub fn local_settings(&self) -> &Settings {
         &self.local_settings
     }
@@ -494,6 +1350,97 @@ impl<S: SessionOps> H2Server<S> {
     pub fn remote_settings(&self) -> &Settings {
         &self.remote_settings
     }
+
+    /// Get the SETTINGS frames awaiting the client's ACK
+    pub fn settings_tracker(&self) -> &SettingsTracker {
+        &self.settings_tracker
+    }
+
+    /// Get the outstanding pings and measured round-trip times
+    pub fn ping_tracker(&self) -> &PingTracker {
+        &self.ping_tracker
+    }
+
+    /// Get connection-level flow control
+    pub fn flow_control(&self) -> &ConnectionFlowControl {
+        &self.flow_control
+    }
+
+    /// Set the receive window replenishment policy
+    pub fn set_flow_control_policy(&mut self, policy: FlowControlPolicy) {
+        self.flow_control_policy = policy;
+    }
+
+    /// Get the HPACK decoder (for dynamic table inspection)
+    pub fn hpack_decoder(&self) -> &HpackDecoder {
+        &self.hpack_decoder
+    }
+
+    /// Get the stream manager
+    pub fn stream_manager(&self) -> &StreamManager {
+        &self.stream_manager
+    }
+}
+
+impl<S: SessionOps> ScriptConnection for H2Server<S> {
+    fn is_client(&self) -> bool {
+        false
+    }
+
+    fn poll_readable(&self, timeout: Duration) -> Result<bool> {
+        Ok(self.session.get_ref().poll(PollEvents::Read, Some(timeout))?)
+    }
+
+    fn read_frame(&mut self) -> Result<(FrameType, FrameFlags, StreamId, Bytes)> {
+        FrameCodec::read_frame_from_session(&mut self.session).map_err(Error::Io)
+    }
+
+    fn decode_header_block(&mut self, block: &[u8]) -> Result<Vec<HeaderField>> {
+        self.decode_hpack(block)
+    }
+
+    fn write_headers(&mut self, stream_id: StreamId, headers: &[(String, String)], end_stream: bool) -> Result<()> {
+        let mut header_block_vec = Vec::new();
+        let header_tuples: Vec<(&[u8], &[u8])> = headers
+            .iter()
+            .map(|(name, value)| (name.as_bytes(), value.as_bytes()))
+            .collect();
+        self.hpack_encoder
+            .encode_into(header_tuples, &mut header_block_vec)
+            .map_err(|e| Error::Internal(format!("HPACK encode error: {}", e)))?;
+
+        let frame = HeadersFrame::new(stream_id, Bytes::from(header_block_vec), end_stream, true);
+        self.session.write(&FrameCodec::encode_headers_frame(&frame))?;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.session.write(frame)?;
+        Ok(())
+    }
+}
+
+impl<S: SessionOps> TunnelEndpoint for H2Server<S> {
+    fn send_stream_data(&mut self, stream_id: StreamId, data: Bytes, end_stream: bool) -> Result<()> {
+        self.send_body(stream_id, data, end_stream)
+    }
+
+    fn recv_stream_data(&mut self, stream_id: StreamId) -> Result<Option<Bytes>> {
+        H2Server::recv_stream_data(self, stream_id)
+    }
+
+    fn poll_stream(&self, stream_id: StreamId, timeout: Option<Duration>) -> Result<bool> {
+        let ready = self
+            .stream_manager
+            .get_stream(stream_id)
+            .is_none_or(|stream| !stream.body().is_empty() || stream.is_finished())
+            || self.pending_frames.iter().any(|(_, _, id, _)| *id == stream_id);
+        if ready {
+            return Ok(true);
+        }
+
+        Ok(self.session.get_ref().poll(PollEvents::Read, timeout)?)
+    }
 }
 
 /// HTTP/2 request
@@ -511,6 +1458,8 @@ pub struct H2Request {
     pub authority: String,
     /// Headers
     pub headers: HashMap<String, String>,
+    /// Trailers
+    pub trailers: HashMap<String, String>,
     /// Body
     pub body: Bytes,
 }
@@ -541,6 +1490,21 @@ impl H2Request {
         self.headers.get(name).map(|s| s.as_str())
     }
 
+    /// Get trailer value
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers.get(name).map(|s| s.as_str())
+    }
+
+    /// Get the `:protocol` of an extended CONNECT request (RFC 8441)
+    pub fn protocol(&self) -> Option<&str> {
+        self.header(":protocol")
+    }
+
+    /// Check whether this is a CONNECT request, plain or extended
+    pub fn is_connect(&self) -> bool {
+        self.method == "CONNECT"
+    }
+
     /// Get body as bytes
     pub fn body(&self) -> &[u8] {
         &self.body
@@ -556,6 +1520,9 @@ impl H2Request {
 /// HTTP/2 server builder
 pub struct H2ServerBuilder {
     settings: SettingsBuilder,
+    flow_control_policy: FlowControlPolicy,
+    settings_timeout: Option<Duration>,
+    keepalive: Option<KeepAlive>,
 }
 
 impl H2ServerBuilder {
@@ -567,6 +1534,9 @@ impl H2ServerBuilder {
                 .enable_push(true) // Servers can push by default
                 .initial_window_size(65535)
                 .max_frame_size(16384),
+            flow_control_policy: FlowControlPolicy::default(),
+            settings_timeout: None,
+            keepalive: None,
         }
     }
 
@@ -600,23 +1570,182 @@ impl H2ServerBuilder {
         self
     }
 
+    /// Set max header list size
+    pub fn max_header_list_size(mut self, size: u32) -> Self {
+        self.settings = self.settings.max_header_list_size(size);
+        self
+    }
+
+    /// Advertise extended CONNECT support (RFC 8441)
+    pub fn enable_connect_protocol(mut self, enable: bool) -> Self {
+        self.settings = self.settings.enable_connect_protocol(enable);
+        self
+    }
+
+    /// Set the receive window replenishment policy
+    pub fn flow_control_policy(mut self, policy: FlowControlPolicy) -> Self {
+        self.flow_control_policy = policy;
+        self
+    }
+
+    /// Fail with SETTINGS_TIMEOUT if a SETTINGS ACK takes longer than this
+    ///
+    /// By default the server waits for acknowledgements indefinitely.
+    pub fn settings_timeout(mut self, timeout: Duration) -> Self {
+        self.settings_timeout = Some(timeout);
+        self
+    }
+
+    /// Send a PING after `interval` without receiving any frame
+    ///
+    /// Reading fails with `Error::Timeout` if the client does not
+    /// acknowledge a ping within `timeout`.
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive = Some(KeepAlive { interval, timeout });
+        self
+    }
+
     /// Build the server
     pub fn build<S: SessionOps>(self, session: S) -> Result<H2Server<S>> {
-        let local_settings = self.settings.build()?;
+        // Our settings take effect once acknowledged, until then the
+        // defaults apply (RFC 9113 Section 6.5.3)
+        let initial_settings = self.settings.build()?;
+        let local_settings = Settings::default_settings();
+
+        let mut hpack_decoder = HpackDecoder::new(local_settings.get_header_table_size() as usize);
+        hpack_decoder.set_max_header_list_size(
+            local_settings.get_max_header_list_size().map(|size| size as usize),
+        );
+
+        let mut stream_manager = StreamManager::new(false); // Server uses even stream IDs
+        stream_manager.set_initial_recv_window_size(local_settings.get_initial_window_size());
+
+        let mut validator = FrameValidator::new(false);
+        validator.set_max_frame_size(local_settings.get_max_frame_size());
 
         Ok(H2Server {
             session: HttpSession::new(session),
-            stream_manager: StreamManager::new(false), // Server uses even stream IDs
+            stream_manager,
             flow_control: ConnectionFlowControl::new(),
             hpack_encoder: HpackEncoder::new(),
-            hpack_decoder: hpack::Decoder::new(),
+            hpack_decoder,
+            flow_control_policy: self.flow_control_policy,
             local_settings,
+            initial_settings,
             remote_settings: Settings::default_settings(),
+            settings_tracker: SettingsTracker::new(self.settings_timeout),
+            ping_tracker: PingTracker::new(self.keepalive),
+            pending_frames: VecDeque::new(),
+            partial_requests: HashMap::new(),
+            continued_headers: None,
+            discarded_block: Vec::new(),
+            validator,
+            pending_priorities: HashMap::new(),
+            priority_signals: VecDeque::new(),
+            upgraded_request: None,
+            tunnels: HashSet::new(),
+            goaway: None,
+            goaway_sent: None,
             connected: false,
         })
     }
 }
 
+impl H2ServerBuilder {
+    /// Switch an HTTP/1.1 connection to HTTP/2 after an h2c upgrade request
+    ///
+    /// Sends `101 Switching Protocols`, applies the client's `HTTP2-Settings`
+    /// and exchanges the connection preface. The upgrade request is returned
+    /// by the first `recv_request` as stream 1 (RFC 7540 Section 3.2).
+    pub fn upgrade<S: SessionOps>(
+        self,
+        mut http: HttpServer<S>,
+        request: HttpRequest,
+    ) -> Result<H2Server<PrefixedSession<S>>> {
+        if !h2c::is_upgrade_request(&request) {
+            return Err(Error::Protocol("Not an h2c upgrade request".to_string()));
+        }
+
+        let settings_value = request.headers().get(h2c::HTTP2_SETTINGS_HEADER).unwrap_or_default();
+        let client_settings = h2c::decode_settings_header(settings_value)?;
+
+        let response = HttpResponse::builder()
+            .status(Status::new(101)?)
+            .header("Connection", "Upgrade")
+            .header("Upgrade", h2c::H2C_TOKEN)
+            .build();
+        http.send_response(&response)?;
+
+        let (session, remaining) = http.into_parts();
+        let mut server = self.build(PrefixedSession::new(session, remaining))?;
+        server.apply_remote_settings(&client_settings)?;
+
+        // The upgrade request is complete: stream 1 is half-closed (remote)
+        server
+            .stream_manager
+            .get_or_create_stream(1)?
+            .set_state(StreamState::HalfClosedRemote);
+        server.upgraded_request = Some(upgraded_request(&request));
+
+        server.accept()?;
+        Ok(server)
+    }
+
+    /// Accept a connection speaking HTTP/1 or HTTP/2 with prior knowledge
+    ///
+    /// Reads just enough bytes to recognize the HTTP/2 connection preface
+    /// (RFC 7540 Section 3.4). On a match the connection preface exchange is
+    /// completed; otherwise the bytes read are replayed to an `HttpServer`.
+    pub fn detect<S: SessionOps>(self, session: S) -> Result<ServerConnection<S>> {
+        let mut session = HttpSession::new(session);
+        let mut prefix = Vec::with_capacity(CONNECTION_PREFACE.len());
+
+        while prefix.len() < CONNECTION_PREFACE.len() && CONNECTION_PREFACE.starts_with(&prefix) {
+            let mut buf = [0u8; 24];
+            let n = session.read(&mut buf[..CONNECTION_PREFACE.len() - prefix.len()])?;
+            if n == 0 {
+                return Err(Error::ConnectionClosed);
+            }
+            prefix.extend_from_slice(&buf[..n]);
+        }
+
+        let is_h2 = prefix == CONNECTION_PREFACE;
+        let session = PrefixedSession::new(session.into_inner(), prefix);
+
+        if is_h2 {
+            let mut server = self.build(session)?;
+            server.accept()?;
+            Ok(ServerConnection::H2(Box::new(server)))
+        } else {
+            Ok(ServerConnection::Http1(HttpServer::new(session)))
+        }
+    }
+}
+
+/// Convert an HTTP/1.1 upgrade request into the request of stream 1
+fn upgraded_request(request: &HttpRequest) -> H2Request {
+    // Connection-specific headers do not carry over (RFC 7540 Section 8.1.2.2)
+    const HOP_BY_HOP: [&str; 6] = ["host", "connection", "upgrade", "http2-settings", "keep-alive", "transfer-encoding"];
+
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string()))
+        .filter(|(name, _)| !HOP_BY_HOP.contains(&name.as_str()))
+        .collect();
+
+    H2Request {
+        stream_id: 1,
+        method: request.method().as_str().to_string(),
+        path: request.uri().to_string(),
+        scheme: "http".to_string(),
+        authority: request.headers().get("Host").unwrap_or_default().to_string(),
+        headers,
+        trailers: HashMap::new(),
+        body: Bytes::from(request.body().to_vec()),
+    }
+}
+
 impl Default for H2ServerBuilder {
     fn default() -> Self {
         Self::new()
@@ -629,7 +1758,7 @@ mod tests {
 
     #[test]
     fn test_server_builder() {
-        let builder = H2ServerBuilder::new()
+        let _builder = H2ServerBuilder::new()
             .header_table_size(8192)
             .enable_push(true)
             .initial_window_size(65535)
@@ -650,6 +1779,7 @@ mod tests {
             scheme: "https".to_string(),
             authority: "example.com".to_string(),
             headers,
+            trailers: HashMap::new(),
             body: Bytes::from(r#"{"key":"value"}"#),
         };
 
@@ -4,7 +4,9 @@
 //! as defined in RFC 7540 Section 6.5.
 
 use super::error::{Error, Result};
+use std::collections::VecDeque;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 /// HTTP/2 settings parameters (RFC 7540 Section 6.5.2)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -202,7 +204,7 @@ impl Settings {
 
         // Validate SETTINGS_MAX_FRAME_SIZE (16384 to 16777215)
         if let Some(max_frame_size) = self.max_frame_size {
-            if max_frame_size < 16384 || max_frame_size > 16777215 {
+            if !(16384..=16777215).contains(&max_frame_size) {
                 return Err(Error::InvalidSettings(format!(
                     "Max frame size {} outside valid range (16384-16777215)",
                     max_frame_size
@@ -213,6 +215,39 @@ impl Settings {
         Ok(())
     }
 
+    /// Parse a SETTINGS frame payload
+    ///
+    /// Unknown identifiers are ignored per RFC 7540 Section 6.5.2.
+    pub fn from_payload(payload: &[u8]) -> Result<Settings> {
+        if !payload.len().is_multiple_of(6) {
+            return Err(Error::FrameSize(format!(
+                "SETTINGS payload length {} is not a multiple of 6",
+                payload.len()
+            )));
+        }
+
+        let mut settings = Settings::new();
+        for entry in payload.chunks_exact(6) {
+            let id = u16::from_be_bytes([entry[0], entry[1]]);
+            let value = u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]);
+
+            match SettingsParameter::from_u16(id) {
+                Some(SettingsParameter::HeaderTableSize) => settings.header_table_size = Some(value),
+                Some(SettingsParameter::EnablePush) => settings.enable_push = Some(value != 0),
+                Some(SettingsParameter::MaxConcurrentStreams) => settings.max_concurrent_streams = Some(value),
+                Some(SettingsParameter::InitialWindowSize) => settings.initial_window_size = Some(value),
+                Some(SettingsParameter::MaxFrameSize) => settings.max_frame_size = Some(value),
+                Some(SettingsParameter::MaxHeaderListSize) => settings.max_header_list_size = Some(value),
+                Some(SettingsParameter::EnableConnectProtocol) => settings.enable_connect_protocol = Some(value != 0),
+                Some(SettingsParameter::NoRfc7540Priorities) => settings.no_rfc7540_priorities = Some(value != 0),
+                None => {}
+            }
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
     /// Merge settings from another Settings object
     /// (values in `other` override values in `self`)
     pub fn merge(&mut self, other: &Settings) {
@@ -250,6 +285,7 @@ impl Default for Settings {
 }
 
 /// Builder for HTTP/2 settings
+#[derive(Debug, Clone)]
 pub struct SettingsBuilder {
     settings: Settings,
 }
@@ -323,6 +359,91 @@ impl Default for SettingsBuilder {
     }
 }
 
+/// Local SETTINGS frames awaiting acknowledgement
+///
+/// The peer acknowledges SETTINGS frames in the order they were sent, and
+/// the values of a frame only take effect once its ACK is received
+/// (RFC 9113 Section 6.5.3). A frame left unacknowledged longer than the
+/// timeout is a connection error of type SETTINGS_TIMEOUT.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsTracker {
+    /// Sent frames in order, with the time they were sent
+    pending: VecDeque<(Settings, Instant)>,
+    /// Number of frames acknowledged so far
+    acked: usize,
+    /// How long to wait for each ACK (None = forever)
+    timeout: Option<Duration>,
+}
+
+impl SettingsTracker {
+    /// Create a tracker with the given ACK timeout
+    pub fn new(timeout: Option<Duration>) -> Self {
+        SettingsTracker {
+            timeout,
+            ..Default::default()
+        }
+    }
+
+    /// Get the ACK timeout
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set the ACK timeout
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Record a SETTINGS frame sent to the peer
+    pub fn sent(&mut self, settings: Settings) {
+        self.pending.push_back((settings, Instant::now()));
+    }
+
+    /// Record a received ACK, returning the settings it acknowledges
+    ///
+    /// Returns None if no SETTINGS frame was outstanding.
+    pub fn acknowledge(&mut self) -> Option<Settings> {
+        let (settings, _) = self.pending.pop_front()?;
+        self.acked += 1;
+        Some(settings)
+    }
+
+    /// Check whether any SETTINGS frame awaits its ACK
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Number of SETTINGS frames awaiting their ACK
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Number of SETTINGS frames acknowledged by the peer
+    pub fn acked_count(&self) -> usize {
+        self.acked
+    }
+
+    /// Settings sent but not yet acknowledged, oldest first
+    pub fn pending(&self) -> impl Iterator<Item = &Settings> {
+        self.pending.iter().map(|(settings, _)| settings)
+    }
+
+    /// Time left before the oldest outstanding frame times out
+    ///
+    /// Returns None if nothing is pending or there is no timeout, and
+    /// `Duration::ZERO` once the deadline has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        let timeout = self.timeout?;
+        let (_, sent_at) = self.pending.front()?;
+        Some(timeout.saturating_sub(sent_at.elapsed()))
+    }
+
+    /// Check whether the oldest outstanding frame timed out
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -347,7 +468,7 @@ mod tests {
     fn test_settings_defaults() {
         let settings = Settings::default_settings();
         assert_eq!(settings.get_header_table_size(), 4096);
-        assert_eq!(settings.get_enable_push(), true);
+        assert!(settings.get_enable_push());
         assert_eq!(settings.get_initial_window_size(), 65535);
         assert_eq!(settings.get_max_frame_size(), 16384);
     }
@@ -363,7 +484,7 @@ mod tests {
             .unwrap();
 
         assert_eq!(settings.get_header_table_size(), 8192);
-        assert_eq!(settings.get_enable_push(), false);
+        assert!(!settings.get_enable_push());
         assert_eq!(settings.get_max_concurrent_streams(), Some(100));
         assert_eq!(settings.get_initial_window_size(), 65535);
     }
@@ -413,7 +534,31 @@ mod tests {
         settings1.merge(&settings2);
 
         assert_eq!(settings1.get_header_table_size(), 8192); // Overridden
-        assert_eq!(settings1.get_enable_push(), true); // Unchanged
+        assert!(settings1.get_enable_push()); // Unchanged
         assert_eq!(settings1.get_max_concurrent_streams(), Some(100)); // Added
     }
+
+    #[test]
+    fn test_settings_tracker() {
+        let mut tracker = SettingsTracker::new(Some(Duration::from_secs(60)));
+        assert!(!tracker.is_pending());
+        assert_eq!(tracker.remaining(), None);
+        assert_eq!(tracker.acknowledge().map(|s| s.max_frame_size), None);
+
+        tracker.sent(SettingsBuilder::new().max_frame_size(20000).build().unwrap());
+        tracker.sent(SettingsBuilder::new().enable_push(false).build().unwrap());
+        assert_eq!(tracker.pending_count(), 2);
+        assert!(tracker.remaining().is_some());
+        assert!(!tracker.is_expired());
+
+        // ACKs apply in the order the frames were sent
+        assert_eq!(tracker.acknowledge().unwrap().max_frame_size, Some(20000));
+        assert_eq!(tracker.acknowledge().unwrap().enable_push, Some(false));
+        assert_eq!(tracker.acked_count(), 2);
+        assert!(!tracker.is_pending());
+
+        tracker.set_timeout(Some(Duration::ZERO));
+        tracker.sent(Settings::new());
+        assert!(tracker.is_expired());
+    }
 }
@@ -2,9 +2,11 @@
 //!
 //! This module implements stream management as defined in RFC 7540 Section 5.1.
 
-use super::error::{Error, Result};
+use super::error::{Error, ErrorCode, Result};
 use super::flow_control::StreamFlowControl;
-use super::frames::{DataFrame, HeadersFrame, PrioritySpec};
+use super::frames::{ContinuationFrame, DataFrame, HeadersFrame, PrioritySpec};
+use super::priority::{ExtensiblePriority, PriorityTree};
+use super::DEFAULT_INITIAL_WINDOW_SIZE;
 use std::collections::HashMap;
 
 /// Stream ID type
@@ -57,6 +59,8 @@ pub struct H2Stream {
     flow_control: StreamFlowControl,
     /// Priority information
     priority: Option<PrioritySpec>,
+    /// Extensible priority parameters (RFC 9218)
+    extensible_priority: ExtensiblePriority,
     /// Accumulated header block
     header_block: Vec<u8>,
     /// Accumulated body data
@@ -65,6 +69,12 @@ pub struct H2Stream {
     headers_complete: bool,
     /// Whether we've received END_STREAM
     stream_complete: bool,
+    /// Decoded header fields
+    headers: Vec<(String, String)>,
+    /// Decoded trailer fields
+    trailers: Vec<(String, String)>,
+    /// Error code from a received RST_STREAM
+    reset_code: Option<ErrorCode>,
 }
 
 impl H2Stream {
@@ -75,10 +85,14 @@ impl H2Stream {
             state: StreamState::Idle,
             flow_control: StreamFlowControl::new(id),
             priority: None,
+            extensible_priority: ExtensiblePriority::default(),
             header_block: Vec::new(),
             body: Vec::new(),
             headers_complete: false,
             stream_complete: false,
+            headers: Vec::new(),
+            trailers: Vec::new(),
+            reset_code: None,
         }
     }
 
@@ -89,10 +103,14 @@ impl H2Stream {
             state: StreamState::Idle,
             flow_control: StreamFlowControl::with_initial_sizes(id, send_size, recv_size),
             priority: None,
+            extensible_priority: ExtensiblePriority::default(),
             header_block: Vec::new(),
             body: Vec::new(),
             headers_complete: false,
             stream_complete: false,
+            headers: Vec::new(),
+            trailers: Vec::new(),
+            reset_code: None,
         }
     }
 
@@ -131,6 +149,26 @@ impl H2Stream {
         self.priority = Some(priority);
     }
 
+    /// Get extensible priority parameters (RFC 9218)
+    pub fn extensible_priority(&self) -> ExtensiblePriority {
+        self.extensible_priority
+    }
+
+    /// Set extensible priority parameters
+    pub fn set_extensible_priority(&mut self, priority: ExtensiblePriority) {
+        self.extensible_priority = priority;
+    }
+
+    /// Get urgency, 0 (highest) to 7 (lowest)
+    pub fn urgency(&self) -> u8 {
+        self.extensible_priority.urgency
+    }
+
+    /// Check if the response can be processed incrementally
+    pub fn incremental(&self) -> bool {
+        self.extensible_priority.incremental
+    }
+
     /// Check if headers are complete
     pub fn headers_complete(&self) -> bool {
         self.headers_complete
@@ -156,6 +194,48 @@ impl H2Stream {
         std::mem::take(&mut self.body)
     }
 
+    /// Take accumulated header block (consumes the header block)
+    pub fn take_header_block(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.header_block)
+    }
+
+    /// Get decoded header fields
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Get decoded trailer fields
+    pub fn trailers(&self) -> &[(String, String)] {
+        &self.trailers
+    }
+
+    /// Store a decoded header block
+    ///
+    /// The first block (or the block following an informational 1xx
+    /// response) holds the headers; any later block holds the trailers.
+    pub fn set_decoded_headers(&mut self, fields: Vec<(String, String)>) {
+        let informational = self
+            .headers
+            .iter()
+            .any(|(name, value)| name == ":status" && value.starts_with('1'));
+
+        if self.headers.is_empty() || informational {
+            self.headers = fields;
+        } else {
+            self.trailers = fields;
+        }
+    }
+
+    /// Get the error code from a received RST_STREAM
+    pub fn reset_code(&self) -> Option<ErrorCode> {
+        self.reset_code
+    }
+
+    /// Check if the stream is finished (END_STREAM received or reset)
+    pub fn is_finished(&self) -> bool {
+        self.stream_complete || self.reset_code.is_some()
+    }
+
     /// Process incoming HEADERS frame
     pub fn receive_headers(&mut self, frame: &HeadersFrame) -> Result<()> {
         // Validate state transition
@@ -168,12 +248,19 @@ impl H2Stream {
                 };
             }
             StreamState::ReservedRemote => {
-                self.state = StreamState::HalfClosedLocal;
+                self.state = if frame.end_stream {
+                    StreamState::Closed
+                } else {
+                    StreamState::HalfClosedLocal
+                };
             }
             StreamState::Open | StreamState::HalfClosedLocal => {
-                // Trailers
+                // Response headers or trailers
                 if frame.end_stream {
-                    self.state = StreamState::HalfClosedRemote;
+                    self.state = match self.state {
+                        StreamState::Open => StreamState::HalfClosedRemote,
+                        _ => StreamState::Closed,
+                    };
                 }
             }
             _ => {
@@ -187,10 +274,9 @@ impl H2Stream {
         // Accumulate header block
         self.header_block.extend_from_slice(&frame.header_block);
 
-        // Check if headers are complete
-        if frame.end_headers {
-            self.headers_complete = true;
-        }
+        // Check if headers are complete (a trailer block may still
+        // be followed by CONTINUATION frames)
+        self.headers_complete = frame.end_headers;
 
         // Check if stream is complete
         if frame.end_stream {
@@ -205,6 +291,30 @@ impl H2Stream {
         Ok(())
     }
 
+    /// Process incoming CONTINUATION frame
+    pub fn receive_continuation(&mut self, frame: &ContinuationFrame) -> Result<()> {
+        if self.headers_complete {
+            return Err(Error::Protocol(format!(
+                "Unexpected CONTINUATION on stream {}",
+                self.id
+            )));
+        }
+
+        self.header_block.extend_from_slice(&frame.header_block);
+
+        if frame.end_headers {
+            self.headers_complete = true;
+        }
+
+        Ok(())
+    }
+
+    /// Process incoming RST_STREAM frame
+    pub fn receive_rst_stream(&mut self, error_code: ErrorCode) {
+        self.state = StreamState::Closed;
+        self.reset_code = Some(error_code);
+    }
+
     /// Process incoming DATA frame
     pub fn receive_data(&mut self, frame: &DataFrame) -> Result<()> {
         // Validate state
@@ -212,8 +322,8 @@ impl H2Stream {
             return Err(Error::StreamClosed(self.id));
         }
 
-        // Update flow control
-        self.flow_control.consume_recv_window(frame.data.len());
+        // Flow control covers the whole payload, including padding
+        self.flow_control.consume_recv_window(frame.frame_size());
 
         // Accumulate body data
         self.body.extend_from_slice(&frame.data);
@@ -309,6 +419,14 @@ pub struct StreamManager {
     next_stream_id: StreamId,
     /// Maximum number of concurrent streams (from SETTINGS)
     max_concurrent_streams: Option<u32>,
+    /// Initial send window for new streams (peer's SETTINGS_INITIAL_WINDOW_SIZE)
+    initial_send_window_size: u32,
+    /// Initial receive window for new streams (our SETTINGS_INITIAL_WINDOW_SIZE)
+    initial_recv_window_size: u32,
+    /// Highest stream ID opened by the peer
+    last_remote_stream_id: StreamId,
+    /// Dependency tree (RFC 7540 Section 5.3)
+    priority_tree: PriorityTree,
 }
 
 impl StreamManager {
@@ -321,7 +439,83 @@ impl StreamManager {
             streams: HashMap::new(),
             next_stream_id: if is_client { 1 } else { 2 },
             max_concurrent_streams: None,
+            initial_send_window_size: DEFAULT_INITIAL_WINDOW_SIZE,
+            initial_recv_window_size: DEFAULT_INITIAL_WINDOW_SIZE,
+            last_remote_stream_id: 0,
+            priority_tree: PriorityTree::new(),
+        }
+    }
+
+    /// Set the initial send window used for new streams
+    ///
+    /// Streams that are already open are adjusted separately, since the
+    /// change applies as a delta to their current window (RFC 7540 Section 6.9.2).
+    pub fn set_initial_send_window_size(&mut self, size: u32) {
+        self.initial_send_window_size = size;
+    }
+
+    /// Get the initial send window used for new streams
+    pub fn initial_send_window_size(&self) -> u32 {
+        self.initial_send_window_size
+    }
+
+    /// Set the initial receive window used for new streams
+    pub fn set_initial_recv_window_size(&mut self, size: u32) {
+        self.initial_recv_window_size = size;
+    }
+
+    /// Get the initial receive window used for new streams
+    pub fn initial_recv_window_size(&self) -> u32 {
+        self.initial_recv_window_size
+    }
+
+    /// Apply a change of the peer's SETTINGS_INITIAL_WINDOW_SIZE
+    ///
+    /// New streams start with the new size, and the difference is applied to
+    /// the send window of every stream that is not closed. Windows may become
+    /// negative as a result.
+    pub fn update_initial_send_window_size(&mut self, size: u32) -> Result<()> {
+        self.initial_send_window_size = size;
+
+        for stream in self.streams.values_mut() {
+            if stream.state().is_closed() {
+                continue;
+            }
+            stream
+                .flow_control_mut()
+                .send_window_mut()
+                .update_initial_size(size)?;
         }
+
+        Ok(())
+    }
+
+    /// Apply a change of our own SETTINGS_INITIAL_WINDOW_SIZE
+    ///
+    /// Called once the peer acknowledged the new value; the difference is
+    /// applied to the receive window of every stream that is not closed.
+    pub fn update_initial_recv_window_size(&mut self, size: u32) -> Result<()> {
+        self.initial_recv_window_size = size;
+
+        for stream in self.streams.values_mut() {
+            if stream.state().is_closed() {
+                continue;
+            }
+            stream
+                .flow_control_mut()
+                .recv_window_mut()
+                .update_initial_size(size)?;
+        }
+
+        Ok(())
+    }
+
+    fn new_stream(&self, stream_id: StreamId) -> H2Stream {
+        H2Stream::with_window_sizes(
+            stream_id,
+            self.initial_send_window_size,
+            self.initial_recv_window_size,
+        )
     }
 
     /// Set maximum concurrent streams
@@ -356,8 +550,9 @@ impl StreamManager {
         let stream_id = self.next_stream_id;
         self.next_stream_id += 2; // Skip even for client, odd for server
 
-        let stream = H2Stream::new(stream_id);
+        let stream = self.new_stream(stream_id);
         self.streams.insert(stream_id, stream);
+        self.priority_tree.insert(stream_id);
 
         Ok(stream_id)
     }
@@ -372,28 +567,83 @@ impl StreamManager {
         self.streams.get_mut(&stream_id)
     }
 
-    /// Get or create a stream (for incoming frames)
+    /// Get or create a stream (for incoming frames and promised streams)
     pub fn get_or_create_stream(&mut self, stream_id: StreamId) -> Result<&mut H2Stream> {
         if !self.streams.contains_key(&stream_id) {
-            // Validate stream ID parity
-            let _is_client_initiated = (stream_id % 2) == 1;
-            let _we_are_client = (self.next_stream_id % 2) == 1;
-
-            // Server can create even IDs, client can create odd IDs
-            // But we can receive any valid ID from peer
+            // Streams with the other parity are opened by the peer
+            if self.is_local_stream_id(stream_id) {
+                self.next_stream_id = self.next_stream_id.max(stream_id + 2);
+            } else {
+                self.last_remote_stream_id = self.last_remote_stream_id.max(stream_id);
+            }
 
-            let stream = H2Stream::new(stream_id);
+            let stream = self.new_stream(stream_id);
             self.streams.insert(stream_id, stream);
+            self.priority_tree.insert(stream_id);
         }
 
         Ok(self.streams.get_mut(&stream_id).unwrap())
     }
 
+    /// Get the state of a stream, including streams not (or no longer) tracked
+    ///
+    /// Unknown IDs below the highest ID opened by their initiator are
+    /// implicitly closed (RFC 9113 Section 5.1.1); the others are idle.
+    pub fn stream_state(&self, stream_id: StreamId) -> StreamState {
+        if let Some(stream) = self.streams.get(&stream_id) {
+            return stream.state();
+        }
+
+        let used = if self.is_local_stream_id(stream_id) {
+            stream_id < self.next_stream_id
+        } else {
+            stream_id <= self.last_remote_stream_id
+        };
+
+        if used {
+            StreamState::Closed
+        } else {
+            StreamState::Idle
+        }
+    }
+
+    /// Get the highest stream ID opened by the peer
+    pub fn last_remote_stream_id(&self) -> StreamId {
+        self.last_remote_stream_id
+    }
+
+    fn is_local_stream_id(&self, stream_id: StreamId) -> bool {
+        stream_id % 2 == self.next_stream_id % 2
+    }
+
     /// Remove a stream
+    ///
+    /// The stream also leaves the dependency tree, its dependents moving
+    /// to its parent.
     pub fn remove_stream(&mut self, stream_id: StreamId) -> Option<H2Stream> {
+        self.priority_tree.remove(stream_id);
         self.streams.remove(&stream_id)
     }
 
+    /// Apply a priority specification from HEADERS or PRIORITY
+    ///
+    /// Streams that are not open yet (e.g. idle streams named by a PRIORITY
+    /// frame) only get a place in the dependency tree.
+    pub fn reprioritize(&mut self, stream_id: StreamId, priority: &PrioritySpec) -> Result<()> {
+        self.priority_tree.reprioritize(stream_id, priority)?;
+
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.set_priority(*priority);
+        }
+
+        Ok(())
+    }
+
+    /// Get the dependency tree
+    pub fn priority_tree(&self) -> &PriorityTree {
+        &self.priority_tree
+    }
+
     /// Get number of active streams
     pub fn active_stream_count(&self) -> usize {
         self.streams
@@ -409,7 +659,14 @@ impl StreamManager {
 
     /// Clean up closed streams
     pub fn cleanup_closed_streams(&mut self) {
-        self.streams.retain(|_, stream| !stream.state().is_closed());
+        let tree = &mut self.priority_tree;
+        self.streams.retain(|&stream_id, stream| {
+            let closed = stream.state().is_closed();
+            if closed {
+                tree.remove(stream_id);
+            }
+            !closed
+        });
     }
 }
 
@@ -467,6 +724,21 @@ mod tests {
         assert_eq!(stream.state(), StreamState::HalfClosedRemote);
     }
 
+    #[test]
+    fn test_stream_receive_padded_data() {
+        let mut stream = H2Stream::new(1);
+        stream.state = StreamState::Open;
+
+        // 1 byte of pad length, 4 of data and 10 of padding
+        let frame = DataFrame::new(1, Bytes::from("body"), false).with_padding(10);
+        stream.receive_data(&frame).unwrap();
+        assert_eq!(stream.body(), b"body");
+        assert_eq!(
+            stream.flow_control().recv_window().size(),
+            DEFAULT_INITIAL_WINDOW_SIZE as i64 - 15
+        );
+    }
+
     #[test]
     fn test_stream_manager_client() {
         let mut manager = StreamManager::new(true);
@@ -531,5 +803,98 @@ mod tests {
         assert_eq!(manager.stream_ids().len(), 1);
         assert!(manager.get_stream(id1).is_none());
         assert!(manager.get_stream(id2).is_some());
+        assert!(!manager.priority_tree().contains(id1));
+    }
+
+    #[test]
+    fn test_stream_manager_priority_tree() {
+        let mut manager = StreamManager::new(false);
+        manager.get_or_create_stream(1).unwrap();
+        manager.get_or_create_stream(3).unwrap();
+
+        manager.reprioritize(3, &PrioritySpec::new(1, true, 63)).unwrap();
+        assert_eq!(manager.priority_tree().parent(3), Some(1));
+        assert_eq!(manager.priority_tree().weight(3), Some(64));
+        assert_eq!(manager.get_stream(3).unwrap().priority().unwrap().weight, 63);
+
+        // PRIORITY may name an idle stream
+        manager.reprioritize(5, &PrioritySpec::new(3, false, 15)).unwrap();
+        assert!(manager.get_stream(5).is_none());
+        assert_eq!(manager.priority_tree().parent(5), Some(3));
+
+        assert!(manager.reprioritize(7, &PrioritySpec::new(7, false, 15)).is_err());
+
+        manager.remove_stream(3);
+        assert_eq!(manager.priority_tree().parent(5), Some(1));
+    }
+
+    #[test]
+    fn test_stream_manager_stream_state() {
+        let mut manager = StreamManager::new(true);
+
+        let id = manager.create_stream().unwrap();
+        manager.get_or_create_stream(4).unwrap();
+        manager.remove_stream(id);
+
+        assert_eq!(manager.stream_state(id), StreamState::Closed);
+        assert_eq!(manager.stream_state(3), StreamState::Idle);
+        assert_eq!(manager.stream_state(2), StreamState::Closed);
+        assert_eq!(manager.stream_state(4), StreamState::Idle);
+        assert_eq!(manager.stream_state(6), StreamState::Idle);
+        assert_eq!(manager.last_remote_stream_id(), 4);
+    }
+
+    #[test]
+    fn test_stream_headers_and_trailers() {
+        let mut stream = H2Stream::new(1);
+        stream.send_headers(true).unwrap();
+
+        let frame = HeadersFrame::new(1, Bytes::from("part1"), false, false);
+        stream.receive_headers(&frame).unwrap();
+        assert!(!stream.headers_complete());
+
+        let continuation = ContinuationFrame {
+            stream_id: 1,
+            header_block: Bytes::from("part2"),
+            end_headers: true,
+        };
+        stream.receive_continuation(&continuation).unwrap();
+        assert!(stream.headers_complete());
+        assert_eq!(stream.take_header_block(), b"part1part2");
+
+        stream.set_decoded_headers(vec![(":status".to_string(), "200".to_string())]);
+        stream.set_decoded_headers(vec![("grpc-status".to_string(), "0".to_string())]);
+        assert_eq!(stream.headers()[0].1, "200");
+        assert_eq!(stream.trailers()[0].0, "grpc-status");
+
+        // A second CONTINUATION after END_HEADERS is rejected
+        assert!(stream.receive_continuation(&continuation).is_err());
+
+        stream.receive_rst_stream(ErrorCode::Cancel);
+        assert!(stream.is_finished());
+        assert_eq!(stream.reset_code(), Some(ErrorCode::Cancel));
+        assert_eq!(stream.state(), StreamState::Closed);
+    }
+
+    #[test]
+    fn test_stream_manager_initial_window_size_change() {
+        let mut manager = StreamManager::new(true);
+        let id1 = manager.create_stream().unwrap();
+        manager
+            .get_stream_mut(id1)
+            .unwrap()
+            .flow_control_mut()
+            .consume_send_window(60000)
+            .unwrap();
+
+        // Shrinking the initial window can make open streams go negative
+        manager.update_initial_send_window_size(16384).unwrap();
+        let window = manager.get_stream(id1).unwrap().flow_control().send_window();
+        assert_eq!(window.size(), 16384 - 60000);
+
+        // New streams start with the new size
+        let id2 = manager.create_stream().unwrap();
+        let window = manager.get_stream(id2).unwrap().flow_control().send_window();
+        assert_eq!(window.size(), 16384);
     }
 }
@@ -0,0 +1,235 @@
+//! Byte-stream tunnels over HTTP/2 streams
+//!
+//! A CONNECT request turns its stream into a tunnel: once the response
+//! headers are sent, DATA frames carry an opaque byte stream in both
+//! directions until either side sends END_STREAM (RFC 9113 Section 8.5).
+//! Extended CONNECT (RFC 8441) adds a `:protocol` pseudo-header naming
+//! what runs over the tunnel, such as WebSockets (see `websocket`). The
+//! server must first advertise SETTINGS_ENABLE_CONNECT_PROTOCOL.
+//!
+//! `H2Tunnel` wraps the client or server side of such a stream. It
+//! implements `std::io::Read` and `std::io::Write`, and `SessionOps` so that
+//! HTTP/1, HTTP/2 or TLS (through `SessionStream`) can run inside it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vtest2::http::h2::{H2Client, H2ServerBuilder};
+//! use vtest2::http::session::FdSessionOps;
+//! use std::io::{Read, Write};
+//! use std::net::{TcpListener, TcpStream};
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! // Server side
+//! let listener = TcpListener::bind("127.0.0.1:8080")?;
+//! let (stream, _) = listener.accept()?;
+//! let mut server = H2ServerBuilder::new()
+//!     .enable_connect_protocol(true)
+//!     .build(FdSessionOps::new(stream))?;
+//! let request = server.recv_request()?;
+//! assert_eq!(request.protocol(), Some("echo"));
+//! server.accept_connect(request.stream_id, &[])?;
+//! let mut tunnel = server.tunnel(request.stream_id);
+//! let mut buf = [0u8; 5];
+//! tunnel.read_exact(&mut buf)?;
+//! tunnel.write_all(&buf)?;
+//! tunnel.close()?;
+//!
+//! // Client side
+//! let mut client = H2Client::new(FdSessionOps::new(TcpStream::connect("127.0.0.1:8080")?))?;
+//! client.connect()?;
+//! let mut tunnel = client.extended_connect("echo", "/", &[])?;
+//! tunnel.write_all(b"hello")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::error::Result;
+use super::stream::StreamId;
+use crate::http::session::{PollEvents, SessionOps};
+use crate::http::Result as HttpResult;
+use bytes::Bytes;
+use std::io;
+use std::time::Duration;
+
+/// Side of an HTTP/2 connection able to carry a tunnel
+///
+/// Implemented by `H2Client` and `H2Server`.
+pub trait TunnelEndpoint {
+    /// Send data on a stream, ending it if `end_stream` is set
+    fn send_stream_data(&mut self, stream_id: StreamId, data: Bytes, end_stream: bool) -> Result<()>;
+
+    /// Receive the next data on a stream, or None once the peer ended it
+    fn recv_stream_data(&mut self, stream_id: StreamId) -> Result<Option<Bytes>>;
+
+    /// Check whether `recv_stream_data` may return without blocking
+    ///
+    /// May report readiness for frames of other streams.
+    fn poll_stream(&self, stream_id: StreamId, timeout: Option<Duration>) -> Result<bool>;
+}
+
+/// Bidirectional byte stream carried by a CONNECT stream
+pub struct H2Tunnel<'a, E: TunnelEndpoint> {
+    /// Connection carrying the tunnel
+    endpoint: &'a mut E,
+    /// Stream ID of the CONNECT request
+    stream_id: StreamId,
+    /// Received data not yet read
+    buffer: Bytes,
+    /// The peer ended the stream
+    eof: bool,
+}
+
+impl<'a, E: TunnelEndpoint> H2Tunnel<'a, E> {
+    /// Wrap an established CONNECT stream
+    pub fn new(endpoint: &'a mut E, stream_id: StreamId) -> Self {
+        H2Tunnel {
+            endpoint,
+            stream_id,
+            buffer: Bytes::new(),
+            eof: false,
+        }
+    }
+
+    /// Get the stream ID
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Get the underlying connection
+    pub fn endpoint(&mut self) -> &mut E {
+        self.endpoint
+    }
+
+    /// Send data through the tunnel
+    pub fn send(&mut self, data: impl Into<Bytes>) -> Result<()> {
+        self.endpoint.send_stream_data(self.stream_id, data.into(), false)
+    }
+
+    /// Receive the next chunk of data, or None once the peer closed
+    pub fn recv(&mut self) -> Result<Option<Bytes>> {
+        if !self.buffer.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.buffer)));
+        }
+        if self.eof {
+            return Ok(None);
+        }
+
+        let data = self.endpoint.recv_stream_data(self.stream_id)?;
+        self.eof = data.is_none();
+        Ok(data)
+    }
+
+    /// Check whether the peer closed its side of the tunnel
+    pub fn is_eof(&self) -> bool {
+        self.eof && self.buffer.is_empty()
+    }
+
+    /// Close our side of the tunnel with END_STREAM
+    pub fn close(&mut self) -> Result<()> {
+        self.endpoint.send_stream_data(self.stream_id, Bytes::new(), true)
+    }
+}
+
+impl<E: TunnelEndpoint> io::Read for H2Tunnel<'_, E> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            match self.recv().map_err(io::Error::other)? {
+                Some(data) => self.buffer = data,
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len());
+        buf[..len].copy_from_slice(&self.buffer.split_to(len));
+        Ok(len)
+    }
+}
+
+impl<E: TunnelEndpoint> io::Write for H2Tunnel<'_, E> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(Bytes::copy_from_slice(buf)).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<E: TunnelEndpoint> SessionOps for H2Tunnel<'_, E> {
+    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> HttpResult<bool> {
+        if events == PollEvents::Write || !self.buffer.is_empty() || self.eof {
+            return Ok(true);
+        }
+        self.endpoint
+            .poll_stream(self.stream_id, timeout)
+            .map_err(|e| io::Error::other(e).into())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> HttpResult<usize> {
+        Ok(io::Read::read(self, buf)?)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> HttpResult<usize> {
+        Ok(io::Write::write(self, buf)?)
+    }
+
+    fn close(&mut self) -> HttpResult<()> {
+        H2Tunnel::close(self).map_err(|e| io::Error::other(e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::{Read, Write};
+
+    /// Endpoint replaying canned DATA and recording what is sent
+    #[derive(Default)]
+    struct Loopback {
+        incoming: VecDeque<Bytes>,
+        sent: Vec<(Bytes, bool)>,
+    }
+
+    impl TunnelEndpoint for Loopback {
+        fn send_stream_data(&mut self, _stream_id: StreamId, data: Bytes, end_stream: bool) -> Result<()> {
+            self.sent.push((data, end_stream));
+            Ok(())
+        }
+
+        fn recv_stream_data(&mut self, _stream_id: StreamId) -> Result<Option<Bytes>> {
+            Ok(self.incoming.pop_front())
+        }
+
+        fn poll_stream(&self, _stream_id: StreamId, _timeout: Option<Duration>) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_tunnel_read_write() {
+        let mut endpoint = Loopback::default();
+        endpoint.incoming.push_back(Bytes::from_static(b"hello "));
+        endpoint.incoming.push_back(Bytes::from_static(b"world"));
+
+        let mut tunnel = H2Tunnel::new(&mut endpoint, 1);
+        let mut buf = [0u8; 4];
+        tunnel.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hell");
+
+        let mut rest = String::new();
+        tunnel.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "o world");
+        assert!(tunnel.is_eof());
+        assert_eq!(tunnel.recv().unwrap(), None);
+
+        tunnel.write_all(b"ping").unwrap();
+        tunnel.close().unwrap();
+        assert_eq!(
+            endpoint.sent,
+            vec![(Bytes::from_static(b"ping"), false), (Bytes::new(), true)]
+        );
+    }
+}
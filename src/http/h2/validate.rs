@@ -0,0 +1,829 @@
+//! Received frame validation
+//!
+//! This module checks every received frame against the rules of RFC 9113
+//! (frame layout, stream identifiers, header block sequencing, the stream
+//! state machine and the receive windows) and reports the exact error the receiver must
+//! signal, as a connection error (GOAWAY) or a stream error (RST_STREAM).
+//!
+//! `H2Client` and `H2Server` run the validator on the frames they process;
+//! it can also be used on its own to assert how a peer is expected to react
+//! to a crafted frame sequence.
+//!
+//! # Example
+//!
+//! ```
+//! use vtest2::http::h2::validate::{FrameValidator, ViolationScope};
+//! use vtest2::http::h2::{FrameFlags, FrameType, StreamState};
+//! use vtest2::http::h2::error::ErrorCode;
+//!
+//! let mut validator = FrameValidator::new(false);
+//!
+//! // PING payloads must be exactly 8 bytes
+//! let violation = validator
+//!     .validate(FrameType::Ping, FrameFlags::empty(), 0, &[0; 4], StreamState::Idle)
+//!     .unwrap_err();
+//! assert_eq!(violation.scope, ViolationScope::Connection);
+//! assert_eq!(violation.error_code, ErrorCode::FrameSizeError);
+//! ```
+
+use super::error::{Error, ErrorCode};
+use super::frames::{FrameFlags, FrameType};
+use super::settings::SettingsParameter;
+use super::stream::{StreamId, StreamState};
+use super::{CONNECTION_STREAM_ID, DEFAULT_MAX_FRAME_SIZE};
+use std::fmt;
+
+/// Scope of a protocol violation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationScope {
+    /// Connection error: the receiver sends GOAWAY (RFC 9113 Section 5.4.1)
+    Connection,
+    /// Stream error: the receiver sends RST_STREAM (RFC 9113 Section 5.4.2)
+    Stream(StreamId),
+}
+
+/// A received frame breaking a protocol rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Connection or stream error
+    pub scope: ViolationScope,
+    /// Error code to signal
+    pub error_code: ErrorCode,
+    /// Description of the broken rule
+    pub reason: String,
+}
+
+impl Violation {
+    /// Create a connection error
+    pub fn connection(error_code: ErrorCode, reason: impl Into<String>) -> Self {
+        Violation {
+            scope: ViolationScope::Connection,
+            error_code,
+            reason: reason.into(),
+        }
+    }
+
+    /// Create a stream error
+    pub fn stream(stream_id: StreamId, error_code: ErrorCode, reason: impl Into<String>) -> Self {
+        Violation {
+            scope: ViolationScope::Stream(stream_id),
+            error_code,
+            reason: reason.into(),
+        }
+    }
+
+    /// Check if this is a connection error
+    pub fn is_connection_error(&self) -> bool {
+        self.scope == ViolationScope::Connection
+    }
+
+    /// Convert into the matching `Error`
+    pub fn into_error(self) -> Error {
+        match self.error_code {
+            ErrorCode::FrameSizeError => Error::FrameSize(self.reason),
+            ErrorCode::FlowControlError => Error::FlowControl(self.reason),
+            ErrorCode::CompressionError => Error::Compression(self.reason),
+            ErrorCode::StreamClosed => match self.scope {
+                ViolationScope::Stream(stream_id) => Error::StreamClosed(stream_id),
+                ViolationScope::Connection => Error::Protocol(self.reason),
+            },
+            _ => Error::Protocol(self.reason),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scope {
+            ViolationScope::Connection => write!(f, "connection error {}: {}", self.error_code, self.reason),
+            ViolationScope::Stream(id) => write!(f, "stream {} error {}: {}", id, self.error_code, self.reason),
+        }
+    }
+}
+
+/// Validator for the frames received on one connection
+///
+/// The validator tracks the connection-level state the rules depend on
+/// (pending header blocks, promised streams); the state of the frame's
+/// stream is supplied by the caller, see `StreamManager::stream_state`.
+#[derive(Debug, Clone)]
+pub struct FrameValidator {
+    /// Validating frames received by a client (sent by a server)
+    is_client: bool,
+    /// Our SETTINGS_MAX_FRAME_SIZE
+    max_frame_size: u32,
+    /// Our SETTINGS_ENABLE_PUSH
+    enable_push: bool,
+    /// Stream whose header block awaits CONTINUATION
+    continuation_stream: Option<StreamId>,
+    /// Highest promised stream ID seen
+    last_promised_stream_id: StreamId,
+    /// Highest stream ID opened by a client HEADERS
+    last_opened_stream_id: StreamId,
+}
+
+impl FrameValidator {
+    /// Create a validator for frames received by a client or a server
+    pub fn new(is_client: bool) -> Self {
+        FrameValidator {
+            is_client,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            enable_push: false,
+            continuation_stream: None,
+            last_promised_stream_id: 0,
+            last_opened_stream_id: 0,
+        }
+    }
+
+    /// Set our advertised SETTINGS_MAX_FRAME_SIZE
+    pub fn set_max_frame_size(&mut self, size: u32) {
+        self.max_frame_size = size;
+    }
+
+    /// Set our advertised SETTINGS_ENABLE_PUSH
+    pub fn set_enable_push(&mut self, enable: bool) {
+        self.enable_push = enable;
+    }
+
+    /// Get the stream whose header block awaits CONTINUATION
+    pub fn continuation_stream(&self) -> Option<StreamId> {
+        self.continuation_stream
+    }
+
+    /// Validate a received frame
+    ///
+    /// `state` is the state of the frame's stream before the frame is
+    /// applied (ignored for stream 0). Header blocks are tracked unless the
+    /// frame is a connection error: a stream error still leaves the
+    /// CONTINUATION frames of its header block to come.
+    pub fn validate(
+        &mut self,
+        frame_type: FrameType,
+        flags: FrameFlags,
+        stream_id: StreamId,
+        payload: &[u8],
+        state: StreamState,
+    ) -> Result<(), Violation> {
+        let result = self.check(frame_type, flags, stream_id, payload, state);
+        if result.as_ref().is_err_and(Violation::is_connection_error) {
+            return result;
+        }
+
+        // Track header blocks spanning several frames
+        match frame_type {
+            FrameType::Headers | FrameType::PushPromise if !flags.is_end_headers() => {
+                self.continuation_stream = Some(stream_id);
+            }
+            FrameType::Continuation if flags.is_end_headers() => {
+                self.continuation_stream = None;
+            }
+            _ => {}
+        }
+
+        result
+    }
+
+    /// Check a DATA frame against our receive windows (RFC 9113 Section 6.9.1)
+    ///
+    /// `connection_window` and `stream_window` are the windows before the
+    /// frame is applied; the stream window is `None` when the stream is not
+    /// tracked. `len` is the whole payload, padding included.
+    pub fn validate_data_window(
+        &self,
+        stream_id: StreamId,
+        len: usize,
+        connection_window: i64,
+        stream_window: Option<i64>,
+    ) -> Result<(), Violation> {
+        if len as i64 > connection_window {
+            return Err(Violation::connection(
+                ErrorCode::FlowControlError,
+                format!("DATA of {} bytes exceeds the connection window of {}", len, connection_window),
+            ));
+        }
+        if let Some(window) = stream_window.filter(|window| len as i64 > *window) {
+            return Err(Violation::stream(
+                stream_id,
+                ErrorCode::FlowControlError,
+                format!("DATA of {} bytes exceeds the window of stream {} of {}", len, stream_id, window),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check(
+        &mut self,
+        frame_type: FrameType,
+        flags: FrameFlags,
+        stream_id: StreamId,
+        payload: &[u8],
+        state: StreamState,
+    ) -> Result<(), Violation> {
+        // A header block must be continued on the same stream (RFC 9113 Section 6.10)
+        if let Some(expected) = self.continuation_stream {
+            if frame_type != FrameType::Continuation || stream_id != expected {
+                return Err(Violation::connection(
+                    ErrorCode::ProtocolError,
+                    format!("{} received while a header block on stream {} is open", frame_type.name(), expected),
+                ));
+            }
+        }
+
+        self.check_frame_size(frame_type, stream_id, payload.len())?;
+
+        match frame_type {
+            FrameType::Data => self.check_data(flags, stream_id, payload, state)?,
+            FrameType::Headers => self.check_headers(flags, stream_id, payload, state)?,
+            FrameType::Priority => check_priority(stream_id, payload)?,
+            FrameType::RstStream => check_rst_stream(stream_id, payload, state)?,
+            FrameType::Settings => self.check_settings(flags, stream_id, payload)?,
+            FrameType::PushPromise => self.check_push_promise(flags, stream_id, payload, state)?,
+            FrameType::Ping => check_ping(stream_id, payload)?,
+            FrameType::Goaway => check_goaway(stream_id, payload)?,
+            FrameType::WindowUpdate => check_window_update(stream_id, payload, state)?,
+            FrameType::Continuation => {
+                if self.continuation_stream.is_none() {
+                    return Err(Violation::connection(
+                        ErrorCode::ProtocolError,
+                        format!("CONTINUATION on stream {} without a header block", stream_id),
+                    ));
+                }
+            }
+            FrameType::PriorityUpdate => self.check_priority_update(stream_id, payload)?,
+            // Extension and unknown frames are ignored (RFC 9113 Section 5.5)
+            FrameType::AltSvc | FrameType::Origin | FrameType::Unknown(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Check the payload length against SETTINGS_MAX_FRAME_SIZE (RFC 9113 Section 4.2)
+    fn check_frame_size(&self, frame_type: FrameType, stream_id: StreamId, len: usize) -> Result<(), Violation> {
+        if len <= self.max_frame_size as usize {
+            return Ok(());
+        }
+
+        let reason = format!("{} payload of {} bytes exceeds {}", frame_type.name(), len, self.max_frame_size);
+
+        // Frames that alter connection state are connection errors
+        let connection_state = matches!(
+            frame_type,
+            FrameType::Headers | FrameType::PushPromise | FrameType::Continuation | FrameType::Settings
+        );
+        if connection_state || stream_id == CONNECTION_STREAM_ID {
+            Err(Violation::connection(ErrorCode::FrameSizeError, reason))
+        } else {
+            Err(Violation::stream(stream_id, ErrorCode::FrameSizeError, reason))
+        }
+    }
+
+    /// DATA (RFC 9113 Section 6.1)
+    fn check_data(&self, flags: FrameFlags, stream_id: StreamId, payload: &[u8], state: StreamState) -> Result<(), Violation> {
+        require_stream(FrameType::Data, stream_id)?;
+        check_padding(FrameType::Data, flags, payload, 0)?;
+
+        match state {
+            StreamState::Open | StreamState::HalfClosedLocal => Ok(()),
+            StreamState::Idle => Err(idle_stream(FrameType::Data, stream_id)),
+            _ => Err(Violation::stream(
+                stream_id,
+                ErrorCode::StreamClosed,
+                format!("DATA on stream {} in state {:?}", stream_id, state),
+            )),
+        }
+    }
+
+    /// HEADERS (RFC 9113 Section 6.2)
+    fn check_headers(&mut self, flags: FrameFlags, stream_id: StreamId, payload: &[u8], state: StreamState) -> Result<(), Violation> {
+        require_stream(FrameType::Headers, stream_id)?;
+
+        let priority_len = if flags.is_priority() { 5 } else { 0 };
+        check_padding(FrameType::Headers, flags, payload, priority_len)?;
+
+        if flags.is_priority() {
+            let start = if flags.is_padded() { 1 } else { 0 };
+            check_self_dependency(FrameType::Headers, stream_id, &payload[start..start + 4])?;
+        }
+
+        match state {
+            StreamState::Idle => {
+                // Only clients open streams with HEADERS; servers use PUSH_PROMISE
+                let client_initiated = stream_id % 2 == 1;
+                if self.is_client || !client_initiated {
+                    return Err(Violation::connection(
+                        ErrorCode::ProtocolError,
+                        format!("HEADERS opening stream {} from the wrong peer", stream_id),
+                    ));
+                }
+                // New streams must use increasing IDs (RFC 9113 Section 5.1.1)
+                if stream_id < self.last_opened_stream_id {
+                    return Err(Violation::connection(
+                        ErrorCode::ProtocolError,
+                        format!("HEADERS opening stream {} after stream {}", stream_id, self.last_opened_stream_id),
+                    ));
+                }
+                self.last_opened_stream_id = stream_id;
+                Ok(())
+            }
+            StreamState::Open | StreamState::HalfClosedLocal => Ok(()),
+            StreamState::ReservedRemote if self.is_client => Ok(()),
+            _ => Err(Violation::stream(
+                stream_id,
+                ErrorCode::StreamClosed,
+                format!("HEADERS on stream {} in state {:?}", stream_id, state),
+            )),
+        }
+    }
+
+    /// SETTINGS (RFC 9113 Section 6.5)
+    fn check_settings(&self, flags: FrameFlags, stream_id: StreamId, payload: &[u8]) -> Result<(), Violation> {
+        require_connection(FrameType::Settings, stream_id)?;
+
+        if flags.is_ack() {
+            if !payload.is_empty() {
+                return Err(Violation::connection(ErrorCode::FrameSizeError, "SETTINGS ACK with a payload"));
+            }
+            return Ok(());
+        }
+
+        if !payload.len().is_multiple_of(6) {
+            return Err(Violation::connection(
+                ErrorCode::FrameSizeError,
+                format!("SETTINGS payload length {} is not a multiple of 6", payload.len()),
+            ));
+        }
+
+        for entry in payload.chunks_exact(6) {
+            let id = u16::from_be_bytes([entry[0], entry[1]]);
+            let value = u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]);
+            let Some(parameter) = SettingsParameter::from_u16(id) else {
+                continue;
+            };
+
+            let reason = || format!("{} = {}", parameter.name(), value);
+            match parameter {
+                // A server must never announce push support
+                SettingsParameter::EnablePush if value > 1 || (self.is_client && value == 1) => {
+                    return Err(Violation::connection(ErrorCode::ProtocolError, reason()));
+                }
+                SettingsParameter::InitialWindowSize if value > 0x7FFFFFFF => {
+                    return Err(Violation::connection(ErrorCode::FlowControlError, reason()));
+                }
+                SettingsParameter::MaxFrameSize if !(16384..=16777215).contains(&value) => {
+                    return Err(Violation::connection(ErrorCode::ProtocolError, reason()));
+                }
+                SettingsParameter::EnableConnectProtocol | SettingsParameter::NoRfc7540Priorities
+                    if value > 1 =>
+                {
+                    return Err(Violation::connection(ErrorCode::ProtocolError, reason()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// PUSH_PROMISE (RFC 9113 Section 6.6)
+    fn check_push_promise(
+        &mut self,
+        flags: FrameFlags,
+        stream_id: StreamId,
+        payload: &[u8],
+        state: StreamState,
+    ) -> Result<(), Violation> {
+        if !self.is_client {
+            return Err(Violation::connection(ErrorCode::ProtocolError, "PUSH_PROMISE sent by a client"));
+        }
+        if !self.enable_push {
+            return Err(Violation::connection(
+                ErrorCode::ProtocolError,
+                "PUSH_PROMISE received with push disabled",
+            ));
+        }
+        require_stream(FrameType::PushPromise, stream_id)?;
+        check_padding(FrameType::PushPromise, flags, payload, 4)?;
+
+        if !matches!(state, StreamState::Open | StreamState::HalfClosedLocal) {
+            return Err(Violation::connection(
+                ErrorCode::ProtocolError,
+                format!("PUSH_PROMISE on stream {} in state {:?}", stream_id, state),
+            ));
+        }
+
+        let start = if flags.is_padded() { 1 } else { 0 };
+        let promised = u32::from_be_bytes([payload[start], payload[start + 1], payload[start + 2], payload[start + 3]])
+            & 0x7FFFFFFF;
+        if promised == 0 || !promised.is_multiple_of(2) || promised <= self.last_promised_stream_id {
+            return Err(Violation::connection(
+                ErrorCode::ProtocolError,
+                format!("Invalid promised stream ID {}", promised),
+            ));
+        }
+        self.last_promised_stream_id = promised;
+
+        Ok(())
+    }
+
+    /// PRIORITY_UPDATE (RFC 9218 Section 7.1)
+    fn check_priority_update(&self, stream_id: StreamId, payload: &[u8]) -> Result<(), Violation> {
+        if self.is_client {
+            return Err(Violation::connection(ErrorCode::ProtocolError, "PRIORITY_UPDATE sent by a server"));
+        }
+        require_connection(FrameType::PriorityUpdate, stream_id)?;
+
+        if payload.len() < 4 {
+            return Err(Violation::connection(
+                ErrorCode::FrameSizeError,
+                format!("PRIORITY_UPDATE payload of {} bytes", payload.len()),
+            ));
+        }
+
+        let prioritized = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7FFFFFFF;
+        if prioritized == CONNECTION_STREAM_ID {
+            return Err(Violation::connection(
+                ErrorCode::ProtocolError,
+                "PRIORITY_UPDATE for stream 0",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Reject frames that must be on a stream
+fn require_stream(frame_type: FrameType, stream_id: StreamId) -> Result<(), Violation> {
+    if stream_id == CONNECTION_STREAM_ID {
+        return Err(Violation::connection(
+            ErrorCode::ProtocolError,
+            format!("{} frame on stream 0", frame_type.name()),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject frames that must be on stream 0
+fn require_connection(frame_type: FrameType, stream_id: StreamId) -> Result<(), Violation> {
+    if stream_id != CONNECTION_STREAM_ID {
+        return Err(Violation::connection(
+            ErrorCode::ProtocolError,
+            format!("{} frame on stream {}", frame_type.name(), stream_id),
+        ));
+    }
+    Ok(())
+}
+
+/// Frames other than HEADERS and PRIORITY are illegal on idle streams (RFC 9113 Section 5.1)
+fn idle_stream(frame_type: FrameType, stream_id: StreamId) -> Violation {
+    Violation::connection(
+        ErrorCode::ProtocolError,
+        format!("{} on idle stream {}", frame_type.name(), stream_id),
+    )
+}
+
+/// Check the pad length and the fixed fields preceding the padding
+fn check_padding(frame_type: FrameType, flags: FrameFlags, payload: &[u8], fixed_len: usize) -> Result<(), Violation> {
+    let (pad_len, header_len) = if flags.is_padded() {
+        match payload.first() {
+            Some(&pad_len) => (pad_len as usize, 1 + fixed_len),
+            None => {
+                return Err(Violation::connection(
+                    ErrorCode::FrameSizeError,
+                    format!("Padded {} without pad length", frame_type.name()),
+                ));
+            }
+        }
+    } else {
+        (0, fixed_len)
+    };
+
+    if payload.len() < header_len {
+        return Err(Violation::connection(
+            ErrorCode::FrameSizeError,
+            format!("{} payload too short", frame_type.name()),
+        ));
+    }
+
+    if pad_len > payload.len() - header_len {
+        return Err(Violation::connection(
+            ErrorCode::ProtocolError,
+            format!("{} padding of {} bytes exceeds the payload", frame_type.name(), pad_len),
+        ));
+    }
+
+    Ok(())
+}
+
+/// A stream cannot depend on itself (RFC 7540 Section 5.3.1)
+fn check_self_dependency(frame_type: FrameType, stream_id: StreamId, dependency: &[u8]) -> Result<(), Violation> {
+    let dependency = u32::from_be_bytes([dependency[0], dependency[1], dependency[2], dependency[3]]) & 0x7FFFFFFF;
+    if dependency == stream_id {
+        return Err(Violation::stream(
+            stream_id,
+            ErrorCode::ProtocolError,
+            format!("{} makes stream {} depend on itself", frame_type.name(), stream_id),
+        ));
+    }
+    Ok(())
+}
+
+/// PRIORITY (RFC 9113 Section 6.3)
+fn check_priority(stream_id: StreamId, payload: &[u8]) -> Result<(), Violation> {
+    require_stream(FrameType::Priority, stream_id)?;
+
+    if payload.len() != 5 {
+        return Err(Violation::stream(
+            stream_id,
+            ErrorCode::FrameSizeError,
+            format!("PRIORITY payload of {} bytes", payload.len()),
+        ));
+    }
+
+    check_self_dependency(FrameType::Priority, stream_id, &payload[..4])
+}
+
+/// RST_STREAM (RFC 9113 Section 6.4)
+fn check_rst_stream(stream_id: StreamId, payload: &[u8], state: StreamState) -> Result<(), Violation> {
+    require_stream(FrameType::RstStream, stream_id)?;
+
+    if payload.len() != 4 {
+        return Err(Violation::connection(
+            ErrorCode::FrameSizeError,
+            format!("RST_STREAM payload of {} bytes", payload.len()),
+        ));
+    }
+
+    if state == StreamState::Idle {
+        return Err(idle_stream(FrameType::RstStream, stream_id));
+    }
+
+    Ok(())
+}
+
+/// PING (RFC 9113 Section 6.7)
+fn check_ping(stream_id: StreamId, payload: &[u8]) -> Result<(), Violation> {
+    require_connection(FrameType::Ping, stream_id)?;
+
+    if payload.len() != 8 {
+        return Err(Violation::connection(
+            ErrorCode::FrameSizeError,
+            format!("PING payload of {} bytes", payload.len()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// GOAWAY (RFC 9113 Section 6.8)
+fn check_goaway(stream_id: StreamId, payload: &[u8]) -> Result<(), Violation> {
+    require_connection(FrameType::Goaway, stream_id)?;
+
+    if payload.len() < 8 {
+        return Err(Violation::connection(
+            ErrorCode::FrameSizeError,
+            format!("GOAWAY payload of {} bytes", payload.len()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// WINDOW_UPDATE (RFC 9113 Section 6.9)
+fn check_window_update(stream_id: StreamId, payload: &[u8], state: StreamState) -> Result<(), Violation> {
+    if payload.len() != 4 {
+        return Err(Violation::connection(
+            ErrorCode::FrameSizeError,
+            format!("WINDOW_UPDATE payload of {} bytes", payload.len()),
+        ));
+    }
+
+    let increment = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7FFFFFFF;
+    if increment == 0 {
+        let reason = "WINDOW_UPDATE with a zero increment";
+        return Err(if stream_id == CONNECTION_STREAM_ID {
+            Violation::connection(ErrorCode::ProtocolError, reason)
+        } else {
+            Violation::stream(stream_id, ErrorCode::ProtocolError, reason)
+        });
+    }
+
+    if stream_id != CONNECTION_STREAM_ID && state == StreamState::Idle {
+        return Err(idle_stream(FrameType::WindowUpdate, stream_id));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(bits: u8) -> FrameFlags {
+        FrameFlags::from_u8(bits)
+    }
+
+    fn check(
+        validator: &mut FrameValidator,
+        frame_type: FrameType,
+        flag_bits: u8,
+        stream_id: StreamId,
+        payload: &[u8],
+        state: StreamState,
+    ) -> Option<(ViolationScope, ErrorCode)> {
+        validator
+            .validate(frame_type, flags(flag_bits), stream_id, payload, state)
+            .err()
+            .map(|v| (v.scope, v.error_code))
+    }
+
+    #[test]
+    fn test_connection_level_frames() {
+        let mut v = FrameValidator::new(false);
+        let conn = ViolationScope::Connection;
+
+        assert_eq!(check(&mut v, FrameType::Data, 0, 0, b"x", StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::Settings, 0, 1, &[], StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::Settings, 0x1, 0, &[0; 6], StreamState::Idle), Some((conn, ErrorCode::FrameSizeError)));
+        assert_eq!(check(&mut v, FrameType::Settings, 0, 0, &[0; 5], StreamState::Idle), Some((conn, ErrorCode::FrameSizeError)));
+        assert_eq!(check(&mut v, FrameType::Ping, 0, 0, &[0; 7], StreamState::Idle), Some((conn, ErrorCode::FrameSizeError)));
+        assert_eq!(check(&mut v, FrameType::Ping, 0, 3, &[0; 8], StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::Goaway, 0, 0, &[0; 4], StreamState::Idle), Some((conn, ErrorCode::FrameSizeError)));
+        assert_eq!(check(&mut v, FrameType::WindowUpdate, 0, 0, &[0; 4], StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::Ping, 0, 0, &[0; 8], StreamState::Idle), None);
+
+        // SETTINGS values out of range
+        let window = [0, 4, 0x80, 0, 0, 0];
+        assert_eq!(check(&mut v, FrameType::Settings, 0, 0, &window, StreamState::Idle), Some((conn, ErrorCode::FlowControlError)));
+        let frame_size = [0, 5, 0, 0, 0x10, 0];
+        assert_eq!(check(&mut v, FrameType::Settings, 0, 0, &frame_size, StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+        let push = [0, 2, 0, 0, 0, 2];
+        assert_eq!(check(&mut v, FrameType::Settings, 0, 0, &push, StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+    }
+
+    #[test]
+    fn test_stream_states() {
+        let mut v = FrameValidator::new(false);
+
+        assert_eq!(check(&mut v, FrameType::Headers, 0x4, 1, &[], StreamState::Idle), None);
+        assert_eq!(
+            check(&mut v, FrameType::Headers, 0x4, 2, &[], StreamState::Idle),
+            Some((ViolationScope::Connection, ErrorCode::ProtocolError))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::Data, 0, 3, b"x", StreamState::Idle),
+            Some((ViolationScope::Connection, ErrorCode::ProtocolError))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::Data, 0, 1, b"x", StreamState::HalfClosedRemote),
+            Some((ViolationScope::Stream(1), ErrorCode::StreamClosed))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::WindowUpdate, 0, 1, &[0; 4], StreamState::Open),
+            Some((ViolationScope::Stream(1), ErrorCode::ProtocolError))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::Priority, 0, 5, &[0, 0, 0, 5, 16], StreamState::Idle),
+            Some((ViolationScope::Stream(5), ErrorCode::ProtocolError))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::RstStream, 0, 7, &[0; 4], StreamState::Idle),
+            Some((ViolationScope::Connection, ErrorCode::ProtocolError))
+        );
+    }
+
+    #[test]
+    fn test_headers_lower_stream_id() {
+        let mut v = FrameValidator::new(false);
+
+        assert_eq!(check(&mut v, FrameType::Headers, 0x5, 5, &[], StreamState::Idle), None);
+
+        // Stream 3 below the last opened stream, stream 1 opened and closed
+        assert_eq!(
+            check(&mut v, FrameType::Headers, 0x5, 3, &[], StreamState::Idle),
+            Some((ViolationScope::Connection, ErrorCode::ProtocolError))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::Headers, 0x5, 1, &[], StreamState::Closed),
+            Some((ViolationScope::Stream(1), ErrorCode::StreamClosed))
+        );
+        assert_eq!(check(&mut v, FrameType::Headers, 0x5, 7, &[], StreamState::Idle), None);
+    }
+
+    #[test]
+    fn test_padding_and_frame_size() {
+        let mut v = FrameValidator::new(true);
+
+        // Pad length 5 with only 3 bytes left
+        assert_eq!(
+            check(&mut v, FrameType::Data, 0x8, 1, &[5, 0, 0, 0], StreamState::Open),
+            Some((ViolationScope::Connection, ErrorCode::ProtocolError))
+        );
+        assert_eq!(check(&mut v, FrameType::Data, 0x8, 1, &[3, 0, 0, 0], StreamState::Open), None);
+
+        let big = vec![0; 16385];
+        assert_eq!(
+            check(&mut v, FrameType::Data, 0, 1, &big, StreamState::Open),
+            Some((ViolationScope::Stream(1), ErrorCode::FrameSizeError))
+        );
+        assert_eq!(
+            check(&mut v, FrameType::Headers, 0, 1, &big, StreamState::Open),
+            Some((ViolationScope::Connection, ErrorCode::FrameSizeError))
+        );
+    }
+
+    #[test]
+    fn test_continuation_sequencing() {
+        let mut v = FrameValidator::new(true);
+        let conn = ViolationScope::Connection;
+
+        assert_eq!(check(&mut v, FrameType::Continuation, 0x4, 1, &[], StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+
+        // HEADERS without END_HEADERS
+        assert_eq!(check(&mut v, FrameType::Headers, 0, 1, &[], StreamState::HalfClosedLocal), None);
+        assert_eq!(v.continuation_stream(), Some(1));
+        assert_eq!(check(&mut v, FrameType::Ping, 0, 0, &[0; 8], StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::Continuation, 0, 3, &[], StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::Continuation, 0x4, 1, &[], StreamState::HalfClosedLocal), None);
+        assert_eq!(v.continuation_stream(), None);
+
+        // A stream error still expects the rest of the header block
+        assert_eq!(
+            check(&mut v, FrameType::Headers, 0, 3, &[], StreamState::Closed),
+            Some((ViolationScope::Stream(3), ErrorCode::StreamClosed))
+        );
+        assert_eq!(v.continuation_stream(), Some(3));
+    }
+
+    #[test]
+    fn test_unknown_frames_ignored() {
+        let mut v = FrameValidator::new(true);
+
+        assert_eq!(check(&mut v, FrameType::Unknown(0xbb), 0xff, 7, b"opaque", StreamState::Idle), None);
+        assert_eq!(check(&mut v, FrameType::Origin, 0, 0, &[], StreamState::Idle), None);
+
+        // Still not allowed inside a header block
+        assert_eq!(check(&mut v, FrameType::Headers, 0, 1, &[], StreamState::HalfClosedLocal), None);
+        assert_eq!(
+            check(&mut v, FrameType::Unknown(0xbb), 0, 1, &[], StreamState::HalfClosedLocal),
+            Some((ViolationScope::Connection, ErrorCode::ProtocolError))
+        );
+    }
+
+    #[test]
+    fn test_priority_update() {
+        let conn = ViolationScope::Connection;
+        let mut server = FrameValidator::new(false);
+
+        assert_eq!(check(&mut server, FrameType::PriorityUpdate, 0, 0, &[0, 0, 0, 1, b'i'], StreamState::Idle), None);
+        assert_eq!(check(&mut server, FrameType::PriorityUpdate, 0, 1, &[0, 0, 0, 1], StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut server, FrameType::PriorityUpdate, 0, 0, &[0, 0, 0, 0], StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut server, FrameType::PriorityUpdate, 0, 0, &[0, 1], StreamState::Idle), Some((conn, ErrorCode::FrameSizeError)));
+
+        let mut client = FrameValidator::new(true);
+        assert_eq!(check(&mut client, FrameType::PriorityUpdate, 0, 0, &[0, 0, 0, 1], StreamState::Idle), Some((conn, ErrorCode::ProtocolError)));
+    }
+
+    #[test]
+    fn test_push_promise() {
+        let mut v = FrameValidator::new(true);
+        let conn = ViolationScope::Connection;
+        let promise = |id: u32| id.to_be_bytes().to_vec();
+
+        // Push disabled by default
+        assert_eq!(check(&mut v, FrameType::PushPromise, 0x4, 1, &promise(2), StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+
+        v.set_enable_push(true);
+        assert_eq!(check(&mut v, FrameType::PushPromise, 0x4, 1, &promise(2), StreamState::Open), None);
+        assert_eq!(check(&mut v, FrameType::PushPromise, 0x4, 1, &promise(2), StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::PushPromise, 0x4, 1, &promise(5), StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+        assert_eq!(check(&mut v, FrameType::PushPromise, 0x4, 1, &promise(4), StreamState::Closed), Some((conn, ErrorCode::ProtocolError)));
+
+        let mut server = FrameValidator::new(false);
+        assert_eq!(check(&mut server, FrameType::PushPromise, 0x4, 1, &promise(2), StreamState::Open), Some((conn, ErrorCode::ProtocolError)));
+    }
+
+    #[test]
+    fn test_data_window() {
+        let v = FrameValidator::new(false);
+        let window = |len, connection, stream| {
+            v.validate_data_window(1, len, connection, stream).err().map(|v| (v.scope, v.error_code))
+        };
+
+        assert_eq!(window(100, 100, Some(100)), None);
+        assert_eq!(window(100, 1000, None), None);
+        assert_eq!(window(101, 100, Some(1000)), Some((ViolationScope::Connection, ErrorCode::FlowControlError)));
+        assert_eq!(window(101, 1000, Some(100)), Some((ViolationScope::Stream(1), ErrorCode::FlowControlError)));
+        assert_eq!(window(1, 1000, Some(-5)), Some((ViolationScope::Stream(1), ErrorCode::FlowControlError)));
+    }
+
+    #[test]
+    fn test_violation_into_error() {
+        let violation = Violation::stream(3, ErrorCode::StreamClosed, "closed");
+        assert!(!violation.is_connection_error());
+        assert!(matches!(violation.into_error(), Error::StreamClosed(3)));
+
+        let violation = Violation::connection(ErrorCode::FrameSizeError, "PING payload of 7 bytes");
+        assert_eq!(violation.to_string(), "connection error FRAME_SIZE_ERROR (0x6): PING payload of 7 bytes");
+        assert!(matches!(violation.into_error(), Error::FrameSize(_)));
+    }
+}
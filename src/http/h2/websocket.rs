@@ -0,0 +1,459 @@
+//! WebSockets over HTTP/2 (RFC 8441)
+//!
+//! The WebSocket opening handshake becomes an extended CONNECT request
+//! with `:protocol: websocket` and `sec-websocket-version: 13`; there is no
+//! `Sec-WebSocket-Key` or `Sec-WebSocket-Accept` exchange. A 2xx response
+//! establishes the tunnel, which then carries RFC 6455 frames.
+//!
+//! This module provides the frame codec (`WsFrame`) and a `WebSocket`
+//! endpoint over any byte stream, usually an `H2Tunnel`. Frames can be
+//! built by hand, so malformed or unusual framing can be sent as well.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vtest2::http::h2::H2Client;
+//! use vtest2::http::h2::websocket::{Opcode, WsFrame};
+//! use vtest2::http::session::FdSessionOps;
+//! use std::net::TcpStream;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let tcp_stream = TcpStream::connect("127.0.0.1:8080")?;
+//! let mut client = H2Client::new(FdSessionOps::new(tcp_stream))?;
+//! client.connect()?;
+//!
+//! let mut ws = client.connect_websocket("/chat", &[])?;
+//! ws.send_text("hello")?;
+//! let message = ws.recv_message()?;
+//! assert_eq!(message.opcode, Opcode::Text);
+//!
+//! // Fragmented message, sent frame by frame
+//! ws.send_frame(WsFrame::new(Opcode::Text, "frag").fin(false))?;
+//! ws.send_frame(WsFrame::new(Opcode::Continuation, "ment"))?;
+//!
+//! ws.close(1000, "bye")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::error::{Error, Result};
+use bytes::{Bytes, BytesMut};
+use std::io::{Read, Write};
+
+/// `:protocol` value for WebSockets
+pub const WEBSOCKET_PROTOCOL: &str = "websocket";
+
+/// WebSocket protocol version sent in `sec-websocket-version`
+pub const WEBSOCKET_VERSION: &str = "13";
+
+/// Largest payload of a control frame (RFC 6455 Section 5.5)
+pub const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// WebSocket frame opcode (RFC 6455 Section 5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    /// Continuation of a fragmented message
+    Continuation,
+    /// UTF-8 text
+    Text,
+    /// Binary data
+    Binary,
+    /// Close the connection
+    Close,
+    /// Ping
+    Ping,
+    /// Pong
+    Pong,
+}
+
+impl Opcode {
+    /// Convert from the wire value; reserved opcodes give None
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xa => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    /// Get the wire value
+    pub fn as_u8(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xa,
+        }
+    }
+
+    /// Check whether this is a control opcode (Close, Ping, Pong)
+    pub fn is_control(self) -> bool {
+        self.as_u8() & 0x8 != 0
+    }
+}
+
+/// WebSocket frame (RFC 6455 Section 5.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsFrame {
+    /// Final fragment of a message
+    pub fin: bool,
+    /// Opcode
+    pub opcode: Opcode,
+    /// Masking key; the payload is kept unmasked
+    pub mask: Option<[u8; 4]>,
+    /// Payload data
+    pub payload: Bytes,
+}
+
+impl WsFrame {
+    /// Create a final, unmasked frame
+    pub fn new(opcode: Opcode, payload: impl Into<Bytes>) -> Self {
+        WsFrame {
+            fin: true,
+            opcode,
+            mask: None,
+            payload: payload.into(),
+        }
+    }
+
+    /// Create a text frame
+    pub fn text(text: &str) -> Self {
+        Self::new(Opcode::Text, Bytes::copy_from_slice(text.as_bytes()))
+    }
+
+    /// Create a binary frame
+    pub fn binary(data: impl Into<Bytes>) -> Self {
+        Self::new(Opcode::Binary, data)
+    }
+
+    /// Create a close frame with a status code and reason
+    pub fn close(code: u16, reason: &str) -> Self {
+        let mut payload = BytesMut::with_capacity(2 + reason.len());
+        payload.extend_from_slice(&code.to_be_bytes());
+        payload.extend_from_slice(reason.as_bytes());
+        Self::new(Opcode::Close, payload.freeze())
+    }
+
+    /// Set the FIN bit
+    pub fn fin(mut self, fin: bool) -> Self {
+        self.fin = fin;
+        self
+    }
+
+    /// Set the masking key
+    pub fn masked(mut self, key: [u8; 4]) -> Self {
+        self.mask = Some(key);
+        self
+    }
+
+    /// Get the status code of a close frame
+    pub fn close_code(&self) -> Option<u16> {
+        (self.opcode == Opcode::Close && self.payload.len() >= 2)
+            .then(|| u16::from_be_bytes([self.payload[0], self.payload[1]]))
+    }
+
+    /// Get the reason of a close frame
+    pub fn close_reason(&self) -> Option<&str> {
+        if self.opcode != Opcode::Close || self.payload.len() < 2 {
+            return None;
+        }
+        std::str::from_utf8(&self.payload[2..]).ok()
+    }
+
+    /// Get the payload as text
+    pub fn text_payload(&self) -> Result<&str> {
+        std::str::from_utf8(&self.payload)
+            .map_err(|e| Error::Protocol(format!("Invalid UTF-8 in WebSocket payload: {}", e)))
+    }
+
+    /// Encode the frame, masking the payload if a key is set
+    pub fn encode(&self) -> Bytes {
+        let len = self.payload.len();
+        let mut buf = BytesMut::with_capacity(14 + len);
+
+        buf.extend_from_slice(&[(self.fin as u8) << 7 | self.opcode.as_u8()]);
+
+        let mask_bit = (self.mask.is_some() as u8) << 7;
+        if len < 126 {
+            buf.extend_from_slice(&[mask_bit | len as u8]);
+        } else if len <= u16::MAX as usize {
+            buf.extend_from_slice(&[mask_bit | 126]);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            buf.extend_from_slice(&[mask_bit | 127]);
+            buf.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        match self.mask {
+            Some(key) => {
+                buf.extend_from_slice(&key);
+                buf.extend(self.payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+            }
+            None => buf.extend_from_slice(&self.payload),
+        }
+
+        buf.freeze()
+    }
+
+    /// Decode a frame from the start of a buffer
+    ///
+    /// Returns the frame and the number of bytes it used, or None if the
+    /// buffer does not hold a complete frame yet. Reserved opcodes and
+    /// RSV bits are rejected.
+    pub fn decode(buf: &[u8]) -> Result<Option<(WsFrame, usize)>> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+
+        if buf[0] & 0x70 != 0 {
+            return Err(Error::Protocol("WebSocket frame with RSV bits set".to_string()));
+        }
+        let opcode = Opcode::from_u8(buf[0] & 0x0f)
+            .ok_or_else(|| Error::Protocol(format!("Reserved WebSocket opcode {:#x}", buf[0] & 0x0f)))?;
+        let fin = buf[0] & 0x80 != 0;
+        let masked = buf[1] & 0x80 != 0;
+
+        let (len, mut offset) = match buf[1] & 0x7f {
+            126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+            127 if buf.len() >= 10 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+            126 | 127 => return Ok(None),
+            len => (len as u64, 2),
+        };
+
+        if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+            return Err(Error::Protocol("Fragmented or oversized WebSocket control frame".to_string()));
+        }
+
+        let mask = if masked {
+            if buf.len() < offset + 4 {
+                return Ok(None);
+            }
+            let key = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+            offset += 4;
+            Some(key)
+        } else {
+            None
+        };
+
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| offset.checked_add(len))
+            .ok_or_else(|| Error::Protocol("WebSocket frame too large".to_string()))?;
+        if buf.len() < end {
+            return Ok(None);
+        }
+
+        let payload = match mask {
+            Some(key) => buf[offset..end].iter().enumerate().map(|(i, b)| b ^ key[i % 4]).collect(),
+            None => Bytes::copy_from_slice(&buf[offset..end]),
+        };
+
+        Ok(Some((WsFrame { fin, opcode, mask, payload }, end)))
+    }
+}
+
+/// WebSocket endpoint over a byte stream
+///
+/// A client endpoint masks every frame it sends with a random key, as
+/// RFC 6455 Section 5.3 requires, unless the frame already has one.
+pub struct WebSocket<T: Read + Write> {
+    /// Underlying byte stream
+    io: T,
+    /// Mask outgoing frames (client side)
+    mask: bool,
+    /// Received bytes not yet decoded
+    buffer: BytesMut,
+}
+
+impl<T: Read + Write> WebSocket<T> {
+    /// Create the client side of a WebSocket
+    pub fn client(io: T) -> Self {
+        WebSocket {
+            io,
+            mask: true,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Create the server side of a WebSocket
+    pub fn server(io: T) -> Self {
+        WebSocket {
+            io,
+            mask: false,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Get the underlying byte stream
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Unwrap the underlying byte stream
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Send a frame
+    pub fn send_frame(&mut self, mut frame: WsFrame) -> Result<()> {
+        if self.mask && frame.mask.is_none() {
+            let mut key = [0u8; 4];
+            openssl::rand::rand_bytes(&mut key)
+                .map_err(|e| Error::Internal(format!("Cannot generate masking key: {}", e)))?;
+            frame.mask = Some(key);
+        }
+
+        self.io.write_all(&frame.encode())?;
+        self.io.flush()?;
+        Ok(())
+    }
+
+    /// Send a text message
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        self.send_frame(WsFrame::text(text))
+    }
+
+    /// Send a binary message
+    pub fn send_binary(&mut self, data: impl Into<Bytes>) -> Result<()> {
+        self.send_frame(WsFrame::binary(data))
+    }
+
+    /// Send a ping
+    pub fn send_ping(&mut self, data: impl Into<Bytes>) -> Result<()> {
+        self.send_frame(WsFrame::new(Opcode::Ping, data))
+    }
+
+    /// Send a close frame
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        self.send_frame(WsFrame::close(code, reason))
+    }
+
+    /// Receive the next frame
+    pub fn recv_frame(&mut self) -> Result<WsFrame> {
+        loop {
+            if let Some((frame, used)) = WsFrame::decode(&self.buffer)? {
+                let _ = self.buffer.split_to(used);
+                return Ok(frame);
+            }
+
+            let mut chunk = [0u8; 4096];
+            let n = self.io.read(&mut chunk)?;
+            if n == 0 {
+                return Err(Error::ConnectionClosed);
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Receive the next message
+    ///
+    /// Fragments are joined into a single final frame carrying the opcode
+    /// of the first one. Pings are answered with a pong and skipped; close
+    /// and pong frames are returned as they come.
+    pub fn recv_message(&mut self) -> Result<WsFrame> {
+        let mut message: Option<WsFrame> = None;
+
+        loop {
+            let frame = self.recv_frame()?;
+
+            match frame.opcode {
+                Opcode::Ping => {
+                    self.send_frame(WsFrame::new(Opcode::Pong, frame.payload))?;
+                    continue;
+                }
+                Opcode::Close | Opcode::Pong => return Ok(frame),
+                Opcode::Continuation => {
+                    let Some(mut partial) = message.take() else {
+                        return Err(Error::Protocol("WebSocket continuation without a message".to_string()));
+                    };
+                    let mut payload = BytesMut::from(&partial.payload[..]);
+                    payload.extend_from_slice(&frame.payload);
+                    partial.payload = payload.freeze();
+                    partial.fin = frame.fin;
+                    message = Some(partial);
+                }
+                Opcode::Text | Opcode::Binary => {
+                    if message.is_some() {
+                        return Err(Error::Protocol("WebSocket message interrupted by a new one".to_string()));
+                    }
+                    message = Some(frame);
+                }
+            }
+
+            if let Some(frame) = message.take_if(|m| m.fin) {
+                return Ok(WsFrame { mask: None, ..frame });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = WsFrame::text("Hello").masked([0x37, 0xfa, 0x21, 0x3d]);
+        let encoded = frame.encode();
+        // Masked "Hello" from RFC 6455 Section 5.7
+        assert_eq!(&encoded[..], &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]);
+
+        let (decoded, used) = WsFrame::decode(&encoded).unwrap().unwrap();
+        assert_eq!(used, encoded.len());
+        assert_eq!(decoded, frame);
+
+        // Extended payload lengths
+        for len in [126, 65535, 65536] {
+            let frame = WsFrame::binary(vec![0xab; len]);
+            let encoded = frame.encode();
+            let (decoded, _) = WsFrame::decode(&encoded).unwrap().unwrap();
+            assert_eq!(decoded.payload.len(), len);
+            assert!(WsFrame::decode(&encoded[..encoded.len() - 1]).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_frame_decode_errors() {
+        assert!(WsFrame::decode(&[0x81]).unwrap().is_none());
+        // Reserved opcode
+        assert!(WsFrame::decode(&[0x83, 0x00]).is_err());
+        // RSV1 without an extension
+        assert!(WsFrame::decode(&[0xc1, 0x00]).is_err());
+        // Fragmented ping
+        assert!(WsFrame::decode(&[0x09, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_close_frame() {
+        let frame = WsFrame::close(1001, "going away");
+        assert_eq!(frame.close_code(), Some(1001));
+        assert_eq!(frame.close_reason(), Some("going away"));
+        assert_eq!(WsFrame::text("x").close_code(), None);
+    }
+
+    #[test]
+    fn test_recv_message_reassembles_fragments() {
+        let mut input = Vec::new();
+        input.extend_from_slice(&WsFrame::new(Opcode::Text, "Hel").fin(false).encode());
+        input.extend_from_slice(&WsFrame::new(Opcode::Ping, "p").encode());
+        input.extend_from_slice(&WsFrame::new(Opcode::Continuation, "lo").masked([1, 2, 3, 4]).encode());
+
+        let mut ws = WebSocket::server(Cursor::new(input));
+        let message = ws.recv_message().unwrap();
+        assert_eq!(message.opcode, Opcode::Text);
+        assert!(message.fin);
+        assert_eq!(message.text_payload().unwrap(), "Hello");
+
+        // The ping was answered with an unmasked pong
+        let written = &ws.get_mut().get_ref()[..];
+        assert!(written.ends_with(&WsFrame::new(Opcode::Pong, "p").encode()));
+        assert!(matches!(ws.recv_frame(), Err(Error::ConnectionClosed)));
+    }
+}
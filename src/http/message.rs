@@ -21,6 +21,7 @@ pub enum Method {
 
 impl Method {
     /// Parse method from string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         match s {
             "GET" => Ok(Method::Get),
@@ -59,14 +60,16 @@ impl fmt::Display for Method {
 }
 
 /// HTTP version
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum Version {
     Http10,
+    #[default]
     Http11,
 }
 
 impl Version {
     /// Parse version from string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         match s {
             "HTTP/1.0" => Ok(Version::Http10),
@@ -90,12 +93,6 @@ impl fmt::Display for Version {
     }
 }
 
-impl Default for Version {
-    fn default() -> Self {
-        Version::Http11
-    }
-}
-
 /// HTTP status code
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Status {
@@ -442,7 +439,7 @@ impl HttpResponse {
 }
 
 /// Builder for HTTP responses
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct HttpResponseBuilder {
     version: Option<Version>,
     status: Option<Status>,
@@ -451,18 +448,6 @@ pub struct HttpResponseBuilder {
     body: Vec<u8>,
 }
 
-impl Default for HttpResponseBuilder {
-    fn default() -> Self {
-        HttpResponseBuilder {
-            version: None,
-            status: None,
-            reason: None,
-            headers: Headers::new(),
-            body: Vec::new(),
-        }
-    }
-}
-
 impl HttpResponseBuilder {
     /// Set the HTTP version
     pub fn version(mut self, version: Version) -> Self {
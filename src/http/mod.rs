@@ -16,11 +16,12 @@
 //!
 //! ```no_run
 //! use vtest2::http::{HttpClient, HttpRequest, Method};
+//! use vtest2::http::session::FdSessionOps;
 //! use std::net::TcpStream;
 //!
 //! // Create client from TCP stream
 //! let stream = TcpStream::connect("127.0.0.1:8080").unwrap();
-//! let mut client = HttpClient::new(stream);
+//! let mut client = HttpClient::new(FdSessionOps::new(stream));
 //!
 //! // Send request
 //! let request = HttpRequest::builder()
@@ -50,7 +51,7 @@ pub use headers::Headers;
 pub use message::{HttpRequest, HttpResponse, Method, Status, Version};
 pub use parser::{RequestParser, ResponseParser};
 pub use server::HttpServer;
-pub use session::{SessionOps, HttpSession};
+pub use session::{SessionOps, HttpSession, PrefixedSession, SessionStream};
 
 /// Result type for HTTP operations
 pub type Result<T> = std::result::Result<T, Error>;
@@ -104,6 +104,11 @@ impl RequestParser {
         }
     }
 
+    /// Take the bytes received after the end of the parsed message
+    pub fn take_remaining(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+
     fn parse_request_line(&mut self) -> Result<Option<HttpRequest>> {
         if let Some(crlf_pos) = find_crlf(&self.buffer) {
             let line = String::from_utf8_lossy(&self.buffer[..crlf_pos]).to_string();
@@ -231,6 +236,11 @@ impl ResponseParser {
         }
     }
 
+    /// Take the bytes received after the end of the parsed message
+    pub fn take_remaining(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+
     fn parse_status_line(&mut self) -> Result<Option<HttpResponse>> {
         if let Some(crlf_pos) = find_crlf(&self.buffer) {
             let line = String::from_utf8_lossy(&self.buffer[..crlf_pos]).to_string();
@@ -383,6 +393,18 @@ mod tests {
         assert_eq!(resp.headers().get("Content-Type"), Some("text/plain"));
     }
 
+    #[test]
+    fn test_response_parser_remaining() {
+        let mut parser = ResponseParser::new();
+
+        let data = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: h2c\r\n\r\n\x00\x00\x00\x04";
+        let response = parser.parse(data).unwrap().unwrap();
+
+        assert_eq!(response.status().code(), 101);
+        assert_eq!(parser.take_remaining(), b"\x00\x00\x00\x04");
+        assert!(parser.take_remaining().is_empty());
+    }
+
     #[test]
     fn test_find_crlf() {
         assert_eq!(find_crlf(b"Hello\r\nWorld"), Some(5));
@@ -2,9 +2,10 @@
 //!
 //! This module provides HTTP server functionality for testing.
 
+use super::h2::{self, H2Server, H2ServerBuilder};
 use super::{
-    chunked, Error, Headers, HttpRequest, HttpResponse, HttpSession, RequestParser, Result,
-    SessionOps, Status, CRLF,
+    chunked, Error, Headers, HttpRequest, HttpResponse, HttpSession, PrefixedSession,
+    RequestParser, Result, SessionOps, Status, CRLF,
 };
 
 /// HTTP server
@@ -264,6 +265,29 @@ impl<S: SessionOps> HttpServer<S> {
     pub fn session_mut(&mut self) -> &mut HttpSession<S> {
         &mut self.session
     }
+
+    /// Unwrap the session, with any bytes received past the last request
+    pub fn into_parts(mut self) -> (S, Vec<u8>) {
+        let remaining = self.parser.take_remaining();
+        (self.session.into_inner(), remaining)
+    }
+
+    /// Hand over the connection as a raw byte stream
+    ///
+    /// Meant for after answering CONNECT with a 2xx response (RFC 9110
+    /// Section 9.3.6). Bytes received past the request are replayed first.
+    pub fn into_tunnel(self) -> PrefixedSession<S> {
+        let (session, remaining) = self.into_parts();
+        PrefixedSession::new(session, remaining)
+    }
+
+    /// Hand the connection off to HTTP/2 after an h2c upgrade request
+    ///
+    /// Use `h2::h2c::is_upgrade_request` to check the request first. See
+    /// `H2ServerBuilder::upgrade`; this uses the default server settings.
+    pub fn upgrade_h2c(self, request: HttpRequest) -> h2::Result<H2Server<PrefixedSession<S>>> {
+        H2ServerBuilder::new().upgrade(self, request)
+    }
 }
 
 #[cfg(test)]
@@ -288,7 +312,7 @@ mod tests {
 
             // Read response
             let mut buf = vec![0u8; 1024];
-            stream.read(&mut buf).unwrap();
+            let _ = stream.read(&mut buf).unwrap();
         });
 
         let (stream, _) = listener.accept().unwrap();
@@ -99,6 +99,11 @@ impl<S: SessionOps> HttpSession<S> {
     pub fn get_mut(&mut self) -> &mut S {
         &mut self.session
     }
+
+    /// Unwrap the underlying session
+    pub fn into_inner(self) -> S {
+        self.session
+    }
 }
 
 /// Plain file descriptor session operations
@@ -167,6 +172,127 @@ impl SessionOps for FdSessionOps {
     }
 }
 
+/// Session operations replaying buffered bytes before the inner session
+///
+/// Used when the protocol changes after bytes were already read from the
+/// connection, e.g. an h2c upgrade or HTTP/2 preface detection.
+pub struct PrefixedSession<S: SessionOps> {
+    inner: S,
+    prefix: Vec<u8>,
+    pos: usize,
+}
+
+impl<S: SessionOps> PrefixedSession<S> {
+    /// Create a session returning `prefix` before reading from `inner`
+    pub fn new(inner: S, prefix: Vec<u8>) -> Self {
+        PrefixedSession { inner, prefix, pos: 0 }
+    }
+
+    /// Get the buffered bytes not yet read
+    pub fn remaining_prefix(&self) -> &[u8] {
+        &self.prefix[self.pos..]
+    }
+
+    /// Get a reference to the inner session
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner session
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner session, dropping unread buffered bytes
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: SessionOps> SessionOps for PrefixedSession<S> {
+    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> Result<bool> {
+        if events != PollEvents::Write && self.pos < self.prefix.len() {
+            return Ok(true);
+        }
+        self.inner.poll(events, timeout)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let remaining = &self.prefix[self.pos..];
+        if remaining.is_empty() {
+            return self.inner.read(buf);
+        }
+
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
+
+/// `std::io` adapter over session operations
+///
+/// Lets code expecting `Read` and `Write` run over any session, e.g. TLS
+/// through a CONNECT tunnel (`TlsConfig::connect(SessionStream::new(..))`).
+pub struct SessionStream<S: SessionOps> {
+    inner: S,
+}
+
+impl<S: SessionOps> SessionStream<S> {
+    /// Wrap a session
+    pub fn new(inner: S) -> Self {
+        SessionStream { inner }
+    }
+
+    /// Get a reference to the inner session
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner session
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner session
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Convert a session error into an I/O error, unwrapping I/O errors
+fn into_io_error(error: Error) -> io::Error {
+    match error {
+        Error::Io(e) => e,
+        Error::Timeout => io::ErrorKind::TimedOut.into(),
+        e => io::Error::other(e),
+    }
+}
+
+impl<S: SessionOps> Read for SessionStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(into_io_error)
+    }
+}
+
+impl<S: SessionOps> Write for SessionStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf).map_err(into_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Helper to create an HTTP session from a TCP stream
 pub fn from_tcp_stream(stream: TcpStream) -> HttpSession<FdSessionOps> {
     HttpSession::new(FdSessionOps::new(stream))
@@ -224,4 +350,56 @@ mod tests {
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), Error::Timeout));
     }
+
+    #[test]
+    fn test_prefixed_session_replays_prefix() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"World").unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut session = PrefixedSession::new(FdSessionOps::new(stream), b"Hello ".to_vec());
+        assert!(session.poll(PollEvents::Read, Some(Duration::ZERO)).unwrap());
+
+        let mut buf = [0u8; 4];
+        assert_eq!(session.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"Hell");
+        assert_eq!(session.remaining_prefix(), b"o ");
+
+        let mut buf = [0u8; 16];
+        assert_eq!(session.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"o ");
+
+        handle.join().unwrap();
+        let n = session.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"World");
+    }
+
+    #[test]
+    fn test_session_stream_io() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf).unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let session = PrefixedSession::new(FdSessionOps::new(stream), b">".to_vec());
+        let mut stream = SessionStream::new(session);
+        stream.write_all(b"ping").unwrap();
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b">ping");
+
+        handle.join().unwrap();
+    }
 }
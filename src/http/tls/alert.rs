@@ -0,0 +1,204 @@
+//! TLS alert descriptions
+//!
+//! Alert codes and their names, as in `tbl/tls_alert_tbl.h` of the C
+//! implementation (RFC 8446 Appendix B.2), and the log of alerts sent
+//! and received on a connection, which backs `tls.alert`.
+
+use openssl::ex_data::Index;
+use openssl::ssl::{Ssl, SslAlert, SslContextBuilder, SslRef};
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+/// A TLS alert description
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TlsAlert(pub u8);
+
+macro_rules! alerts {
+    ($($konst:ident = $name:literal, $code:literal;)*) => {
+        impl TlsAlert {
+            $(pub const $konst: TlsAlert = TlsAlert($code);)*
+        }
+
+        /// Alert names by code
+        const ALERTS: &[(&str, u8)] = &[$(($name, $code)),*];
+    };
+}
+
+alerts! {
+    CLOSE_NOTIFY = "close_notify", 0;
+    END_OF_EARLY_DATA = "end_of_early_data", 1;
+    UNEXPECTED_MESSAGE = "unexpected_message", 10;
+    BAD_RECORD_MAC = "bad_record_mac", 20;
+    DECRYPTION_FAILED = "decryption_failed", 21;
+    RECORD_OVERFLOW = "record_overflow", 22;
+    DECOMPRESSION_FAILURE = "decompression_failure", 30;
+    HANDSHAKE_FAILURE = "handshake_failure", 40;
+    BAD_CERTIFICATE = "bad_certificate", 42;
+    UNSUPPORTED_CERTIFICATE = "unsupported_certificate", 43;
+    CERTIFICATE_REVOKED = "certificate_revoked", 44;
+    CERTIFICATE_EXPIRED = "certificate_expired", 45;
+    CERTIFICATE_UNKNOWN = "certificate_unknown", 46;
+    ILLEGAL_PARAMETER = "illegal_parameter", 47;
+    UNKNOWN_CA = "unknown_ca", 48;
+    ACCESS_DENIED = "access_denied", 49;
+    DECODE_ERROR = "decode_error", 50;
+    DECRYPT_ERROR = "decrypt_error", 51;
+    EXPORT_RESTRICTION = "export_restriction", 60;
+    PROTOCOL_VERSION = "protocol_version", 70;
+    INSUFFICIENT_SECURITY = "insufficient_security", 71;
+    INTERNAL_ERROR = "internal_error", 80;
+    INAPPROPRIATE_FALLBACK = "inappropriate_fallback", 86;
+    USER_CANCELED = "user_canceled", 90;
+    NO_RENEGOTIATION = "no_renegotiation", 100;
+    MISSING_EXTENSION = "missing_extension", 109;
+    UNSUPPORTED_EXTENSION = "unsupported_extension", 110;
+    CERTIFICATE_UNOBTAINABLE = "certificate_unobtainable", 111;
+    UNRECOGNIZED_NAME = "unrecognized_name", 112;
+    BAD_CERTIFICATE_STATUS_RESPONSE = "bad_certificate_status_response", 113;
+    BAD_CERTIFICATE_HASH_VALUE = "bad_certificate_hash_value", 114;
+    UNKNOWN_PSK_IDENTITY = "unknown_psk_identity", 115;
+    CERTIFICATE_REQUIRED = "certificate_required", 116;
+    NO_APPLICATION_PROTOCOL = "no_application_protocol", 120;
+}
+
+impl TlsAlert {
+    /// Look up an alert by name (e.g. `unrecognized_name`)
+    pub fn from_name(name: &str) -> Option<TlsAlert> {
+        ALERTS.iter().find(|(n, _)| *n == name).map(|&(_, code)| TlsAlert(code))
+    }
+
+    /// Get the alert name, if the code is known
+    pub fn name(&self) -> Option<&'static str> {
+        ALERTS.iter().find(|&&(_, code)| code == self.0).map(|&(name, _)| name)
+    }
+
+    /// Store the alert in the out parameter of an OpenSSL callback
+    ///
+    /// The openssl crate only names a few alerts, so the code is written
+    /// to the `SslAlert` directly.
+    pub(crate) fn set(&self, alert: &mut SslAlert) {
+        const _: () = assert!(std::mem::size_of::<SslAlert>() == std::mem::size_of::<libc::c_int>());
+        // SAFETY: SslAlert wraps a single c_int, which the size check
+        // places at offset 0
+        unsafe { *(alert as *mut SslAlert).cast::<libc::c_int>() = libc::c_int::from(self.0) }
+    }
+}
+
+impl fmt::Display for TlsAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "alert_{}", self.0),
+        }
+    }
+}
+
+/// Alert level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertLevel {
+    Warning,
+    Fatal,
+}
+
+impl fmt::Display for AlertLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlertLevel::Warning => "warning",
+            AlertLevel::Fatal => "fatal",
+        })
+    }
+}
+
+/// An alert sent or received on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlertRecord {
+    /// Whether we sent the alert (as opposed to received it)
+    pub sent: bool,
+    pub level: AlertLevel,
+    pub alert: TlsAlert,
+}
+
+/// Alerts of a connection, in order
+type AlertLog = Mutex<Vec<AlertRecord>>;
+
+fn alert_log_index() -> Index<Ssl, AlertLog> {
+    static INDEX: OnceLock<Index<Ssl, AlertLog>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().expect("Failed to allocate SSL ex_data index"))
+}
+
+// Info callback arguments (ssl.h)
+const SSL_CB_READ: libc::c_int = 0x04;
+const SSL_CB_WRITE: libc::c_int = 0x08;
+const SSL_CB_ALERT: libc::c_int = 0x4000;
+
+extern "C" {
+    // Not bound by openssl-sys
+    fn SSL_CTX_set_info_callback(
+        ctx: *mut openssl_sys::SSL_CTX,
+        cb: Option<unsafe extern "C" fn(*const openssl_sys::SSL, libc::c_int, libc::c_int)>,
+    );
+}
+
+/// Record an alert in the connection's log
+///
+/// For alerts, `val` holds the level in its high byte and the
+/// description in its low byte.
+unsafe extern "C" fn info_callback(ssl: *const openssl_sys::SSL, where_: libc::c_int, val: libc::c_int) {
+    if where_ & SSL_CB_ALERT == 0 || where_ & (SSL_CB_READ | SSL_CB_WRITE) == 0 {
+        return;
+    }
+    // SAFETY: the openssl crate stores ex_data as a pointer to the value,
+    // which lives until the Ssl is freed
+    let log = openssl_sys::SSL_get_ex_data(ssl, alert_log_index().as_raw()) as *const AlertLog;
+    if log.is_null() {
+        return;
+    }
+    let record = AlertRecord {
+        sent: where_ & SSL_CB_WRITE != 0,
+        level: if val >> 8 == 2 { AlertLevel::Fatal } else { AlertLevel::Warning },
+        alert: TlsAlert((val & 0xff) as u8),
+    };
+    if let Ok(mut log) = (*log).lock() {
+        log.push(record);
+    }
+}
+
+/// Log the alerts of connections made with this context
+///
+/// Only connections started with `start_log` are recorded.
+pub(crate) fn log_alerts(ctx_builder: &mut SslContextBuilder) {
+    // SAFETY: the callback only touches the ex_data it looks up
+    unsafe { SSL_CTX_set_info_callback(ctx_builder.as_ptr(), Some(info_callback)) }
+}
+
+/// Start recording the alerts of a connection
+pub(crate) fn start_log(ssl: &mut SslRef) {
+    ssl.set_ex_data(alert_log_index(), Mutex::new(Vec::new()));
+}
+
+/// Alerts sent and received so far on a connection
+pub fn alerts(ssl: &SslRef) -> Vec<AlertRecord> {
+    ssl.ex_data(alert_log_index())
+        .and_then(|log| log.lock().ok().map(|log| log.clone()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_names() {
+        assert_eq!(TlsAlert::from_name("unrecognized_name"), Some(TlsAlert::UNRECOGNIZED_NAME));
+        assert_eq!(TlsAlert::UNRECOGNIZED_NAME.0, 112);
+        assert_eq!(TlsAlert(48).to_string(), "unknown_ca");
+        assert_eq!(TlsAlert(200).to_string(), "alert_200");
+        assert_eq!(TlsAlert::from_name("no_such_alert"), None);
+
+        let mut alert = SslAlert::DECODE_ERROR;
+        TlsAlert::ACCESS_DENIED.set(&mut alert);
+        assert_ne!(alert, SslAlert::DECODE_ERROR);
+        TlsAlert::UNRECOGNIZED_NAME.set(&mut alert);
+        assert_eq!(alert, SslAlert::UNRECOGNIZED_NAME);
+    }
+}
@@ -84,7 +84,7 @@ mod tests {
         let cn = subject.entries_by_nid(openssl::nid::Nid::COMMONNAME)
             .next()
             .unwrap();
-        let cn_str = cn.data().as_utf8().unwrap().to_string();
+        let cn_str = cn.data().to_string().unwrap();
         assert_eq!(cn_str, "example.com");
     }
 
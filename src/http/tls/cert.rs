@@ -3,8 +3,12 @@
 //! This module provides functionality for parsing and extracting information
 //! from X.509 certificates.
 
-use openssl::x509::X509;
+use super::der;
+use openssl::asn1::Asn1Object;
+use openssl::hash::MessageDigest;
 use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::x509::{X509, X509NameRef, X509Ref};
 
 /// Certificate information
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -15,48 +19,146 @@ pub struct CertInfo {
     pub issuer: String,
     /// Subject Alternative Names (DNS names and IP addresses)
     pub subject_alt_names: Vec<String>,
+    /// Full subject name (e.g. `C=US, O=Example, CN=example.com`)
+    pub subject_dn: String,
+    /// Full issuer name
+    pub issuer_dn: String,
+    /// Serial number, in hexadecimal
+    pub serial: String,
+    /// Start of validity (ISO 8601)
+    pub not_before: String,
+    /// End of validity (ISO 8601)
+    pub not_after: String,
+    /// SHA-256 fingerprint, as colon-separated hexadecimal bytes
+    pub fingerprint_sha256: String,
+    /// Public key type (`RSA`, `EC`, `Ed25519`...)
+    pub key_type: String,
+    /// Public key size in bits
+    pub key_bits: u32,
+    /// Signature algorithm (e.g. `sha256WithRSAEncryption`)
+    pub signature_algorithm: String,
+    /// Extension names, in certificate order; critical ones are suffixed
+    /// with ` (critical)`
+    pub extensions: Vec<String>,
+}
+
+/// Convert OpenSSL's time display (`Jan  2 03:04:05 2024 GMT`) to ISO 8601
+pub(crate) fn asn1_time_to_iso8601(time: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let mut parts = time.split_whitespace();
+    let name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == name)? + 1;
+    let day: u32 = parts.next()?.parse().ok()?;
+    let clock = parts.next()?;
+    let year: u32 = parts.next()?.parse().ok()?;
+    Some(format!("{:04}-{:02}-{:02}T{}Z", year, month, day, clock))
 }
 
 impl CertInfo {
     /// Extract certificate information from an X.509 certificate
     pub fn from_x509(cert: &X509) -> Self {
-        CertInfo {
-            subject: Self::get_cn(cert.subject_name()),
-            issuer: Self::get_cn(cert.issuer_name()),
-            subject_alt_names: Self::get_subject_alt_names(cert),
-        }
+        Self::from_x509_ref(cert)
     }
 
     /// Extract certificate information from an X.509 certificate reference
-    pub fn from_x509_ref(cert: &openssl::x509::X509Ref) -> Self {
+    pub fn from_x509_ref(cert: &X509Ref) -> Self {
+        let time = |time: String| asn1_time_to_iso8601(&time).unwrap_or(time);
+        let key = cert.public_key().ok();
+
         CertInfo {
             subject: Self::get_cn(cert.subject_name()),
             issuer: Self::get_cn(cert.issuer_name()),
             subject_alt_names: Self::get_subject_alt_names_ref(cert),
+            subject_dn: Self::get_dn(cert.subject_name()),
+            issuer_dn: Self::get_dn(cert.issuer_name()),
+            serial: cert
+                .serial_number()
+                .to_bn()
+                .and_then(|serial| serial.to_hex_str().map(|hex| hex.to_string()))
+                .unwrap_or_else(|_| "<undef>".to_string()),
+            not_before: time(cert.not_before().to_string()),
+            not_after: time(cert.not_after().to_string()),
+            fingerprint_sha256: cert
+                .digest(MessageDigest::sha256())
+                .map(|digest| digest.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":"))
+                .unwrap_or_else(|_| "<undef>".to_string()),
+            key_type: key.as_ref().map_or("<undef>".to_string(), |key| Self::key_type_name(key.id())),
+            key_bits: key.as_ref().map_or(0, |key| key.bits()),
+            signature_algorithm: cert.signature_algorithm().object().to_string(),
+            extensions: Self::get_extensions(cert),
         }
     }
 
+    /// Format a name with the short names of its attributes, in order
+    fn get_dn(name: &X509NameRef) -> String {
+        let entries: Vec<String> = name
+            .entries()
+            .map(|entry| {
+                let field = entry.object().nid().short_name().map(str::to_string);
+                let value = entry.data().to_string().map(|v| v.to_string());
+                format!(
+                    "{}={}",
+                    field.unwrap_or_else(|_| entry.object().to_string()),
+                    value.unwrap_or_else(|_| "<undef>".to_string())
+                )
+            })
+            .collect();
+        entries.join(", ")
+    }
+
+    fn key_type_name(id: Id) -> String {
+        match id {
+            Id::RSA => "RSA".to_string(),
+            Id::RSA_PSS => "RSA-PSS".to_string(),
+            Id::EC => "EC".to_string(),
+            Id::ED25519 => "Ed25519".to_string(),
+            Id::ED448 => "Ed448".to_string(),
+            Id::DSA => "DSA".to_string(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    /// Get extension names, from the DER encoding
+    ///
+    /// The openssl crate has no way to list extensions.
+    fn get_extensions(cert: &X509Ref) -> Vec<String> {
+        let Ok(der) = cert.to_der() else {
+            return Vec::new();
+        };
+        der::extensions(&der)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(oid, critical)| {
+                let dotted = der::oid_to_string(oid).unwrap_or_else(|| "<undef>".to_string());
+                let name = Asn1Object::from_str(&dotted)
+                    .ok()
+                    .and_then(|object| object.nid().short_name().ok().map(str::to_string))
+                    .unwrap_or(dotted);
+                if critical {
+                    format!("{} (critical)", name)
+                } else {
+                    name
+                }
+            })
+            .collect()
+    }
+
     /// Get Common Name from X509_NAME
-    fn get_cn(name: &openssl::x509::X509NameRef) -> String {
+    fn get_cn(name: &X509NameRef) -> String {
         name.entries_by_nid(Nid::COMMONNAME)
             .next()
-            .and_then(|entry| entry.data().as_utf8().ok())
-            .map(|s| s.to_string())
+            .and_then(|entry| entry.data().to_string().ok())
             .unwrap_or_else(|| "<undef>".to_string())
     }
 
-    /// Get Subject Alternative Names
-    fn get_subject_alt_names(cert: &X509) -> Vec<String> {
-        Self::get_subject_alt_names_impl(cert)
-    }
-
     /// Get Subject Alternative Names from X509Ref
-    fn get_subject_alt_names_ref(cert: &openssl::x509::X509Ref) -> Vec<String> {
+    fn get_subject_alt_names_ref(cert: &X509Ref) -> Vec<String> {
         Self::get_subject_alt_names_impl(cert)
     }
 
     /// Get Subject Alternative Names implementation (works with both X509 and X509Ref)
-    fn get_subject_alt_names_impl<T: AsRef<openssl::x509::X509Ref>>(cert: &T) -> Vec<String> {
+    fn get_subject_alt_names_impl<T: AsRef<X509Ref>>(cert: &T) -> Vec<String> {
         let mut names = Vec::new();
         let cert_ref = cert.as_ref();
 
@@ -88,12 +190,17 @@ impl CertInfo {
 }
 
 /// Extract certificate chain information from SSL connection
+///
+/// The peer certificate comes first. Clients get it at the head of the
+/// peer chain, servers do not (same as `cert_resolve` in the C code).
 pub fn get_cert_chain(ssl: &openssl::ssl::SslRef) -> Vec<CertInfo> {
     let mut chain = Vec::new();
 
     // Get peer certificate (index 0)
-    if let Some(peer_cert) = ssl.peer_certificate() {
-        chain.push(CertInfo::from_x509(&peer_cert));
+    if ssl.is_server() {
+        if let Some(peer_cert) = ssl.peer_certificate() {
+            chain.push(CertInfo::from_x509(&peer_cert));
+        }
     }
 
     // Get certificate chain (index 1+)
@@ -124,6 +231,95 @@ mod tests {
         assert!(info.subject_alt_names.contains(&"DNS:*.example.com".to_string()));
     }
 
+    #[test]
+    fn test_cert_info_details() {
+        let cert = X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+        let info = CertInfo::from_x509(&cert);
+
+        assert_eq!(info.subject_dn, "C=NO, ST=Some-State, O=Varnish Software AS, CN=example.com");
+        assert_eq!(info.issuer_dn, info.subject_dn);
+        assert_eq!(info.serial, "7B8BFE3E00597A885D75B87DD8300A9B2F0DEA70");
+        assert_eq!(info.not_before, "2020-01-30T10:03:38Z");
+        assert_eq!(info.not_after, "2047-06-17T10:03:38Z");
+        assert_eq!(
+            info.fingerprint_sha256,
+            "70:E3:3E:9F:96:35:B6:23:45:1D:B8:1C:39:0B:40:36:CC:1B:0B:60:07:FD:B7:BA:DD:8D:6F:41:97:7F:03:37"
+        );
+        assert_eq!(info.key_type, "RSA");
+        assert_eq!(info.key_bits, 2048);
+        assert_eq!(info.signature_algorithm, "sha256WithRSAEncryption");
+        assert_eq!(
+            info.extensions,
+            [
+                "subjectAltName",
+                "subjectKeyIdentifier",
+                "authorityKeyIdentifier",
+                "basicConstraints (critical)",
+                "keyUsage",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_cert_chain() {
+        use super::super::pki::{CertBuilder, KeyType};
+        use super::super::{ClientVerify, TlsConfig};
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let intermediate = CertBuilder::intermediate("Intermediate").issued_by(&root).unwrap();
+        let client_cert = CertBuilder::leaf("client")
+            .key_type(KeyType::EcP256)
+            .serial(0x1234)
+            .issued_by(&intermediate)
+            .unwrap();
+
+        let server_config = TlsConfig::server()
+            .cert(&CertBuilder::leaf("server").issued_by(&intermediate).unwrap())
+            .unwrap()
+            .client_verify(ClientVerify::Required)
+            .client_verify_ca_cert(root.cert())
+            .unwrap()
+            .build()
+            .unwrap();
+        let client_config = TlsConfig::client().cert(&client_cert).unwrap().build().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            server_config.accept(tcp_stream).unwrap().vars().clone()
+        });
+        let client = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        let server_vars = server_handle.join().unwrap();
+
+        // The server sees the client certificate, then its chain
+        assert!(!server_vars.failed);
+        assert_eq!(server_vars.cert_chain.len(), 2);
+        assert_eq!(server_vars.get("tls.cert.subject").as_deref(), Some("client"));
+        assert_eq!(server_vars.get("tls.cert.subject_dn").as_deref(), Some("CN=client"));
+        assert_eq!(server_vars.get("tls.cert.issuer_dn").as_deref(), Some("CN=Intermediate"));
+        assert_eq!(server_vars.get("tls.cert.serial").as_deref(), Some("1234"));
+        assert_eq!(server_vars.get("tls.cert.key_type").as_deref(), Some("EC"));
+        assert_eq!(server_vars.get("tls.cert.key_bits").as_deref(), Some("256"));
+        assert_eq!(server_vars.get("tls.cert.signature_algorithm").as_deref(), Some("ecdsa-with-SHA256"));
+        let fingerprint = server_vars.get("tls.cert.fingerprint_sha256").unwrap();
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+        let extensions = server_vars.get("tls.cert.extensions").unwrap();
+        assert!(extensions.contains("keyUsage (critical)"), "{}", extensions);
+        assert!(extensions.contains("extendedKeyUsage"), "{}", extensions);
+        assert!(server_vars.get("tls.cert.notbefore").unwrap().ends_with('Z'));
+        assert_eq!(server_vars.get("tls.cert1.subject").as_deref(), Some("Intermediate"));
+        assert_eq!(server_vars.get("tls.cert2.subject"), None);
+
+        // The client sees the server certificate once, then its chain
+        let client_vars = client.vars();
+        assert_eq!(client_vars.cert_chain.len(), 2);
+        assert_eq!(client_vars.get("tls.cert.subject").as_deref(), Some("server"));
+        assert_eq!(client_vars.get("tls.cert1.subject").as_deref(), Some("Intermediate"));
+    }
+
     #[test]
     fn test_get_cn() {
         let cert = X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
@@ -134,7 +330,7 @@ mod tests {
     #[test]
     fn test_get_subject_alt_names() {
         let cert = X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
-        let sans = CertInfo::get_subject_alt_names(&cert);
+        let sans = CertInfo::get_subject_alt_names_ref(&cert);
 
         assert_eq!(sans.len(), 2);
         assert!(sans.contains(&"DNS:example.com".to_string()));
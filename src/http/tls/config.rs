@@ -5,6 +5,18 @@
 use std::path::Path;
 use std::fs::File;
 use std::io::Read;
+use super::session::TlsTransport;
+use super::alert::TlsAlert;
+use super::handshake::{HandshakeStep, DEFAULT_HANDSHAKE_TIMEOUT_SECS};
+use super::keylog::KeyLog;
+use super::pki::CertifiedKey;
+use super::sni::SniCerts;
+use openssl::ssl::SslContextBuilder;
+use openssl::x509::{X509CrlRef, X509Ref};
+use openssl::x509::verify::X509VerifyFlags;
+use std::time::Duration;
+
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(DEFAULT_HANDSHAKE_TIMEOUT_SECS);
 
 /// TLS version
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -23,6 +35,7 @@ pub enum TlsVersion {
 
 impl TlsVersion {
     /// Parse TLS version from string (case-insensitive)
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, TlsError> {
         match s.to_uppercase().as_str() {
             "SSLV3" | "SSL3" => Ok(TlsVersion::Ssl3),
@@ -109,8 +122,10 @@ pub struct TlsConfig {
     pub(crate) servername: Option<String>,
     pub(crate) _verify_peer: bool,
     pub(crate) cert_status: bool,
-    pub(crate) _sess_out: Option<String>,
-    pub(crate) _sess_in: Option<String>,
+    pub(crate) sess_in: Option<String>,
+    pub(crate) early_data: Option<Vec<u8>>,
+    pub(crate) handshake_timeout: Duration,
+    pub(crate) stop_after: Option<HandshakeStep>,
 }
 
 impl TlsConfig {
@@ -125,7 +140,10 @@ impl TlsConfig {
     }
 
     /// Connect to a server with TLS (client-side)
-    pub fn connect(&self, stream: std::net::TcpStream) -> Result<super::TlsSessionOps, TlsError> {
+    ///
+    /// The transport is usually a `TcpStream`; wrap any other session in a
+    /// `SessionStream`, e.g. to reach a server through a CONNECT tunnel.
+    pub fn connect<T: TlsTransport>(&self, stream: T) -> Result<super::TlsSessionOps<T>, TlsError> {
         if self.is_server {
             return Err(TlsError::InvalidConfig(
                 "Cannot use server config for client connection".to_string(),
@@ -135,7 +153,7 @@ impl TlsConfig {
     }
 
     /// Accept a client connection with TLS (server-side)
-    pub fn accept(&self, stream: std::net::TcpStream) -> Result<super::TlsSessionOps, TlsError> {
+    pub fn accept<T: TlsTransport>(&self, stream: T) -> Result<super::TlsSessionOps<T>, TlsError> {
         if !self.is_server {
             return Err(TlsError::InvalidConfig(
                 "Cannot use client config for server accept".to_string(),
@@ -150,9 +168,15 @@ pub struct ClientConfigBuilder {
     ctx_builder: openssl::ssl::SslContextBuilder,
     servername: Option<String>,
     verify_peer: bool,
+    verify_hostname: bool,
+    verify_name: Option<String>,
     cert_status: bool,
     sess_out: Option<String>,
     sess_in: Option<String>,
+    early_data: Option<Vec<u8>>,
+    keylog: Option<KeyLog>,
+    handshake_timeout: Duration,
+    stop_after: Option<HandshakeStep>,
 }
 
 impl ClientConfigBuilder {
@@ -169,9 +193,15 @@ impl ClientConfigBuilder {
             ctx_builder,
             servername: None,
             verify_peer: false,  // Will be mapped to _verify_peer in TlsConfig
+            verify_hostname: false,
+            verify_name: None,
             cert_status: false,
-            sess_out: None,      // Will be mapped to _sess_out in TlsConfig
-            sess_in: None,       // Will be mapped to _sess_in in TlsConfig
+            sess_out: None,
+            sess_in: None,
+            early_data: None,
+            keylog: KeyLog::from_env(),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            stop_after: None,
         }
     }
 
@@ -234,6 +264,29 @@ impl ClientConfigBuilder {
         self
     }
 
+    /// Check that the server certificate matches the SNI servername
+    ///
+    /// A mismatch fails verification: the handshake fails if
+    /// `verify_peer` is enabled, and `tls.verify_result` shows it either way.
+    pub fn verify_hostname(mut self, verify: bool) -> Self {
+        self.verify_hostname = verify;
+        self
+    }
+
+    /// Check that the server certificate matches a name other than the SNI
+    ///
+    /// The name is a hostname or an IP address.
+    pub fn verify_name(mut self, name: impl Into<String>) -> Self {
+        self.verify_name = Some(name.into());
+        self
+    }
+
+    /// Limit the number of intermediate CAs in the server chain
+    pub fn verify_depth(mut self, depth: u32) -> Self {
+        self.ctx_builder.set_verify_depth(depth);
+        self
+    }
+
     /// Load client certificate from PEM file
     pub fn cert_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
         // Read certificate file
@@ -264,28 +317,139 @@ impl ClientConfigBuilder {
         self
     }
 
+    /// Set CA file for server certificate and OCSP response verification
+    pub fn ca_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
+        self.ctx_builder.set_ca_file(path.as_ref())?;
+        Ok(self)
+    }
+
+    /// Set a CA directory, in the hashed layout of `openssl rehash`
+    pub fn ca_dir<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
+        self.ctx_builder.load_verify_locations(None, Some(path.as_ref()))?;
+        Ok(self)
+    }
+
+    /// Trust a CA certificate, like `ca_file`
+    pub fn ca_cert(mut self, cert: &X509Ref) -> Result<Self, TlsError> {
+        self.ctx_builder.cert_store_mut().add_cert(cert.to_owned())?;
+        Ok(self)
+    }
+
+    /// Check the server certificate against a revocation list
+    ///
+    /// Only the server certificate is checked, so the CRL must come from
+    /// its issuer.
+    pub fn crl(mut self, crl: &X509CrlRef) -> Result<Self, TlsError> {
+        add_crl(&mut self.ctx_builder, crl)?;
+        Ok(self)
+    }
+
+    /// Use a generated client certificate
+    pub fn cert(mut self, cert: &CertifiedKey) -> Result<Self, TlsError> {
+        set_certified_key(&mut self.ctx_builder, cert)?;
+        Ok(self)
+    }
+
     /// Save session to file for resumption
+    ///
+    /// The file is rewritten with every new session; with TLS 1.3 tickets
+    /// arrive after the handshake, once data is read from the server.
+    /// `tls.sess_out_error` tells why the latest write failed, if one did.
     pub fn sess_out(mut self, path: impl Into<String>) -> Self {
         self.sess_out = Some(path.into());
         self
     }
 
     /// Load session from file for resumption
+    ///
+    /// The session is offered in every handshake made with this
+    /// configuration; `tls.sess_reused` tells whether the server accepted it.
     pub fn sess_in(mut self, path: impl Into<String>) -> Self {
         self.sess_in = Some(path.into());
         self
     }
 
+    /// Send early data (TLS 1.3 0-RTT) when resuming a session
+    ///
+    /// Only sent if the `sess_in` session allows early data;
+    /// `tls.early_data_status` tells whether the server accepted it. If not,
+    /// the data must be written again after the handshake.
+    pub fn early_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.early_data = Some(data.into());
+        self
+    }
+
+    /// Log session secrets, instead of to `SSLKEYLOGFILE`
+    pub fn keylog(mut self, keylog: &KeyLog) -> Self {
+        self.keylog = Some(keylog.clone());
+        self
+    }
+
+    /// Log session secrets to a file, instead of to `SSLKEYLOGFILE`
+    pub fn keylog_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
+        self.keylog = Some(KeyLog::file(path)?);
+        Ok(self)
+    }
+
+    /// Do not log session secrets, even if `SSLKEYLOGFILE` is set
+    pub fn no_keylog(mut self) -> Self {
+        self.keylog = None;
+        self
+    }
+
+    /// Give up handshakes not done within `timeout` (10 seconds by default)
+    ///
+    /// A timed out handshake leaves the session in the failed state.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Stop handshakes after a step, see `TlsSessionOps::continue_handshake`
+    pub fn stop_after(mut self, step: HandshakeStep) -> Self {
+        self.stop_after = Some(step);
+        self
+    }
+
     /// Build the TLS configuration
-    pub fn build(self) -> Result<TlsConfig, TlsError> {
+    pub fn build(mut self) -> Result<TlsConfig, TlsError> {
+        if let Some(path) = self.sess_out.take() {
+            super::resume::save_sessions(&mut self.ctx_builder, path);
+        }
+
+        // Names are checked during chain verification
+        let verify_name = match self.verify_name {
+            Some(name) => Some(name),
+            None if self.verify_hostname => Some(self.servername.clone().ok_or_else(|| {
+                TlsError::InvalidConfig("verify_hostname requires a servername".to_string())
+            })?),
+            None => None,
+        };
+        if let Some(name) = verify_name {
+            let param = self.ctx_builder.verify_param_mut();
+            match name.parse::<std::net::IpAddr>() {
+                Ok(ip) => param.set_ip(ip)?,
+                Err(_) => param.set_host(&name)?,
+            }
+        }
+
+        // Record alerts for tls.alert, and verification errors
+        super::alert::log_alerts(&mut self.ctx_builder);
+        super::verify::record_errors(&mut self.ctx_builder);
+        if let Some(keylog) = self.keylog {
+            super::keylog::install(&mut self.ctx_builder, keylog);
+        }
+
         Ok(TlsConfig {
             ctx: self.ctx_builder.build(),
             is_server: false,
             servername: self.servername,
             _verify_peer: self.verify_peer,
             cert_status: self.cert_status,
-            _sess_out: self.sess_out,
-            _sess_in: self.sess_in,
+            sess_in: self.sess_in,
+            early_data: self.early_data,
+            handshake_timeout: self.handshake_timeout,
+            stop_after: self.stop_after,
         })
     }
 }
@@ -293,19 +457,31 @@ impl ClientConfigBuilder {
 /// Server configuration builder
 pub struct ServerConfigBuilder {
     ctx_builder: openssl::ssl::SslContextBuilder,
-    has_cert: bool,
+    certs: SniCerts,
+    staple: Option<Vec<u8>>,
+    keylog: Option<KeyLog>,
+    handshake_timeout: Duration,
+    stop_after: Option<HandshakeStep>,
 }
 
 impl ServerConfigBuilder {
     fn new() -> Self {
         use openssl::ssl::{SslMethod, SslContextBuilder};
 
-        let ctx_builder = SslContextBuilder::new(SslMethod::tls_server())
+        let mut ctx_builder = SslContextBuilder::new(SslMethod::tls_server())
             .expect("Failed to create SSL context");
 
+        // Required to resume sessions when client certificates are verified
+        ctx_builder.set_session_id_context(b"VTEST")
+            .expect("Failed to set session ID context");
+
         ServerConfigBuilder {
             ctx_builder,
-            has_cert: false,
+            certs: SniCerts::default(),
+            staple: None,
+            keylog: KeyLog::from_env(),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            stop_after: None,
         }
     }
 
@@ -360,28 +536,38 @@ impl ServerConfigBuilder {
     }
 
     /// Load server certificate from PEM file
+    ///
+    /// Certificates after the first one are sent as its chain. Loading
+    /// certificates with different key types (RSA, ECDSA) lets the client's
+    /// signature algorithms choose between them.
     pub fn cert_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
-        // Read certificate file
-        let mut cert_pem = Vec::new();
-        File::open(path.as_ref())?.read_to_end(&mut cert_pem)?;
-
-        // Load certificate and chain
-        use openssl::x509::X509;
-        use openssl::pkey::PKey;
-
-        let cert = X509::from_pem(&cert_pem)
-            .map_err(|e| TlsError::Certificate(format!("Failed to load certificate: {}", e)))?;
-
-        self.ctx_builder.set_certificate(&cert)?;
+        let cert = load_certified_key(path.as_ref())?;
+        self.certs.default.push(cert);
+        Ok(self)
+    }
 
-        // Load private key
-        let key = PKey::private_key_from_pem(&cert_pem)
-            .map_err(|e| TlsError::Certificate(format!("Failed to load private key: {}", e)))?;
+    /// Add a certificate selected by SNI, from a PEM file
+    ///
+    /// The certificate serves the DNS names of its SANs, or its CN.
+    /// Clients sending other names, or none, get the default certificate.
+    pub fn sni_cert_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
+        let cert = load_certified_key(path.as_ref())?;
+        self.certs.add(cert);
+        Ok(self)
+    }
 
-        self.ctx_builder.set_private_key(&key)?;
+    /// Add a generated certificate selected by SNI
+    pub fn sni_cert(mut self, cert: &CertifiedKey) -> Self {
+        self.certs.add(cert.clone());
+        self
+    }
 
-        self.has_cert = true;
-        Ok(self)
+    /// Abort handshakes for names matching no SNI certificate
+    ///
+    /// Clients sending no name still get the default certificate.
+    pub fn reject_unknown_sni(mut self, alert: TlsAlert) -> Self {
+        self.certs.reject = Some(alert);
+        self
     }
 
     /// Set client certificate verification mode
@@ -404,27 +590,202 @@ impl ServerConfigBuilder {
         Ok(self)
     }
 
+    /// Trust a CA certificate for client certificate verification
+    ///
+    /// The CA is also named in the certificate request sent to clients.
+    pub fn client_verify_ca_cert(mut self, cert: &X509Ref) -> Result<Self, TlsError> {
+        self.ctx_builder.cert_store_mut().add_cert(cert.to_owned())?;
+        self.ctx_builder.add_client_ca(cert)?;
+        Ok(self)
+    }
+
+    /// Check client certificates against a revocation list
+    pub fn crl(mut self, crl: &X509CrlRef) -> Result<Self, TlsError> {
+        add_crl(&mut self.ctx_builder, crl)?;
+        Ok(self)
+    }
+
+    /// Log session secrets, instead of to `SSLKEYLOGFILE`
+    pub fn keylog(mut self, keylog: &KeyLog) -> Self {
+        self.keylog = Some(keylog.clone());
+        self
+    }
+
+    /// Log session secrets to a file, instead of to `SSLKEYLOGFILE`
+    pub fn keylog_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
+        self.keylog = Some(KeyLog::file(path)?);
+        Ok(self)
+    }
+
+    /// Do not log session secrets, even if `SSLKEYLOGFILE` is set
+    pub fn no_keylog(mut self) -> Self {
+        self.keylog = None;
+        self
+    }
+
+    /// Give up handshakes not done within `timeout` (10 seconds by default)
+    ///
+    /// A timed out handshake leaves the session in the failed state.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Stop handshakes after a step, see `TlsSessionOps::continue_handshake`
+    pub fn stop_after(mut self, step: HandshakeStep) -> Self {
+        self.stop_after = Some(step);
+        self
+    }
+
+    /// Use a generated server certificate, sent with its chain
+    pub fn cert(mut self, cert: &CertifiedKey) -> Result<Self, TlsError> {
+        self.certs.default.push(cert.clone());
+        Ok(self)
+    }
+
     /// Set OCSP staple response file
-    pub fn staple<P: AsRef<Path>>(self, path: P) -> Result<Self, TlsError> {
+    ///
+    /// The DER response is sent to clients requesting certificate status.
+    pub fn staple<P: AsRef<Path>>(mut self, path: P) -> Result<Self, TlsError> {
         // Read OCSP response
         let mut ocsp_resp = Vec::new();
         File::open(path.as_ref())?.read_to_end(&mut ocsp_resp)?;
 
-        // Note: Setting OCSP staple requires additional OpenSSL setup
-        // This is a simplified version - full implementation would set up
-        // the status_request callback
+        self.staple = Some(ocsp_resp);
+        Ok(self)
+    }
+
+    /// Enable or disable the server-side session cache (enabled by default)
+    ///
+    /// Without a cache, TLS 1.2 sessions can only be resumed with tickets.
+    pub fn session_cache(mut self, enable: bool) -> Self {
+        use openssl::ssl::SslSessionCacheMode;
+
+        let mode = if enable { SslSessionCacheMode::SERVER } else { SslSessionCacheMode::OFF };
+        self.ctx_builder.set_session_cache_mode(mode);
+        self
+    }
+
+    /// Set the maximum number of sessions in the server-side cache
+    pub fn session_cache_size(mut self, size: i32) -> Self {
+        self.ctx_builder.set_session_cache_size(size);
+        self
+    }
+
+    /// Enable or disable session tickets (enabled by default)
+    ///
+    /// Without tickets, TLS 1.2 resumes with session IDs and TLS 1.3 uses
+    /// stateful tickets referring to the session cache.
+    pub fn tickets(mut self, enable: bool) -> Self {
+        use openssl::ssl::SslOptions;
+
+        if enable {
+            self.ctx_builder.clear_options(SslOptions::NO_TICKET);
+        } else {
+            self.ctx_builder.set_options(SslOptions::NO_TICKET);
+        }
+        self
+    }
+
+    /// Set the number of TLS 1.3 tickets sent after a full handshake
+    pub fn num_tickets(mut self, count: usize) -> Result<Self, TlsError> {
+        self.ctx_builder.set_num_tickets(count)?;
+        Ok(self)
+    }
+
+    /// Accept up to `bytes` of early data (TLS 1.3 0-RTT)
+    ///
+    /// Advertised in the tickets sent from then on. Zero, the default,
+    /// disables early data: clients resuming a ticket that allowed it see
+    /// their early data rejected.
+    pub fn max_early_data(mut self, bytes: u32) -> Result<Self, TlsError> {
+        self.ctx_builder.set_max_early_data(bytes)?;
+        Ok(self)
+    }
+
+    /// Enable or disable replay protection of early data (enabled by default)
+    ///
+    /// With protection, a session accepts early data once: replayed
+    /// early data is rejected. It requires the session cache, and makes
+    /// TLS 1.3 tickets stateful: they only resume on the issuing server.
+    pub fn anti_replay(mut self, enable: bool) -> Self {
+        use openssl::ssl::SslOptions;
 
-        // For now, we'll store it but the full OCSP implementation
-        // would require more work
+        /// SSL_OP_NO_ANTI_REPLAY, not named by the openssl crate
+        const NO_ANTI_REPLAY: SslOptions = SslOptions::from_bits_retain(1 << 24);
 
+        if enable {
+            self.ctx_builder.clear_options(NO_ANTI_REPLAY);
+        } else {
+            self.ctx_builder.set_options(NO_ANTI_REPLAY);
+        }
+        self
+    }
+
+    /// Set the session ticket key
+    ///
+    /// The 80 bytes hold the key name (16), HMAC secret (32) and AES key
+    /// (32). Servers sharing a key accept each other's tickets; by default
+    /// every configuration gets a random key.
+    pub fn ticket_key(self, key: &[u8; 80]) -> Result<Self, TlsError> {
+        /// SSL_CTRL_SET_TLSEXT_TICKET_KEYS, behind the
+        /// `SSL_CTX_set_tlsext_ticket_keys` macro
+        const SSL_CTRL_SET_TLSEXT_TICKET_KEYS: libc::c_int = 59;
+
+        let mut key = *key;
+        let ret = unsafe {
+            openssl_sys::SSL_CTX_ctrl(
+                self.ctx_builder.as_ptr(),
+                SSL_CTRL_SET_TLSEXT_TICKET_KEYS,
+                key.len() as libc::c_long,
+                key.as_mut_ptr().cast(),
+            )
+        };
+        if ret <= 0 {
+            return Err(openssl::error::ErrorStack::get().into());
+        }
         Ok(self)
     }
 
     /// Build the TLS configuration
     pub fn build(mut self) -> Result<TlsConfig, TlsError> {
         // If no certificate was loaded, use the built-in certificate
-        if !self.has_cert {
-            self = self.load_builtin_cert()?;
+        if self.certs.default.is_empty() {
+            let cert = CertifiedKey::from_pem(super::builtin_cert::BUILTIN_CERT.as_bytes())
+                .map_err(|e| TlsError::Certificate(format!("Failed to load built-in certificate: {}", e)))?;
+            self.certs.default.push(cert);
+        }
+
+        // Certificates are set per connection once the SNI is known, or
+        // once and for all
+        if self.certs.is_empty() {
+            for cert in &self.certs.default {
+                set_certified_key(&mut self.ctx_builder, cert)?;
+            }
+        } else {
+            let certs = self.certs;
+            self.ctx_builder.set_servername_callback(move |ssl, alert| certs.install(ssl, alert));
+        }
+
+        // Called when the client sends a status request: record it for
+        // tls.staple_requested, and staple the response if there is one
+        let staple = self.staple.take();
+        self.ctx_builder.set_status_callback(move |ssl| {
+            ssl.set_ex_data(super::ocsp::staple_requested_index(), true);
+            match staple {
+                Some(ref resp) => {
+                    ssl.set_ocsp_status(resp)?;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        })?;
+
+        // Record alerts for tls.alert, and verification errors
+        super::alert::log_alerts(&mut self.ctx_builder);
+        super::verify::record_errors(&mut self.ctx_builder);
+        if let Some(keylog) = self.keylog {
+            super::keylog::install(&mut self.ctx_builder, keylog);
         }
 
         Ok(TlsConfig {
@@ -433,31 +794,57 @@ impl ServerConfigBuilder {
             servername: None,
             _verify_peer: false,
             cert_status: false,
-            _sess_out: None,
-            _sess_in: None,
+            sess_in: None,
+            early_data: None,
+            handshake_timeout: self.handshake_timeout,
+            stop_after: self.stop_after,
         })
     }
+}
 
-    fn load_builtin_cert(mut self) -> Result<Self, TlsError> {
-        // Load the built-in certificate
-        let cert_pem = super::builtin_cert::BUILTIN_CERT;
-
-        use openssl::x509::X509;
-        use openssl::pkey::PKey;
-
-        let cert = X509::from_pem(cert_pem.as_bytes())
-            .map_err(|e| TlsError::Certificate(format!("Failed to load built-in certificate: {}", e)))?;
-
-        self.ctx_builder.set_certificate(&cert)?;
+/// Load a certificate, its key and chain from a PEM file
+fn load_certified_key(path: &Path) -> Result<CertifiedKey, TlsError> {
+    let mut cert_pem = Vec::new();
+    File::open(path)?.read_to_end(&mut cert_pem)?;
+    CertifiedKey::from_pem(&cert_pem)
+}
 
-        let key = PKey::private_key_from_pem(cert_pem.as_bytes())
-            .map_err(|e| TlsError::Certificate(format!("Failed to load built-in private key: {}", e)))?;
+/// Set a generated certificate, its key and chain on a context
+fn set_certified_key(ctx_builder: &mut SslContextBuilder, cert: &CertifiedKey) -> Result<(), TlsError> {
+    ctx_builder.set_certificate(cert.cert())?;
+    ctx_builder.set_private_key(cert.key())?;
+    for chain_cert in cert.chain() {
+        ctx_builder.add_extra_chain_cert(chain_cert.clone())?;
+    }
+    Ok(())
+}
 
-        self.ctx_builder.set_private_key(&key)?;
+/// Add a CRL to the certificate store, and check peer certificates with it
+fn add_crl(ctx_builder: &mut SslContextBuilder, crl: &X509CrlRef) -> Result<(), TlsError> {
+    extern "C" {
+        // Not bound by openssl-sys
+        fn X509_STORE_add_crl(store: *mut openssl_sys::X509_STORE, crl: *mut openssl_sys::X509_CRL) -> libc::c_int;
+    }
 
-        self.has_cert = true;
-        Ok(self)
+    // The store takes its own reference, to a copy decoded from DER
+    let der = crl.to_der()?;
+    let ret = unsafe {
+        let mut p = der.as_ptr();
+        let copy = openssl_sys::d2i_X509_CRL(std::ptr::null_mut(), &mut p, der.len() as libc::c_long);
+        if copy.is_null() {
+            return Err(openssl::error::ErrorStack::get().into());
+        }
+        let store = openssl_sys::SSL_CTX_get_cert_store(ctx_builder.as_ptr());
+        let ret = X509_STORE_add_crl(store, copy);
+        openssl_sys::X509_CRL_free(copy);
+        ret
+    };
+    if ret <= 0 {
+        return Err(openssl::error::ErrorStack::get().into());
     }
+
+    ctx_builder.cert_store_mut().set_flags(X509VerifyFlags::CRL_CHECK)?;
+    Ok(())
 }
 
 /// Configuration builder (unified interface)
@@ -0,0 +1,226 @@
+//! Minimal DER encoding
+//!
+//! Just enough ASN.1 to build the structures OpenSSL can parse but the
+//! openssl crate cannot create: OCSP responses and revocation lists.
+
+use super::config::TlsError;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKeyRef, Private};
+use openssl::sign::Signer;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// sha1 (1.3.14.3.2.26)
+pub const SHA1_OID: &[u8] = &[0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a];
+/// id-pkix-ocsp-basic (1.3.6.1.5.5.7.48.1.1)
+pub const OCSP_BASIC_OID: &[u8] = &[0x06, 0x09, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+/// sha256WithRSAEncryption (1.2.840.113549.1.1.11)
+pub const SHA256_WITH_RSA_OID: &[u8] = &[0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+/// ecdsa-with-SHA256 (1.2.840.10045.4.3.2)
+pub const ECDSA_WITH_SHA256_OID: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// id-Ed25519 (1.3.101.112)
+pub const ED25519_OID: &[u8] = &[0x06, 0x03, 0x2b, 0x65, 0x70];
+/// NULL
+pub const NULL: &[u8] = &[0x05, 0x00];
+
+/// Encode a tag, length and content
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+pub fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    tlv(0x30, &items.concat())
+}
+
+pub fn octet_string(content: &[u8]) -> Vec<u8> {
+    tlv(0x04, content)
+}
+
+pub fn bit_string(content: &[u8]) -> Vec<u8> {
+    tlv(0x03, &[&[0u8][..], content].concat())
+}
+
+pub fn enumerated(value: u8) -> Vec<u8> {
+    tlv(0x0a, &[value])
+}
+
+/// Encode a non-negative big-endian integer
+pub fn integer(magnitude: &[u8]) -> Vec<u8> {
+    let mut content: Vec<u8> = magnitude.iter().copied().skip_while(|&b| b == 0).collect();
+    if content.first().is_none_or(|&b| b & 0x80 != 0) {
+        content.insert(0, 0);
+    }
+    tlv(0x02, &content)
+}
+
+/// Split a time into year, month, day, hour, minute and second (UTC)
+fn civil_time(time: SystemTime) -> (i64, i64, i64, u64, u64, u64) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// Encode a time as GeneralizedTime (`YYYYMMDDHHMMSSZ`)
+pub fn generalized_time(time: SystemTime) -> Vec<u8> {
+    let (year, month, day, hour, minute, second) = civil_time(time);
+    let text = format!("{:04}{:02}{:02}{:02}{:02}{:02}Z", year, month, day, hour, minute, second);
+    tlv(0x18, text.as_bytes())
+}
+
+/// Encode a certificate or CRL time (RFC 5280 Section 4.1.2.5)
+///
+/// UTCTime (`YYMMDDHHMMSSZ`) through 2049, GeneralizedTime after.
+pub fn time(time: SystemTime) -> Vec<u8> {
+    let (year, month, day, hour, minute, second) = civil_time(time);
+    if year >= 2050 {
+        return generalized_time(time);
+    }
+    let text = format!("{:02}{:02}{:02}{:02}{:02}{:02}Z", year % 100, month, day, hour, minute, second);
+    tlv(0x17, text.as_bytes())
+}
+
+/// AlgorithmIdentifier of the signatures made by `sign`
+pub fn signature_algorithm(key: &PKeyRef<Private>) -> Result<Vec<u8>, TlsError> {
+    match key.id() {
+        Id::RSA => Ok(sequence(&[SHA256_WITH_RSA_OID.to_vec(), NULL.to_vec()])),
+        Id::EC => Ok(sequence(&[ECDSA_WITH_SHA256_OID.to_vec()])),
+        Id::ED25519 => Ok(sequence(&[ED25519_OID.to_vec()])),
+        other => Err(TlsError::Certificate(format!("Unsupported signing key type {:?}", other))),
+    }
+}
+
+/// Sign data with SHA-256 (RSA, EC) or without digest (Ed25519)
+pub fn sign(key: &PKeyRef<Private>, data: &[u8]) -> Result<Vec<u8>, TlsError> {
+    let mut signer = match key.id() {
+        Id::ED25519 => Signer::new_without_digest(key)?,
+        _ => Signer::new(MessageDigest::sha256(), key)?,
+    };
+    Ok(signer.sign_oneshot_to_vec(data)?)
+}
+
+/// Split a DER element into its tag, content and the bytes after it
+pub fn read_tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count > rest.len() || count > 4 {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (len, &rest[count..])
+    };
+    (len <= rest.len()).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Extract the key bits from a DER SubjectPublicKeyInfo
+///
+/// The OCSP issuer key hash covers the BIT STRING value, without the
+/// algorithm identifier.
+pub fn subject_public_key(spki: &[u8]) -> Result<Vec<u8>, TlsError> {
+    let malformed = || TlsError::Certificate("Malformed SubjectPublicKeyInfo".to_string());
+    let (_, content, _) = read_tlv(spki).ok_or_else(malformed)?;
+    let (_, _, rest) = read_tlv(content).ok_or_else(malformed)?;
+    let (tag, bits, _) = read_tlv(rest).ok_or_else(malformed)?;
+    match (tag, bits.split_first()) {
+        (0x03, Some((0, key))) => Ok(key.to_vec()),
+        _ => Err(malformed()),
+    }
+}
+
+/// Format the content of an OBJECT IDENTIFIER in dotted notation
+pub fn oid_to_string(content: &[u8]) -> Option<String> {
+    let (&first, _) = content.split_first()?;
+    let mut arcs = vec![u64::from(first / 40).min(2), 0];
+    arcs[1] = u64::from(first) - arcs[0] * 40;
+    let mut arc = 0u64;
+    for &byte in &content[1..] {
+        arc = arc.checked_mul(128)? | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    let arcs: Vec<String> = arcs.iter().map(u64::to_string).collect();
+    Some(arcs.join("."))
+}
+
+/// List the extensions of a DER certificate: OID content and criticality
+pub fn extensions(cert: &[u8]) -> Option<Vec<(&[u8], bool)>> {
+    let (_, cert, _) = read_tlv(cert)?;
+    let (_, mut tbs, _) = read_tlv(cert)?;
+    while !tbs.is_empty() {
+        let (tag, content, rest) = read_tlv(tbs)?;
+        tbs = rest;
+        // extensions [3] EXPLICIT SEQUENCE OF Extension
+        if tag != 0xa3 {
+            continue;
+        }
+        let (_, mut list, _) = read_tlv(content)?;
+        let mut extensions = Vec::new();
+        while !list.is_empty() {
+            let (_, extension, rest) = read_tlv(list)?;
+            list = rest;
+            let (_, oid, rest) = read_tlv(extension)?;
+            let critical = matches!(read_tlv(rest)?, (0x01, [value], _) if *value != 0);
+            extensions.push((oid, critical));
+        }
+        return Some(extensions);
+    }
+    Some(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_integer() {
+        assert_eq!(integer(&[0x01]), vec![0x02, 0x01, 0x01]);
+        assert_eq!(integer(&[0x80]), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(integer(&[]), vec![0x02, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn test_times() {
+        let time_2023 = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(generalized_time(time_2023), [&[0x18, 15][..], b"20231114221320Z"].concat());
+        assert_eq!(time(time_2023), [&[0x17, 13][..], b"231114221320Z"].concat());
+
+        // 2050-01-01T00:00:00Z
+        let time_2050 = UNIX_EPOCH + Duration::from_secs(2_524_608_000);
+        assert_eq!(time(time_2050), [&[0x18, 15][..], b"20500101000000Z"].concat());
+    }
+
+    #[test]
+    fn test_oid_to_string() {
+        assert_eq!(oid_to_string(&SHA1_OID[2..]).as_deref(), Some("1.3.14.3.2.26"));
+        assert_eq!(oid_to_string(&SHA256_WITH_RSA_OID[2..]).as_deref(), Some("1.2.840.113549.1.1.11"));
+        assert_eq!(oid_to_string(&[]), None);
+    }
+}
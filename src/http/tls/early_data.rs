@@ -0,0 +1,48 @@
+//! TLS 1.3 early data (0-RTT)
+//!
+//! A client resuming a session whose ticket allows early data sends it
+//! with the ClientHello. The server accepts it, or rejects it (replay, or
+//! early data disabled) in which case the client must send it again once
+//! the handshake completes, e.g. after a `425 Too Early`.
+
+use openssl::ssl::SslRef;
+use std::fmt;
+
+/// What became of the early data of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyDataStatus {
+    /// No early data was sent
+    NotSent,
+    /// The server did not accept the early data
+    Rejected,
+    /// The server accepted the early data
+    Accepted,
+}
+
+impl fmt::Display for EarlyDataStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EarlyDataStatus::NotSent => "not_sent",
+            EarlyDataStatus::Rejected => "rejected",
+            EarlyDataStatus::Accepted => "accepted",
+        })
+    }
+}
+
+extern "C" {
+    // Not bound by openssl-sys
+    fn SSL_get_early_data_status(ssl: *const openssl_sys::SSL) -> libc::c_int;
+}
+
+/// Early data status of a connection, once its handshake is done
+pub fn status(ssl: &SslRef) -> EarlyDataStatus {
+    // SAFETY: an SslRef is the SSL it points to, which is what
+    // ForeignTypeRef::as_ptr returns
+    let ptr = (ssl as *const SslRef).cast::<openssl_sys::SSL>();
+    match unsafe { SSL_get_early_data_status(ptr) } {
+        // SSL_EARLY_DATA_REJECTED and SSL_EARLY_DATA_ACCEPTED
+        1 => EarlyDataStatus::Rejected,
+        2 => EarlyDataStatus::Accepted,
+        _ => EarlyDataStatus::NotSent,
+    }
+}
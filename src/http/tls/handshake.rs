@@ -1,10 +1,16 @@
 //! TLS handshake logic
 //!
-//! This module provides utilities for TLS handshake operations.
-//! Note: The actual handshake is performed by the `openssl` crate's
-//! `Ssl::connect()` and `Ssl::accept()` methods.
+//! The handshake runs over a `HandshakeIo`, which waits for the transport
+//! with `poll` so that a peer stalling the handshake (slow-loris) runs
+//! into a timeout instead of blocking forever. It can also stop the
+//! handshake at a given step, by holding back the I/O that would go
+//! past it, to leave peers with half-finished handshakes.
 
 use super::config::TlsError;
+use super::session::TlsTransport;
+use crate::http::session::PollEvents;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
 
 /// Default handshake timeout (used by session module)
 pub const DEFAULT_HANDSHAKE_TIMEOUT_SECS: u64 = 10;
@@ -12,17 +18,256 @@ pub const DEFAULT_HANDSHAKE_TIMEOUT_SECS: u64 = 10;
 /// Handshake result helper
 pub type HandshakeResult = std::result::Result<(), TlsError>;
 
-// Note: The handshake logic has been moved to the session module
-// where it's implemented using Ssl::connect() and Ssl::accept()
-// which handle the complexity of the TLS handshake internally.
-//
-// This module is kept for future enhancements like custom
-// handshake timeouts, retry logic, or handshake callbacks.
+/// Step after which to stop a handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStep {
+    /// The client sent its ClientHello, and reads nothing more. The server
+    /// read the ClientHello, and answers nothing.
+    ClientHello,
+    /// The server sent its first flight (ServerHello up to its Finished
+    /// with TLS 1.3), and reads nothing more. The client read it, and
+    /// sends nothing more.
+    ServerHello,
+}
+
+impl HandshakeStep {
+    /// I/O to hold back on one side to stop at this step
+    fn hold(self, is_server: bool) -> Hold {
+        match (self, is_server) {
+            (HandshakeStep::ClientHello, false) | (HandshakeStep::ServerHello, true) => Hold::ReadsAfterWrite,
+            (HandshakeStep::ClientHello, true) | (HandshakeStep::ServerHello, false) => Hold::WritesAfterRead,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hold {
+    /// Our flight is out: wait for no answer
+    ReadsAfterWrite,
+    /// The peer's flight is in: send no answer
+    WritesAfterRead,
+}
+
+/// Transport wrapper driving the handshake
+///
+/// Outside of the handshake, I/O goes straight to the transport.
+pub struct HandshakeIo<T: TlsTransport> {
+    inner: T,
+    deadline: Option<Instant>,
+    hold: Option<Hold>,
+    read_any: bool,
+    written_any: bool,
+    stopped: bool,
+}
+
+impl<T: TlsTransport> HandshakeIo<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        HandshakeIo {
+            inner,
+            deadline: None,
+            hold: None,
+            read_any: false,
+            written_any: false,
+            stopped: false,
+        }
+    }
+
+    /// Enter the handshake: wait for the transport until `timeout`, and
+    /// stop after `stop` if given
+    pub(crate) fn start(&mut self, timeout: Duration, stop: Option<HandshakeStep>, is_server: bool) {
+        self.deadline = Instant::now().checked_add(timeout);
+        self.hold = stop.map(|step| step.hold(is_server));
+        self.read_any = false;
+        self.written_any = false;
+        self.stopped = false;
+    }
+
+    /// Leave the handshake
+    pub(crate) fn finish(&mut self) {
+        self.deadline = None;
+        self.hold = None;
+    }
+
+    /// Whether the handshake was stopped by holding back I/O
+    pub(crate) fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Wait for the transport until the deadline
+    fn wait(&self, events: PollEvents) -> io::Result<()> {
+        let Some(deadline) = self.deadline else {
+            return Ok(());
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let ready = !remaining.is_zero()
+            && self
+                .inner
+                .poll(events, Some(remaining))
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        if ready {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))
+        }
+    }
+
+    /// Hold back I/O past the stop point
+    fn held(&mut self, hold: Hold) -> io::Result<()> {
+        if self.hold == Some(hold) {
+            self.stopped = true;
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(())
+    }
+}
+
+impl<T: TlsTransport> Read for HandshakeIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.written_any {
+            self.held(Hold::ReadsAfterWrite)?;
+        }
+        self.wait(PollEvents::Read)?;
+        let n = self.inner.read(buf)?;
+        self.read_any |= n > 0;
+        Ok(n)
+    }
+}
+
+impl<T: TlsTransport> Write for HandshakeIo<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.read_any {
+            self.held(Hold::WritesAfterRead)?;
+        }
+        self.wait(PollEvents::Write)?;
+        let n = self.inner.write(buf)?;
+        self.written_any |= n > 0;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::http::session::SessionOps;
+    use crate::http::tls::{TlsConfig, TlsVersion};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
     #[test]
     fn test_handshake_timeout_constant() {
         assert_eq!(super::DEFAULT_HANDSHAKE_TIMEOUT_SECS, 10);
     }
+
+    #[test]
+    fn test_connect_timeout() {
+        // A server that never answers the ClientHello
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_millis(500));
+            drop(tcp_stream);
+        });
+
+        let config = TlsConfig::client().handshake_timeout(Duration::from_millis(100)).build().unwrap();
+        let start = Instant::now();
+        let session = config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(400));
+        assert!(session.failed());
+        assert!(session.error().unwrap().contains("timed out"), "{:?}", session.error());
+        assert_eq!(session.handshake_stopped(), None);
+        server_handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_accept_timeout() {
+        // A client that never sends its ClientHello (slow-loris)
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(addr).unwrap();
+
+        let config = TlsConfig::server().handshake_timeout(Duration::from_millis(100)).build().unwrap();
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let session = config.accept(tcp_stream).unwrap();
+        assert!(session.failed());
+        assert!(session.error().unwrap().contains("timed out"), "{:?}", session.error());
+        drop(client);
+    }
+
+    #[test]
+    fn test_stop_after_client_hello() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_config = TlsConfig::server().stop_after(HandshakeStep::ClientHello).build().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            let session = server_config.accept(tcp_stream).unwrap();
+            assert!(!session.failed());
+            assert_eq!(session.handshake_stopped(), Some(HandshakeStep::ClientHello));
+            assert_eq!(session.vars().get("tls.failed").as_deref(), Some("false"));
+            // Keep the connection open past the client's timeout
+            thread::sleep(Duration::from_millis(300));
+        });
+
+        // The client sends its ClientHello, and gets no answer
+        let client_config = TlsConfig::client().stop_after(HandshakeStep::ClientHello).build().unwrap();
+        let session = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        assert!(!session.failed());
+        assert_eq!(session.handshake_stopped(), Some(HandshakeStep::ClientHello));
+
+        server_handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_stop_after_server_hello() {
+        for version in [TlsVersion::Tls12, TlsVersion::Tls13] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server_config = TlsConfig::server()
+                .version(version)
+                .stop_after(HandshakeStep::ServerHello)
+                .build()
+                .unwrap();
+            let server_handle = thread::spawn(move || {
+                let (tcp_stream, _) = listener.accept().unwrap();
+                let mut session = server_config.accept(tcp_stream).unwrap();
+                assert_eq!(session.handshake_stopped(), Some(HandshakeStep::ServerHello));
+                session.continue_handshake().unwrap();
+                assert_eq!(session.handshake_stopped(), None);
+                let mut buf = [0u8; 5];
+                assert_eq!(session.read(&mut buf).unwrap(), 5);
+                assert_eq!(&buf, b"hello");
+            });
+
+            let client_config = TlsConfig::client()
+                .version(version)
+                .stop_after(HandshakeStep::ServerHello)
+                .build()
+                .unwrap();
+            let mut session = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+            assert!(!session.failed());
+            assert_eq!(session.handshake_stopped(), Some(HandshakeStep::ServerHello));
+
+            session.continue_handshake().unwrap();
+            assert!(!session.failed());
+            assert_eq!(session.handshake_stopped(), None);
+            assert_eq!(session.write(b"hello").unwrap(), 5);
+            server_handle.join().unwrap();
+
+            // Nothing left to continue
+            session.continue_handshake().unwrap();
+        }
+    }
 }
@@ -0,0 +1,146 @@
+//! TLS key logging
+//!
+//! Writes the secrets of each connection in the NSS key log format, so
+//! that packet captures can be decrypted by Wireshark. Client and server
+//! configurations log to the file named by `SSLKEYLOGFILE` by default.
+
+use super::config::TlsError;
+use openssl::ssl::SslContextBuilder;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+enum Sink {
+    File(Mutex<File>),
+    Memory(Mutex<Vec<String>>),
+}
+
+/// Destination of key log lines
+///
+/// Clones share the destination, so one sink can serve several
+/// configurations.
+#[derive(Clone)]
+pub struct KeyLog(Arc<Sink>);
+
+impl KeyLog {
+    /// Append key log lines to a file, created if needed
+    pub fn file<P: AsRef<Path>>(path: P) -> Result<KeyLog, TlsError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(KeyLog(Arc::new(Sink::File(Mutex::new(file)))))
+    }
+
+    /// Keep key log lines in memory, see `lines`
+    pub fn memory() -> KeyLog {
+        KeyLog(Arc::new(Sink::Memory(Mutex::new(Vec::new()))))
+    }
+
+    /// The file named by `SSLKEYLOGFILE`, if set
+    ///
+    /// The file is opened once per process. A file that cannot be opened
+    /// disables key logging rather than failing configurations.
+    pub fn from_env() -> Option<KeyLog> {
+        static ENV: OnceLock<Option<KeyLog>> = OnceLock::new();
+        ENV.get_or_init(|| {
+            let path = std::env::var_os("SSLKEYLOGFILE").filter(|path| !path.is_empty())?;
+            KeyLog::file(path).ok()
+        })
+        .clone()
+    }
+
+    /// Lines logged so far to a memory sink (empty for files)
+    pub fn lines(&self) -> Vec<String> {
+        match &*self.0 {
+            Sink::File(_) => Vec::new(),
+            Sink::Memory(lines) => lines.lock().map(|lines| lines.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn write(&self, line: &str) {
+        match &*self.0 {
+            Sink::File(file) => {
+                // One write per line keeps lines whole with O_APPEND
+                if let Ok(mut file) = file.lock() {
+                    let _ = file.write_all(format!("{}\n", line).as_bytes());
+                }
+            }
+            Sink::Memory(lines) => {
+                if let Ok(mut lines) = lines.lock() {
+                    lines.push(line.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Log the secrets of connections made with this context
+pub(crate) fn install(ctx_builder: &mut SslContextBuilder, keylog: KeyLog) {
+    ctx_builder.set_keylog_callback(move |_ssl, line| keylog.write(line));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tls::{TlsConfig, TlsVersion};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn handshake(version: TlsVersion, client_log: &KeyLog, server_log: &KeyLog) {
+        let server_config = TlsConfig::server().version(version).keylog(server_log).build().unwrap();
+        let client_config = TlsConfig::client().version(version).keylog(client_log).build().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            assert!(!server_config.accept(tcp_stream).unwrap().failed());
+        });
+        assert!(!client_config.connect(TcpStream::connect(addr).unwrap()).unwrap().failed());
+        server_handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_keylog_memory() {
+        let (client_log, server_log) = (KeyLog::memory(), KeyLog::memory());
+        handshake(TlsVersion::Tls13, &client_log, &server_log);
+
+        let mut client_lines = client_log.lines();
+        let labels: Vec<&str> = client_lines.iter().filter_map(|line| line.split(' ').next()).collect();
+        for label in [
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET",
+            "SERVER_HANDSHAKE_TRAFFIC_SECRET",
+            "CLIENT_TRAFFIC_SECRET_0",
+            "SERVER_TRAFFIC_SECRET_0",
+        ] {
+            assert!(labels.contains(&label), "{:?}", labels);
+        }
+
+        // Both ends derive the same secrets
+        let mut server_lines = server_log.lines();
+        client_lines.sort();
+        server_lines.sort();
+        assert_eq!(client_lines, server_lines);
+    }
+
+    #[test]
+    fn test_keylog_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.log");
+        let keylog = KeyLog::file(&path).unwrap();
+
+        // TLS 1.2 logs the master secret by client random
+        handshake(TlsVersion::Tls12, &keylog, &KeyLog::memory());
+        handshake(TlsVersion::Tls12, &keylog, &KeyLog::memory());
+        assert!(keylog.lines().is_empty());
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", contents);
+        for line in lines {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields[0], "CLIENT_RANDOM");
+            assert_eq!(fields[1].len(), 64);
+            assert_eq!(fields[2].len(), 96);
+        }
+    }
+}
@@ -15,12 +15,21 @@
 //! # Features
 //!
 //! - TLS 1.0 through TLS 1.3 support (OpenSSL version dependent)
-//! - Certificate loading and validation
+//! - Certificate loading and validation: CA files and directories, hostname
+//!   and IP checks, verify depth
 //! - ALPN (Application-Layer Protocol Negotiation)
-//! - SNI (Server Name Indication)
-//! - Session resumption
+//! - SNI (Server Name Indication), with certificate selection on the server
+//! - Handshake timeouts, and handshakes stopped after ClientHello or
+//!   ServerHello
+//! - Session resumption, with TLS 1.3 early data (0-RTT)
 //! - OCSP stapling
 //! - Client certificate verification
+//! - Alerts sent and received (`tls.alert`), failed handshakes kept as
+//!   sessions in the failed state
+//! - Key logging for Wireshark, to `SSLKEYLOGFILE` by default
+//! - Test PKI: CAs, intermediates, leaves and CRLs generated at runtime
+//! - Any transport: TLS through CONNECT tunnels, or nested in TLS, with
+//!   `SessionStream`
 //!
 //! # Examples
 //!
@@ -40,7 +49,7 @@
 //!
 //! let tcp_stream = TcpStream::connect("example.com:443").unwrap();
 //! let tls_session = tls_config.connect(tcp_stream).unwrap();
-//! let mut client = HttpClient::from_tls(tls_session);
+//! let mut client = HttpClient::new(tls_session);
 //! ```
 //!
 //! ## Server with TLS
@@ -61,7 +70,7 @@
 //! let listener = TcpListener::bind("127.0.0.1:443").unwrap();
 //! let (tcp_stream, _) = listener.accept().unwrap();
 //! let tls_session = tls_config.accept(tcp_stream).unwrap();
-//! let mut server = HttpServer::from_tls(tls_session);
+//! let mut server = HttpServer::new(tls_session);
 //! ```
 
 pub mod config;
@@ -69,15 +78,27 @@ pub mod session;
 pub mod handshake;
 pub mod cert;
 pub mod vars;
+pub mod resume;
+pub mod ocsp;
+pub mod pki;
+pub mod sni;
+pub mod alert;
+pub mod verify;
+pub mod keylog;
+pub mod early_data;
+mod der;
 pub mod builtin_cert;
 
 pub use config::{
     TlsConfig, TlsConfigBuilder, TlsVersion, ClientVerify, TlsError,
     ClientConfigBuilder, ServerConfigBuilder,
 };
-pub use session::TlsSessionOps;
+pub use session::{TlsSessionOps, TlsTransport};
+pub use handshake::HandshakeStep;
 pub use vars::TlsVars;
 pub use cert::CertInfo;
+pub use alert::TlsAlert;
+pub use keylog::KeyLog;
 
 /// Result type for TLS operations
 pub type Result<T> = std::result::Result<T, TlsError>;
@@ -0,0 +1,299 @@
+//! OCSP stapling
+//!
+//! A client configured with `cert_status` asks the server for a stapled
+//! OCSP response (RFC 6066 Section 8), and a server configured with
+//! `staple` sends the DER response loaded from a file. The client checks
+//! the staple like the C implementation does, and reports the results
+//! through the `tls.ocsp_*` variables:
+//!
+//! - `tls.ocsp_resp_status`: status of the response (`successful`,
+//!   `malformed`, `internalerror`, `trylater`, `sigrequired`, `unauthorized`)
+//! - `tls.ocsp_verify`: signature and signer chain checked against the
+//!   client's CA store (`OK` or `failed`)
+//! - `tls.ocsp_cert_status`: stated status of the server certificate
+//!   (`good`, `revoked`, `unknown`, `ocsp-not-found`, `issuer-not-found`)
+//! - `tls.ocsp_this_update`: time of the status, as `YYYY-MM-DDTHH:MM:SSZ`
+//!
+//! `OcspResponseBuilder` produces signed responses for tests, without an
+//! OCSP responder.
+
+use super::cert::asn1_time_to_iso8601;
+use super::config::TlsError;
+use super::der;
+use openssl::hash::{hash, MessageDigest};
+use openssl::ocsp::{OcspCertId, OcspCertStatus, OcspFlag, OcspResponse, OcspResponseStatus};
+use openssl::pkey::{PKeyRef, Private};
+use openssl::ssl::{Ssl, SslRef};
+use openssl::x509::{X509Ref, X509VerifyResult};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+/// Ssl ex_data index recording that a staple was requested
+pub(crate) fn staple_requested_index() -> openssl::ex_data::Index<Ssl, bool> {
+    static INDEX: OnceLock<openssl::ex_data::Index<Ssl, bool>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().expect("Failed to allocate ex_data index"))
+}
+
+/// Check whether the peer (server side) or we (client side) asked for a staple
+pub fn staple_requested(ssl: &SslRef) -> bool {
+    ssl.ex_data(staple_requested_index()).copied().unwrap_or(false)
+}
+
+/// Results of checking a stapled OCSP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StapleCheck {
+    /// Response status (`tls.ocsp_resp_status`)
+    pub resp_status: String,
+    /// Signature verification result (`tls.ocsp_verify`)
+    pub verify: String,
+    /// Status of the server certificate (`tls.ocsp_cert_status`)
+    pub cert_status: String,
+    /// Time of the status (`tls.ocsp_this_update`)
+    pub this_update: String,
+}
+
+impl StapleCheck {
+    /// Check the response stapled by the server, if any (client side)
+    pub fn from_ssl(ssl: &SslRef) -> Option<StapleCheck> {
+        let der = ssl.ocsp_status()?;
+        let undef = || "<undef>".to_string();
+        let mut check = StapleCheck {
+            resp_status: "malformed".to_string(),
+            verify: "malformed".to_string(),
+            cert_status: "malformed".to_string(),
+            this_update: undef(),
+        };
+
+        let Ok(response) = OcspResponse::from_der(der) else {
+            return Some(check);
+        };
+        check.resp_status = response_status_name(response.status()).to_string();
+        check.verify = undef();
+        check.cert_status = undef();
+        if response.status() != OcspResponseStatus::SUCCESSFUL {
+            return Some(check);
+        }
+
+        let (Ok(basic), Some(chain), Some(subject)) =
+            (response.basic(), ssl.peer_cert_chain(), ssl.peer_certificate())
+        else {
+            return Some(check);
+        };
+
+        let store = ssl.ssl_context().cert_store();
+        check.verify = match basic.verify(chain, store, OcspFlag::empty()) {
+            Ok(()) => "OK",
+            Err(_) => "failed",
+        }
+        .to_string();
+
+        let Some(issuer) = chain.iter().find(|cert| cert.issued(&subject) == X509VerifyResult::OK) else {
+            check.cert_status = "issuer-not-found".to_string();
+            return Some(check);
+        };
+        let status = OcspCertId::from_cert(MessageDigest::sha1(), &subject, issuer)
+            .ok()
+            .and_then(|id| {
+                basic.find_status(&id).map(|status| {
+                    (cert_status_name(status.status), status.this_update.to_string())
+                })
+            });
+        match status {
+            Some((cert_status, this_update)) => {
+                check.cert_status = cert_status.to_string();
+                check.this_update = asn1_time_to_iso8601(&this_update).unwrap_or(this_update);
+            }
+            None => check.cert_status = "ocsp-not-found".to_string(),
+        }
+
+        Some(check)
+    }
+}
+
+/// Name of an OCSP response status, as in `tls.ocsp_resp_status`
+fn response_status_name(status: OcspResponseStatus) -> &'static str {
+    match status {
+        OcspResponseStatus::SUCCESSFUL => "successful",
+        OcspResponseStatus::MALFORMED_REQUEST => "malformed",
+        OcspResponseStatus::INTERNAL_ERROR => "internalerror",
+        OcspResponseStatus::TRY_LATER => "trylater",
+        OcspResponseStatus::SIG_REQUIRED => "sigrequired",
+        OcspResponseStatus::UNAUTHORIZED => "unauthorized",
+        _ => "<undef>",
+    }
+}
+
+/// Name of a certificate status, as in `tls.ocsp_cert_status`
+fn cert_status_name(status: OcspCertStatus) -> &'static str {
+    match status {
+        OcspCertStatus::GOOD => "good",
+        OcspCertStatus::REVOKED => "revoked",
+        _ => "unknown",
+    }
+}
+
+/// Certificate status reported by a generated response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertStatus {
+    /// The certificate is valid
+    Good,
+    /// The certificate was revoked at the given time
+    Revoked(SystemTime),
+    /// The responder does not know the certificate
+    Unknown,
+}
+
+/// Generator of OCSP responses for tests
+///
+/// Builds a `BasicOCSPResponse` (RFC 6960 Section 4.2.1) about one
+/// certificate, signed by an RSA, EC or Ed25519 key, with the signer
+/// certificate included.
+///
+/// # Example
+///
+/// ```
+/// use vtest2::http::tls::ocsp::{CertStatus, OcspResponseBuilder};
+/// use vtest2::http::tls::builtin_cert::BUILTIN_CERT;
+/// use openssl::pkey::PKey;
+/// use openssl::x509::X509;
+///
+/// // A self-signed certificate signing a response about itself
+/// let cert = X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+/// let key = PKey::private_key_from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+/// let der = OcspResponseBuilder::new(&cert, &cert)
+///     .status(CertStatus::Good)
+///     .sign(&cert, &key)
+///     .unwrap();
+/// assert!(!der.is_empty());
+/// ```
+pub struct OcspResponseBuilder<'a> {
+    cert: &'a X509Ref,
+    issuer: &'a X509Ref,
+    status: CertStatus,
+    this_update: SystemTime,
+    next_update: Option<SystemTime>,
+}
+
+impl<'a> OcspResponseBuilder<'a> {
+    /// Report on `cert`, issued by `issuer`; good, valid from now for a day
+    pub fn new(cert: &'a X509Ref, issuer: &'a X509Ref) -> Self {
+        let now = SystemTime::now();
+        OcspResponseBuilder {
+            cert,
+            issuer,
+            status: CertStatus::Good,
+            this_update: now,
+            next_update: Some(now + Duration::from_secs(86400)),
+        }
+    }
+
+    /// Set the certificate status
+    pub fn status(mut self, status: CertStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Set the time of the status
+    pub fn this_update(mut self, time: SystemTime) -> Self {
+        self.this_update = time;
+        self
+    }
+
+    /// Set when newer information will be available (None = omitted)
+    pub fn next_update(mut self, time: Option<SystemTime>) -> Self {
+        self.next_update = time;
+        self
+    }
+
+    /// Build an unsuccessful response, which carries no status
+    pub fn error(status: OcspResponseStatus) -> Vec<u8> {
+        der::sequence(&[der::enumerated(status.as_raw() as u8)])
+    }
+
+    /// Sign the response, returning its DER encoding
+    ///
+    /// The signer is either the issuer itself or a certificate the issuer
+    /// delegated OCSP signing to.
+    pub fn sign(&self, signer: &X509Ref, key: &PKeyRef<Private>) -> Result<Vec<u8>, TlsError> {
+        let cert_id = der::sequence(&[
+            der::sequence(&[der::SHA1_OID.to_vec(), der::NULL.to_vec()]),
+            der::octet_string(&hash(MessageDigest::sha1(), &self.issuer.subject_name().to_der()?)?),
+            der::octet_string(&hash(MessageDigest::sha1(), &der::subject_public_key(&self.issuer.public_key()?.public_key_to_der()?)?)?),
+            der::integer(&self.cert.serial_number().to_bn()?.to_vec()),
+        ]);
+        let cert_status = match self.status {
+            CertStatus::Good => vec![0x80, 0x00],
+            CertStatus::Revoked(time) => der::tlv(0xa1, &der::generalized_time(time)),
+            CertStatus::Unknown => vec![0x82, 0x00],
+        };
+
+        let mut single = vec![cert_id, cert_status, der::generalized_time(self.this_update)];
+        if let Some(next_update) = self.next_update {
+            single.push(der::tlv(0xa0, &der::generalized_time(next_update)));
+        }
+
+        let tbs = der::sequence(&[
+            // responderID byName
+            der::tlv(0xa1, &signer.subject_name().to_der()?),
+            der::generalized_time(SystemTime::now()),
+            der::sequence(&[der::sequence(&single)]),
+        ]);
+
+        let algorithm = der::signature_algorithm(key)?;
+        let signature = der::sign(key, &tbs)?;
+
+        let basic = der::sequence(&[
+            tbs,
+            algorithm,
+            der::bit_string(&signature),
+            der::tlv(0xa0, &der::sequence(&[signer.to_der()?])),
+        ]);
+
+        Ok(der::sequence(&[
+            der::enumerated(OcspResponseStatus::SUCCESSFUL.as_raw() as u8),
+            der::tlv(0xa0, &der::sequence(&[der::OCSP_BASIC_OID.to_vec(), der::octet_string(&basic)])),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tls::builtin_cert::BUILTIN_CERT;
+    use openssl::pkey::PKey;
+    use openssl::x509::X509;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_generated_response_parses() {
+        let cert = X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+        let key = PKey::private_key_from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+        let revoked_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let der = OcspResponseBuilder::new(&cert, &cert)
+            .status(CertStatus::Revoked(revoked_at))
+            .this_update(UNIX_EPOCH + Duration::from_secs(1_700_000_100))
+            .sign(&cert, &key)
+            .unwrap();
+
+        let response = OcspResponse::from_der(&der).unwrap();
+        assert_eq!(response.status(), OcspResponseStatus::SUCCESSFUL);
+        let basic = response.basic().unwrap();
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), &cert, &cert).unwrap();
+        let status = basic.find_status(&id).unwrap();
+        assert_eq!(status.status, OcspCertStatus::REVOKED);
+        assert_eq!(status.this_update.to_string(), "Nov 14 22:15:00 2023 GMT");
+        assert_eq!(
+            asn1_time_to_iso8601(&status.this_update.to_string()).as_deref(),
+            Some("2023-11-14T22:15:00Z")
+        );
+    }
+
+    #[test]
+    fn test_error_response() {
+        let der = OcspResponseBuilder::error(OcspResponseStatus::TRY_LATER);
+        let response = OcspResponse::from_der(&der).unwrap();
+        assert_eq!(response_status_name(response.status()), "trylater");
+    }
+
+}
@@ -0,0 +1,673 @@
+//! On-the-fly test PKI
+//!
+//! Mints CAs, intermediates and leaf certificates at runtime, so tests can
+//! set up the exact chain they need instead of relying on the built-in
+//! certificate or PEM files: custom names and SANs, expired or
+//! not-yet-valid certificates, various key types, key usages, and
+//! revocation lists. A `CertifiedKey` is handed to the configuration
+//! builders directly (`cert`, `ca_cert`, `crl`), without touching the
+//! filesystem; `to_pem` gives the `cert_file` format when a file is needed.
+//!
+//! # Example
+//!
+//! ```
+//! use vtest2::http::tls::pki::{CertBuilder, KeyType};
+//! use vtest2::http::tls::TlsConfig;
+//!
+//! let root = CertBuilder::ca("Test Root").self_signed().unwrap();
+//! let intermediate = CertBuilder::intermediate("Test Intermediate").issued_by(&root).unwrap();
+//! let leaf = CertBuilder::leaf("example.com")
+//!     .dns("www.example.com")
+//!     .key_type(KeyType::EcP384)
+//!     .issued_by(&intermediate)
+//!     .unwrap();
+//!
+//! // The server sends the leaf and the intermediate
+//! assert_eq!(leaf.chain().len(), 1);
+//! let server = TlsConfig::server().cert(&leaf).unwrap().build().unwrap();
+//! let client = TlsConfig::client().verify_peer(true).ca_cert(root.cert()).unwrap().build().unwrap();
+//! ```
+
+use super::config::TlsError;
+use super::der;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, PKeyRef, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{
+    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage as EkuExtension,
+    KeyUsage as KeyUsageExtension, SubjectAlternativeName as SanExtension, SubjectKeyIdentifier,
+};
+use openssl::x509::{X509, X509Crl, X509NameBuilder, X509Ref};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One day, the unit of the default validity windows
+const DAY: Duration = Duration::from_secs(86400);
+
+/// Key algorithm of a generated certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    /// RSA with the given modulus size in bits
+    Rsa(u32),
+    /// ECDSA on P-256
+    EcP256,
+    /// ECDSA on P-384
+    EcP384,
+    /// Ed25519
+    Ed25519,
+}
+
+impl KeyType {
+    /// Generate a private key of this type
+    pub fn generate(&self) -> Result<PKey<Private>, TlsError> {
+        let key = match self {
+            KeyType::Rsa(bits) => PKey::from_rsa(Rsa::generate(*bits)?)?,
+            KeyType::EcP256 => ec_key(Nid::X9_62_PRIME256V1)?,
+            KeyType::EcP384 => ec_key(Nid::SECP384R1)?,
+            KeyType::Ed25519 => PKey::generate_ed25519()?,
+        };
+        Ok(key)
+    }
+}
+
+fn ec_key(curve: Nid) -> Result<PKey<Private>, TlsError> {
+    let group = EcGroup::from_curve_name(curve)?;
+    Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+}
+
+/// Digest used to sign with a key (none for Ed25519)
+fn signing_digest(key: &PKeyRef<Private>) -> MessageDigest {
+    match key.id() {
+        Id::ED25519 => MessageDigest::null(),
+        _ => MessageDigest::sha256(),
+    }
+}
+
+/// Subject alternative name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubjectAltName {
+    /// DNS name, possibly a wildcard (`*.example.com`)
+    Dns(String),
+    /// IP address
+    Ip(IpAddr),
+    /// URI
+    Uri(String),
+}
+
+/// Key usage (RFC 5280 Section 4.2.1.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyUsage {
+    DigitalSignature,
+    NonRepudiation,
+    KeyEncipherment,
+    DataEncipherment,
+    KeyAgreement,
+    KeyCertSign,
+    CrlSign,
+}
+
+/// Extended key usage (RFC 5280 Section 4.2.1.12)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtendedKeyUsage {
+    ServerAuth,
+    ClientAuth,
+    CodeSigning,
+    EmailProtection,
+    TimeStamping,
+    OcspSigning,
+}
+
+/// A certificate with its private key and the chain up to its root
+#[derive(Clone)]
+pub struct CertifiedKey {
+    cert: X509,
+    key: PKey<Private>,
+    /// Intermediates, from the issuer up to (but not including) the root
+    chain: Vec<X509>,
+    self_signed: bool,
+}
+
+impl CertifiedKey {
+    /// Load a certificate, its key and chain from PEM, the `cert_file` format
+    pub fn from_pem(pem: &[u8]) -> Result<CertifiedKey, TlsError> {
+        let mut certs = X509::stack_from_pem(pem)
+            .map_err(|e| TlsError::Certificate(format!("Failed to load certificate: {}", e)))?
+            .into_iter();
+        let cert = certs.next()
+            .ok_or_else(|| TlsError::Certificate("No certificate found".to_string()))?;
+        let key = PKey::private_key_from_pem(pem)
+            .map_err(|e| TlsError::Certificate(format!("Failed to load private key: {}", e)))?;
+
+        let self_signed = cert.subject_name().to_der()? == cert.issuer_name().to_der()?;
+        Ok(CertifiedKey { cert, key, chain: certs.collect(), self_signed })
+    }
+
+    /// Get the certificate
+    pub fn cert(&self) -> &X509Ref {
+        &self.cert
+    }
+
+    /// Get the private key
+    pub fn key(&self) -> &PKeyRef<Private> {
+        &self.key
+    }
+
+    /// Get the intermediates sent along with the certificate
+    pub fn chain(&self) -> &[X509] {
+        &self.chain
+    }
+
+    /// DNS names the certificate is valid for: its SANs, or its CN without SANs
+    pub fn dns_names(&self) -> Vec<String> {
+        match self.cert.subject_alt_names() {
+            Some(names) => names.iter().filter_map(|name| name.dnsname().map(str::to_string)).collect(),
+            None => self
+                .cert
+                .subject_name()
+                .entries_by_nid(Nid::COMMONNAME)
+                .filter_map(|entry| entry.data().to_string().ok())
+                .collect(),
+        }
+    }
+
+    /// Encode the certificate as PEM
+    pub fn cert_pem(&self) -> Result<Vec<u8>, TlsError> {
+        Ok(self.cert.to_pem()?)
+    }
+
+    /// Encode the certificate, key and chain as PEM, the `cert_file` format
+    pub fn to_pem(&self) -> Result<Vec<u8>, TlsError> {
+        let mut pem = self.cert.to_pem()?;
+        pem.extend(self.key.private_key_to_pem_pkcs8()?);
+        for cert in &self.chain {
+            pem.extend(cert.to_pem()?);
+        }
+        Ok(pem)
+    }
+}
+
+/// Builder for generated certificates
+///
+/// Start from `ca`, `intermediate` or `leaf`, which set the usual
+/// extensions for each role, then finish with `self_signed` or `issued_by`.
+#[derive(Debug, Clone)]
+pub struct CertBuilder {
+    cn: String,
+    sans: Vec<SubjectAltName>,
+    key_type: KeyType,
+    not_before: SystemTime,
+    not_after: SystemTime,
+    /// CA basic constraint, with the optional path length
+    ca: Option<Option<u32>>,
+    key_usage: Vec<KeyUsage>,
+    extended_key_usage: Vec<ExtendedKeyUsage>,
+    serial: Option<u64>,
+}
+
+impl CertBuilder {
+    fn new(cn: &str, ca: Option<Option<u32>>) -> Self {
+        let now = SystemTime::now();
+        CertBuilder {
+            cn: cn.to_string(),
+            sans: Vec::new(),
+            key_type: KeyType::EcP256,
+            // Tolerate clock skew between the test processes
+            not_before: now - Duration::from_secs(3600),
+            not_after: now + 30 * DAY,
+            ca,
+            key_usage: Vec::new(),
+            extended_key_usage: Vec::new(),
+            serial: None,
+        }
+    }
+
+    /// Root CA: may sign certificates and CRLs, at any depth
+    pub fn ca(cn: &str) -> Self {
+        let mut builder = CertBuilder::new(cn, Some(None));
+        builder.key_usage = vec![KeyUsage::KeyCertSign, KeyUsage::CrlSign];
+        builder
+    }
+
+    /// Intermediate CA: may sign leaf certificates and CRLs
+    pub fn intermediate(cn: &str) -> Self {
+        let mut builder = CertBuilder::new(cn, Some(Some(0)));
+        builder.key_usage = vec![KeyUsage::KeyCertSign, KeyUsage::CrlSign];
+        builder
+    }
+
+    /// Leaf certificate for TLS servers and clients
+    ///
+    /// Unless SANs are added, the CN is used as the only DNS name.
+    pub fn leaf(cn: &str) -> Self {
+        let mut builder = CertBuilder::new(cn, None);
+        builder.key_usage = vec![KeyUsage::DigitalSignature, KeyUsage::KeyEncipherment];
+        builder.extended_key_usage = vec![ExtendedKeyUsage::ServerAuth, ExtendedKeyUsage::ClientAuth];
+        builder
+    }
+
+    /// Add a DNS subject alternative name
+    pub fn dns(mut self, name: &str) -> Self {
+        self.sans.push(SubjectAltName::Dns(name.to_string()));
+        self
+    }
+
+    /// Add an IP subject alternative name
+    pub fn ip(mut self, addr: IpAddr) -> Self {
+        self.sans.push(SubjectAltName::Ip(addr));
+        self
+    }
+
+    /// Add a URI subject alternative name
+    pub fn uri(mut self, uri: &str) -> Self {
+        self.sans.push(SubjectAltName::Uri(uri.to_string()));
+        self
+    }
+
+    /// Set the key type (default: ECDSA P-256)
+    pub fn key_type(mut self, key_type: KeyType) -> Self {
+        self.key_type = key_type;
+        self
+    }
+
+    /// Set the validity window
+    pub fn validity(mut self, not_before: SystemTime, not_after: SystemTime) -> Self {
+        self.not_before = not_before;
+        self.not_after = not_after;
+        self
+    }
+
+    /// Make the certificate expired since yesterday
+    pub fn expired(self) -> Self {
+        let now = SystemTime::now();
+        self.validity(now - 30 * DAY, now - DAY)
+    }
+
+    /// Make the certificate valid from tomorrow
+    pub fn not_yet_valid(self) -> Self {
+        let now = SystemTime::now();
+        self.validity(now + DAY, now + 30 * DAY)
+    }
+
+    /// Replace the key usages (empty = extension omitted)
+    pub fn key_usage(mut self, usages: &[KeyUsage]) -> Self {
+        self.key_usage = usages.to_vec();
+        self
+    }
+
+    /// Replace the extended key usages (empty = extension omitted)
+    pub fn extended_key_usage(mut self, usages: &[ExtendedKeyUsage]) -> Self {
+        self.extended_key_usage = usages.to_vec();
+        self
+    }
+
+    /// Set the serial number (default: random)
+    pub fn serial(mut self, serial: u64) -> Self {
+        self.serial = Some(serial);
+        self
+    }
+
+    /// Create a self-signed certificate
+    pub fn self_signed(self) -> Result<CertifiedKey, TlsError> {
+        let key = self.key_type.generate()?;
+        let cert = self.sign(&key, None)?;
+        Ok(CertifiedKey { cert, key, chain: Vec::new(), self_signed: true })
+    }
+
+    /// Create a certificate signed by `issuer`
+    pub fn issued_by(self, issuer: &CertifiedKey) -> Result<CertifiedKey, TlsError> {
+        let key = self.key_type.generate()?;
+        let cert = self.sign(&key, Some(issuer))?;
+
+        // The root is left out of the chain: peers must already trust it
+        let mut chain = Vec::new();
+        if !issuer.self_signed {
+            chain.push(issuer.cert.clone());
+            chain.extend(issuer.chain.iter().cloned());
+        }
+        Ok(CertifiedKey { cert, key, chain, self_signed: false })
+    }
+
+    fn sign(&self, key: &PKeyRef<Private>, issuer: Option<&CertifiedKey>) -> Result<X509, TlsError> {
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", &self.cn)?;
+        let name = name.build();
+
+        let serial = match self.serial {
+            Some(serial) => BigNum::from_slice(&serial.to_be_bytes())?,
+            None => {
+                let mut serial = BigNum::new()?;
+                serial.rand(63, MsbOption::MAYBE_ZERO, false)?;
+                serial
+            }
+        };
+
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        let serial = serial.to_asn1_integer()?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(issuer.map_or(&*name, |issuer| issuer.cert.subject_name()))?;
+        builder.set_pubkey(key)?;
+        let (not_before, not_after) = (asn1_time(self.not_before)?, asn1_time(self.not_after)?);
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+
+        if let Some(path_len) = self.ca {
+            let mut constraints = BasicConstraints::new();
+            constraints.critical().ca();
+            if let Some(path_len) = path_len {
+                constraints.pathlen(path_len);
+            }
+            builder.append_extension(constraints.build()?)?;
+        }
+
+        if !self.key_usage.is_empty() {
+            let mut ext = KeyUsageExtension::new();
+            ext.critical();
+            for usage in &self.key_usage {
+                match usage {
+                    KeyUsage::DigitalSignature => ext.digital_signature(),
+                    KeyUsage::NonRepudiation => ext.non_repudiation(),
+                    KeyUsage::KeyEncipherment => ext.key_encipherment(),
+                    KeyUsage::DataEncipherment => ext.data_encipherment(),
+                    KeyUsage::KeyAgreement => ext.key_agreement(),
+                    KeyUsage::KeyCertSign => ext.key_cert_sign(),
+                    KeyUsage::CrlSign => ext.crl_sign(),
+                };
+            }
+            builder.append_extension(ext.build()?)?;
+        }
+
+        if !self.extended_key_usage.is_empty() {
+            let mut ext = EkuExtension::new();
+            for usage in &self.extended_key_usage {
+                match usage {
+                    ExtendedKeyUsage::ServerAuth => ext.server_auth(),
+                    ExtendedKeyUsage::ClientAuth => ext.client_auth(),
+                    ExtendedKeyUsage::CodeSigning => ext.code_signing(),
+                    ExtendedKeyUsage::EmailProtection => ext.email_protection(),
+                    ExtendedKeyUsage::TimeStamping => ext.time_stamping(),
+                    ExtendedKeyUsage::OcspSigning => ext.other("OCSPSigning"),
+                };
+            }
+            builder.append_extension(ext.build()?)?;
+        }
+
+        let sans = match (&self.ca, self.sans.is_empty()) {
+            (None, true) => vec![SubjectAltName::Dns(self.cn.clone())],
+            _ => self.sans.clone(),
+        };
+        if !sans.is_empty() {
+            let ext = {
+                let ctx = builder.x509v3_context(issuer.map(|issuer| &*issuer.cert), None);
+                let mut ext = SanExtension::new();
+                for san in &sans {
+                    match san {
+                        SubjectAltName::Dns(name) => ext.dns(name),
+                        SubjectAltName::Ip(addr) => ext.ip(&addr.to_string()),
+                        SubjectAltName::Uri(uri) => ext.uri(uri),
+                    };
+                }
+                ext.build(&ctx)?
+            };
+            builder.append_extension(ext)?;
+        }
+
+        let ext = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(ext)?;
+        if let Some(issuer) = issuer {
+            let ctx = builder.x509v3_context(Some(&issuer.cert), None);
+            let ext = AuthorityKeyIdentifier::new().keyid(true).build(&ctx)?;
+            builder.append_extension(ext)?;
+        }
+
+        let signing_key = issuer.map_or(key, |issuer| &issuer.key);
+        builder.sign(signing_key, signing_digest(signing_key))?;
+        Ok(builder.build())
+    }
+}
+
+fn asn1_time(time: SystemTime) -> Result<Asn1Time, TlsError> {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    Ok(Asn1Time::from_unix(secs as libc::time_t)?)
+}
+
+/// Builder for certificate revocation lists
+///
+/// The CRL is issued by a CA from `CertBuilder`, and handed to a
+/// configuration with `crl` so that revoked peer certificates are rejected.
+pub struct CrlBuilder<'a> {
+    issuer: &'a CertifiedKey,
+    revoked: Vec<(Vec<u8>, SystemTime)>,
+    this_update: SystemTime,
+    next_update: SystemTime,
+}
+
+impl<'a> CrlBuilder<'a> {
+    /// Create an empty CRL issued by `issuer`, valid from now for a day
+    pub fn new(issuer: &'a CertifiedKey) -> Self {
+        let now = SystemTime::now();
+        CrlBuilder {
+            issuer,
+            revoked: Vec::new(),
+            this_update: now - Duration::from_secs(3600),
+            next_update: now + DAY,
+        }
+    }
+
+    /// Revoke a certificate, as of now
+    pub fn revoke(self, cert: &X509Ref) -> Result<Self, TlsError> {
+        self.revoke_at(cert, SystemTime::now())
+    }
+
+    /// Revoke a certificate, as of the given time
+    pub fn revoke_at(mut self, cert: &X509Ref, time: SystemTime) -> Result<Self, TlsError> {
+        self.revoked.push((cert.serial_number().to_bn()?.to_vec(), time));
+        Ok(self)
+    }
+
+    /// Set when the CRL was issued
+    pub fn this_update(mut self, time: SystemTime) -> Self {
+        self.this_update = time;
+        self
+    }
+
+    /// Set when the next CRL will be issued
+    pub fn next_update(mut self, time: SystemTime) -> Self {
+        self.next_update = time;
+        self
+    }
+
+    /// Sign the CRL
+    pub fn build(&self) -> Result<X509Crl, TlsError> {
+        let key = &self.issuer.key;
+        let algorithm = der::signature_algorithm(key)?;
+
+        // TBSCertList (RFC 5280 Section 5.1), version 1: no extensions
+        let mut tbs = vec![
+            algorithm.clone(),
+            self.issuer.cert.subject_name().to_der()?,
+            der::time(self.this_update),
+            der::time(self.next_update),
+        ];
+        if !self.revoked.is_empty() {
+            let entries: Vec<Vec<u8>> = self
+                .revoked
+                .iter()
+                .map(|(serial, time)| der::sequence(&[der::integer(serial), der::time(*time)]))
+                .collect();
+            tbs.push(der::sequence(&entries));
+        }
+        let tbs = der::sequence(&tbs);
+
+        let signature = der::sign(key, &tbs)?;
+        let crl = der::sequence(&[tbs, algorithm, der::bit_string(&signature)]);
+        Ok(X509Crl::from_der(&crl)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tls::{TlsConfig, TlsVersion};
+    use openssl::x509::X509VerifyResult;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// Handshake, returning the client's result
+    fn handshake(server_config: TlsConfig, client_config: TlsConfig) -> Result<(), TlsError> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            let _ = server_config.accept(tcp_stream);
+        });
+
+        let result = client_config
+            .connect(TcpStream::connect(addr).unwrap())
+            .and_then(|session| session.established())
+            .map(|_| ());
+        server_handle.join().unwrap();
+        result
+    }
+
+    /// Check that the client rejected the server certificate for `reason`
+    fn assert_rejected(result: Result<(), TlsError>, reason: &str) {
+        match result {
+            Err(TlsError::HandshakeFailed(msg)) => assert!(msg.contains(reason), "{}", msg),
+            other => panic!("expected rejection ({}), got {:?}", reason, other),
+        }
+    }
+
+    fn server(cert: &CertifiedKey) -> TlsConfig {
+        TlsConfig::server().cert(cert).unwrap().build().unwrap()
+    }
+
+    fn client(root: &CertifiedKey) -> crate::http::tls::ClientConfigBuilder {
+        TlsConfig::client().verify_peer(true).ca_cert(root.cert()).unwrap()
+    }
+
+    #[test]
+    fn test_chain_with_key_types() {
+        let root = CertBuilder::ca("Root").key_type(KeyType::Rsa(2048)).self_signed().unwrap();
+        let intermediate = CertBuilder::intermediate("Intermediate")
+            .key_type(KeyType::EcP384)
+            .issued_by(&root)
+            .unwrap();
+        assert_eq!(intermediate.cert().issuer_name().to_der().unwrap(), root.cert().subject_name().to_der().unwrap());
+        assert_eq!(root.cert().issued(intermediate.cert()), X509VerifyResult::OK);
+        assert!(intermediate.chain().is_empty());
+
+        for key_type in [KeyType::EcP256, KeyType::EcP384, KeyType::Ed25519, KeyType::Rsa(2048)] {
+            let leaf = CertBuilder::leaf("example.com").key_type(key_type).issued_by(&intermediate).unwrap();
+            assert_eq!(leaf.chain().len(), 1);
+            assert_eq!(intermediate.cert().issued(leaf.cert()), X509VerifyResult::OK);
+
+            handshake(server(&leaf), client(&root).build().unwrap()).unwrap();
+        }
+
+        // Without the intermediate the chain cannot be built
+        let leaf = CertBuilder::leaf("example.com").issued_by(&intermediate).unwrap();
+        let unchained = CertifiedKey { chain: Vec::new(), ..leaf.clone() };
+        assert_rejected(handshake(server(&unchained), client(&root).build().unwrap()), "unable to get local issuer certificate");
+
+        // Nor without the root
+        let untrusting = TlsConfig::client().verify_peer(true).build().unwrap();
+        assert_rejected(handshake(server(&leaf), untrusting), "unable to get local issuer certificate");
+    }
+
+    #[test]
+    fn test_subject_alt_names() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let leaf = CertBuilder::leaf("example.com")
+            .dns("*.example.com")
+            .ip("192.0.2.1".parse().unwrap())
+            .ip("2001:db8::1".parse().unwrap())
+            .uri("spiffe://example.com/server")
+            .issued_by(&root)
+            .unwrap();
+
+        let names = leaf.cert().subject_alt_names().unwrap();
+        let dns: Vec<_> = names.iter().filter_map(|name| name.dnsname()).collect();
+        let ips: Vec<_> = names.iter().filter_map(|name| name.ipaddress()).collect();
+        let uris: Vec<_> = names.iter().filter_map(|name| name.uri()).collect();
+        assert_eq!(dns, vec!["*.example.com"]);
+        assert_eq!(ips, vec![&[192, 0, 2, 1][..], &"2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets()[..]]);
+        assert_eq!(uris, vec!["spiffe://example.com/server"]);
+
+        // Without explicit SANs the CN is the DNS name
+        let leaf = CertBuilder::leaf("www.example.com").issued_by(&root).unwrap();
+        let names = leaf.cert().subject_alt_names().unwrap();
+        assert_eq!(names.iter().filter_map(|name| name.dnsname()).collect::<Vec<_>>(), vec!["www.example.com"]);
+        assert!(root.cert().subject_alt_names().is_none());
+    }
+
+    #[test]
+    fn test_validity_windows() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let valid = CertBuilder::leaf("example.com").issued_by(&root).unwrap();
+        let expired = CertBuilder::leaf("example.com").expired().issued_by(&root).unwrap();
+        let not_yet_valid = CertBuilder::leaf("example.com").not_yet_valid().issued_by(&root).unwrap();
+
+        handshake(server(&valid), client(&root).build().unwrap()).unwrap();
+        assert_rejected(handshake(server(&expired), client(&root).build().unwrap()), "certificate has expired");
+        assert_rejected(handshake(server(&not_yet_valid), client(&root).build().unwrap()), "certificate is not yet valid");
+
+        // Not checked without verification
+        let client = TlsConfig::client().build().unwrap();
+        handshake(server(&expired), client).unwrap();
+    }
+
+    #[test]
+    fn test_extended_key_usage() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let client_only = CertBuilder::leaf("example.com")
+            .extended_key_usage(&[ExtendedKeyUsage::ClientAuth])
+            .issued_by(&root)
+            .unwrap();
+        assert_rejected(handshake(server(&client_only), client(&root).build().unwrap()), "unsuitable certificate purpose");
+
+        // A client certificate, verified by the server
+        let server_leaf = CertBuilder::leaf("example.com").issued_by(&root).unwrap();
+        let server_config = TlsConfig::server()
+            .version(TlsVersion::Tls12)
+            .cert(&server_leaf)
+            .unwrap()
+            .client_verify(crate::http::tls::ClientVerify::Required)
+            .client_verify_ca_cert(root.cert())
+            .unwrap()
+            .build()
+            .unwrap();
+        let client_config = client(&root).cert(&client_only).unwrap().build().unwrap();
+        handshake(server_config, client_config).unwrap();
+    }
+
+    #[test]
+    fn test_revocation_list() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let intermediate = CertBuilder::intermediate("Intermediate").issued_by(&root).unwrap();
+        let revoked = CertBuilder::leaf("example.com").issued_by(&intermediate).unwrap();
+        let valid = CertBuilder::leaf("example.com").issued_by(&intermediate).unwrap();
+
+        let crl = CrlBuilder::new(&intermediate).revoke(revoked.cert()).unwrap().build().unwrap();
+        assert_eq!(crl.issuer_name().to_der().unwrap(), intermediate.cert().subject_name().to_der().unwrap());
+        assert!(crl.verify(intermediate.key()).unwrap());
+        assert_eq!(crl.get_revoked().unwrap().len(), 1);
+
+        let with_crl = || client(&root).crl(&crl).unwrap().build().unwrap();
+        handshake(server(&valid), with_crl()).unwrap();
+        assert_rejected(handshake(server(&revoked), with_crl()), "certificate revoked");
+        handshake(server(&revoked), client(&root).build().unwrap()).unwrap();
+
+        // An empty CRL revokes nothing; Ed25519 issuers sign CRLs too
+        let ed_root = CertBuilder::ca("Ed Root").key_type(KeyType::Ed25519).self_signed().unwrap();
+        let leaf = CertBuilder::leaf("example.com").issued_by(&ed_root).unwrap();
+        let crl = CrlBuilder::new(&ed_root).build().unwrap();
+        assert!(crl.get_revoked().is_none());
+        handshake(server(&leaf), client(&ed_root).crl(&crl).unwrap().build().unwrap()).unwrap();
+    }
+}
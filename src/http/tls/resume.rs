@@ -0,0 +1,109 @@
+//! TLS session resumption
+//!
+//! Sessions are stored in PEM files (`-----BEGIN SSL SESSION PARAMETERS-----`),
+//! the format written by OpenSSL's `PEM_write_SSL_SESSION` and read by
+//! `openssl sess_id`. A client configured with `sess_out` saves every new
+//! session to such a file: during the handshake up to TLS 1.2, and once
+//! each ticket arrives after the handshake with TLS 1.3. When the server
+//! sends several tickets, the file holds the last one. A client configured
+//! with `sess_in` offers the stored session in its next handshake. A save
+//! that fails is reported in `tls.sess_out_error`.
+
+use super::config::TlsError;
+use openssl::base64;
+use openssl::ex_data::Index;
+use openssl::ssl::{Ssl, SslContextBuilder, SslRef, SslSession, SslSessionCacheMode, SslSessionRef};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// PEM label of a serialized session
+const PEM_LABEL: &str = "SSL SESSION PARAMETERS";
+
+/// Ssl ex_data index recording the latest failed session save
+fn save_error_index() -> Index<Ssl, String> {
+    static INDEX: OnceLock<Index<Ssl, String>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().expect("Failed to allocate SSL ex_data index"))
+}
+
+/// Save the new sessions of connections made with this context to `path`
+pub(crate) fn save_sessions(ctx_builder: &mut SslContextBuilder, path: String) {
+    // Called during the handshake up to TLS 1.2, and for each ticket
+    // received after the handshake with TLS 1.3. A failed write leaves the
+    // file stale, which shows on resumption: the error is kept to tell why.
+    ctx_builder.set_session_cache_mode(SslSessionCacheMode::CLIENT | SslSessionCacheMode::NO_INTERNAL);
+    ctx_builder.set_new_session_callback(move |ssl, session| {
+        if let Err(e) = save_session(&path, &session) {
+            ssl.set_ex_data(save_error_index(), format!("{}: {}", path, e));
+        }
+    });
+}
+
+/// Error of the latest failed session save, if any
+pub fn save_error(ssl: &SslRef) -> Option<&str> {
+    ssl.ex_data(save_error_index()).map(String::as_str)
+}
+
+/// Encode a session as PEM
+pub fn session_to_pem(session: &SslSessionRef) -> Result<String, TlsError> {
+    let der = session.to_der()?;
+    let encoded = base64::encode_block(&der);
+
+    let mut pem = format!("-----BEGIN {}-----\n", PEM_LABEL);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", PEM_LABEL));
+    Ok(pem)
+}
+
+/// Decode a PEM-encoded session
+pub fn session_from_pem(pem: &str) -> Result<SslSession, TlsError> {
+    let begin = format!("-----BEGIN {}-----", PEM_LABEL);
+    let end = format!("-----END {}-----", PEM_LABEL);
+
+    let body = pem
+        .split_once(&begin)
+        .and_then(|(_, rest)| rest.split_once(&end))
+        .map(|(body, _)| body)
+        .ok_or_else(|| TlsError::SessionResumptionFailed("no SSL SESSION PARAMETERS block".to_string()))?;
+    let encoded: String = body.split_whitespace().collect();
+
+    let der = base64::decode_block(&encoded)
+        .map_err(|e| TlsError::SessionResumptionFailed(format!("invalid base64: {}", e)))?;
+    SslSession::from_der(&der)
+        .map_err(|e| TlsError::SessionResumptionFailed(format!("invalid session: {}", e)))
+}
+
+/// Write a session to a PEM file, replacing its contents
+pub fn save_session<P: AsRef<Path>>(path: P, session: &SslSessionRef) -> Result<(), TlsError> {
+    fs::write(path, session_to_pem(session)?)?;
+    Ok(())
+}
+
+/// Read a session from a PEM file
+pub fn load_session<P: AsRef<Path>>(path: P) -> Result<SslSession, TlsError> {
+    let pem = fs::read_to_string(path.as_ref()).map_err(|e| {
+        TlsError::SessionResumptionFailed(format!("cannot read {}: {}", path.as_ref().display(), e))
+    })?;
+    session_from_pem(&pem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_from_pem_rejects_garbage() {
+        assert!(matches!(
+            session_from_pem("not a session"),
+            Err(TlsError::SessionResumptionFailed(_))
+        ));
+        assert!(matches!(
+            session_from_pem("-----BEGIN SSL SESSION PARAMETERS-----\nAAAA\n-----END SSL SESSION PARAMETERS-----\n"),
+            Err(TlsError::SessionResumptionFailed(_))
+        ));
+        assert!(load_session("/nonexistent/session.pem").is_err());
+    }
+}
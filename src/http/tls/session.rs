@@ -4,29 +4,135 @@
 //! enabling transparent switching between plain TCP and TLS I/O.
 
 use super::config::{TlsConfig, TlsError};
+use super::handshake::{HandshakeIo, HandshakeStep};
 use super::vars::TlsVars;
-use crate::http::session::{SessionOps, PollEvents};
+use crate::http::session::{SessionOps, SessionStream, PollEvents};
 use crate::http::{Error, Result as HttpResult};
-use openssl::ssl::{Ssl, SslStream};
+use openssl::ssl::{Ssl, SslRef, SslStream};
+use openssl::x509::X509VerifyResult;
 use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::os::fd::AsRawFd;
 use std::time::Duration;
 
+/// Transport carrying a TLS session
+///
+/// Implemented by `TcpStream` and by `SessionStream`, so TLS can run over
+/// any session: a CONNECT tunnel, or another TLS session.
+pub trait TlsTransport: Read + Write {
+    /// Poll the transport for events
+    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> HttpResult<bool>;
+
+    /// Shut the transport down once the TLS session is closed
+    fn shutdown(&mut self) -> HttpResult<()>;
+}
+
+impl TlsTransport for TcpStream {
+    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> HttpResult<bool> {
+        use libc::{poll, pollfd, POLLIN, POLLOUT};
+
+        let mut pfd = pollfd {
+            fd: self.as_raw_fd(),
+            events: match events {
+                PollEvents::Read => POLLIN,
+                PollEvents::Write => POLLOUT,
+                PollEvents::Both => POLLIN | POLLOUT,
+            },
+            revents: 0,
+        };
+
+        let timeout_ms = timeout
+            .map(|d| d.as_millis() as i32)
+            .unwrap_or(-1);
+
+        let result = unsafe { poll(&mut pfd as *mut pollfd, 1, timeout_ms) };
+
+        if result < 0 {
+            return Err(Error::Io(std::io::Error::last_os_error()));
+        }
+
+        Ok(result > 0)
+    }
+
+    fn shutdown(&mut self) -> HttpResult<()> {
+        // The peer may already have torn down the socket after our
+        // close_notify, which is not an error.
+        use std::net::Shutdown;
+        match TcpStream::shutdown(self, Shutdown::Both) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotConnected => Ok(()),
+            result => result.map_err(Error::from),
+        }
+    }
+}
+
+impl<S: SessionOps> TlsTransport for SessionStream<S> {
+    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> HttpResult<bool> {
+        self.get_ref().poll(events, timeout)
+    }
+
+    fn shutdown(&mut self) -> HttpResult<()> {
+        self.get_mut().close()
+    }
+}
+
 /// TLS session operations
 ///
 /// Implements SessionOps trait for TLS-encrypted connections.
 /// Wraps an OpenSSL SslStream and provides poll/read/write/close operations.
-pub struct TlsSessionOps {
-    stream: SslStream<TcpStream>,
-    _config: TlsConfig,
+/// The transport is a TCP connection unless given otherwise.
+///
+/// A failed handshake still yields a session, in the failed state, so
+/// that `tls.failed` and `tls.alert` can be checked. So does a handshake
+/// stopped at a step, which `continue_handshake` takes up again.
+pub struct TlsSessionOps<T: TlsTransport = TcpStream> {
+    stream: SslStream<HandshakeIo<T>>,
+    config: TlsConfig,
     vars: TlsVars,
     failed: bool,
+    error: Option<String>,
+    early_data: Vec<u8>,
+    stopped: Option<HandshakeStep>,
 }
 
-impl TlsSessionOps {
+/// Send all of the early data
+fn write_early_data<T: TlsTransport>(
+    stream: &mut SslStream<HandshakeIo<T>>,
+    mut data: &[u8],
+) -> Result<(), openssl::ssl::Error> {
+    while !data.is_empty() {
+        let n = stream.write_early_data(data)?;
+        data = &data[n..];
+    }
+    Ok(())
+}
+
+/// Read early data until the client is done sending it
+fn read_early_data<T: TlsTransport>(
+    stream: &mut SslStream<HandshakeIo<T>>,
+    data: &mut Vec<u8>,
+) -> Result<(), openssl::ssl::Error> {
+    let mut buf = [0u8; 16384];
+    loop {
+        match stream.read_early_data(&mut buf)? {
+            0 => return Ok(()),
+            n => data.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+/// Describe a failed handshake
+fn handshake_error(error: &openssl::ssl::Error, ssl: &SslRef) -> String {
+    let operation = if ssl.is_server() { "Accept" } else { "Connection" };
+    // Say why the peer certificate was rejected
+    match ssl.verify_result() {
+        X509VerifyResult::OK => format!("{} failed: {}", operation, error),
+        result => format!("{} failed: {} ({})", operation, error, result.error_string()),
+    }
+}
+
+impl<T: TlsTransport> TlsSessionOps<T> {
     /// Create a client TLS connection (perform handshake)
-    pub fn connect(tcp_stream: TcpStream, config: TlsConfig) -> std::result::Result<Self, TlsError> {
+    pub fn connect(transport: T, config: TlsConfig) -> std::result::Result<Self, TlsError> {
         // Create SSL connection
         let mut ssl = Ssl::new(&config.ctx)?;
 
@@ -35,55 +141,126 @@ impl TlsSessionOps {
             ssl.set_hostname(servername)?;
         }
 
+        // Offer a saved session for resumption
+        let mut early_data_allowed = false;
+        if let Some(ref path) = config.sess_in {
+            let session = super::resume::load_session(path)?;
+            early_data_allowed = session.max_early_data() > 0;
+            // SAFETY: the session was deserialized from a file and is not
+            // associated with another context
+            unsafe { ssl.set_session(&session) }.map_err(|e| {
+                TlsError::SessionResumptionFailed(format!("cannot use session from {}: {}", path, e))
+            })?;
+        }
+
         // Request OCSP staple if configured
         if config.cert_status {
             // Enable status request
             ssl.set_status_type(openssl::ssl::StatusType::OCSP)?;
+            ssl.set_ex_data(super::ocsp::staple_requested_index(), true);
         }
 
-        // Keep in blocking mode for handshake
-        // The openssl crate's connect() method handles the handshake synchronously
-        let ssl_stream = match ssl.connect(tcp_stream) {
-            Ok(stream) => stream,
-            Err(e) => {
-                return Err(TlsError::HandshakeFailed(format!("Connection failed: {}", e)));
-            }
-        };
+        super::alert::start_log(&mut ssl);
+        super::verify::start_record(&mut ssl);
 
-        // Create TLS vars from the SSL session
-        let vars = TlsVars::from_ssl(ssl_stream.ssl(), false);
+        ssl.set_connect_state();
+        let mut io = HandshakeIo::new(transport);
+        io.start(config.handshake_timeout, config.stop_after, false);
+        let mut ssl_stream = SslStream::new(ssl, io)?;
 
-        Ok(TlsSessionOps {
-            stream: ssl_stream,
-            _config: config,
-            vars,
-            failed: false,
-        })
+        // Send early data if the ticket allows it
+        let result = match config.early_data.clone().filter(|_| early_data_allowed) {
+            Some(data) => write_early_data(&mut ssl_stream, &data).and_then(|()| ssl_stream.do_handshake()),
+            None => ssl_stream.do_handshake(),
+        };
+
+        Ok(Self::new(ssl_stream, config, result, Vec::new()))
     }
 
     /// Accept a client connection with TLS (perform handshake)
-    pub fn accept(tcp_stream: TcpStream, config: TlsConfig) -> std::result::Result<Self, TlsError> {
+    pub fn accept(transport: T, config: TlsConfig) -> std::result::Result<Self, TlsError> {
         // Create SSL connection
-        let ssl = Ssl::new(&config.ctx)?;
-
-        // Keep in blocking mode for handshake
-        // The openssl crate's accept() method handles the handshake synchronously
-        let ssl_stream = match ssl.accept(tcp_stream) {
-            Ok(stream) => stream,
-            Err(e) => {
-                return Err(TlsError::HandshakeFailed(format!("Accept failed: {}", e)));
-            }
+        let mut ssl = Ssl::new(&config.ctx)?;
+        super::alert::start_log(&mut ssl);
+        super::verify::start_record(&mut ssl);
+
+        let accept_early_data = ssl.max_early_data() > 0;
+        ssl.set_accept_state();
+        let mut io = HandshakeIo::new(transport);
+        io.start(config.handshake_timeout, config.stop_after, true);
+        let mut ssl_stream = SslStream::new(ssl, io)?;
+
+        // Read early data first, when accepted
+        let mut early_data = Vec::new();
+        let result = if accept_early_data {
+            read_early_data(&mut ssl_stream, &mut early_data).and_then(|()| ssl_stream.do_handshake())
+        } else {
+            ssl_stream.do_handshake()
         };
 
-        // Create TLS vars from the SSL session
-        let vars = TlsVars::from_ssl(ssl_stream.ssl(), false);
+        Ok(Self::new(ssl_stream, config, result, early_data))
+    }
 
-        Ok(TlsSessionOps {
-            stream: ssl_stream,
-            _config: config,
-            vars,
+    /// Wrap a stream after its handshake, stopped or not
+    fn new(
+        stream: SslStream<HandshakeIo<T>>,
+        config: TlsConfig,
+        result: Result<(), openssl::ssl::Error>,
+        early_data: Vec<u8>,
+    ) -> Self {
+        let mut session = TlsSessionOps {
+            stream,
+            vars: TlsVars::default(),
             failed: false,
-        })
+            error: None,
+            early_data,
+            stopped: config.stop_after,
+            config,
+        };
+        session.handshake_done(result);
+        session
+    }
+
+    /// Record the outcome of the handshake so far
+    fn handshake_done(&mut self, result: Result<(), openssl::ssl::Error>) {
+        match result {
+            Err(_) if self.stream.get_ref().stopped() => {}
+            result => {
+                self.stopped = None;
+                self.stream.get_mut().finish();
+                self.error = result.err().map(|e| handshake_error(&e, self.stream.ssl()));
+                self.failed = self.error.is_some();
+            }
+        }
+        self.vars = TlsVars::from_ssl(self.stream.ssl(), self.failed);
+    }
+
+    /// Step at which the handshake is stopped, if it is
+    pub fn handshake_stopped(&self) -> Option<HandshakeStep> {
+        self.stopped
+    }
+
+    /// Handshake state, as described by OpenSSL
+    /// (e.g. `SSLv3/TLS write client hello`)
+    pub fn handshake_state(&self) -> &'static str {
+        self.stream.ssl().state_string_long()
+    }
+
+    /// Finish a stopped handshake, within the handshake timeout
+    ///
+    /// Does nothing if the handshake is not stopped.
+    pub fn continue_handshake(&mut self) -> std::result::Result<(), TlsError> {
+        if self.stopped.is_none() {
+            return Ok(());
+        }
+        let is_server = self.stream.ssl().is_server();
+        self.stream.get_mut().start(self.config.handshake_timeout, None, is_server);
+        let result = self.stream.do_handshake();
+        self.handshake_done(result);
+        match self.error {
+            Some(ref error) => Err(TlsError::HandshakeFailed(error.clone())),
+            None => Ok(()),
+        }
     }
 
     /// Get TLS variables (for expect commands)
@@ -101,54 +278,57 @@ impl TlsSessionOps {
         self.failed
     }
 
-    /// Get reference to underlying TCP stream
-    pub fn get_ref(&self) -> &TcpStream {
-        self.stream.get_ref()
+    /// Early data received from the client (server-side)
+    ///
+    /// Kept apart from the data read after the handshake; empty unless
+    /// `tls.early_data_status` is `accepted`.
+    pub fn early_data(&self) -> &[u8] {
+        &self.early_data
     }
 
-    /// Get mutable reference to underlying TCP stream
-    pub fn get_mut(&mut self) -> &mut TcpStream {
-        self.stream.get_mut()
+    /// Why the handshake failed
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
     }
-}
-
-impl SessionOps for TlsSessionOps {
-    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> HttpResult<bool> {
-        use libc::{poll, pollfd, POLLIN, POLLOUT};
 
-        // Check if SSL has pending data
-        if events == PollEvents::Read || events == PollEvents::Both {
-            if self.stream.ssl().pending() > 0 {
-                return Ok(true);
-            }
+    /// Fail the session with a handshake error if the handshake failed
+    ///
+    /// For callers that cannot go on without a TLS connection.
+    pub fn established(self) -> std::result::Result<Self, TlsError> {
+        match self.error {
+            Some(error) => Err(TlsError::HandshakeFailed(error)),
+            None => Ok(self),
         }
+    }
 
-        // Poll the underlying file descriptor
-        let mut pfd = pollfd {
-            fd: self.stream.get_ref().as_raw_fd(),
-            events: match events {
-                PollEvents::Read => POLLIN,
-                PollEvents::Write => POLLOUT,
-                PollEvents::Both => POLLIN | POLLOUT,
-            },
-            revents: 0,
-        };
-
-        let timeout_ms = timeout
-            .map(|d| d.as_millis() as i32)
-            .unwrap_or(-1);
+    /// Get reference to underlying transport
+    pub fn get_ref(&self) -> &T {
+        self.stream.get_ref().get_ref()
+    }
 
-        let result = unsafe { poll(&mut pfd as *mut pollfd, 1, timeout_ms) };
+    /// Get mutable reference to underlying transport
+    pub fn get_mut(&mut self) -> &mut T {
+        self.stream.get_mut().get_mut()
+    }
+}
 
-        if result < 0 {
-            return Err(Error::Io(std::io::Error::last_os_error()));
+impl<T: TlsTransport> SessionOps for TlsSessionOps<T> {
+    fn poll(&self, events: PollEvents, timeout: Option<Duration>) -> HttpResult<bool> {
+        // Check if SSL has pending data
+        if (events == PollEvents::Read || events == PollEvents::Both)
+            && self.stream.ssl().pending() > 0
+        {
+            return Ok(true);
         }
 
-        Ok(result > 0)
+        // Poll the underlying transport
+        self.get_ref().poll(events, timeout)
     }
 
     fn read(&mut self, buf: &mut [u8]) -> HttpResult<usize> {
-        match self.stream.read(buf) {
+        let result = self.stream.read(buf);
+        self.vars.update(self.stream.ssl());
+        match result {
             Ok(n) => Ok(n),
             Err(e) => {
                 self.failed = true;
@@ -159,7 +339,9 @@ impl SessionOps for TlsSessionOps {
     }
 
     fn write(&mut self, buf: &[u8]) -> HttpResult<usize> {
-        match self.stream.write(buf) {
+        let result = self.stream.write(buf);
+        self.vars.update(self.stream.ssl());
+        match result {
             Ok(n) => Ok(n),
             Err(e) => {
                 self.failed = true;
@@ -173,13 +355,11 @@ impl SessionOps for TlsSessionOps {
         // Perform SSL shutdown if not failed
         if !self.failed {
             let _ = self.stream.shutdown();
+            self.vars.update(self.stream.ssl());
         }
 
-        // Shutdown the underlying TCP connection
-        use std::net::Shutdown;
-        self.stream.get_mut()
-            .shutdown(Shutdown::Both)
-            .map_err(Error::from)
+        // Shutdown the underlying transport
+        self.get_mut().shutdown()
     }
 }
 
@@ -188,6 +368,8 @@ mod tests {
     use super::*;
     use super::super::TlsConfig;
     use super::super::TlsVersion;
+    use super::super::TlsError;
+    use super::super::ClientConfigBuilder;
     use std::net::{TcpListener, TcpStream};
     use std::thread;
     use std::time::Duration;
@@ -294,4 +476,366 @@ mod tests {
 
         server_handle.join().unwrap();
     }
+
+    #[test]
+    fn test_alerts() {
+        // The client rejects the self-signed builtin certificate
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_config = TlsConfig::server().build().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            server_config.accept(tcp_stream).unwrap()
+        });
+
+        let client_config = TlsConfig::client().verify_peer(true).build().unwrap();
+        let mut client = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        let server = server_handle.join().unwrap();
+
+        assert!(client.failed());
+        assert!(client.error().unwrap().contains("self-signed certificate"), "{:?}", client.error());
+        assert_eq!(client.vars().get("tls.failed").as_deref(), Some("true"));
+        assert_eq!(client.vars().get("tls.alert_sent").as_deref(), Some("unknown_ca"));
+        assert_eq!(client.vars().get("tls.alert_level").as_deref(), Some("fatal"));
+        assert!(server.failed());
+        assert_eq!(server.vars().get("tls.alert").as_deref(), Some("unknown_ca"));
+        assert_eq!(server.vars().get("tls.alert_received").as_deref(), Some("unknown_ca"));
+        assert!(client.close().is_ok());
+        assert!(matches!(client.established(), Err(TlsError::HandshakeFailed(_))));
+
+        // A clean close sends and receives close_notify
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_config = TlsConfig::server().build().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            let mut tls_session = server_config.accept(tcp_stream).unwrap();
+            let mut buf = [0u8; 5];
+            assert_eq!(tls_session.read(&mut buf).unwrap(), 0);
+            tls_session.vars().clone()
+        });
+
+        let client_config = TlsConfig::client().verify_peer(false).build().unwrap();
+        let mut client = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        assert_eq!(client.vars().get("tls.alert").as_deref(), Some("<undef>"));
+        client.close().unwrap();
+        let server_vars = server_handle.join().unwrap();
+
+        assert_eq!(client.vars().get("tls.alert_sent").as_deref(), Some("close_notify"));
+        assert_eq!(client.vars().get("tls.alert_level").as_deref(), Some("warning"));
+        assert_eq!(server_vars.get("tls.alert_received").as_deref(), Some("close_notify"));
+        assert_eq!(server_vars.get("tls.failed").as_deref(), Some("false"));
+    }
+
+    /// Serve `count` connections, returning whether each resumed a session
+    fn serve_resumption(listener: TcpListener, config: TlsConfig, count: usize) -> thread::JoinHandle<Vec<bool>> {
+        thread::spawn(move || {
+            (0..count)
+                .map(|_| {
+                    let (tcp_stream, _) = listener.accept().unwrap();
+                    let mut tls_session = config.accept(tcp_stream).unwrap();
+                    let mut buf = [0u8; 5];
+                    tls_session.read(&mut buf).unwrap();
+                    tls_session.write(b"World").unwrap();
+                    tls_session.close().unwrap();
+                    tls_session.vars().sess_reused
+                })
+                .collect()
+        })
+    }
+
+    /// Exchange a message, so that TLS 1.3 tickets are received
+    fn resumption_client(addr: std::net::SocketAddr, config: &TlsConfig) -> bool {
+        let mut tls_session = config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        tls_session.write(b"Hello").unwrap();
+        let mut buf = [0u8; 5];
+        tls_session.read(&mut buf).unwrap();
+        tls_session.close().unwrap();
+        tls_session.vars().sess_reused
+    }
+
+    #[test]
+    fn test_session_resumption() {
+        let dir = tempfile::tempdir().unwrap();
+        let cases = [
+            // TLS 1.2 with session IDs, and with tickets
+            (TlsVersion::Tls12, false),
+            (TlsVersion::Tls12, true),
+            (TlsVersion::Tls13, true),
+        ];
+
+        for (version, tickets) in cases {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server_config = TlsConfig::server()
+                .version(version)
+                .tickets(tickets)
+                .num_tickets(3)
+                .unwrap()
+                .build()
+                .unwrap();
+            let server_handle = serve_resumption(listener, server_config, 2);
+
+            let path = dir.path().join(format!("{:?}-{}.sess", version, tickets));
+            let path = path.to_str().unwrap();
+            let first = TlsConfig::client().version(version).sess_out(path).build().unwrap();
+            assert!(!resumption_client(addr, &first));
+
+            // Several TLS 1.3 tickets leave a single session in the file
+            let pem = std::fs::read_to_string(path).unwrap();
+            assert_eq!(pem.matches("BEGIN SSL SESSION").count(), 1);
+
+            let second = TlsConfig::client().version(version).sess_in(path).build().unwrap();
+            assert!(resumption_client(addr, &second), "{:?} tickets={}", version, tickets);
+
+            assert_eq!(server_handle.join().unwrap(), vec![false, true]);
+        }
+    }
+
+    #[test]
+    fn test_session_resumption_with_shared_ticket_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("c1.sess");
+        let path = path.to_str().unwrap();
+        let key = [7u8; 80];
+
+        let mut resumed = Vec::new();
+        for sess in [TlsConfig::client().sess_out(path), TlsConfig::client().sess_in(path)] {
+            // A new server each time, resuming only through the ticket key
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server_config = TlsConfig::server()
+                .session_cache(false)
+                .ticket_key(&key)
+                .unwrap()
+                .build()
+                .unwrap();
+            let server_handle = serve_resumption(listener, server_config, 1);
+
+            resumed.push(resumption_client(addr, &sess.build().unwrap()));
+            server_handle.join().unwrap();
+        }
+        assert_eq!(resumed, vec![false, true]);
+
+        let missing = TlsConfig::client().sess_in(dir.path().join("none").to_str().unwrap()).build().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        assert!(matches!(missing.connect(tcp_stream), Err(TlsError::SessionResumptionFailed(_))));
+    }
+
+    #[test]
+    fn test_session_save_error() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for version in [TlsVersion::Tls12, TlsVersion::Tls13] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server_config = TlsConfig::server().version(version).build().unwrap();
+            let server_handle = serve_resumption(listener, server_config, 1);
+
+            // The directory does not exist: every save fails
+            let path = dir.path().join("none").join("c1.sess");
+            let config = TlsConfig::client().version(version).sess_out(path.to_str().unwrap()).build().unwrap();
+            let mut tls_session = config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+            tls_session.write(b"Hello").unwrap();
+            let mut buf = [0u8; 5];
+            tls_session.read(&mut buf).unwrap();
+            tls_session.close().unwrap();
+            server_handle.join().unwrap();
+
+            let error = tls_session.vars().get("tls.sess_out_error").unwrap();
+            assert!(error.starts_with(path.to_str().unwrap()), "{:?}: {}", version, error);
+        }
+    }
+
+    /// Early data status, early data and data read after the handshake
+    type Served = (String, Vec<u8>, Vec<u8>);
+
+    /// Serve `count` connections, returning what each received
+    fn serve_early_data(listener: TcpListener, config: TlsConfig, count: usize) -> thread::JoinHandle<Vec<Served>> {
+        thread::spawn(move || {
+            (0..count)
+                .map(|_| {
+                    let (tcp_stream, _) = listener.accept().unwrap();
+                    let mut tls_session = config.accept(tcp_stream).unwrap();
+                    let mut buf = [0u8; 5];
+                    tls_session.read(&mut buf).unwrap();
+                    tls_session.write(b"World").unwrap();
+                    tls_session.close().unwrap();
+                    let status = tls_session.vars().get("tls.early_data_status").unwrap();
+                    (status, tls_session.early_data().to_vec(), buf.to_vec())
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_early_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("early.sess");
+        let path = path.to_str().unwrap();
+        let key = [9u8; 80];
+        let server = |max_early_data| {
+            TlsConfig::server()
+                .version(TlsVersion::Tls13)
+                .ticket_key(&key)
+                .unwrap()
+                .max_early_data(max_early_data)
+                .unwrap()
+        };
+        let request = b"GET / HTTP/1.1\r\nEarly-Data: 1\r\n\r\n".to_vec();
+        let client = |sess: ClientConfigBuilder| {
+            sess.version(TlsVersion::Tls13).early_data(request.clone()).build().unwrap()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = serve_early_data(listener, server(16384).build().unwrap(), 3);
+
+        // No session to resume yet
+        let mut statuses = Vec::new();
+        for config in [
+            client(TlsConfig::client().sess_out(path)),
+            client(TlsConfig::client().sess_in(path)),
+            // Replayed
+            client(TlsConfig::client().sess_in(path)),
+        ] {
+            let mut tls_session = config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+            tls_session.write(b"Hello").unwrap();
+            let mut buf = [0u8; 5];
+            tls_session.read(&mut buf).unwrap();
+            tls_session.close().unwrap();
+            statuses.push(tls_session.vars().get("tls.early_data_status").unwrap());
+        }
+        assert_eq!(statuses, ["not_sent", "accepted", "rejected"]);
+
+        let served = server_handle.join().unwrap();
+        assert_eq!(served[0], ("not_sent".to_string(), Vec::new(), b"Hello".to_vec()));
+        assert_eq!(served[1], ("accepted".to_string(), request.clone(), b"Hello".to_vec()));
+        assert_eq!(served[2], ("rejected".to_string(), Vec::new(), b"Hello".to_vec()));
+
+        // Without replay protection tickets are stateless: resumed by any
+        // server with the key, which may accept early data more than once
+        let path = dir.path().join("stateless.sess");
+        let path = path.to_str().unwrap();
+        let mut statuses = Vec::new();
+        for (config, sess) in [
+            (server(16384).anti_replay(false), TlsConfig::client().sess_out(path)),
+            (server(16384).anti_replay(false), TlsConfig::client().sess_in(path)),
+            (server(16384).anti_replay(false), TlsConfig::client().sess_in(path)),
+            (server(0), TlsConfig::client().sess_in(path)),
+        ] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server_handle = serve_early_data(listener, config.build().unwrap(), 1);
+
+            let mut tls_session = client(sess).connect(TcpStream::connect(addr).unwrap()).unwrap();
+            tls_session.write(b"Hello").unwrap();
+            let mut buf = [0u8; 5];
+            tls_session.read(&mut buf).unwrap();
+            let status = tls_session.vars().get("tls.early_data_status").unwrap();
+            assert_eq!(server_handle.join().unwrap()[0].0, status);
+            statuses.push((tls_session.vars().sess_reused, status));
+        }
+        let expected = [(false, "not_sent"), (true, "accepted"), (true, "accepted"), (true, "rejected")];
+        assert_eq!(statuses, expected.map(|(reused, status)| (reused, status.to_string())));
+    }
+
+    /// Handshake with a client requesting a staple, returning both sides' vars
+    fn staple_handshake(server_config: TlsConfig, client_config: TlsConfig) -> (TlsVars, TlsVars) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            let mut tls_session = server_config.accept(tcp_stream).unwrap();
+            let mut buf = [0u8; 5];
+            tls_session.read(&mut buf).unwrap();
+            tls_session.vars().clone()
+        });
+
+        let mut tls_session = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        tls_session.write(b"Hello").unwrap();
+        (tls_session.vars().clone(), server_handle.join().unwrap())
+    }
+
+    #[test]
+    fn test_ocsp_stapling() {
+        use super::super::builtin_cert::BUILTIN_CERT;
+        use super::super::ocsp::{CertStatus, OcspResponseBuilder};
+        use super::super::pki::CertBuilder;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = CertBuilder::ca("Test Root").self_signed().unwrap();
+        let ca = CertBuilder::intermediate("Test CA").issued_by(&root).unwrap();
+        let leaf = CertBuilder::leaf("example.com").issued_by(&ca).unwrap();
+        let ca_file = dir.path().join("ca.pem");
+        std::fs::write(&ca_file, root.cert_pem().unwrap()).unwrap();
+
+        let this_update = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let good = dir.path().join("good.der");
+        let resp = OcspResponseBuilder::new(leaf.cert(), ca.cert()).this_update(this_update).sign(ca.cert(), ca.key()).unwrap();
+        std::fs::write(&good, resp).unwrap();
+        let revoked = dir.path().join("revoked.der");
+        let resp = OcspResponseBuilder::new(leaf.cert(), ca.cert())
+            .status(CertStatus::Revoked(SystemTime::now()))
+            .sign(ca.cert(), ca.key())
+            .unwrap();
+        std::fs::write(&revoked, resp).unwrap();
+
+        let server = |version: TlsVersion, staple: &std::path::Path| {
+            TlsConfig::server().version(version).cert(&leaf).unwrap().staple(staple).unwrap().build().unwrap()
+        };
+
+        for version in [TlsVersion::Tls12, TlsVersion::Tls13] {
+            let client = TlsConfig::client().version(version).cert_status(true).ca_file(&ca_file).unwrap();
+            let (client_vars, server_vars) = staple_handshake(server(version, &good), client.build().unwrap());
+            assert_eq!(server_vars.get("tls.staple_requested").as_deref(), Some("true"));
+            assert_eq!(client_vars.get("tls.staple_requested").as_deref(), Some("true"));
+            assert_eq!(client_vars.get("tls.ocsp_resp_status").as_deref(), Some("successful"));
+            assert_eq!(client_vars.get("tls.ocsp_verify").as_deref(), Some("OK"), "{:?}", version);
+            assert_eq!(client_vars.get("tls.ocsp_cert_status").as_deref(), Some("good"));
+            assert_eq!(client_vars.get("tls.ocsp_this_update").as_deref(), Some("2023-11-14T22:13:20Z"));
+
+            // Without the CA, the response signature cannot be trusted
+            let client = TlsConfig::client().version(version).cert_status(true).build().unwrap();
+            let (client_vars, _) = staple_handshake(server(version, &good), client);
+            assert_eq!(client_vars.get("tls.ocsp_verify").as_deref(), Some("failed"));
+            assert_eq!(client_vars.get("tls.ocsp_cert_status").as_deref(), Some("good"));
+
+            // Without a status request, nothing is stapled
+            let client = TlsConfig::client().version(version).build().unwrap();
+            let (client_vars, server_vars) = staple_handshake(server(version, &good), client);
+            assert_eq!(server_vars.get("tls.staple_requested").as_deref(), Some("false"));
+            assert_eq!(client_vars.get("tls.ocsp_resp_status").as_deref(), Some("<undef>"));
+        }
+
+        let client = TlsConfig::client().cert_status(true).ca_file(&ca_file).unwrap().build().unwrap();
+        let (client_vars, _) = staple_handshake(server(TlsVersion::Tls13, &revoked), client);
+        assert_eq!(client_vars.get("tls.ocsp_cert_status").as_deref(), Some("revoked"));
+
+        // The built-in certificate may not sign certificates, so it is not
+        // accepted as its own issuer
+        let cert = openssl::x509::X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+        let key = openssl::pkey::PKey::private_key_from_pem(BUILTIN_CERT.as_bytes()).unwrap();
+        let resp = OcspResponseBuilder::new(&cert, &cert).sign(&cert, &key).unwrap();
+        std::fs::write(&good, resp).unwrap();
+        let builtin_server = TlsConfig::server().staple(&good).unwrap().build().unwrap();
+        let client = TlsConfig::client().cert_status(true).build().unwrap();
+        let (client_vars, _) = staple_handshake(builtin_server, client);
+        assert_eq!(client_vars.get("tls.ocsp_resp_status").as_deref(), Some("successful"));
+        assert_eq!(client_vars.get("tls.ocsp_cert_status").as_deref(), Some("issuer-not-found"));
+
+        // A server without a staple still records the request
+        let client = TlsConfig::client().cert_status(true).build().unwrap();
+        let (client_vars, server_vars) = staple_handshake(TlsConfig::server().build().unwrap(), client);
+        assert_eq!(server_vars.get("tls.staple_requested").as_deref(), Some("true"));
+        assert_eq!(client_vars.get("tls.ocsp_resp_status").as_deref(), Some("<undef>"));
+
+        // A staple that is not an OCSP response
+        std::fs::write(&good, b"garbage").unwrap();
+        let client = TlsConfig::client().cert_status(true).build().unwrap();
+        let (client_vars, _) = staple_handshake(server(TlsVersion::Tls13, &good), client);
+        assert_eq!(client_vars.get("tls.ocsp_resp_status").as_deref(), Some("malformed"));
+    }
 }
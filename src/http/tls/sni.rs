@@ -0,0 +1,312 @@
+//! SNI-based certificate selection
+//!
+//! A server with several certificates picks them by the name the client
+//! sends (RFC 6066 Section 3), like Varnish or hitch do. Each certificate
+//! serves the DNS names of its SANs, or its CN without SANs. Exact names
+//! take precedence over wildcards, and `*.example.com` covers a single
+//! label. All the certificates for the matched name are installed, and
+//! OpenSSL picks among them by the client's signature algorithms, e.g.
+//! ECDSA for clients supporting it and RSA otherwise. Without a match the
+//! default certificates are used, unless unknown names are rejected with
+//! an alert.
+//!
+//! After the handshake, `tls.servername` gives the name the client sent,
+//! `tls.sni_match` the name that selected the certificates, and
+//! `tls.selected_cert` the subject of the certificate sent.
+
+use super::alert::TlsAlert;
+use super::pki::CertifiedKey;
+use openssl::ex_data::Index;
+use openssl::ssl::{NameType, SniError, Ssl, SslAlert, SslRef};
+use std::sync::OnceLock;
+
+/// Ssl ex_data index recording the name that selected the certificates
+fn sni_match_index() -> Index<Ssl, String> {
+    static INDEX: OnceLock<Index<Ssl, String>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().expect("Failed to allocate ex_data index"))
+}
+
+/// Get the certificate name matched by the client's SNI (server side)
+///
+/// Returns None if the default certificates were used.
+pub fn sni_match(ssl: &SslRef) -> Option<&str> {
+    ssl.ex_data(sni_match_index()).map(String::as_str)
+}
+
+/// Check whether a certificate name covers a host name
+///
+/// Names compare case-insensitively, and a wildcard covers exactly one
+/// label: `*.example.com` matches `www.example.com`, but neither
+/// `example.com` nor `a.b.example.com`.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => name
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(suffix)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+/// Certificates of a server, selected by SNI
+#[derive(Clone, Default)]
+pub(crate) struct SniCerts {
+    /// Certificates with the names they serve
+    entries: Vec<(Vec<String>, CertifiedKey)>,
+    /// Certificates used when no name matches
+    pub(crate) default: Vec<CertifiedKey>,
+    /// Alert rejecting names that match no certificate
+    pub(crate) reject: Option<TlsAlert>,
+}
+
+impl SniCerts {
+    /// Add a certificate selected by the names it is valid for
+    pub(crate) fn add(&mut self, cert: CertifiedKey) {
+        self.entries.push((cert.dns_names(), cert));
+    }
+
+    /// Check whether any certificate is selected by name
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Find the certificates for a host name, with the name that matched
+    fn select(&self, servername: &str) -> Option<(&str, Vec<&CertifiedKey>)> {
+        let exact = |pattern: &str| !pattern.starts_with("*.") && name_matches(pattern, servername);
+        let wildcard = |pattern: &str| pattern.starts_with("*.") && name_matches(pattern, servername);
+
+        for matches in [&exact as &dyn Fn(&str) -> bool, &wildcard] {
+            let mut found: Option<&str> = None;
+            let mut certs = Vec::new();
+            for (names, cert) in &self.entries {
+                if let Some(name) = names.iter().find(|name| matches(name)) {
+                    found.get_or_insert(name);
+                    certs.push(cert);
+                }
+            }
+            if let Some(name) = found {
+                return Some((name, certs));
+            }
+        }
+        None
+    }
+
+    /// Install the certificates for the client's SNI (servername callback)
+    pub(crate) fn install(&self, ssl: &mut SslRef, alert: &mut SslAlert) -> Result<(), SniError> {
+        let servername = ssl.servername(NameType::HOST_NAME).map(str::to_string);
+        let selected = servername.as_deref().and_then(|name| self.select(name));
+
+        let certs = match (selected, self.reject) {
+            (Some((name, certs)), _) => {
+                ssl.set_ex_data(sni_match_index(), name.to_string());
+                certs
+            }
+            (None, Some(reject)) if servername.is_some() => {
+                reject.set(alert);
+                return Err(SniError::ALERT_FATAL);
+            }
+            (None, _) => self.default.iter().collect(),
+        };
+
+        // One certificate per key type: OpenSSL keeps them side by side
+        let result = certs.into_iter().try_for_each(|cert| {
+            ssl.set_certificate(cert.cert())?;
+            ssl.set_private_key(cert.key())?;
+            cert.chain().iter().try_for_each(|chain_cert| ssl.add_chain_cert(chain_cert.clone()))
+        });
+        result.map_err(|_| {
+            TlsAlert::INTERNAL_ERROR.set(alert);
+            SniError::ALERT_FATAL
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tls::pki::{CertBuilder, KeyType};
+    use crate::http::tls::{TlsConfig, TlsError, TlsVars, TlsVersion};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// Handshake, returning the client's and the server's vars
+    fn handshake(server_config: TlsConfig, client_config: TlsConfig) -> Result<(TlsVars, TlsVars), TlsError> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            server_config
+                .accept(tcp_stream)
+                .and_then(|session| session.established())
+                .map(|session| session.vars().clone())
+        });
+
+        let client = client_config
+            .connect(TcpStream::connect(addr).unwrap())
+            .and_then(|session| session.established());
+        let server = server_handle.join().unwrap();
+        let client_vars = client?.vars().clone();
+        Ok((client_vars, server.unwrap()))
+    }
+
+    /// Handshake, returning the client's and the server's vars even if it fails
+    fn handshake_vars(server_config: TlsConfig, client_config: TlsConfig) -> (TlsVars, TlsVars) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            server_config.accept(tcp_stream).unwrap().vars().clone()
+        });
+
+        let client = client_config.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        (client.vars().clone(), server_handle.join().unwrap())
+    }
+
+    fn client(servername: Option<&str>) -> TlsConfig {
+        let builder = TlsConfig::client();
+        match servername {
+            Some(name) => builder.servername(name).build().unwrap(),
+            None => builder.build().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_name_matches() {
+        assert!(name_matches("example.com", "example.com"));
+        assert!(name_matches("Example.COM", "example.com"));
+        assert!(!name_matches("example.com", "www.example.com"));
+        assert!(name_matches("*.example.com", "www.example.com"));
+        assert!(name_matches("*.example.com", "WWW.Example.com"));
+        assert!(!name_matches("*.example.com", "example.com"));
+        assert!(!name_matches("*.example.com", "a.b.example.com"));
+        assert!(!name_matches("*.example.com", ".example.com"));
+    }
+
+    #[test]
+    fn test_select_prefers_exact_names() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let wildcard = CertBuilder::leaf("wildcard").dns("*.example.com").issued_by(&root).unwrap();
+        let www = CertBuilder::leaf("www.example.com").issued_by(&root).unwrap();
+
+        let mut certs = SniCerts::default();
+        certs.add(wildcard);
+        certs.add(www);
+
+        let (name, selected) = certs.select("www.example.com").unwrap();
+        assert_eq!(name, "www.example.com");
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].dns_names(), vec!["www.example.com"]);
+
+        let (name, _) = certs.select("api.example.com").unwrap();
+        assert_eq!(name, "*.example.com");
+        assert!(certs.select("example.org").is_none());
+    }
+
+    #[test]
+    fn test_certificate_selected_by_sni() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let default = CertBuilder::leaf("default.test").issued_by(&root).unwrap();
+        let wildcard = CertBuilder::leaf("wildcard").dns("*.example.com").issued_by(&root).unwrap();
+        let www = CertBuilder::leaf("www.example.com").issued_by(&root).unwrap();
+        let server = || {
+            TlsConfig::server()
+                .cert(&default)
+                .unwrap()
+                .sni_cert(&wildcard)
+                .sni_cert(&www)
+                .build()
+                .unwrap()
+        };
+
+        let cases = [
+            (Some("www.example.com"), "www.example.com", "www.example.com"),
+            (Some("WWW.EXAMPLE.COM"), "www.example.com", "www.example.com"),
+            (Some("api.example.com"), "wildcard", "*.example.com"),
+            (Some("a.b.example.com"), "default.test", "<undef>"),
+            (Some("example.org"), "default.test", "<undef>"),
+            (None, "default.test", "<undef>"),
+        ];
+        for (servername, subject, sni_match) in cases {
+            let (client_vars, server_vars) = handshake(server(), client(servername)).unwrap();
+            assert_eq!(client_vars.get("tls.cert.subject").as_deref(), Some(subject), "{:?}", servername);
+            assert_eq!(server_vars.get("tls.selected_cert").as_deref(), Some(subject));
+            assert_eq!(server_vars.get("tls.sni_match").as_deref(), Some(sni_match));
+            assert_eq!(server_vars.servername.as_deref(), servername);
+        }
+
+        // Without SNI certificates, the default is set on the context
+        let (client_vars, server_vars) = handshake(TlsConfig::server().build().unwrap(), client(Some("www.example.com"))).unwrap();
+        assert_eq!(client_vars.get("tls.cert.subject").as_deref(), Some("example.com"));
+        assert_eq!(server_vars.get("tls.selected_cert").as_deref(), Some("example.com"));
+        assert_eq!(server_vars.get("tls.sni_match").as_deref(), Some("<undef>"));
+    }
+
+    #[test]
+    fn test_certificate_selected_by_signature_algorithms() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let rsa = CertBuilder::leaf("rsa").dns("example.com").key_type(KeyType::Rsa(2048)).issued_by(&root).unwrap();
+        let ecdsa = CertBuilder::leaf("ecdsa").dns("example.com").issued_by(&root).unwrap();
+        let server = || TlsConfig::server().sni_cert(&rsa).sni_cert(&ecdsa).build().unwrap();
+
+        let cases = [
+            ("ECDHE-RSA-AES128-GCM-SHA256", "rsa"),
+            ("ECDHE-ECDSA-AES128-GCM-SHA256", "ecdsa"),
+        ];
+        for (cipher, subject) in cases {
+            let client = TlsConfig::client()
+                .version(TlsVersion::Tls12)
+                .cipher_list(cipher)
+                .unwrap()
+                .servername("example.com")
+                .build()
+                .unwrap();
+            let (client_vars, server_vars) = handshake(server(), client).unwrap();
+            assert_eq!(client_vars.get("tls.cert.subject").as_deref(), Some(subject));
+            assert_eq!(server_vars.get("tls.selected_cert").as_deref(), Some(subject));
+        }
+
+        // Only the RSA certificate serves this name: ECDSA-only clients fail
+        let other = CertBuilder::leaf("other").dns("other.com").key_type(KeyType::Rsa(2048)).issued_by(&root).unwrap();
+        let client = TlsConfig::client()
+            .version(TlsVersion::Tls12)
+            .cipher_list("ECDHE-ECDSA-AES128-GCM-SHA256")
+            .unwrap()
+            .servername("other.com")
+            .build()
+            .unwrap();
+        let config = TlsConfig::server().sni_cert(&other).sni_cert(&ecdsa).build().unwrap();
+        assert!(handshake(config, client).is_err());
+    }
+
+    #[test]
+    fn test_reject_unknown_sni() {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let www = CertBuilder::leaf("www.example.com").issued_by(&root).unwrap();
+        let server = |alert| TlsConfig::server().sni_cert(&www).reject_unknown_sni(alert).build().unwrap();
+
+        handshake(server(TlsAlert::UNRECOGNIZED_NAME), client(Some("www.example.com"))).unwrap();
+
+        match handshake(server(TlsAlert::UNRECOGNIZED_NAME), client(Some("example.org"))) {
+            Err(TlsError::HandshakeFailed(msg)) => assert!(msg.contains("unrecognized name"), "{}", msg),
+            other => panic!("expected unrecognized_name, got {:?}", other.map(|_| ())),
+        }
+        match handshake(server(TlsAlert::ACCESS_DENIED), client(Some("example.org"))) {
+            Err(TlsError::HandshakeFailed(msg)) => assert!(msg.contains("access denied"), "{}", msg),
+            other => panic!("expected access_denied, got {:?}", other.map(|_| ())),
+        }
+
+        // Both sides see the alert, and fail
+        let (client_vars, server_vars) =
+            handshake_vars(server(TlsAlert::UNRECOGNIZED_NAME), client(Some("example.org")));
+        assert_eq!(client_vars.get("tls.failed").as_deref(), Some("true"));
+        assert_eq!(client_vars.get("tls.alert").as_deref(), Some("unrecognized_name"));
+        assert_eq!(client_vars.get("tls.alert_received").as_deref(), Some("unrecognized_name"));
+        assert_eq!(client_vars.get("tls.alert_level").as_deref(), Some("fatal"));
+        assert_eq!(server_vars.get("tls.failed").as_deref(), Some("true"));
+        assert_eq!(server_vars.get("tls.alert_sent").as_deref(), Some("unrecognized_name"));
+        assert_eq!(server_vars.get("tls.alert_received").as_deref(), Some("<undef>"));
+
+        // Clients without SNI get the default certificate
+        let (client_vars, _) = handshake(server(TlsAlert::UNRECOGNIZED_NAME), client(None)).unwrap();
+        assert_eq!(client_vars.get("tls.cert.subject").as_deref(), Some("example.com"));
+    }
+}
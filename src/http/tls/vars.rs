@@ -4,8 +4,12 @@
 //! These variables are populated after a TLS handshake and provide information
 //! about the negotiated connection.
 
+use super::alert::AlertRecord;
 use super::cert::{CertInfo, get_cert_chain};
+use super::early_data::EarlyDataStatus;
+use super::ocsp::StapleCheck;
 use openssl::ssl::SslRef;
+use openssl::x509::X509VerifyResult;
 
 /// TLS variables available after handshake
 #[derive(Debug, Clone)]
@@ -16,24 +20,45 @@ pub struct TlsVars {
     /// Negotiated cipher suite
     pub cipher: String,
 
-    /// SNI servername (client-side)
+    /// SNI servername (sent by the client, received by the server)
     pub servername: Option<String>,
 
+    /// Certificate name matched by the SNI (server-side, None = default)
+    pub sni_match: Option<String>,
+
+    /// Certificate we sent
+    pub selected_cert: Option<CertInfo>,
+
     /// Negotiated ALPN protocol
     pub alpn: Option<String>,
 
-    /// Latest TLS alert message
+    /// Latest TLS alert, sent or received (name from `tls_alert_tbl.h`)
     pub alert: Option<String>,
 
+    /// Alerts sent and received, in order
+    pub alerts: Vec<AlertRecord>,
+
     /// Whether handshake or I/O failed
     pub failed: bool,
 
     /// Certificate chain (index 0 is peer cert)
     pub cert_chain: Vec<CertInfo>,
 
+    /// Peer certificate verification result ("ok", or the error)
+    pub verify_result: Option<String>,
+
+    /// Depth in the peer chain of the first verification error
+    pub verify_error_depth: Option<u32>,
+
     /// Whether session was resumed
     pub sess_reused: bool,
 
+    /// Why the latest save of a new session (`sess_out`) failed
+    pub sess_out_error: Option<String>,
+
+    /// What became of the early data (TLS 1.3 0-RTT)
+    pub early_data_status: Option<EarlyDataStatus>,
+
     /// Whether client requested OCSP staple
     pub staple_requested: bool,
 
@@ -46,6 +71,9 @@ pub struct TlsVars {
 
     /// OCSP verification result
     pub ocsp_verify: Option<String>,
+
+    /// OCSP status time (thisUpdate)
+    pub ocsp_this_update: Option<String>,
 }
 
 impl TlsVars {
@@ -79,6 +107,18 @@ impl TlsVars {
             None
         };
 
+        let sni_match = if !failed {
+            super::sni::sni_match(ssl).map(str::to_string)
+        } else {
+            None
+        };
+
+        let selected_cert = if !failed {
+            ssl.certificate().map(CertInfo::from_x509_ref)
+        } else {
+            None
+        };
+
         let cert_chain = if !failed {
             get_cert_chain(ssl)
         } else {
@@ -91,24 +131,67 @@ impl TlsVars {
             false
         };
 
-        // Note: staple_requested requires checking if status_type is set
-        // This is a simplified version
-        let staple_requested = false; // TODO: Implement proper check
+        // Kept after a failed handshake, which it may explain
+        let verify_result = ssl.verify_result();
+        let verify_result = (ssl.peer_certificate().is_some() || verify_result != X509VerifyResult::OK)
+            .then(|| verify_result.error_string().to_string());
+        let verify_error_depth = super::verify::error_depth(ssl);
 
-        TlsVars {
+        let early_data_status = if !failed {
+            Some(super::early_data::status(ssl))
+        } else {
+            None
+        };
+
+        let staple_requested = super::ocsp::staple_requested(ssl);
+
+        // Check the staple sent by the server
+        let staple = if !failed && !ssl.is_server() && staple_requested {
+            StapleCheck::from_ssl(ssl)
+        } else {
+            None
+        };
+
+        let mut vars = TlsVars {
             version,
             cipher,
             servername,
+            sni_match,
+            selected_cert,
             alpn,
-            alert: None, // Set by handshake errors or SSL alerts
+            alert: None,
+            alerts: Vec::new(),
             failed,
             cert_chain,
+            verify_result,
+            verify_error_depth,
             sess_reused,
+            sess_out_error: None,
+            early_data_status,
             staple_requested,
-            ocsp_cert_status: None,
-            ocsp_resp_status: None,
-            ocsp_verify: None,
-        }
+            ocsp_cert_status: staple.as_ref().map(|s| s.cert_status.clone()),
+            ocsp_resp_status: staple.as_ref().map(|s| s.resp_status.clone()),
+            ocsp_verify: staple.as_ref().map(|s| s.verify.clone()),
+            ocsp_this_update: staple.map(|s| s.this_update),
+        };
+        vars.update(ssl);
+        vars
+    }
+
+    /// Refresh the variables that change after the handshake
+    ///
+    /// Alerts keep coming: close_notify, or a client certificate rejected
+    /// by the server with TLS 1.3. So do TLS 1.3 tickets, saved to the
+    /// `sess_out` file.
+    pub fn update(&mut self, ssl: &SslRef) {
+        self.alerts = super::alert::alerts(ssl);
+        self.alert = self.alerts.last().map(|record| record.alert.to_string());
+        self.sess_out_error = super::resume::save_error(ssl).map(str::to_string);
+    }
+
+    /// Latest alert we sent, or received
+    fn last_alert(&self, sent: bool) -> Option<String> {
+        self.alerts.iter().rev().find(|record| record.sent == sent).map(|record| record.alert.to_string())
     }
 
     /// Get certificate info by index (0 = peer cert, 1+ = chain)
@@ -122,11 +205,32 @@ impl TlsVars {
             "tls.version" => Some(self.version.clone()),
             "tls.cipher" => Some(self.cipher.clone()),
             "tls.servername" => self.servername.clone().or(Some("<undef>".to_string())),
+            "tls.sni_match" => self.sni_match.clone().or(Some("<undef>".to_string())),
+            "tls.selected_cert" => Some(
+                self.selected_cert.as_ref().map_or("<undef>".to_string(), |cert| cert.subject.clone()),
+            ),
             "tls.alpn" => self.alpn.clone().or(Some("<undef>".to_string())),
             "tls.alert" => self.alert.clone().or(Some("<undef>".to_string())),
+            "tls.alert_level" => Some(
+                self.alerts.last().map_or("<undef>".to_string(), |record| record.level.to_string()),
+            ),
+            "tls.alert_sent" => self.last_alert(true).or(Some("<undef>".to_string())),
+            "tls.alert_received" => self.last_alert(false).or(Some("<undef>".to_string())),
             "tls.failed" => Some(if self.failed { "true" } else { "false" }.to_string()),
+            "tls.verify_result" => self.verify_result.clone().or(Some("<undef>".to_string())),
+            "tls.verify_error_depth" => Some(
+                self.verify_error_depth.map_or("<undef>".to_string(), |depth| depth.to_string()),
+            ),
             "tls.sess_reused" => Some(if self.sess_reused { "true" } else { "false" }.to_string()),
+            "tls.sess_out_error" => self.sess_out_error.clone().or(Some("<undef>".to_string())),
+            "tls.early_data_status" => Some(
+                self.early_data_status.map_or("<undef>".to_string(), |status| status.to_string()),
+            ),
             "tls.staple_requested" => Some(if self.staple_requested { "true" } else { "false" }.to_string()),
+            "tls.ocsp_cert_status" => self.ocsp_cert_status.clone().or(Some("<undef>".to_string())),
+            "tls.ocsp_resp_status" => self.ocsp_resp_status.clone().or(Some("<undef>".to_string())),
+            "tls.ocsp_verify" => self.ocsp_verify.clone().or(Some("<undef>".to_string())),
+            "tls.ocsp_this_update" => self.ocsp_this_update.clone().or(Some("<undef>".to_string())),
             _ => {
                 // Check for cert.* variables
                 if name.starts_with("tls.cert") {
@@ -167,6 +271,22 @@ impl TlsVars {
                     Some(cert.subject_alt_names.join(", "))
                 }
             }
+            "subject_dn" => Some(cert.subject_dn.clone()),
+            "issuer_dn" => Some(cert.issuer_dn.clone()),
+            "serial" => Some(cert.serial.clone()),
+            "notbefore" => Some(cert.not_before.clone()),
+            "notafter" => Some(cert.not_after.clone()),
+            "fingerprint_sha256" => Some(cert.fingerprint_sha256.clone()),
+            "key_type" => Some(cert.key_type.clone()),
+            "key_bits" => Some(cert.key_bits.to_string()),
+            "signature_algorithm" => Some(cert.signature_algorithm.clone()),
+            "extensions" => {
+                if cert.extensions.is_empty() {
+                    Some("<undef>".to_string())
+                } else {
+                    Some(cert.extensions.join(", "))
+                }
+            }
             _ => None,
         }
     }
@@ -178,15 +298,23 @@ impl Default for TlsVars {
             version: "<undef>".to_string(),
             cipher: "<undef>".to_string(),
             servername: None,
+            sni_match: None,
+            selected_cert: None,
             alpn: None,
             alert: None,
+            alerts: Vec::new(),
             failed: true,
             cert_chain: Vec::new(),
+            verify_result: None,
+            verify_error_depth: None,
             sess_reused: false,
+            sess_out_error: None,
+            early_data_status: None,
             staple_requested: false,
             ocsp_cert_status: None,
             ocsp_resp_status: None,
             ocsp_verify: None,
+            ocsp_this_update: None,
         }
     }
 }
@@ -202,6 +330,9 @@ mod tests {
         assert_eq!(vars.get("tls.cipher"), Some("<undef>".to_string()));
         assert_eq!(vars.get("tls.failed"), Some("true".to_string()));
         assert_eq!(vars.get("tls.sess_reused"), Some("false".to_string()));
+        assert_eq!(vars.get("tls.sess_out_error"), Some("<undef>".to_string()));
+        assert_eq!(vars.get("tls.ocsp_resp_status"), Some("<undef>".to_string()));
+        assert_eq!(vars.get("tls.ocsp_verify"), Some("<undef>".to_string()));
     }
 
     #[test]
@@ -212,9 +343,11 @@ mod tests {
         let cert = X509::from_pem(BUILTIN_CERT.as_bytes()).unwrap();
         let cert_info = CertInfo::from_x509(&cert);
 
-        let mut vars = TlsVars::default();
-        vars.cert_chain = vec![cert_info];
-        vars.failed = false;
+        let vars = TlsVars {
+            cert_chain: vec![cert_info],
+            failed: false,
+            ..Default::default()
+        };
 
         // Test tls.cert.subject (index 0 implicit)
         assert_eq!(vars.get("tls.cert.subject"), Some("example.com".to_string()));
@@ -233,11 +366,35 @@ mod tests {
 
     #[test]
     fn test_boolean_vars() {
-        let mut vars = TlsVars::default();
-        vars.failed = false;
-        vars.sess_reused = true;
+        let vars = TlsVars {
+            failed: false,
+            sess_reused: true,
+            ..Default::default()
+        };
 
         assert_eq!(vars.get("tls.failed"), Some("false".to_string()));
         assert_eq!(vars.get("tls.sess_reused"), Some("true".to_string()));
     }
+
+    #[test]
+    fn test_alert_vars() {
+        use super::super::alert::{AlertLevel, TlsAlert};
+
+        let vars = TlsVars::default();
+        assert_eq!(vars.get("tls.alert"), Some("<undef>".to_string()));
+        assert_eq!(vars.get("tls.alert_level"), Some("<undef>".to_string()));
+
+        let vars = TlsVars {
+            alert: Some("close_notify".to_string()),
+            alerts: vec![
+                AlertRecord { sent: false, level: AlertLevel::Fatal, alert: TlsAlert::UNKNOWN_CA },
+                AlertRecord { sent: true, level: AlertLevel::Warning, alert: TlsAlert::CLOSE_NOTIFY },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(vars.get("tls.alert"), Some("close_notify".to_string()));
+        assert_eq!(vars.get("tls.alert_level"), Some("warning".to_string()));
+        assert_eq!(vars.get("tls.alert_sent"), Some("close_notify".to_string()));
+        assert_eq!(vars.get("tls.alert_received"), Some("unknown_ca".to_string()));
+    }
 }
@@ -0,0 +1,177 @@
+//! Peer certificate verification results
+//!
+//! OpenSSL keeps the verification result of a connection, but not where
+//! in the chain the error was found. A verify callback records the depth
+//! of the first error, for `tls.verify_error_depth`.
+
+use openssl::ex_data::Index;
+use openssl::ssl::{Ssl, SslContextBuilder, SslRef, SslVerifyMode};
+use openssl::x509::{X509StoreContext, X509StoreContextRef};
+use std::sync::OnceLock;
+
+fn error_depth_index() -> Index<Ssl, OnceLock<u32>> {
+    static INDEX: OnceLock<Index<Ssl, OnceLock<u32>>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().expect("Failed to allocate SSL ex_data index"))
+}
+
+/// Record the depth of the first error, keeping OpenSSL's verdict
+fn verify_callback(preverify_ok: bool, ctx: &mut X509StoreContextRef) -> bool {
+    if !preverify_ok {
+        let depth = ctx.error_depth();
+        let ssl = X509StoreContext::ssl_idx().ok().and_then(|index| ctx.ex_data(index));
+        if let Some(error_depth) = ssl.and_then(|ssl| ssl.ex_data(error_depth_index())) {
+            let _ = error_depth.set(depth);
+        }
+    }
+    preverify_ok
+}
+
+/// Record verification errors of connections made with this context
+///
+/// Must come after the verify mode is set. Only connections started with
+/// `start_record` are recorded.
+pub(crate) fn record_errors(ctx_builder: &mut SslContextBuilder) {
+    // SAFETY: the context is valid; the builder has no getter
+    let bits = unsafe { openssl_sys::SSL_CTX_get_verify_mode(ctx_builder.as_ptr()) };
+    let mode = SslVerifyMode::from_bits_truncate(bits);
+    ctx_builder.set_verify_callback(mode, verify_callback);
+}
+
+/// Start recording the verification errors of a connection
+pub(crate) fn start_record(ssl: &mut SslRef) {
+    ssl.set_ex_data(error_depth_index(), OnceLock::new());
+}
+
+/// Depth in the peer chain of the first verification error
+///
+/// The peer certificate is at depth 0.
+pub fn error_depth(ssl: &SslRef) -> Option<u32> {
+    ssl.ex_data(error_depth_index()).and_then(|depth| depth.get().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::tls::pki::{CertBuilder, CertifiedKey};
+    use crate::http::tls::{ClientConfigBuilder, TlsConfig, TlsVars};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// Handshake, returning the client's vars and handshake error
+    fn handshake(server_cert: &CertifiedKey, client: ClientConfigBuilder) -> (TlsVars, Option<String>) {
+        let server_config = TlsConfig::server().cert(server_cert).unwrap().build().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = thread::spawn(move || {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            let _ = server_config.accept(tcp_stream);
+        });
+
+        let session = client.build().unwrap().connect(TcpStream::connect(addr).unwrap()).unwrap();
+        server_handle.join().unwrap();
+        (session.vars().clone(), session.error().map(str::to_string))
+    }
+
+    /// Root, intermediate and leaf for www.example.com and 127.0.0.1
+    fn chain() -> (CertifiedKey, CertifiedKey) {
+        let root = CertBuilder::ca("Root").self_signed().unwrap();
+        let intermediate = CertBuilder::intermediate("Intermediate").issued_by(&root).unwrap();
+        let leaf = CertBuilder::leaf("www.example.com")
+            .dns("www.example.com")
+            .ip("127.0.0.1".parse().unwrap())
+            .issued_by(&intermediate)
+            .unwrap();
+        (root, leaf)
+    }
+
+    fn client(root: &CertifiedKey, servername: &str) -> ClientConfigBuilder {
+        TlsConfig::client().verify_peer(true).ca_cert(root.cert()).unwrap().servername(servername)
+    }
+
+    #[test]
+    fn test_verify_hostname() {
+        let (root, leaf) = chain();
+
+        let (vars, error) = handshake(&leaf, client(&root, "www.example.com").verify_hostname(true));
+        assert_eq!(error, None);
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("ok"));
+        assert_eq!(vars.get("tls.verify_error_depth").as_deref(), Some("<undef>"));
+
+        let (vars, error) = handshake(&leaf, client(&root, "example.org").verify_hostname(true));
+        assert!(error.unwrap().contains("hostname mismatch"));
+        assert_eq!(vars.get("tls.failed").as_deref(), Some("true"));
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("hostname mismatch"));
+        assert_eq!(vars.get("tls.verify_error_depth").as_deref(), Some("0"));
+
+        // Without verify_peer, the mismatch is only reported
+        let lenient = client(&root, "example.org").verify_peer(false).verify_hostname(true);
+        let (vars, error) = handshake(&leaf, lenient);
+        assert_eq!(error, None);
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("hostname mismatch"));
+
+        // Not checked unless asked
+        let (vars, error) = handshake(&leaf, client(&root, "example.org"));
+        assert_eq!(error, None);
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("ok"));
+
+        assert!(TlsConfig::client().verify_hostname(true).build().is_err());
+    }
+
+    #[test]
+    fn test_verify_name() {
+        let (root, leaf) = chain();
+
+        // Checked instead of the SNI
+        let (_, error) = handshake(&leaf, client(&root, "example.org").verify_name("www.example.com"));
+        assert_eq!(error, None);
+        let (_, error) = handshake(&leaf, client(&root, "www.example.com").verify_name("127.0.0.1"));
+        assert_eq!(error, None);
+
+        let (vars, error) = handshake(&leaf, client(&root, "www.example.com").verify_name("127.0.0.2"));
+        assert!(error.unwrap().contains("IP address mismatch"));
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("IP address mismatch"));
+    }
+
+    #[test]
+    fn test_ca_dir() {
+        let (root, leaf) = chain();
+
+        let dir = tempfile::tempdir().unwrap();
+        let client = || TlsConfig::client().verify_peer(true).ca_dir(dir.path()).unwrap();
+
+        // The intermediate is sent by the server, the root is missing
+        let (vars, error) = handshake(&leaf, client());
+        assert!(error.unwrap().contains("unable to get local issuer certificate"));
+        assert_eq!(vars.get("tls.verify_error_depth").as_deref(), Some("1"));
+
+        let path = dir.path().join(format!("{:08x}.0", root.cert().subject_name_hash()));
+        std::fs::write(path, root.cert_pem().unwrap()).unwrap();
+        let (vars, error) = handshake(&leaf, client());
+        assert_eq!(error, None);
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("ok"));
+    }
+
+    #[test]
+    fn test_verify_depth() {
+        let (root, leaf) = chain();
+
+        let (_, error) = handshake(&leaf, client(&root, "www.example.com").verify_depth(1));
+        assert_eq!(error, None);
+
+        let (vars, error) = handshake(&leaf, client(&root, "www.example.com").verify_depth(0));
+        assert!(error.unwrap().contains("certificate chain too long"));
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("certificate chain too long"));
+        assert_eq!(vars.get("tls.verify_error_depth").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_verify_result_untrusted() {
+        // The builtin certificate is self-signed
+        let (vars, error) = handshake(
+            &CertifiedKey::from_pem(crate::http::tls::builtin_cert::BUILTIN_CERT.as_bytes()).unwrap(),
+            TlsConfig::client(),
+        );
+        assert_eq!(error, None);
+        assert_eq!(vars.get("tls.verify_result").as_deref(), Some("self-signed certificate"));
+        assert_eq!(vars.get("tls.verify_error_depth").as_deref(), Some("0"));
+    }
+}
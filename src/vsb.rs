@@ -7,7 +7,7 @@
 //! # Examples
 //!
 //! ```
-//! use vsb::Vsb;
+//! use vtest2::vsb::Vsb;
 //!
 //! let mut vsb = Vsb::new();
 //! vsb.cat("Hello, ");
@@ -17,7 +17,7 @@
 //!
 //! ```
 //! use std::fmt::Write;
-//! use vsb::Vsb;
+//! use vtest2::vsb::Vsb;
 //!
 //! let mut vsb = Vsb::new();
 //! write!(&mut vsb, "The answer is {}", 42).unwrap();
@@ -65,7 +65,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let vsb = Vsb::new();
     /// assert_eq!(vsb.len(), 0);
@@ -88,7 +88,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let vsb = Vsb::with_capacity(100);
     /// assert!(vsb.capacity() >= 100);
@@ -110,7 +110,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.cat("Hello");
@@ -144,7 +144,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.bcat(&[72, 101, 108, 108, 111]); // "Hello"
@@ -167,7 +167,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.push_byte(b'A');
@@ -188,7 +188,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.push('A');
@@ -208,7 +208,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.cat("Hello");
@@ -231,7 +231,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.cat("Hello, world!");
@@ -252,7 +252,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.bcat(&[1, 2, 3, 4]);
@@ -270,7 +270,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.cat("Hello");
@@ -286,7 +286,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// assert!(vsb.is_empty());
@@ -303,7 +303,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let vsb = Vsb::with_capacity(100);
     /// assert!(vsb.capacity() >= 100);
@@ -318,7 +318,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.reserve(100);
@@ -338,7 +338,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.indent(4);
@@ -357,14 +357,14 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.indent(8);
     /// vsb.cat("Hello\n");
     /// vsb.dedent(4);
     /// vsb.cat("World");
-    /// assert_eq!(vsb.as_str(), "    Hello\n    World");
+    /// assert_eq!(vsb.as_str(), "        Hello\n    World");
     /// ```
     #[inline]
     pub fn dedent(&mut self, spaces: usize) {
@@ -376,7 +376,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.set_indent(4);
@@ -393,7 +393,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// assert_eq!(vsb.get_indent(), 0);
@@ -415,7 +415,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.cat("Hello");
@@ -431,7 +431,7 @@ impl Vsb {
     /// # Examples
     ///
     /// ```
-    /// use vsb::Vsb;
+    /// use vtest2::vsb::Vsb;
     ///
     /// let mut vsb = Vsb::new();
     /// vsb.bcat(&[1, 2, 3]);
@@ -459,7 +459,7 @@ impl Default for Vsb {
 ///
 /// ```
 /// use std::fmt::Write;
-/// use vsb::Vsb;
+/// use vtest2::vsb::Vsb;
 ///
 /// let mut vsb = Vsb::new();
 /// write!(&mut vsb, "Hello, {}!", "world").unwrap();
@@ -485,7 +485,7 @@ impl fmt::Write for Vsb {
 ///
 /// ```
 /// use std::io::Write;
-/// use vsb::Vsb;
+/// use vtest2::vsb::Vsb;
 ///
 /// let mut vsb = Vsb::new();
 /// vsb.write_all(b"Hello").unwrap();
@@ -14,7 +14,10 @@ use vtest2::http::h2::flow_control::*;
 use vtest2::http::h2::settings::*;
 use vtest2::http::h2::stream::*;
 use vtest2::http::h2::codec::*;
+use vtest2::http::session::FdSessionOps;
 use bytes::Bytes;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
 
 #[test]
 fn test_settings_frame_encoding() {
@@ -401,3 +404,1701 @@ fn test_default_settings_values() {
     assert_eq!(DEFAULT_MAX_FRAME_SIZE, 16384);
     assert_eq!(DEFAULT_HEADER_TABLE_SIZE, 4096);
 }
+
+#[test]
+fn test_client_concurrent_streams_out_of_order() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2ServerBuilder::new()
+            .max_concurrent_streams(2)
+            .build(FdSessionOps::new(tcp_stream))
+            .unwrap();
+        server.accept().unwrap();
+
+        let first = server.recv_request().unwrap();
+        let second = server.recv_request().unwrap();
+
+        // Respond in reverse order
+        server
+            .send_response(second.stream_id, 200, &[], Bytes::from(second.path.clone()))
+            .unwrap();
+        server
+            .send_response(first.stream_id, 200, &[], Bytes::from(first.path.clone()))
+            .unwrap();
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+
+    let a = client.open_stream("GET", "/a", &[], true).unwrap();
+    let b = client.open_stream("GET", "/b", &[], true).unwrap();
+
+    // Server advertised SETTINGS_MAX_CONCURRENT_STREAMS = 2
+    assert_eq!(client.stream_manager().active_stream_count(), 2);
+    let result = client.open_stream("GET", "/c", &[], true);
+    assert!(matches!(result, Err(Error::TooManyStreams)));
+
+    // Waiting on the first stream routes the second stream's frames too
+    let response_a = client.recv_response(a).unwrap();
+    assert_eq!(response_a.status(), 200);
+    assert_eq!(response_a.body(), b"/a");
+    assert!(client.stream(b).unwrap().stream_complete());
+
+    let response_b = client.recv_any_response().unwrap();
+    assert_eq!(response_b.stream_id, b);
+    assert_eq!(response_b.body(), b"/b");
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_receives_body_larger_than_window() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        let request = server.recv_request().unwrap();
+
+        // Blocks on WINDOW_UPDATE from the client's automatic policy
+        server
+            .send_response(request.stream_id, 200, &[], Bytes::from(vec![b'x'; 200_000]))
+            .unwrap();
+
+        // Drain trailing WINDOW_UPDATE frames until the client hangs up
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    let response = client.get("/large").unwrap();
+    assert_eq!(response.body().len(), 200_000);
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_manual_flow_control() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        let request = server.recv_request().unwrap();
+        server
+            .send_response(request.stream_id, 200, &[], Bytes::from(vec![b'x'; 100_000]))
+            .unwrap();
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .flow_control_policy(FlowControlPolicy::Manual)
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+
+    let stream_id = client.open_stream("GET", "/", &[], true).unwrap();
+
+    // The server stalls once the initial window is used up
+    while client.stream(stream_id).unwrap().body().len() < DEFAULT_INITIAL_WINDOW_SIZE as usize {
+        client.process_frame().unwrap();
+    }
+    assert_eq!(client.flow_control().recv_window().size(), 0);
+
+    // The test drives the WINDOW_UPDATE frames
+    client.send_window_update(CONNECTION_STREAM_ID, 40_000).unwrap();
+    client.send_window_update(stream_id, 40_000).unwrap();
+    assert_eq!(client.flow_control().recv_window().size(), 40_000);
+
+    let response = client.recv_response(stream_id).unwrap();
+    assert_eq!(response.body().len(), 100_000);
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_blocked_send_survives_other_reset_and_ping() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        let first = server.recv_request().unwrap();
+        let second = server.recv_request().unwrap();
+
+        // Blocks on the first stream while the client resets the second
+        // and waits for its PING ACK before granting window
+        server
+            .send_response(first.stream_id, 200, &[], Bytes::from(vec![b'x'; 100_000]))
+            .unwrap();
+        let reset = server.stream_manager().get_stream(second.stream_id).unwrap().reset_code();
+        assert_eq!(reset, Some(ErrorCode::Cancel));
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .flow_control_policy(FlowControlPolicy::Manual)
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+
+    let first = client.open_stream("GET", "/first", &[], true).unwrap();
+    let second = client.open_stream("GET", "/second", &[], true).unwrap();
+    while client.stream(first).unwrap().body().len() < DEFAULT_INITIAL_WINDOW_SIZE as usize {
+        client.process_frame().unwrap();
+    }
+
+    client.send_rst_stream(second, ErrorCode::Cancel).unwrap();
+    client.ping_and_wait(std::time::Duration::from_secs(5)).unwrap();
+
+    client.send_window_update(CONNECTION_STREAM_ID, 40_000).unwrap();
+    client.send_window_update(first, 40_000).unwrap();
+    let response = client.recv_response(first).unwrap();
+    assert_eq!(response.body().len(), 100_000);
+
+    server_handle.join().unwrap();
+}
+
+/// WINDOW_UPDATE overflowing a fresh stream's send window, sent raw since
+/// send_window_update also grows our own receive window
+fn window_update_overflow(stream_id: StreamId) -> Frame {
+    Frame::new(FrameType::WindowUpdate, FrameFlags::empty(), stream_id, Bytes::from_static(&[0x7f, 0xff, 0xff, 0xff]))
+}
+
+#[test]
+fn test_client_resets_stream_on_window_overflow() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        let request = server.recv_request().unwrap();
+
+        // 65535 + 2^31 - 1 overflows the client's send window
+        server.send_frame(&window_update_overflow(request.stream_id)).unwrap();
+        loop {
+            let (frame_type, _, stream_id, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::RstStream {
+                assert_eq!(stream_id, request.stream_id);
+                assert_eq!(&payload[..], &ErrorCode::FlowControlError.as_u32().to_be_bytes());
+                break;
+            }
+        }
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // A stream error: the connection stays up
+    let stream_id = client.open_stream("GET", "/", &[], true).unwrap();
+    while client.stream(stream_id).unwrap().reset_code().is_none() {
+        client.process_frame().unwrap();
+    }
+    assert_eq!(client.stream(stream_id).unwrap().reset_code(), Some(ErrorCode::FlowControlError));
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_resets_stream_on_window_overflow() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        let first = server.recv_request().unwrap();
+        let second = server.recv_request().unwrap();
+
+        let reset = server.stream_manager().get_stream(first.stream_id).unwrap().reset_code();
+        assert_eq!(reset, Some(ErrorCode::FlowControlError));
+        server.send_response(second.stream_id, 200, &[], Bytes::new()).unwrap();
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let first = client.open_stream("GET", "/first", &[], true).unwrap();
+    client.send_frame(&window_update_overflow(first)).unwrap();
+    let second = client.open_stream("GET", "/second", &[], true).unwrap();
+
+    // The other stream still gets its response
+    let response = client.recv_response(second).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(client.stream(first).unwrap().reset_code(), Some(ErrorCode::FlowControlError));
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_resets_stream_on_window_overrun() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        let request = server.recv_request().unwrap();
+        server.send_response_headers(request.stream_id, 200, &[], false).unwrap();
+
+        // 4 x 16384 bytes overrun the 65535-byte stream window by one,
+        // sent raw as send_data would refuse
+        for _ in 0..4 {
+            let data = Bytes::from(vec![b'x'; 16384]);
+            server
+                .send_frame(&Frame::new(FrameType::Data, FrameFlags::empty(), request.stream_id, data))
+                .unwrap();
+        }
+        loop {
+            let (frame_type, _, stream_id, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::RstStream {
+                assert_eq!(stream_id, request.stream_id);
+                assert_eq!(&payload[..], &ErrorCode::FlowControlError.as_u32().to_be_bytes());
+                break;
+            }
+        }
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .flow_control_policy(FlowControlPolicy::Manual)
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+
+    // Room on the connection, so that only the stream window is overrun
+    client.send_window_update(CONNECTION_STREAM_ID, 100_000).unwrap();
+    let stream_id = client.open_stream("GET", "/", &[], true).unwrap();
+    while client.stream(stream_id).unwrap().reset_code().is_none() {
+        client.process_frame().unwrap();
+    }
+    assert_eq!(client.stream(stream_id).unwrap().reset_code(), Some(ErrorCode::FlowControlError));
+    assert_eq!(client.stream(stream_id).unwrap().body().len(), 3 * 16384);
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_blocking_send_splits_data() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2ServerBuilder::new()
+            .initial_window_size(1000)
+            .build(FdSessionOps::new(tcp_stream))
+            .unwrap();
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.body().len(), 5000);
+        server
+            .send_response(request.stream_id, 200, &[], Bytes::new())
+            .unwrap();
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // New streams start with the server's SETTINGS_INITIAL_WINDOW_SIZE
+    let stream_id = client.open_stream("POST", "/upload", &[], false).unwrap();
+    let window = client.stream(stream_id).unwrap().flow_control().send_window().size();
+    assert_eq!(window, 1000);
+
+    client.send_body(stream_id, Bytes::from(vec![b'y'; 5000]), true).unwrap();
+    let response = client.recv_response(stream_id).unwrap();
+    assert_eq!(response.status(), 200);
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_send_empty_body_chunk() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        // Only the DATA frames carrying something, or ending the stream
+        let mut data = Vec::new();
+        loop {
+            let (frame_type, flags, _, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::Data {
+                data.push((payload.len(), flags.is_end_stream()));
+                if flags.is_end_stream() {
+                    break;
+                }
+            }
+        }
+        assert_eq!(data, vec![(4, false), (0, true)]);
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let stream_id = client.open_stream("POST", "/upload", &[], false).unwrap();
+    client.send_body(stream_id, Bytes::new(), false).unwrap();
+    client.send_body(stream_id, Bytes::from("data"), false).unwrap();
+    client.send_body(stream_id, Bytes::new(), false).unwrap();
+    client.send_body(stream_id, Bytes::new(), true).unwrap();
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_stream_scripts_on_shared_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let mut runner = ScriptRunner::new(server);
+
+        // Stream 1 answers after stream 3 has finished
+        let s1 = runner.start(
+            StreamScript::new(1)
+                .rxreq()
+                .expect("req.method", "==", "POST")
+                .expect("req.bodylen", "==", "5")
+                .delay(std::time::Duration::from_millis(100))
+                .txresp(200, &[("x-stream", "1")], "first"),
+        );
+        let s3 = runner.start(
+            StreamScript::new(3)
+                .rxreq()
+                .expect("req.url", "==", "/second")
+                .expect("req.scheme", "==", "http")
+                .expect("req.authority", "==", "localhost")
+                .txresp(404, &[("x-stream", "3")], ""),
+        );
+        let s5 = runner.start(StreamScript::new(5).rxhdrs().txrst(ErrorCode::Cancel));
+
+        s1.wait().unwrap();
+        s3.wait().unwrap();
+        s5.wait().unwrap();
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let mut runner = ScriptRunner::new(client);
+    runner.set_authority("localhost");
+
+    let s1 = runner.start(
+        StreamScript::next()
+            .txreq("POST", "/first", &[], "hello")
+            .rxresp()
+            .expect("resp.status", "==", "200")
+            .expect("resp.http.x-stream", "==", "1")
+            .expect("resp.body", "==", "first"),
+    );
+    assert_eq!(s1.stream_id(), 1);
+
+    runner
+        .run(
+            StreamScript::next()
+                .txreq("GET", "/second", &[], "")
+                .rxresp()
+                .expect("resp.status", "==", "404")
+                .expect("req.url", "==", "/second"),
+        )
+        .unwrap();
+
+    runner
+        .run(StreamScript::new(5).txreq("GET", "/reset", &[], "").rxrst().expect("rst.err", "==", "8"))
+        .unwrap();
+
+    s1.wait().unwrap();
+
+    // A failed expectation reports the stream and the step
+    let err = runner
+        .run(StreamScript::new(0).expect("stream.id", "==", "1"))
+        .unwrap_err();
+    assert!(matches!(&err, Error::ExpectFailed(reason) if reason.starts_with("stream 0 step 0")));
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_stream_script_response_trailers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        server.send_response_headers(request.stream_id, 200, &[("x-stream", "1")], false).unwrap();
+        server.send_body(request.stream_id, Bytes::from("body"), false).unwrap();
+
+        // x-checksum: abc, a literal not indexed
+        let mut block = vec![0x00, 10];
+        block.extend_from_slice(b"x-checksum");
+        block.push(3);
+        block.extend_from_slice(b"abc");
+        server
+            .send_headers(&HeadersFrame::new(request.stream_id, Bytes::from(block), true, true))
+            .unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let mut runner = ScriptRunner::new(client);
+    runner
+        .run(
+            StreamScript::next()
+                .txreq("GET", "/", &[], "")
+                .rxresp()
+                .expect("resp.status", "==", "200")
+                .expect("resp.http.x-stream", "==", "1")
+                .expect("resp.body", "==", "body")
+                .expect("resp.trailer.x-checksum", "==", "abc")
+                .expect("resp.http.x-checksum", "==", "<undef>"),
+        )
+        .unwrap();
+
+    drop(runner);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_receives_and_refuses_server_push() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.path, "/");
+
+        let promise = |path| {
+            [
+                (":method", "GET"),
+                (":path", path),
+                (":scheme", "https"),
+                (":authority", "localhost"),
+            ]
+        };
+        server.send_push_promise(1, 2, &promise("/style.css")).unwrap();
+        server.send_push_promise(1, 4, &promise("/script.js")).unwrap();
+
+        server.send_response(1, 200, &[], Bytes::from("index")).unwrap();
+        server.send_response(2, 200, &[("content-type", "text/css")], Bytes::from("css")).unwrap();
+        server.send_response(4, 200, &[], Bytes::from("js")).unwrap();
+
+        // The client refuses the second push; the reset stream is skipped
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.path, "/next");
+        assert_eq!(server.stream_manager().stream_state(4), StreamState::Closed);
+        server.send_response(request.stream_id, 200, &[], Bytes::from("next")).unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .enable_push(true)
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+
+    let response = client.get("/").unwrap();
+    assert_eq!(response.body(), b"index");
+
+    let push = client.recv_push_promise().unwrap();
+    assert_eq!(push.stream_id, 1);
+    assert_eq!(push.promised_stream_id, 2);
+    assert_eq!(push.method(), Some("GET"));
+    assert_eq!(push.path(), Some("/style.css"));
+
+    let push = client.recv_push_promise().unwrap();
+    assert_eq!(push.promised_stream_id, 4);
+    assert_eq!(push.path(), Some("/script.js"));
+    client.refuse_push(4).unwrap();
+
+    let pushed = client.recv_response(2).unwrap();
+    assert_eq!(pushed.status(), 200);
+    assert_eq!(pushed.header("content-type"), Some("text/css"));
+    assert_eq!(pushed.body(), b"css");
+
+    // Frames for the refused stream are discarded without desynchronizing HPACK
+    let response = client.get("/next").unwrap();
+    assert_eq!(response.body(), b"next");
+    assert!(client.stream(4).is_none());
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_rejects_push_when_disabled() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        server
+            .send_push_promise(request.stream_id, 2, &[(":method", "GET"), (":path", "/pushed")])
+            .unwrap();
+
+        loop {
+            let (frame_type, _, _, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::Goaway {
+                let code = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+                assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::ProtocolError));
+                break;
+            }
+        }
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let result = client.get("/");
+    assert!(matches!(result, Err(Error::Protocol(_))));
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_h2c_upgrade_from_http1() {
+    use vtest2::http::{HttpClient, HttpRequest, HttpServer, Method};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut http = HttpServer::new(FdSessionOps::new(tcp_stream));
+
+        let request = http.receive_request().unwrap();
+        assert!(h2c::is_upgrade_request(&request));
+
+        let mut server = http.upgrade_h2c(request).unwrap();
+        assert_eq!(server.remote_settings().get_initial_window_size(), 65535);
+
+        // The upgrade request is stream 1
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.stream_id, 1);
+        assert_eq!(request.path, "/upgrade");
+        assert_eq!(request.authority, "example.com:8080");
+        assert_eq!(request.header("x-test"), Some("1"));
+        assert_eq!(request.header("upgrade"), None);
+        server.send_response(1, 200, &[], Bytes::from("upgraded")).unwrap();
+
+        // Later requests keep the scheme and authority of the upgrade
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.stream_id, 3);
+        assert_eq!(request.scheme, "http");
+        assert_eq!(request.authority, "example.com:8080");
+        server.send_response(3, 200, &[], Bytes::from("next")).unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let http = HttpClient::new(FdSessionOps::new(tcp_stream));
+    let request = HttpRequest::builder()
+        .method(Method::Get)
+        .uri("/upgrade")
+        .header("Host", "example.com:8080")
+        .header("X-Test", "1")
+        .build();
+
+    let mut client = http.upgrade_h2c(request).unwrap();
+
+    let response = client.recv_response(1).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body(), b"upgraded");
+
+    let response = client.get("/next").unwrap();
+    assert_eq!(response.stream_id, 3);
+    assert_eq!(response.body(), b"next");
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_h2c_prior_knowledge_and_http1_on_one_listener() {
+    use vtest2::http::HttpClient;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let mut protocols = Vec::new();
+
+        for _ in 0..2 {
+            let (tcp_stream, _) = listener.accept().unwrap();
+            match H2ServerBuilder::new().detect(FdSessionOps::new(tcp_stream)).unwrap() {
+                ServerConnection::H2(mut server) => {
+                    let request = server.recv_request().unwrap();
+                    assert_eq!(request.scheme, "http");
+                    assert_eq!(request.authority, "127.0.0.1");
+                    server
+                        .send_response(request.stream_id, 200, &[], Bytes::from("h2"))
+                        .unwrap();
+                    protocols.push("h2");
+                    while server.recv_frame().is_ok() {}
+                }
+                ServerConnection::Http1(mut http) => {
+                    let request = http.receive_request().unwrap();
+                    assert_eq!(request.uri(), "/h1");
+                    http.send_ok(b"h1").unwrap();
+                    protocols.push("http/1.1");
+                }
+            }
+        }
+
+        protocols
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .scheme("http")
+        .authority("127.0.0.1")
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+    assert_eq!(client.get("/h2").unwrap().body(), b"h2");
+    drop(client);
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut http = HttpClient::new(FdSessionOps::new(tcp_stream));
+    assert_eq!(http.get("/h1").unwrap().body(), b"h1");
+
+    assert_eq!(server_handle.join().unwrap(), vec!["h2", "http/1.1"]);
+}
+
+fn raw_frame(frame_type: FrameType, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = FrameCodec::encode_header(frame_type, FrameFlags::empty(), stream_id, payload.len()).to_vec();
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[test]
+fn test_server_rejects_malformed_ping() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let result = server.recv_request();
+        assert!(matches!(result, Err(Error::FrameSize(_))));
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // PING payloads must be 8 bytes
+    client.write_frame(&raw_frame(FrameType::Ping, 0, &[0; 4])).unwrap();
+
+    loop {
+        let (frame_type, _, _, payload) = client.recv_frame().unwrap();
+        if frame_type == FrameType::Goaway {
+            let code = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+            assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::FrameSizeError));
+            break;
+        }
+    }
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_resets_headers_on_skipped_stream_id() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.stream_id, 5);
+
+        // Stream 3 is reset, the connection carries on
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.stream_id, 7);
+        assert_eq!(request.authority, "localhost");
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // GET https://localhost/ on stream 5, then on the skipped stream 3
+    let block = [0x82, 0x87, 0x84, 0x41, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't'];
+    for stream_id in [5, 3] {
+        let flags = FrameFlags::from_u8(0x5);
+        let mut frame = FrameCodec::encode_header(FrameType::Headers, flags, stream_id, block.len()).to_vec();
+        frame.extend_from_slice(&block);
+        client.write_frame(&frame).unwrap();
+    }
+
+    loop {
+        let (frame_type, _, stream_id, payload) = client.recv_frame().unwrap();
+        assert_ne!(frame_type, FrameType::Goaway);
+        if frame_type == FrameType::RstStream {
+            assert_eq!(stream_id, 3);
+            let code = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::StreamClosed));
+            break;
+        }
+    }
+
+    // The reset block still went through HPACK: :authority is dynamic entry 62
+    let block = [0x82, 0x87, 0x84, 0xbe];
+    let flags = FrameFlags::from_u8(0x5);
+    let mut frame = FrameCodec::encode_header(FrameType::Headers, flags, 7, block.len()).to_vec();
+    frame.extend_from_slice(&block);
+    client.write_frame(&frame).unwrap();
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_request_trailers_survive_other_reset() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        // Stream 1 is reset midway, stream 3 completes with trailers
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.stream_id, 3);
+        assert_eq!(request.body(), b"data");
+        assert_eq!(request.trailer("x-checksum"), Some("abc"));
+        assert_eq!(request.header("x-checksum"), None);
+        assert_eq!(server.stream_manager().stream_state(1), StreamState::Closed);
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let first = client.open_stream("POST", "/first", &[], false).unwrap();
+    let second = client.open_stream("POST", "/second", &[], false).unwrap();
+    client.send_body(first, Bytes::from("partial"), false).unwrap();
+    client.send_body(second, Bytes::from("data"), false).unwrap();
+    client.send_rst_stream(first, ErrorCode::Cancel).unwrap();
+
+    // x-checksum: abc, literal without indexing
+    let mut block = vec![0x00, 10];
+    block.extend_from_slice(b"x-checksum");
+    block.push(3);
+    block.extend_from_slice(b"abc");
+    client.send_headers(&HeadersFrame::new(second, Bytes::from(block), true, true)).unwrap();
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_settings_wait_for_ack() {
+    use std::io::Write;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2ServerBuilder::new()
+            .header_table_size(0)
+            .build(FdSessionOps::new(tcp_stream))
+            .unwrap();
+        server.accept().unwrap();
+        assert_eq!(server.local_settings().get_header_table_size(), 4096);
+
+        // Until the client acknowledges a size of 0, it may index headers
+        let first = server.recv_request().unwrap();
+        let second = server.recv_request().unwrap();
+        assert_eq!(first.authority(), "localhost");
+        assert_eq!(second.authority(), "localhost");
+
+        // The client acknowledges and goes away
+        assert!(server.recv_request().is_err());
+        assert_eq!(server.local_settings().get_header_table_size(), 0);
+    });
+
+    // A client sending requests before acknowledging the server SETTINGS
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.write_all(CONNECTION_PREFACE).unwrap();
+    client.write_all(&raw_frame(FrameType::Settings, 0, &[])).unwrap();
+
+    // GET https://localhost/, :authority added to the dynamic table
+    let first = [0x82, 0x87, 0x84, 0x41, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't'];
+    let second = [0x82, 0x87, 0x84, 0xbe];
+    for (stream_id, block) in [(1, &first[..]), (3, &second[..])] {
+        let flags = FrameFlags::from_u8(0x5);
+        let mut frame = FrameCodec::encode_header(FrameType::Headers, flags, stream_id, block.len()).to_vec();
+        frame.extend_from_slice(block);
+        client.write_all(&frame).unwrap();
+    }
+
+    let mut ack = FrameCodec::encode_header(FrameType::Settings, FrameFlags::from_u8(0x1), 0, 0).to_vec();
+    ack.extend_from_slice(&raw_frame(FrameType::Goaway, 0, &[0; 8]));
+    client.write_all(&ack).unwrap();
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_request_headers_with_continuation() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let first = server.recv_request().unwrap();
+        assert_eq!(first.stream_id, 1);
+        assert_eq!(first.authority(), "localhost");
+
+        // Indexes the :authority added by the first block
+        let second = server.recv_request().unwrap();
+        assert_eq!(second.stream_id, 3);
+        assert_eq!(second.authority(), "localhost");
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // GET https://localhost/ split over HEADERS and two CONTINUATION frames
+    let frames: [(FrameType, u8, StreamId, &[u8]); 4] = [
+        (FrameType::Headers, 0x1, 1, &[0x82, 0x87]),
+        (FrameType::Continuation, 0x0, 1, &[0x84, 0x41, 0x09, b'l', b'o', b'c']),
+        (FrameType::Continuation, 0x4, 1, b"alhost"),
+        (FrameType::Headers, 0x5, 3, &[0x82, 0x87, 0x84, 0xbe]),
+    ];
+    for (frame_type, flags, stream_id, fragment) in frames {
+        let mut frame = FrameCodec::encode_header(frame_type, FrameFlags::from_u8(flags), stream_id, fragment.len()).to_vec();
+        frame.extend_from_slice(fragment);
+        client.write_frame(&frame).unwrap();
+    }
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_rejects_bad_header_block() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let result = server.recv_request();
+        assert!(matches!(result, Err(Error::Hpack(_))));
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // Index 62 while the dynamic table is empty
+    let block = [0x82, 0x87, 0x84, 0xbe];
+    let mut frame = FrameCodec::encode_header(FrameType::Headers, FrameFlags::from_u8(0x5), 1, block.len()).to_vec();
+    frame.extend_from_slice(&block);
+    client.write_frame(&frame).unwrap();
+
+    loop {
+        let (frame_type, _, _, payload) = client.recv_frame().unwrap();
+        if frame_type == FrameType::Goaway {
+            let code = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+            assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::CompressionError));
+            break;
+        }
+    }
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_rejects_bad_header_block() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        server
+            .send_headers(&HeadersFrame::new(request.stream_id, Bytes::from_static(&[0x88, 0xbe]), true, true))
+            .unwrap();
+
+        loop {
+            let (frame_type, _, _, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::Goaway {
+                let code = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+                assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::CompressionError));
+                break;
+            }
+        }
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let result = client.get("/");
+    assert!(matches!(result, Err(Error::Hpack(_))));
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_client_reports_stream_and_connection_errors() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+
+        // A zero increment on a stream is a stream error
+        server
+            .write_frame(&raw_frame(FrameType::WindowUpdate, request.stream_id, &[0; 4]))
+            .unwrap();
+        loop {
+            let (frame_type, _, stream_id, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::RstStream {
+                assert_eq!(stream_id, request.stream_id);
+                let code = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::ProtocolError));
+                break;
+            }
+        }
+
+        // DATA on stream 0 is a connection error
+        server.write_frame(&raw_frame(FrameType::Data, 0, b"x")).unwrap();
+        loop {
+            let (frame_type, _, _, payload) = server.recv_frame().unwrap();
+            if frame_type == FrameType::Goaway {
+                let code = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+                assert_eq!(ErrorCode::from_u32(code), Some(ErrorCode::ProtocolError));
+                break;
+            }
+        }
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let result = client.get("/");
+    assert!(matches!(result, Err(Error::Cancel(1))));
+
+    let result = client.process_frame();
+    assert!(matches!(result, Err(Error::Protocol(_))));
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_extension_and_unknown_frames_are_ignored() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.path, "/ext");
+
+        server
+            .send_origin(&OriginFrame::default().with_origin("https://example.com"))
+            .unwrap();
+        server
+            .send_altsvc(&AltSvcFrame::for_stream(request.stream_id, "h2=\":8443\""))
+            .unwrap();
+        server
+            .send_frame(&Frame::new(FrameType::Unknown(0xf0), FrameFlags::from_u8(0xff), request.stream_id, Bytes::from("x")))
+            .unwrap();
+        server.send_response(request.stream_id, 200, &[], Bytes::from("ok")).unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // Unknown frames from the client are ignored as well
+    client
+        .send_frame(&Frame::new(FrameType::Unknown(0xf1), FrameFlags::empty(), 0, Bytes::new()))
+        .unwrap();
+
+    let response = client.get("/ext").unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(&response.body[..], b"ok");
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_observes_extensible_priorities() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let first = server.recv_request().unwrap();
+        assert_eq!(server.stream_priority(first.stream_id), Some(ExtensiblePriority::new(1, false)));
+        let signals = server.take_priority_signals();
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].source, PrioritySource::Header);
+
+        // A PRIORITY_UPDATE sent before the request overrides its header
+        let second = server.recv_request().unwrap();
+        assert_eq!(server.stream_priority(second.stream_id), Some(ExtensiblePriority::new(0, true)));
+        let sources: Vec<_> = server.take_priority_signals().iter().map(|s| s.source).collect();
+        assert_eq!(sources, vec![PrioritySource::Frame, PrioritySource::Header]);
+
+        let signal = server.recv_priority_signal().unwrap();
+        assert_eq!(signal.stream_id, first.stream_id);
+        assert_eq!(signal.priority, ExtensiblePriority::new(6, false));
+        assert_eq!(server.stream_priority(first.stream_id).unwrap().urgency, 6);
+
+        server.send_response(first.stream_id, 200, &[], Bytes::from("a")).unwrap();
+        server.send_response(second.stream_id, 200, &[], Bytes::from("b")).unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let first = client.open_stream("GET", "/a", &[("priority", "u=1")], true).unwrap();
+    client.send_priority_update(first + 2, ExtensiblePriority::new(0, true)).unwrap();
+    let second = client.open_stream("GET", "/b", &[("priority", "u=5")], true).unwrap();
+    assert_eq!(second, first + 2);
+    client.send_priority_update(first, ExtensiblePriority::new(6, false)).unwrap();
+
+    assert_eq!(&client.recv_response(first).unwrap().body[..], b"a");
+    assert_eq!(&client.recv_response(second).unwrap().body[..], b"b");
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_priority_tree_orders_response_bodies() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let ids: Vec<_> = (0..3).map(|_| server.recv_request().unwrap().stream_id).collect();
+
+        let tree = server.stream_manager().priority_tree();
+        assert_eq!(tree.parent(ids[0]), Some(0));
+        assert_eq!(tree.weight(ids[1]), Some(64));
+        assert_eq!(tree.parent(ids[2]), Some(ids[0]));
+
+        for &id in &ids {
+            server.send_response_headers(id, 200, &[], false).unwrap();
+        }
+        let bodies = ids.iter().map(|&id| (id, Bytes::from(vec![id as u8; 40000]))).collect();
+        server.send_prioritized(bodies).unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let first = client.open_stream("GET", "/a", &[], true).unwrap();
+    let second = client.open_stream("GET", "/b", &[], true).unwrap();
+    client.send_priority(second, PrioritySpec::new(0, false, 63)).unwrap();
+    // Prioritizing an idle stream before opening it
+    client.send_priority(second + 2, PrioritySpec::new(first, true, 15)).unwrap();
+    let third = client.open_stream("GET", "/c", &[], true).unwrap();
+    assert_eq!(client.stream_manager().priority_tree().parent(third), Some(first));
+
+    for id in [first, second, third] {
+        let response = client.recv_response(id).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body.len(), 40000);
+        assert!(response.body.iter().all(|&b| b == id as u8));
+    }
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_websocket_over_extended_connect() {
+    use vtest2::http::h2::websocket::Opcode;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2ServerBuilder::new()
+            .enable_connect_protocol(true)
+            .build(FdSessionOps::new(tcp_stream))
+            .unwrap();
+        server.accept().unwrap();
+
+        // Refused tunnel
+        let request = server.recv_request().unwrap();
+        assert_eq!(request.protocol(), Some("bogus"));
+        server.send_response(request.stream_id, 404, &[], Bytes::new()).unwrap();
+
+        let request = server.recv_request().unwrap();
+        assert!(request.is_connect());
+        assert_eq!(request.protocol(), Some("websocket"));
+        assert_eq!(request.path(), "/chat");
+        assert_eq!(request.header("sec-websocket-version"), Some("13"));
+        server.accept_connect(request.stream_id, &[]).unwrap();
+
+        let mut ws = WebSocket::server(server.tunnel(request.stream_id));
+        let message = ws.recv_message().unwrap();
+        assert_eq!(message.text_payload().unwrap(), "hello");
+        ws.send_frame(message).unwrap();
+
+        // Fragments from the client, echoed as one message
+        let message = ws.recv_message().unwrap();
+        assert_eq!(message.opcode, Opcode::Binary);
+        assert_eq!(&message.payload[..], b"fragmented");
+        ws.send_frame(message).unwrap();
+
+        let close = ws.recv_message().unwrap();
+        assert_eq!(close.close_code(), Some(1000));
+        ws.close(1000, "").unwrap();
+
+        let mut tunnel = ws.into_inner();
+        assert_eq!(tunnel.recv().unwrap(), None);
+        tunnel.close().unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    match client.extended_connect("bogus", "/", &[]) {
+        Err(Error::Connect(_)) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("tunnel should have been refused"),
+    }
+
+    let mut ws = client.connect_websocket("/chat", &[]).unwrap();
+    ws.send_text("hello").unwrap();
+    let echo = ws.recv_message().unwrap();
+    assert_eq!(echo.opcode, Opcode::Text);
+    assert_eq!(echo.text_payload().unwrap(), "hello");
+
+    ws.send_frame(WsFrame::new(Opcode::Binary, "frag").fin(false)).unwrap();
+    ws.send_frame(WsFrame::new(Opcode::Continuation, "mented")).unwrap();
+    assert_eq!(&ws.recv_message().unwrap().payload[..], b"fragmented");
+
+    ws.close(1000, "done").unwrap();
+    assert_eq!(ws.recv_message().unwrap().opcode, Opcode::Close);
+
+    let mut tunnel = ws.into_inner();
+    tunnel.close().unwrap();
+    assert_eq!(tunnel.recv().unwrap(), None);
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_extended_connect_requires_setting() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        // A client ignoring the missing setting gets its stream reset
+        assert!(matches!(server.recv_request(), Err(Error::Protocol(_))));
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    assert!(matches!(client.open_extended_connect("websocket", "/", &[]), Err(Error::Protocol(_))));
+
+    let headers = [(":protocol", "websocket")];
+    let stream_id = client.open_stream("CONNECT", "/", &headers, false).unwrap();
+    assert!(matches!(client.recv_connect_response(stream_id), Err(Error::Cancel(_))));
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_tls_through_h2_connect_tunnel() {
+    use vtest2::http::tls::{TlsConfig, TlsVersion};
+    use vtest2::http::{HttpClient, HttpServer, SessionStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut proxy = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        proxy.accept().unwrap();
+
+        let request = proxy.recv_request().unwrap();
+        assert!(request.is_connect());
+        assert_eq!(request.protocol(), None);
+        assert_eq!(request.authority(), "backend.test:443");
+        proxy.accept_connect(request.stream_id, &[]).unwrap();
+
+        let tls_config = TlsConfig::server().version(TlsVersion::Tls13).build().unwrap();
+        let tunnel = proxy.tunnel(request.stream_id);
+        let tls_session = tls_config.accept(SessionStream::new(tunnel)).unwrap();
+
+        let mut server = HttpServer::new(tls_session);
+        let request = server.receive_request().unwrap();
+        assert_eq!(request.uri(), "/inner");
+        server.send_ok(b"through h2").unwrap();
+        server.close().unwrap();
+        drop(server);
+
+        while proxy.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let tunnel = client.connect_tunnel("backend.test:443", &[]).unwrap();
+    let tls_config = TlsConfig::client().version(TlsVersion::Tls13).build().unwrap();
+    let tls_session = tls_config.connect(SessionStream::new(tunnel)).unwrap();
+
+    let mut http = HttpClient::new(tls_session);
+    let response = http.get("/inner").unwrap();
+    assert_eq!(response.body(), b"through h2");
+    http.close().unwrap();
+
+    drop(http);
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_goaway_refuses_streams_above_last_stream_id() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let ids: Vec<_> = (0..3).map(|_| server.recv_request().unwrap().stream_id).collect();
+        server.send_goaway(ids[1], ErrorCode::NoError, "maintenance").unwrap();
+        server.send_response(ids[0], 200, &[], Bytes::from("first")).unwrap();
+        server.send_response(ids[1], 200, &[], Bytes::from("second")).unwrap();
+
+        // The client going away ends the request loop, details kept
+        assert!(matches!(server.recv_request(), Err(Error::ConnectionClosed)));
+        let goaway = server.goaway().unwrap();
+        assert_eq!(goaway.last_stream_id, 0);
+        assert_eq!(goaway.debug_string(), "done");
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let ids: Vec<_> = ["/a", "/b", "/c"]
+        .iter()
+        .map(|path| client.open_stream("GET", path, &[], true).unwrap())
+        .collect();
+
+    match client.recv_response(ids[2]) {
+        Err(e) => assert!(e.is_retryable(), "unexpected error: {}", e),
+        Ok(_) => panic!("stream above the last stream ID should be refused"),
+    }
+
+    let goaway = client.goaway().unwrap().clone();
+    assert_eq!(goaway.last_stream_id, ids[1]);
+    assert_eq!(goaway.error_code, ErrorCode::NoError);
+    assert_eq!(goaway.debug_string(), "maintenance");
+    assert!(goaway.is_graceful());
+
+    // Streams up to the last stream ID complete
+    assert_eq!(&client.recv_response(ids[0]).unwrap().body[..], b"first");
+    assert_eq!(&client.recv_response(ids[1]).unwrap().body[..], b"second");
+
+    assert!(matches!(client.open_stream("GET", "/d", &[], true), Err(Error::ConnectionClosed)));
+
+    client.send_goaway(0, ErrorCode::NoError, "done").unwrap();
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_graceful_shutdown() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let first = server.recv_request().unwrap();
+
+        // The second request races the first GOAWAY and is still served
+        let last_stream_id = server.graceful_shutdown("restart", std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(last_stream_id, first.stream_id + 2);
+
+        let second = server.recv_request().unwrap();
+        assert_eq!(second.stream_id, last_stream_id);
+
+        server.send_response(first.stream_id, 200, &[], Bytes::from("1")).unwrap();
+        server.send_response(second.stream_id, 200, &[], Bytes::from("2")).unwrap();
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    let first = client.open_stream("GET", "/1", &[], true).unwrap();
+    let second = client.open_stream("GET", "/2", &[], true).unwrap();
+
+    assert_eq!(&client.recv_response(first).unwrap().body[..], b"1");
+    assert_eq!(&client.recv_response(second).unwrap().body[..], b"2");
+
+    let goaway = client.goaway().unwrap();
+    assert_eq!(goaway.last_stream_id, second);
+    assert_eq!(goaway.debug_string(), "restart");
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_forgets_closed_streams() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        for _ in 0..3 {
+            let request = server.recv_request().unwrap();
+            server.send_response(request.stream_id, 200, &[], Bytes::from("ok")).unwrap();
+
+            let streams = server.stream_manager();
+            assert!(streams.get_stream(request.stream_id).is_none());
+            assert_eq!(streams.stream_state(request.stream_id), StreamState::Closed);
+        }
+        assert_eq!(server.stream_manager().stream_ids().len(), 0);
+
+        while server.recv_frame().is_ok() {}
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(client.get("/").unwrap().body(), b"ok");
+    }
+
+    drop(client);
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_graceful_shutdown_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let result = server.graceful_shutdown("restart", std::time::Duration::from_millis(100));
+        assert!(matches!(result, Err(Error::Timeout)));
+    });
+
+    // The client never reads the shutdown PING
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_server_interleaved_request_bodies() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        // Each body is kept with its own request, which completes first
+        let first = server.recv_request().unwrap();
+        assert_eq!(first.stream_id, 3);
+        assert_eq!(first.body(), b"three");
+
+        let second = server.recv_request().unwrap();
+        assert_eq!(second.stream_id, 1);
+        assert_eq!(second.body(), b"one");
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    // POST https://localhost/ on streams 1 and 3
+    let block = [0x83, 0x87, 0x84, 0x41, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't'];
+    for stream_id in [1, 3] {
+        let mut frame = FrameCodec::encode_header(FrameType::Headers, FrameFlags::from_u8(0x4), stream_id, block.len()).to_vec();
+        frame.extend_from_slice(&block);
+        client.write_frame(&frame).unwrap();
+    }
+    for (stream_id, flags, data) in [(1, 0x0, &b"o"[..]), (3, 0x1, b"three"), (1, 0x1, b"ne")] {
+        let flags = FrameFlags::from_u8(flags);
+        let mut frame = FrameCodec::encode_header(FrameType::Data, flags, stream_id, data.len()).to_vec();
+        frame.extend_from_slice(data);
+        client.write_frame(&frame).unwrap();
+    }
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_settings_applied_after_ack() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        let settings = SettingsBuilder::new().max_frame_size(32768).build().unwrap();
+        server.send_settings(&SettingsFrame::new(settings)).unwrap();
+
+        // Not in effect until the client acknowledges it
+        assert!(server.settings_tracker().is_pending());
+        assert_eq!(server.local_settings().get_max_frame_size(), 16384);
+        server.send_response(request.stream_id, 200, &[], Bytes::from("ok")).unwrap();
+
+        let request = server.recv_request().unwrap();
+        assert!(!server.settings_tracker().is_pending());
+        assert_eq!(server.settings_tracker().acked_count(), 2);
+        assert_eq!(server.local_settings().get_max_frame_size(), 32768);
+        server.send_response(request.stream_id, 200, &[], Bytes::new()).unwrap();
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    client.get("/").unwrap();
+    assert_eq!(client.remote_settings().get_max_frame_size(), 32768);
+    client.get("/").unwrap();
+    assert_eq!(client.settings_tracker().acked_count(), 1);
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_settings_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2ServerBuilder::new()
+            .settings_timeout(std::time::Duration::from_millis(200))
+            .build(FdSessionOps::new(tcp_stream))
+            .unwrap();
+        server.accept().unwrap();
+        server.recv_request().unwrap();
+
+        let settings = SettingsBuilder::new().header_table_size(0).build().unwrap();
+        server.send_settings(&SettingsFrame::new(settings)).unwrap();
+        assert!(matches!(server.recv_request(), Err(Error::SettingsTimeout)));
+        assert_eq!(server.settings_tracker().pending_count(), 1);
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+    client.open_stream("GET", "/", &[], true).unwrap();
+
+    // Read raw frames so the new SETTINGS is never acknowledged
+    let goaway = loop {
+        let (frame_type, _, _, payload) = client.recv_frame().unwrap();
+        if frame_type == FrameType::Goaway {
+            break FrameCodec::decode_goaway_frame(payload).unwrap();
+        }
+    };
+    assert_eq!(goaway.error_code, ErrorCode::SettingsTimeout);
+    assert_eq!(goaway.last_stream_id, 1);
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_ping_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        // The request arriving meanwhile is kept for recv_request
+        let rtt = server.ping_and_wait(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(server.ping_tracker().last_rtt(), Some(rtt));
+        assert_eq!(server.ping_tracker().outstanding_count(), 0);
+
+        let request = server.recv_request().unwrap();
+        server.send_response(request.stream_id, 200, &[], Bytes::new()).unwrap();
+
+        assert!(matches!(server.recv_request(), Err(Error::ConnectionClosed)));
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2Client::new(FdSessionOps::new(tcp_stream)).unwrap();
+    client.connect().unwrap();
+
+    client.get("/").unwrap();
+
+    client.send_ping(*b"manual01").unwrap();
+    let rtt = client.ping_and_wait(std::time::Duration::from_secs(5)).unwrap();
+    assert_eq!(client.ping_tracker().min_rtt().map(|min| min <= rtt), Some(true));
+    assert!(!client.ping_tracker().is_outstanding(*b"manual01"));
+    assert_eq!(client.ping_tracker().acked_count(), 2);
+
+    client.send_goaway(0, ErrorCode::NoError, "").unwrap();
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_keepalive_detects_unresponsive_peer() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut server = H2Server::new(FdSessionOps::new(tcp_stream)).unwrap();
+        server.accept().unwrap();
+
+        let request = server.recv_request().unwrap();
+        server.send_response(request.stream_id, 200, &[], Bytes::new()).unwrap();
+
+        // Keepalive pings are answered while waiting for the next request
+        server.recv_request().unwrap();
+
+        // Stop reading: the next keepalive ping goes unanswered
+        thread::sleep(std::time::Duration::from_secs(1));
+    });
+
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut client = H2ClientBuilder::new()
+        .keepalive(std::time::Duration::from_millis(50), std::time::Duration::from_millis(300))
+        .build(FdSessionOps::new(tcp_stream))
+        .unwrap();
+    client.connect().unwrap();
+    client.get("/").unwrap();
+
+    // An idle connection sends a ping and gets its ACK
+    let flags = loop {
+        let (frame_type, flags, _, _) = client.recv_frame().unwrap();
+        if frame_type == FrameType::Ping {
+            break flags;
+        }
+    };
+    assert!(flags.is_ack());
+    assert_eq!(client.ping_tracker().acked_count(), 1);
+    assert!(client.ping_tracker().last_rtt().is_some());
+
+    let stream_id = client.open_stream("GET", "/", &[], true).unwrap();
+    assert!(matches!(client.recv_response(stream_id), Err(Error::Timeout)));
+    assert_eq!(client.ping_tracker().outstanding_count(), 1);
+
+    server_handle.join().unwrap();
+}
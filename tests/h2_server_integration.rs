@@ -3,12 +3,11 @@
 //! These tests verify the H2Server implementation works correctly.
 
 use vtest2::http::h2::*;
-use vtest2::http::h2::error::Result;
 use bytes::Bytes;
 
 #[test]
 fn test_server_builder() {
-    let builder = H2ServerBuilder::new()
+    let _builder = H2ServerBuilder::new()
         .header_table_size(8192)
         .enable_push(true)
         .initial_window_size(65535)
@@ -31,6 +30,7 @@ fn test_request_structure() {
         scheme: "https".to_string(),
         authority: "example.com:443".to_string(),
         headers,
+        trailers: std::collections::HashMap::new(),
         body: Bytes::from(r#"{"test":"data"}"#),
     };
 
@@ -56,6 +56,7 @@ fn test_request_empty_body() {
         scheme: "https".to_string(),
         authority: "api.example.com".to_string(),
         headers: std::collections::HashMap::new(),
+        trailers: std::collections::HashMap::new(),
         body: Bytes::new(),
     };
 
@@ -75,6 +76,7 @@ fn test_request_with_various_methods() {
             scheme: "https".to_string(),
             authority: "example.com".to_string(),
             headers: std::collections::HashMap::new(),
+            trailers: std::collections::HashMap::new(),
             body: Bytes::new(),
         };
 
@@ -91,6 +93,7 @@ fn test_request_with_query_parameters() {
         scheme: "https".to_string(),
         authority: "search.example.com".to_string(),
         headers: std::collections::HashMap::new(),
+        trailers: std::collections::HashMap::new(),
         body: Bytes::new(),
     };
 
@@ -109,6 +112,7 @@ fn test_request_large_body() {
         scheme: "https".to_string(),
         authority: "upload.example.com".to_string(),
         headers: std::collections::HashMap::new(),
+        trailers: std::collections::HashMap::new(),
         body: body_bytes,
     };
 
@@ -131,6 +135,7 @@ fn test_request_with_multiple_headers() {
         scheme: "https".to_string(),
         authority: "api.example.com".to_string(),
         headers: headers.clone(),
+        trailers: std::collections::HashMap::new(),
         body: Bytes::new(),
     };
 
@@ -148,6 +153,7 @@ fn test_request_pseudo_headers() {
         scheme: "https".to_string(),
         authority: "proxy.example.com:8080".to_string(),
         headers: std::collections::HashMap::new(),
+        trailers: std::collections::HashMap::new(),
         body: Bytes::new(),
     };
 
@@ -166,7 +172,7 @@ fn test_request_pseudo_headers() {
 
 #[test]
 fn test_server_builder_default() {
-    let builder = H2ServerBuilder::default();
+    let _builder = H2ServerBuilder::default();
     // Just verify the default constructor works
 }
 
@@ -181,6 +187,7 @@ fn test_request_body_string_invalid_utf8() {
         scheme: "https".to_string(),
         authority: "example.com".to_string(),
         headers: std::collections::HashMap::new(),
+        trailers: std::collections::HashMap::new(),
         body: Bytes::from(invalid_utf8),
     };
 
@@ -190,7 +197,7 @@ fn test_request_body_string_invalid_utf8() {
 
 #[test]
 fn test_server_settings_defaults() {
-    let builder = H2ServerBuilder::new();
+    let _builder = H2ServerBuilder::new();
 
     // Server should allow push by default (unlike client)
     // This is implicit in the builder, but we can verify the structure exists
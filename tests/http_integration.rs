@@ -252,3 +252,70 @@ fn test_http_404_error() {
     server_handle.join().unwrap();
     client_handle.join().unwrap();
 }
+
+#[test]
+fn test_tls_through_connect_tunnel() {
+    use vtest2::http::tls::{TlsConfig, TlsVersion};
+    use vtest2::http::SessionStream;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Proxy terminating the tunnel itself
+    let server_handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut proxy = HttpServer::new(FdSessionOps::new(stream));
+
+        let request = proxy.receive_request().unwrap();
+        assert_eq!(request.method(), Method::Connect);
+        assert_eq!(request.uri(), "backend.test:443");
+        proxy.send_response(&HttpResponse::builder().status(Status::OK).build()).unwrap();
+
+        let tls_config = TlsConfig::server().version(TlsVersion::Tls13).build().unwrap();
+        let tls_session = tls_config.accept(SessionStream::new(proxy.into_tunnel())).unwrap();
+
+        let mut server = HttpServer::new(tls_session);
+        let request = server.receive_request().unwrap();
+        assert_eq!(request.uri(), "/secret");
+        server.send_ok(b"tunneled").unwrap();
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let tunnel = HttpClient::new(FdSessionOps::new(stream))
+        .connect_tunnel("backend.test:443")
+        .unwrap();
+
+    let tls_config = TlsConfig::client()
+        .version(TlsVersion::Tls13)
+        .servername("backend.test")
+        .build()
+        .unwrap();
+    let tls_session = tls_config.connect(SessionStream::new(tunnel)).unwrap();
+    assert!(tls_session.vars().version.contains("TLS"));
+
+    let mut client = HttpClient::new(tls_session);
+    let response = client.get("/secret").unwrap();
+    assert_eq!(response.status().code(), 200);
+    assert_eq!(response.body(), b"tunneled");
+
+    server_handle.join().unwrap();
+}
+
+#[test]
+fn test_connect_tunnel_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server_handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut proxy = HttpServer::new(FdSessionOps::new(stream));
+        proxy.receive_request().unwrap();
+        proxy.send_error(Status::new(403).unwrap(), "Forbidden").unwrap();
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let result = HttpClient::new(FdSessionOps::new(stream)).connect_tunnel("blocked.test:443");
+    assert!(matches!(result, Err(vtest2::http::Error::Protocol(_))));
+
+    server_handle.join().unwrap();
+}
@@ -171,7 +171,7 @@ fn test_socket_options() {
 
     stream.set_keepalive(true).expect("Failed to set keepalive");
 
-    stream.set_linger(Some(Duration::from_secs(1))).expect("Failed to set linger");
+    TcpExt::set_linger(&stream, Some(Duration::from_secs(1))).expect("Failed to set linger");
 
     stream.set_read_timeout_dur(Some(Duration::from_secs(5)))
         .expect("Failed to set read timeout");